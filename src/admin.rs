@@ -0,0 +1,218 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{extract::{Path, State}, http::{header::AUTHORIZATION, HeaderMap, StatusCode}, routing::{delete, get, post}, Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
+use tracing::{info, warn};
+
+use crate::{budget, config::MIN_ADMIN_TOKEN_LEN, discovery, metrics::METRICS, notify::{self, Event}, pause, reload::Reloader, rpc::RpcPool, source::AccountUpdate, watchdog};
+
+// what `pause` holds sends back for
+const PAUSE_REASON: &str = "paused by an admin";
+
+/// An admin command failing, with the http status it answers with
+pub type AdminError = (StatusCode, String);
+
+/// What an admin can ask the running liquidator to do, over the http api or the unix socket alike
+#[derive(Debug)]
+pub enum AdminCommand {
+    /// feed health, pauses, overrides and counters
+    Status,
+    /// hold back liquidation sends
+    Pause,
+    Resume,
+    /// re-read the config
+    Reload,
+    /// override the static priority fee and the polled fee's cap, the ones not given left as they are
+    SetFees { compute_unit_price: Option<u64>, max_compute_unit_price: Option<u64> },
+    ClearFees,
+    /// disable or enable a perp market whatever its config says, None going back to the config
+    SetMarket { market_index: u16, disabled: Option<bool> },
+    /// refetch every program account into the caches
+    Refresh,
+}
+
+/// Runs admin commands against the running liquidator, changing its behavior without a restart
+pub struct Admin {
+    /// unset when configs aren't reloaded, which the reload and override commands need
+    pub reloader: Option<Arc<Reloader>>,
+    pub rpc: Arc<RpcPool>,
+    pub program_id: Pubkey,
+    /// into the risk stage, for `Refresh`
+    pub updates: Sender<AccountUpdate>,
+}
+
+impl Admin {
+    pub fn execute(self: &Arc<Self>, command: AdminCommand) -> Result<Value, AdminError> {
+        info!(?command, "admin command");
+        match command {
+            AdminCommand::Status => Ok(self.status()),
+            AdminCommand::Pause => {
+                if pause::pause(PAUSE_REASON) {
+                    notify::notify(Event::critical("liquidation sends paused by an admin".to_string()));
+                }
+                Ok(json!({ "paused": pause::reasons() }))
+            }
+            AdminCommand::Resume => {
+                if pause::resume(PAUSE_REASON) {
+                    notify::notify(Event::info("liquidation sends resumed by an admin".to_string()));
+                }
+                // sends stay paused for any other reason, e.g. the fee budget running out
+                Ok(json!({ "paused": pause::reasons() }))
+            }
+            AdminCommand::Reload => {
+                self.reloader()?.reload().map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, err))?;
+                Ok(json!({ "reloaded": true }))
+            }
+            AdminCommand::SetFees { compute_unit_price, max_compute_unit_price } => {
+                let overrides = self.reloader()?.update(|overrides| {
+                    overrides.compute_unit_price = compute_unit_price.or(overrides.compute_unit_price);
+                    overrides.max_compute_unit_price = max_compute_unit_price.or(overrides.max_compute_unit_price);
+                });
+                Ok(json!({ "overrides": overrides }))
+            }
+            AdminCommand::ClearFees => {
+                let overrides = self.reloader()?.update(|overrides| {
+                    overrides.compute_unit_price = None;
+                    overrides.max_compute_unit_price = None;
+                });
+                Ok(json!({ "overrides": overrides }))
+            }
+            AdminCommand::SetMarket { market_index, disabled } => {
+                let overrides = self.reloader()?.update(|overrides| match disabled {
+                    Some(disabled) => {
+                        overrides.disabled_markets.insert(market_index, disabled);
+                    }
+                    None => {
+                        overrides.disabled_markets.remove(&market_index);
+                    }
+                });
+                Ok(json!({ "overrides": overrides }))
+            }
+            // listing and fetching the whole program takes a while, so it runs in the background
+            AdminCommand::Refresh => {
+                let admin = self.clone();
+                tokio::spawn(async move {
+                    match discovery::refresh(&admin.rpc, &admin.program_id, &admin.updates).await {
+                        Ok(accounts) => info!(accounts, "refreshed the caches for an admin"),
+                        Err(err) => warn!(error = %err, "failed to refresh the caches"),
+                    }
+                });
+                Ok(json!({ "refreshing": true }))
+            }
+        }
+    }
+
+    fn status(&self) -> Value {
+        json!({
+            "slot": watchdog::last_slot(),
+            "since_last_update_ms": watchdog::since_last_update().map(|age| age.as_millis() as u64),
+            "paused": pause::reasons(),
+            "overrides": self.reloader.as_ref().map(|reloader| reloader.overrides()),
+            "fees_spent_today_lamports": budget::spent_today(),
+            "metrics": &METRICS,
+        })
+    }
+
+    fn reloader(&self) -> Result<&Reloader, AdminError> {
+        self.reloader.as_deref().ok_or((StatusCode::SERVICE_UNAVAILABLE, "config reloads aren't enabled".to_string()))
+    }
+}
+
+struct Http {
+    admin: Arc<Admin>,
+    token: String,
+}
+
+type HttpResult = Result<(StatusCode, Json<Value>), AdminError>;
+
+#[derive(Deserialize)]
+struct FeesBody {
+    compute_unit_price: Option<u64>,
+    max_compute_unit_price: Option<u64>,
+}
+
+/// Serves the admin commands over http on `addr` in the background, every request needing an
+/// `Authorization: Bearer <token>` header: `GET /status`, `POST /pause` and `/resume`, `POST /reload`,
+/// `POST /fees` with `compute_unit_price` and `max_compute_unit_price` (`DELETE /fees` drops the overrides),
+/// `POST /markets/:index/disable` and `/enable` (`DELETE /markets/:index` goes back to the config) and
+/// `POST /refresh`
+pub fn serve_http(admin: Arc<Admin>, addr: SocketAddr, token: String) -> JoinHandle<()> {
+    let app = Router::new()
+        .route("/status", get(status))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/reload", post(reload))
+        .route("/fees", post(set_fees).delete(clear_fees))
+        .route("/markets/:market_index/disable", post(disable_market))
+        .route("/markets/:market_index/enable", post(enable_market))
+        .route("/markets/:market_index", delete(clear_market))
+        .route("/refresh", post(refresh))
+        .with_state(Arc::new(Http { admin, token }));
+    tokio::spawn(async move {
+        info!(%addr, "serving admin api");
+        if let Err(err) = axum::Server::bind(&addr).serve(app.into_make_service()).await {
+            warn!(error = %err, "admin api stopped");
+        }
+    })
+}
+
+impl Http {
+    fn execute(&self, headers: &HeaderMap, command: AdminCommand) -> HttpResult {
+        let token = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+        // config loads refuse a token that short as well, a caller of `serve_http` may pass one directly
+        if self.token.len() < MIN_ADMIN_TOKEN_LEN || !token.map_or(false, |token| constant_time_eq(token.as_bytes(), self.token.as_bytes())) {
+            warn!("rejected an admin api request without a valid token");
+            return Err((StatusCode::UNAUTHORIZED, "missing or invalid admin token".to_string()));
+        }
+        let status = if matches!(command, AdminCommand::Refresh) { StatusCode::ACCEPTED } else { StatusCode::OK };
+        Ok((status, Json(self.admin.execute(command)?)))
+    }
+}
+
+// compares without leaking through timing how much of the token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+async fn status(State(http): State<Arc<Http>>, headers: HeaderMap) -> HttpResult {
+    http.execute(&headers, AdminCommand::Status)
+}
+
+async fn pause(State(http): State<Arc<Http>>, headers: HeaderMap) -> HttpResult {
+    http.execute(&headers, AdminCommand::Pause)
+}
+
+async fn resume(State(http): State<Arc<Http>>, headers: HeaderMap) -> HttpResult {
+    http.execute(&headers, AdminCommand::Resume)
+}
+
+async fn reload(State(http): State<Arc<Http>>, headers: HeaderMap) -> HttpResult {
+    http.execute(&headers, AdminCommand::Reload)
+}
+
+async fn set_fees(State(http): State<Arc<Http>>, headers: HeaderMap, Json(body): Json<FeesBody>) -> HttpResult {
+    http.execute(&headers, AdminCommand::SetFees { compute_unit_price: body.compute_unit_price, max_compute_unit_price: body.max_compute_unit_price })
+}
+
+async fn clear_fees(State(http): State<Arc<Http>>, headers: HeaderMap) -> HttpResult {
+    http.execute(&headers, AdminCommand::ClearFees)
+}
+
+async fn disable_market(State(http): State<Arc<Http>>, headers: HeaderMap, Path(market_index): Path<u16>) -> HttpResult {
+    http.execute(&headers, AdminCommand::SetMarket { market_index, disabled: Some(true) })
+}
+
+async fn enable_market(State(http): State<Arc<Http>>, headers: HeaderMap, Path(market_index): Path<u16>) -> HttpResult {
+    http.execute(&headers, AdminCommand::SetMarket { market_index, disabled: Some(false) })
+}
+
+async fn clear_market(State(http): State<Arc<Http>>, headers: HeaderMap, Path(market_index): Path<u16>) -> HttpResult {
+    http.execute(&headers, AdminCommand::SetMarket { market_index, disabled: None })
+}
+
+async fn refresh(State(http): State<Arc<Http>>, headers: HeaderMap) -> HttpResult {
+    http.execute(&headers, AdminCommand::Refresh)
+}
@@ -0,0 +1,137 @@
+use std::{collections::HashSet, net::SocketAddr};
+
+use axum::{extract::{Path, Query}, http::StatusCode, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::{budget, metrics::METRICS, pnl::PNL, status::STATUS, watchdog};
+
+// page size when the request doesn't ask for one, and the largest it may ask for
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+type ApiResult = Result<Json<Value>, (StatusCode, String)>;
+
+#[derive(Deserialize)]
+struct UsersQuery {
+    /// "user" (the default) or "margin_ratio", users without a margin ratio last
+    sort: Option<String>,
+    /// "asc" (the default) or "desc"
+    order: Option<String>,
+    /// only users at or under this margin ratio
+    max_margin_ratio: Option<u64>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    /// comma separated fields to keep, all of them by default
+    fields: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LiquidationsQuery {
+    user: Option<String>,
+    /// "landed", "failed" or "expired"
+    outcome: Option<String>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    fields: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FieldsQuery {
+    fields: Option<String>,
+}
+
+/// Serves the liquidator's live view as json on `addr` in the background, for dashboards and other tooling:
+/// `/users`, `/users/:pubkey`, `/liquidations` (newest first), `/stats` and `/pnl`. Lists are paged with `offset` and
+/// `limit` and every endpoint takes `fields` to pick the fields of the items it returns.
+pub fn spawn(addr: SocketAddr) -> JoinHandle<()> {
+    STATUS.enable();
+    let app = Router::new()
+        .route("/users", get(users))
+        .route("/users/:pubkey", get(user))
+        .route("/liquidations", get(liquidations))
+        .route("/stats", get(stats))
+        .route("/pnl", get(pnl));
+    tokio::spawn(async move {
+        info!(%addr, "serving status api");
+        if let Err(err) = axum::Server::bind(&addr).serve(app.into_make_service()).await {
+            warn!(error = %err, "status api stopped");
+        }
+    })
+}
+
+async fn users(Query(query): Query<UsersQuery>) -> ApiResult {
+    let users = STATUS.users.read().unwrap();
+    let mut selected: Vec<_> = users
+        .iter()
+        .filter(|user| query.max_margin_ratio.map_or(true, |max| user.margin_ratio.map_or(false, |margin_ratio| margin_ratio <= max as u128)))
+        .collect();
+    match query.sort.as_deref() {
+        None | Some("user") => selected.sort_by(|a, b| a.user.cmp(&b.user)),
+        Some("margin_ratio") => selected.sort_by_key(|user| user.margin_ratio.unwrap_or(u128::MAX)),
+        Some(sort) => return Err(bad_request(format!("can't sort by {}", sort))),
+    }
+    match query.order.as_deref() {
+        None | Some("asc") => {}
+        Some("desc") => selected.reverse(),
+        Some(order) => return Err(bad_request(format!("unknown order {}", order))),
+    }
+    Ok(Json(page(&selected, query.offset, query.limit, query.fields.as_deref())))
+}
+
+async fn user(Path(pubkey): Path<String>, Query(query): Query<FieldsQuery>) -> ApiResult {
+    let users = STATUS.users.read().unwrap();
+    match users.iter().find(|user| user.user == pubkey) {
+        Some(user) => Ok(Json(select(user, query.fields.as_deref()))),
+        None => Err((StatusCode::NOT_FOUND, format!("{} isn't a tracked user", pubkey))),
+    }
+}
+
+async fn liquidations(Query(query): Query<LiquidationsQuery>) -> ApiResult {
+    let liquidations = STATUS.liquidations.lock().unwrap();
+    let selected: Vec<_> = liquidations
+        .iter()
+        .rev()
+        .filter(|record| query.user.as_ref().map_or(true, |user| record.user == *user))
+        .filter(|record| query.outcome.as_ref().map_or(true, |outcome| record.outcome == outcome.as_str()))
+        .collect();
+    Ok(Json(page(&selected, query.offset, query.limit, query.fields.as_deref())))
+}
+
+async fn stats(Query(query): Query<FieldsQuery>) -> ApiResult {
+    let stats = json!({
+        "tracked_users": STATUS.users.read().unwrap().len(),
+        "slot": watchdog::last_slot(),
+        "fees_spent_today_lamports": budget::spent_today(),
+        "metrics": &METRICS,
+    });
+    Ok(Json(select(&stats, query.fields.as_deref())))
+}
+
+async fn pnl(Query(query): Query<FieldsQuery>) -> ApiResult {
+    Ok(Json(select(&PNL.report(), query.fields.as_deref())))
+}
+
+fn page<T: Serialize>(items: &[T], offset: usize, limit: Option<usize>, fields: Option<&str>) -> Value {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let selected: Vec<Value> = items.iter().skip(offset).take(limit).map(|item| select(item, fields)).collect();
+    json!({ "total": items.len(), "offset": offset, "limit": limit, "items": selected })
+}
+
+// the item as json, keeping only the requested fields
+fn select<T: Serialize>(item: &T, fields: Option<&str>) -> Value {
+    let mut value = serde_json::to_value(item).unwrap_or(Value::Null);
+    if let (Some(fields), Value::Object(object)) = (fields, &mut value) {
+        let fields: HashSet<&str> = fields.split(',').map(str::trim).collect();
+        object.retain(|field, _| fields.contains(field.as_str()));
+    }
+    value
+}
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
@@ -0,0 +1,57 @@
+use std::{sync::{atomic::Ordering, Arc}, time::Duration};
+
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{info, warn};
+
+use crate::{metrics::METRICS, notify::{self, Event}, pause, rpc::RpcPool};
+
+// what a payer too poor to pay for its transactions holds sends back for
+const PAUSE_REASON: &str = "payer balance under halt_balance_lamports";
+
+/// Checks the payer's balance every `interval`, publishing it as a metric. Under `soft_lamports` every check
+/// warns, with a critical notification when it drops under and an info one when it is back over, and under
+/// `hard_lamports` sends halt until it is topped up again, while the checks go on.
+pub fn spawn(rpc: Arc<RpcPool>, payer: Pubkey, soft_lamports: u64, hard_lamports: u64, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // whether the last balance checked was under soft_lamports
+        let mut low = false;
+        loop {
+            match rpc.call(|client| async move { client.get_balance(&payer).await }).await {
+                Ok(balance) => low = check(&payer, balance, soft_lamports, hard_lamports, low),
+                Err(err) => warn!(error = %err, "failed to check the payer's balance"),
+            }
+            sleep(interval).await;
+        }
+    })
+}
+
+// notifies crossings of soft_lamports since the last check, returns whether the balance is under it
+fn check(payer: &Pubkey, balance: u64, soft_lamports: u64, hard_lamports: u64, was_low: bool) -> bool {
+    METRICS.payer_balance_lamports.store(balance, Ordering::Relaxed);
+    let sol = balance as f64 / LAMPORTS_PER_SOL as f64;
+    let low = balance < soft_lamports;
+    if low != was_low {
+        let wallet = bs58::encode(payer.to_bytes()).into_string();
+        notify::notify(if low {
+            Event::critical(format!("wallet {} is low on SOL, {:.4} left", wallet, sol))
+        } else {
+            Event::info(format!("wallet {} is funded again, {:.4} SOL", wallet, sol))
+        });
+    }
+    if balance < hard_lamports {
+        if pause::pause(PAUSE_REASON) {
+            warn!(balance = sol, "payer balance under halt_balance_lamports, halting sends until it is topped up");
+            notify::notify(Event::critical(format!("payer holds {:.4} SOL, liquidation sends are halted until it is topped up", sol)));
+        }
+        return low;
+    }
+    if pause::resume(PAUSE_REASON) {
+        info!(balance = sol, "payer topped up, resuming sends");
+        notify::notify(Event::info(format!("payer holds {:.4} SOL again, liquidation sends resumed", sol)));
+    }
+    if low {
+        warn!(balance = sol, "payer balance under low_balance_alert_lamports");
+    }
+    low
+}
@@ -0,0 +1,41 @@
+use std::{sync::{Arc, RwLock}, time::Duration};
+
+use solana_client::client_error::Result as ClientResult;
+use solana_sdk::hash::Hash;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::warn;
+
+use crate::rpc::RpcPool;
+
+/// A recent blockhash kept fresh by a background task so sends don't wait on a `getLatestBlockhash` round trip
+#[derive(Clone, Default)]
+pub struct BlockhashCache {
+    blockhash: Arc<RwLock<Option<Hash>>>,
+}
+
+impl BlockhashCache {
+    /// Refreshes the blockhash every `interval` until the process exits
+    pub fn spawn_refresh(&self, rpc: Arc<RpcPool>, interval: Duration) -> JoinHandle<()> {
+        let blockhash = self.blockhash.clone();
+        tokio::spawn(async move {
+            loop {
+                match rpc.call(|client| async move { client.get_latest_blockhash().await }).await {
+                    Ok(latest) => *blockhash.write().unwrap() = Some(latest),
+                    Err(err) => warn!(error = %err, "failed to refresh blockhash"),
+                }
+                sleep(interval).await;
+            }
+        })
+    }
+
+    /// Returns the cached blockhash, fetching one if the refresh task hasn't produced one yet
+    pub async fn get(&self, rpc: &RpcPool) -> ClientResult<Hash> {
+        if let Some(blockhash) = *self.blockhash.read().unwrap() {
+            return Ok(blockhash);
+        }
+
+        let latest = rpc.call(|client| async move { client.get_latest_blockhash().await }).await?;
+        *self.blockhash.write().unwrap() = Some(latest);
+        Ok(latest)
+    }
+}
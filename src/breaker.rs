@@ -0,0 +1,79 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+
+use crate::{config::Config, metrics::{self, METRICS}, notify::{self, Event}};
+
+/// Pauses liquidations priced off an oracle that jumped more than `max_move_percent` from one slot to the
+/// next, a flash crash wick or a corrupted feed more likely than a price to act on. The pause lifts by itself
+/// once the oracle has gone `pause` without another jump. Trips and clears are notified. v2 only: v1 values
+/// users off its amms and reads no oracle prices to break on.
+pub struct CircuitBreaker {
+    max_move_percent: u64,
+    pause: Duration,
+    // last price seen per oracle and the slot it was published at
+    last: HashMap<Pubkey, (u64, i64)>,
+    paused_until: HashMap<Pubkey, Instant>,
+}
+
+impl Default for CircuitBreaker {
+    // disabled
+    fn default() -> CircuitBreaker {
+        CircuitBreaker { max_move_percent: 0, pause: Duration::ZERO, last: HashMap::new(), paused_until: HashMap::new() }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &Config) -> CircuitBreaker {
+        CircuitBreaker { max_move_percent: config.circuit_breaker_percent, pause: Duration::from_secs(config.circuit_breaker_pause_secs), ..CircuitBreaker::default() }
+    }
+
+    /// Picks up reloaded breaker settings, keeping the pauses in effect
+    pub fn reconfigure(&mut self, config: &Config) {
+        self.max_move_percent = config.circuit_breaker_percent;
+        self.pause = Duration::from_secs(config.circuit_breaker_pause_secs);
+    }
+
+    /// Records an oracle price, tripping the breaker on the oracle if it moved too far since the last slot and
+    /// clearing it on the first price past its pause
+    pub fn observe(&mut self, oracle: Pubkey, slot: u64, price: i64) {
+        if self.paused_until.get(&oracle).map_or(false, |until| Instant::now() >= *until) {
+            self.paused_until.remove(&oracle);
+            let oracle = bs58::encode(oracle.to_bytes()).into_string();
+            info!(%oracle, "circuit breaker cleared, resuming the oracle's liquidations");
+            notify::notify(Event::info(format!("circuit breaker on oracle {} cleared, its liquidations are sent as usual", oracle)));
+        }
+        if self.max_move_percent == 0 {
+            return;
+        }
+
+        if let Some((last_slot, last_price)) = self.last.insert(oracle, (slot, price)) {
+            let moved_percent = (price as i128 - last_price as i128).abs() * 100 / (last_price as i128).abs().max(1);
+            if slot <= last_slot + 1 && moved_percent > self.max_move_percent as i128 {
+                if !self.is_paused(&oracle) {
+                    metrics::increment(&METRICS.circuit_breaks);
+                    let oracle = bs58::encode(oracle.to_bytes()).into_string();
+                    warn!(
+                        %oracle,
+                        moved_percent = moved_percent as u64,
+                        pause_secs = self.pause.as_secs(),
+                        "circuit breaker tripped, pausing the oracle's liquidations"
+                    );
+                    notify::notify(Event::critical(format!(
+                        "oracle {} moved {}% in a slot, circuit breaker paused its liquidations for {}s",
+                        oracle,
+                        moved_percent,
+                        self.pause.as_secs()
+                    )));
+                }
+                self.paused_until.insert(oracle, Instant::now() + self.pause);
+            }
+        }
+    }
+
+    /// Whether liquidations priced off the oracle are paused
+    pub fn is_paused(&self, oracle: &Pubkey) -> bool {
+        self.paused_until.get(oracle).map_or(false, |until| Instant::now() < *until)
+    }
+}
@@ -0,0 +1,99 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Mutex}, time::Duration};
+
+use chrono::Utc;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{info, warn};
+
+use crate::{history::Store, notify::{self, Event}, pause, pnl, watchdog};
+
+// what running out of budget holds sends back for
+const PAUSE_REASON: &str = "daily fee budget spent";
+// how often the budget checks for the utc day turning over
+const ROLL_INTERVAL: Duration = Duration::from_secs(60);
+
+// fees and tips paid over the utc day, empty before anything is booked
+struct Spend {
+    day: String,
+    lamports: u64,
+}
+
+static SPEND: Mutex<Spend> = Mutex::new(Spend { day: String::new(), lamports: 0 });
+// whether priority fees are held at the static price for the rest of the day
+static THROTTLED: AtomicBool = AtomicBool::new(false);
+
+/// Books fees and tips paid against the day's `budget_lamports`, 0 for no budget. Past `throttle_percent` of
+/// it priority fees stop following the market, once it is spent sends pause until the next utc day.
+pub fn book(lamports: u64, budget_lamports: u64, throttle_percent: u64) {
+    let mut spend = SPEND.lock().unwrap();
+    roll(&mut spend);
+    spend.lamports += lamports;
+    if budget_lamports == 0 {
+        return;
+    }
+    let sol = |lamports: u64| lamports as f64 / LAMPORTS_PER_SOL as f64;
+    if spend.lamports >= budget_lamports {
+        if pause::pause(PAUSE_REASON) {
+            warn!(spent = sol(spend.lamports), budget = sol(budget_lamports), "daily fee budget spent, pausing sends until tomorrow (utc)");
+            notify::notify(Event::critical(format!("spent the daily fee budget of {:.4} SOL, liquidations are paused until tomorrow (utc)", sol(budget_lamports))));
+        }
+    } else if spend.lamports as u128 * 100 >= budget_lamports as u128 * throttle_percent as u128 && !THROTTLED.swap(true, Ordering::Relaxed) {
+        warn!(spent = sol(spend.lamports), budget = sol(budget_lamports), "daily fee budget running out, no longer raising priority fees");
+        notify::notify(Event::critical(format!(
+            "spent {:.4} of the daily fee budget of {:.4} SOL, priority fees are held at the static price for the rest of the day",
+            sol(spend.lamports),
+            sol(budget_lamports)
+        )));
+    }
+}
+
+/// Whether priority fees are to be held at the static `compute_unit_price`
+pub fn throttled() -> bool {
+    THROTTLED.load(Ordering::Relaxed)
+}
+
+/// Fees and tips paid so far today, in lamports
+pub fn spent_today() -> u64 {
+    let mut spend = SPEND.lock().unwrap();
+    roll(&mut spend);
+    spend.lamports
+}
+
+/// Starts the day's spend off with what the history says was paid since midnight utc, so a restart doesn't
+/// hand out a fresh budget
+pub async fn seed(store: &dyn Store) {
+    let midnight_ms = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
+    match store.transactions(midnight_ms, i64::MAX).await {
+        Ok(transactions) => {
+            let lamports: i64 = transactions.iter().map(|transaction| transaction.fee_lamports + transaction.tip_lamports).sum();
+            let mut spend = SPEND.lock().unwrap();
+            roll(&mut spend);
+            spend.lamports += lamports as u64;
+        }
+        Err(err) => warn!(error = %err, "failed to read today's fees from the history, the fee budget starts from 0"),
+    }
+}
+
+/// Resets the budget when the utc day turns over, which resumes sends it paused
+pub fn spawn_roll() -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            sleep(ROLL_INTERVAL).await;
+            roll(&mut SPEND.lock().unwrap());
+        }
+    })
+}
+
+fn roll(spend: &mut Spend) {
+    let today = pnl::day(watchdog::now_ms());
+    if spend.day == today {
+        return;
+    }
+    let throttled = THROTTLED.swap(false, Ordering::Relaxed);
+    if pause::resume(PAUSE_REASON) || throttled {
+        info!(day = today.as_str(), "new day, fee budget reset");
+        notify::notify(Event::info("new day, the fee budget is reset and liquidations are sent as usual".to_string()));
+    }
+    spend.day = today;
+    spend.lamports = 0;
+}
@@ -0,0 +1,170 @@
+use std::{collections::{HashMap, HashSet}, hash::Hash};
+
+use anchor_lang::AccountDeserialize;
+use clearing_house::state::{market::{Markets, AMM}, state::State, user::{User, UserPositions}};
+use solana_sdk::{hash::{hash, Hash}, pubkey::Pubkey};
+
+use crate::filter::UserFilter;
+
+/// In-memory view of the clearing house program accounts, kept up to date by the program subscription.
+/// Users whose account or positions changed since the last `take_dirty` are tracked so the risk loop
+/// only re-evaluates what moved. Market updates are tracked per market for `take_repriced` instead, and only
+/// concern the users with positions in the markets that moved.
+#[derive(Default)]
+pub struct AccountCache {
+    pub users: HashMap<Pubkey, User>,
+    /// keyed by the positions account pubkey (`User::positions`)
+    pub positions: HashMap<Pubkey, UserPositions>,
+    pub markets: (Pubkey, Markets),
+    pub state: (Pubkey, State),
+    /// the liquidator's own user account, whose collateral is kept up with `auto_deposit_amount`
+    pub liquidator: Option<Pubkey>,
+    /// users the risk stage may liquidate, never the liquidator's own account
+    pub filter: UserFilter,
+    /// what each account was last updated to, so redelivered and out of order updates don't mark it dirty
+    pub versions: Versions,
+    dirty: HashSet<Pubkey>,
+    // users by the markets they have positions in
+    holders: Holders<u64>,
+    repriced: HashSet<u64>,
+}
+
+impl AccountCache {
+    /// Classifies the account data by its anchor discriminator and stores it. Returns false if the data
+    /// isn't an account type the liquidator cares about.
+    pub fn apply(&mut self, pubkey: Pubkey, data: &[u8]) -> bool {
+        if let Ok(user) = User::try_deserialize(&mut &*data) {
+            self.users.insert(pubkey, user);
+            self.dirty.insert(pubkey);
+            return true;
+        }
+
+        if let Ok(user_positions) = UserPositions::try_deserialize(&mut &*data) {
+            self.dirty.insert(user_positions.user);
+            let markets = user_positions.positions.iter().filter(|position| position.base_asset_amount != 0).map(|position| position.market_index).collect();
+            self.holders.set(user_positions.user, markets);
+            self.positions.insert(pubkey, user_positions);
+            return true;
+        }
+
+        if let Ok(markets) = Markets::try_deserialize(&mut &*data) {
+            // the margins of a market's holders depend on its amm state
+            for (index, (market, previous)) in markets.markets.iter().zip(self.markets.1.markets.iter()).enumerate() {
+                if amm_moved(&market.amm, &previous.amm) {
+                    self.repriced.insert(index as u64);
+                }
+            }
+            self.markets = (pubkey, markets);
+            return true;
+        }
+
+        if let Ok(state) = State::try_deserialize(&mut &*data) {
+            self.state = (pubkey, state);
+            return true;
+        }
+
+        false
+    }
+
+    /// Drops a closed user or positions account, returning whether it was cached
+    pub fn evict(&mut self, pubkey: &Pubkey) -> bool {
+        self.dirty.remove(pubkey);
+        self.versions.remove(pubkey);
+        if let Some(user_positions) = self.positions.remove(pubkey) {
+            self.holders.remove(&user_positions.user);
+            return true;
+        }
+        self.users.remove(pubkey).is_some()
+    }
+
+    /// Returns the users that changed since the last call and have both their accounts loaded.
+    pub fn take_dirty(&mut self) -> Vec<Pubkey> {
+        let users = &self.users;
+        let positions = &self.positions;
+        self.dirty
+            .drain()
+            .filter(|user| users.get(user).map_or(false, |user| positions.contains_key(&user.positions)))
+            .collect()
+    }
+
+    /// Returns the users with positions in the markets that moved since the last call
+    pub fn take_repriced(&mut self) -> Vec<Pubkey> {
+        let mut users: Vec<Pubkey> = self.repriced.drain().flat_map(|market| self.holders.of(&market)).collect();
+        users.sort_unstable();
+        users.dedup();
+        users
+    }
+}
+
+// reserves and peg set the value of positions, the funding rates what they owe
+fn amm_moved(amm: &AMM, previous: &AMM) -> bool {
+    amm.base_asset_reserve != previous.base_asset_reserve
+        || amm.quote_asset_reserve != previous.quote_asset_reserve
+        || amm.peg_multiplier != previous.peg_multiplier
+        || amm.cumulative_funding_rate_long != previous.cumulative_funding_rate_long
+        || amm.cumulative_funding_rate_short != previous.cumulative_funding_rate_short
+}
+
+/// The slot and data hash every account was last updated to
+#[derive(Default)]
+pub struct Versions {
+    versions: HashMap<Pubkey, (u64, Hash)>,
+}
+
+impl Versions {
+    /// Records an update to the account, returning false if it is older than the last one recorded or carries the
+    /// same data, which the subscription redelivers on reconnects and the deferred load races it with
+    pub fn record(&mut self, pubkey: Pubkey, slot: u64, data: &[u8]) -> bool {
+        let data_hash = hash(data);
+        match self.versions.get(&pubkey) {
+            Some((last_slot, last_hash)) if slot < *last_slot || data_hash == *last_hash => false,
+            _ => {
+                self.versions.insert(pubkey, (slot, data_hash));
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, pubkey: &Pubkey) {
+        self.versions.remove(pubkey);
+    }
+}
+
+/// Which users hold positions in which market, so a market moving only re-evaluates its own holders
+pub struct Holders<K> {
+    users: HashMap<K, HashSet<Pubkey>>,
+    markets: HashMap<Pubkey, Vec<K>>,
+}
+
+impl<K> Default for Holders<K> {
+    fn default() -> Holders<K> {
+        Holders { users: HashMap::new(), markets: HashMap::new() }
+    }
+}
+
+impl<K: Copy + Eq + Hash> Holders<K> {
+    /// Replaces the markets the user holds positions in
+    pub fn set(&mut self, user: Pubkey, markets: Vec<K>) {
+        self.remove(&user);
+        for market in &markets {
+            self.users.entry(*market).or_default().insert(user);
+        }
+        self.markets.insert(user, markets);
+    }
+
+    pub fn remove(&mut self, user: &Pubkey) {
+        for market in self.markets.remove(user).into_iter().flatten() {
+            if let Some(users) = self.users.get_mut(&market) {
+                users.remove(user);
+                if users.is_empty() {
+                    self.users.remove(&market);
+                }
+            }
+        }
+    }
+
+    /// The users holding positions in the market
+    pub fn of(&self, market: &K) -> Vec<Pubkey> {
+        self.users.get(market).map_or(vec![], |users| users.iter().copied().collect())
+    }
+}
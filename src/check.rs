@@ -0,0 +1,248 @@
+use clearing_house::{math::{constants::{AMM_RESERVE_PRECISION, AMM_TO_QUOTE_PRECISION_RATIO_I128, MARGIN_PRECISION}, funding::calculate_funding_payment, position::calculate_base_asset_value_and_pnl}, state::market::Markets};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{cache::AccountCache, config::Config, metrics::QUOTE_PRECISION, risk, v2::{self, MarginType}};
+
+// v2 margin ratios and weights are in margin precision as well
+const V2_MARGIN_PRECISION: f64 = 10_000.0;
+
+fn dollars(amount: i128) -> f64 {
+    amount as f64 / QUOTE_PRECISION
+}
+
+/// How v1 values a user, dollar amounts in quote and margin ratios as fractions
+#[derive(Serialize)]
+pub struct V1Check {
+    pub user: String,
+    pub authority: String,
+    /// as the user account holds it, before the pending funding
+    pub collateral: f64,
+    pub pending_funding: f64,
+    /// the rest valued the way `evaluate` values it, funding settled. None without open positions.
+    pub total_collateral: Option<f64>,
+    pub unrealized_pnl: Option<f64>,
+    pub base_asset_value: Option<f64>,
+    pub margin_ratio: Option<f64>,
+    pub closest_threshold: Option<&'static str>,
+    /// whether the risk stage would liquidate it and if not why
+    pub verdict: String,
+    pub positions: Vec<V1Position>,
+    pub thresholds: Vec<Threshold>,
+}
+
+#[derive(Serialize)]
+pub struct V1Position {
+    pub market_index: u64,
+    pub side: &'static str,
+    pub base_asset_amount: f64,
+    pub value: f64,
+    pub unrealized_pnl: f64,
+    pub pending_funding: f64,
+}
+
+#[derive(Serialize)]
+pub struct Threshold {
+    pub name: &'static str,
+    pub margin_ratio: f64,
+    /// collateral the user can lose before crossing it, negative once it has
+    pub distance: f64,
+}
+
+/// Works out how v1 values the user: its collateral, each position's value, unrealized pnl and the funding it
+/// has yet to settle, its margin ratio against the state's thresholds and what the risk stage makes of it
+pub fn v1(cache: &AccountCache, config: &Config, user_pubkey: &Pubkey) -> Result<V1Check, String> {
+    let (markets, state) = (&cache.markets.1, &cache.state.1);
+    let user = cache.users.get(user_pubkey).ok_or("no such v1 user")?;
+    let user_positions = cache.positions.get(&user.positions).ok_or("the user's positions account isn't loaded")?;
+
+    let mut positions = vec![];
+    for position in user_positions.positions.iter().filter(|position| position.base_asset_amount != 0) {
+        let market = markets.markets.get(Markets::index_from_u64(position.market_index)).ok_or(format!("no market {}", position.market_index))?;
+        let (value, pnl) = calculate_base_asset_value_and_pnl(position, &market.amm).map_err(|err| format!("{:?}", err))?;
+        let cumulative_funding_rate = if position.base_asset_amount > 0 { market.amm.cumulative_funding_rate_long } else { market.amm.cumulative_funding_rate_short };
+        let funding = calculate_funding_payment(cumulative_funding_rate, position).map_err(|err| format!("{:?}", err))? / AMM_TO_QUOTE_PRECISION_RATIO_I128;
+        positions.push(V1Position {
+            market_index: position.market_index,
+            side: if position.base_asset_amount > 0 { "long" } else { "short" },
+            base_asset_amount: position.base_asset_amount.unsigned_abs() as f64 / AMM_RESERVE_PRECISION as f64,
+            value: dollars(value as i128),
+            unrealized_pnl: dollars(pnl),
+            pending_funding: dollars(funding),
+        });
+    }
+    let mut check = V1Check {
+        user: bs58::encode(user_pubkey.to_bytes()).into_string(),
+        authority: bs58::encode(user.authority.to_bytes()).into_string(),
+        collateral: dollars(user.collateral as i128),
+        pending_funding: positions.iter().map(|position| position.pending_funding).sum(),
+        total_collateral: None,
+        unrealized_pnl: None,
+        base_asset_value: None,
+        margin_ratio: None,
+        closest_threshold: None,
+        verdict: "no open positions, nothing to liquidate".to_string(),
+        positions,
+        thresholds: vec![],
+    };
+
+    let mut settled = user.clone();
+    let mut settled_positions = *user_positions;
+    risk::settle_funding_payment(&mut settled, &mut settled_positions, markets).map_err(|err| err.to_string())?;
+    let (total_collateral, unrealized_pnl, base_asset_value, margin_ratio) = risk::calculate_margin_ratio(&settled, &mut settled_positions, markets).map_err(|err| err.to_string())?;
+    if margin_ratio == u128::MAX {
+        return Ok(check);
+    }
+    let thresholds = [
+        ("initial", state.margin_ratio_initial),
+        ("partial liquidation", state.margin_ratio_partial.saturating_sub(risk::margin_ratio_buffer(config, user_positions))),
+        ("full liquidation", state.margin_ratio_maintenance),
+    ];
+    check.total_collateral = Some(dollars(total_collateral as i128));
+    check.unrealized_pnl = Some(dollars(unrealized_pnl));
+    check.base_asset_value = Some(dollars(base_asset_value as i128));
+    check.margin_ratio = Some(margin_ratio as f64 / MARGIN_PRECISION as f64);
+    check.closest_threshold = thresholds.iter().min_by_key(|(_, threshold)| threshold.abs_diff(margin_ratio)).map(|(name, _)| *name);
+    check.thresholds = thresholds
+        .iter()
+        .map(|(name, threshold)| Threshold {
+            name: *name,
+            margin_ratio: *threshold as f64 / MARGIN_PRECISION as f64,
+            distance: dollars(total_collateral as i128 - (base_asset_value * threshold / MARGIN_PRECISION) as i128),
+        })
+        .collect();
+    check.verdict = if !cache.filter.in_shard(user_pubkey) {
+        "not liquidated, the account belongs to another shard".to_string()
+    } else if !cache.filter.allows(user_pubkey, &user.authority) {
+        "not liquidated, the account is filtered out by the block or allow lists".to_string()
+    } else if let Some(market_index) = risk::disabled_market(config, user_positions) {
+        format!("not liquidated, it has a position in disabled market {}", market_index)
+    } else {
+        match risk::evaluate(cache, config, user_pubkey) {
+            Ok(Some(candidate)) => format!("liquidatable, {} liquidation for a ${:.2} reward", candidate.liquidation.kind(), candidate.reward.liquidator as f64 / QUOTE_PRECISION),
+            Ok(None) => "not liquidatable, the margin ratio is above the partial liquidation threshold".to_string(),
+            Err(err) => format!("not evaluated: {}", err),
+        }
+    };
+    Ok(check)
+}
+
+/// How v2 values a user at the markets' last oracle prices, dollar amounts in quote and weights and margin
+/// ratios as fractions
+#[derive(Serialize)]
+pub struct V2Check {
+    pub user: String,
+    pub authority: String,
+    pub sub_account_id: u16,
+    pub closest_requirement: Option<&'static str>,
+    /// whether the risk stage would liquidate it and if not why
+    pub verdict: String,
+    pub spot_positions: Vec<V2SpotPosition>,
+    pub perp_positions: Vec<V2PerpPosition>,
+    pub margins: Vec<Margin>,
+}
+
+#[derive(Serialize)]
+pub struct V2SpotPosition {
+    pub market_index: u16,
+    pub side: &'static str,
+    /// in tokens
+    pub amount: f64,
+    pub value: f64,
+    pub maintenance_weight: f64,
+    pub weighted_value: f64,
+}
+
+#[derive(Serialize)]
+pub struct V2PerpPosition {
+    pub market_index: u16,
+    pub side: &'static str,
+    pub base_asset_amount: f64,
+    pub value: f64,
+    pub unrealized_pnl: f64,
+    pub maintenance_requirement: f64,
+}
+
+#[derive(Serialize)]
+pub struct Margin {
+    pub margin_type: &'static str,
+    pub total_collateral: f64,
+    pub margin_requirement: f64,
+    /// None without a requirement
+    pub margin_ratio: Option<f64>,
+    pub free_collateral: f64,
+}
+
+/// Works out how v2 values the user: each deposit and borrow with its maintenance weight, each perp position's
+/// value, unrealized pnl and requirement, the maintenance and initial margin and what the risk stage makes of
+/// it. Funding the user has yet to settle isn't read, so it is left out.
+pub fn v2(cache: &v2::Cache, config: &Config, user_pubkey: &Pubkey) -> Result<V2Check, String> {
+    let user = cache.users.get(user_pubkey).ok_or("no such v2 user")?;
+
+    let mut spot_positions = vec![];
+    for position in &user.spot_positions {
+        let market = cache.spot_markets.get(&position.market_index).ok_or(format!("no spot market {}", position.market_index))?;
+        let weight = if position.borrow { market.maintenance_liability_weight } else { market.maintenance_asset_weight };
+        let value = v2::spot_value(market, position);
+        spot_positions.push(V2SpotPosition {
+            market_index: position.market_index,
+            side: if position.borrow { "borrow" } else { "deposit" },
+            amount: v2::token_amount(market, position) as f64 / 10f64.powi(market.decimals as i32),
+            value: dollars(value),
+            maintenance_weight: weight as f64 / V2_MARGIN_PRECISION,
+            weighted_value: dollars(value * weight as i128 / V2_MARGIN_PRECISION as i128),
+        });
+    }
+    let mut perp_positions = vec![];
+    for position in &user.perp_positions {
+        let market = cache.perp_markets.get(&position.market_index).ok_or(format!("no perp market {}", position.market_index))?;
+        let value = v2::perp_value(market, position);
+        perp_positions.push(V2PerpPosition {
+            market_index: position.market_index,
+            side: if position.base_asset_amount >= 0 { "long" } else { "short" },
+            base_asset_amount: position.base_asset_amount.unsigned_abs() as f64 / v2::BASE_PRECISION as f64,
+            value: dollars(value.abs()),
+            unrealized_pnl: dollars(value + position.quote_asset_amount as i128),
+            maintenance_requirement: dollars(value.abs() * market.margin_ratio_maintenance as i128 / V2_MARGIN_PRECISION as i128),
+        });
+    }
+
+    let mut margins = vec![];
+    for (name, margin_type) in [("maintenance", MarginType::Maintenance), ("initial", MarginType::Initial)] {
+        let (total_collateral, margin_requirement) = v2::margin(cache, user, margin_type).ok_or("a market the user has a position in isn't loaded")?;
+        margins.push(Margin {
+            margin_type: name,
+            total_collateral: dollars(total_collateral),
+            margin_requirement: dollars(margin_requirement),
+            margin_ratio: Some(margin_requirement).filter(|margin_requirement| *margin_requirement > 0).map(|margin_requirement| total_collateral.max(0) as f64 / margin_requirement as f64),
+            free_collateral: dollars(total_collateral - margin_requirement),
+        });
+    }
+
+    let (total_collateral, margin_requirement) = v2::margin(cache, user, MarginType::Maintenance).unwrap_or_default();
+    let verdict = if !cache.filter.in_shard(user_pubkey) {
+        "not liquidated, the account belongs to another shard".to_string()
+    } else if !cache.filter.allows(user_pubkey, &user.authority) {
+        "not liquidated, the account is filtered out by the block or allow lists".to_string()
+    } else {
+        match v2::evaluate(cache, config, user_pubkey) {
+            Ok(Some(candidate)) => format!("liquidatable, {} liquidation for a ${:.2} reward", candidate.liquidation.kind(), candidate.reward.liquidator as f64 / QUOTE_PRECISION),
+            Ok(None) if total_collateral >= margin_requirement => "not liquidatable, the collateral covers the maintenance requirement".to_string(),
+            Ok(None) => match v2::oracles_usable(cache, config, user, total_collateral - margin_requirement) {
+                Err(reason) => format!("under the maintenance requirement but held back: {}", reason),
+                Ok(()) => "under the maintenance requirement but there's nothing the liquidator can take over".to_string(),
+            },
+            Err(err) => format!("not evaluated: {}", err),
+        }
+    };
+    Ok(V2Check {
+        user: bs58::encode(user_pubkey.to_bytes()).into_string(),
+        authority: bs58::encode(user.authority.to_bytes()).into_string(),
+        sub_account_id: user.sub_account_id,
+        closest_requirement: margins.iter().min_by(|a, b| a.free_collateral.abs().total_cmp(&b.free_collateral.abs())).map(|margin| margin.margin_type),
+        verdict,
+        spot_positions,
+        perp_positions,
+        margins,
+    })
+}
@@ -0,0 +1,148 @@
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc, OnceLock};
+
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signer::Signer};
+use tokio::{sync::mpsc::{channel, Receiver, Sender}, task::JoinHandle};
+use tracing::{info, warn};
+
+use crate::{leader, metrics::{self, METRICS, QUOTE_PRECISION}, notify::{self, Event}, rpc::RpcPool, signer::SharedSigner, watchdog};
+
+// how long after a transfer was sent the next one waits, so that one still confirming isn't repeated
+const COOLDOWN_MS: u64 = 5 * 60 * 1000;
+
+/// Which way collateral moves between the liquidator's token accounts and its drift account
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+    Deposit,
+    Withdraw,
+}
+
+impl Transfer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transfer::Deposit => "deposit",
+            Transfer::Withdraw => "withdrawal",
+        }
+    }
+}
+
+/// Tops the account up with `amount` from `token_account` once it is under `min_collateral`, or under the
+/// keeper's `margin_percent` of the liquidation margin ratio
+pub struct Deposits {
+    pub token_account: Pubkey,
+    /// in the deposited token's native units, usdc with 6 decimals
+    pub amount: u64,
+    /// total collateral in quote precision, 0 to only go by the margin ratio
+    pub min_collateral: u64,
+}
+
+/// Withdraws whatever the account holds over `max_collateral` into `token_account`
+pub struct Withdrawals {
+    pub token_account: Pubkey,
+    /// total collateral in quote precision
+    pub max_collateral: u64,
+}
+
+/// Keeps the liquidator's own drift account between the configured bounds as the risk stage values it.
+/// Liquidations inherit positions, and a liquidator short of collateral has its own liquidations rejected, while
+/// what piles up over the ceiling is profit better kept out of the protocol.
+pub struct Keeper {
+    pub rpc: Arc<RpcPool>,
+    pub payer: SharedSigner,
+    pub deposits: Option<Deposits>,
+    pub withdrawals: Option<Withdrawals>,
+    /// under this percent of the liquidation margin ratio the account is topped up and never withdrawn from
+    pub margin_percent: u128,
+}
+
+// what `observe` needs from the configuration, set once the keeper is spawned
+struct Bounds {
+    deposits: Option<Deposits>,
+    withdrawals: Option<Withdrawals>,
+    margin_percent: u128,
+    transfers: Sender<(Transfer, u64, Instruction)>,
+}
+
+static BOUNDS: OnceLock<Bounds> = OnceLock::new();
+// when the last transfer was handed over, in milliseconds since the unix epoch
+static LAST_TRANSFER_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Publishes the liquidator's own total collateral and margin ratio, both in the protocol's precisions, and has
+/// `transfer` build a deposit from or a withdrawal into the given token account when they are out of bounds.
+/// `margin_ratio` is None without open positions, which no margin ratio is too low for.
+pub fn observe(total_collateral: i128, margin_ratio: Option<u128>, liquidation_ratio: u128, transfer: impl FnOnce(Transfer, &Pubkey, u64) -> Option<Instruction>) {
+    METRICS.liquidator_collateral.store(total_collateral.clamp(i64::MIN as i128, i64::MAX as i128) as i64, Ordering::Relaxed);
+    METRICS.liquidator_margin_ratio.store(margin_ratio.map_or(0, |margin_ratio| margin_ratio.min(u64::MAX as u128) as u64), Ordering::Relaxed);
+
+    let bounds = match BOUNDS.get() {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    // a standby instance leaves the shared account to the leader
+    if !leader::leading() {
+        return;
+    }
+    let close_to_liquidation = margin_ratio.map_or(false, |margin_ratio| margin_ratio < liquidation_ratio * bounds.margin_percent / 100);
+    let (kind, token_account, amount) = match (&bounds.deposits, &bounds.withdrawals) {
+        (Some(deposits), _) if close_to_liquidation || total_collateral < deposits.min_collateral as i128 => (Transfer::Deposit, deposits.token_account, deposits.amount),
+        (_, Some(withdrawals)) if !close_to_liquidation && total_collateral > withdrawals.max_collateral as i128 => {
+            (Transfer::Withdraw, withdrawals.token_account, (total_collateral - withdrawals.max_collateral as i128).min(u64::MAX as i128) as u64)
+        }
+        _ => return,
+    };
+    let now_ms = watchdog::now_ms();
+    if now_ms.saturating_sub(LAST_TRANSFER_MS.load(Ordering::Relaxed)) < COOLDOWN_MS {
+        return;
+    }
+    let instruction = match transfer(kind, &token_account, amount) {
+        Some(instruction) => instruction,
+        None => return,
+    };
+    LAST_TRANSFER_MS.store(now_ms, Ordering::Relaxed);
+    info!(
+        transfer = kind.name(),
+        amount = amount as f64 / QUOTE_PRECISION,
+        total_collateral = total_collateral as f64 / QUOTE_PRECISION,
+        margin_ratio = margin_ratio.map(|margin_ratio| margin_ratio as u64),
+        "liquidator account out of bounds, moving collateral"
+    );
+    if bounds.transfers.try_send((kind, amount, instruction)).is_err() {
+        warn!(transfer = kind.name(), "a transfer is still being sent, skipping this one");
+    }
+}
+
+impl Keeper {
+    /// Sends the transfers `observe` builds in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        let (sender, receiver) = channel(1);
+        let bounds = Bounds { deposits: self.deposits, withdrawals: self.withdrawals, margin_percent: self.margin_percent, transfers: sender };
+        if BOUNDS.set(bounds).is_err() {
+            warn!("the collateral keeper was already set up");
+        }
+        tokio::spawn(send(self.rpc, self.payer, receiver))
+    }
+}
+
+async fn send(rpc: Arc<RpcPool>, payer: SharedSigner, mut transfers: Receiver<(Transfer, u64, Instruction)>) {
+    while let Some((kind, amount, instruction)) = transfers.recv().await {
+        let signer: &dyn Signer = &*payer;
+        let sent = rpc.send_and_confirm(&[instruction], &[signer]).await;
+        let amount = amount as f64 / QUOTE_PRECISION;
+        match (sent, kind) {
+            (Ok(signature), Transfer::Deposit) => {
+                metrics::increment(&METRICS.collateral_deposits);
+                info!(%signature, amount, "deposited collateral into the liquidator account");
+                notify::notify(Event::info(format!("liquidator account was running low, deposited {:.2} collateral ({})", amount, signature)));
+            }
+            (Ok(signature), Transfer::Withdraw) => {
+                metrics::increment(&METRICS.collateral_withdrawals);
+                info!(%signature, amount, "withdrew collateral from the liquidator account");
+                notify::notify(Event::info(format!("liquidator account was over its ceiling, withdrew {:.2} collateral ({})", amount, signature)));
+            }
+            (Err(err), Transfer::Deposit) => {
+                warn!(error = %err, amount, "failed to deposit collateral into the liquidator account");
+                notify::notify(Event::critical(format!("liquidator account is running low and depositing {:.2} collateral failed: {}", amount, err)));
+            }
+            (Err(err), Transfer::Withdraw) => warn!(error = %err, amount, "failed to withdraw collateral from the liquidator account"),
+        }
+    }
+}
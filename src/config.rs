@@ -1,2 +1,1506 @@
+use std::{error::Error, fs, str::FromStr, thread};
+
+use chrono::{DateTime, NaiveDate};
+use clap::{ArgEnum, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{commitment_config::CommitmentLevel, pubkey::Pubkey};
+
 pub const CLI_URL: &str = "https://api.mainnet-beta.solana.com";
-pub const KEYFILE_PATH: &str = "id.json";
\ No newline at end of file
+pub const WS_URL: &str = "wss://api.mainnet-beta.solana.com";
+pub const KEYFILE_PATH: &str = "id.json";
+pub const LEDGER_URL: &str = "usb://ledger";
+pub const JITO_URL: &str = "https://mainnet.block-engine.jito.wtf";
+pub const CONFIG_PATH: &str = "config.toml";
+// capacity of the channels between the fetcher, risk and executor stages
+pub const UPDATE_CHANNEL_SIZE: usize = 65536;
+pub const CANDIDATE_CHANNEL_SIZE: usize = 256;
+/// shortest `admin_token` the admin api is served with, e.g. `openssl rand -hex 16`
+pub const MIN_ADMIN_TOKEN_LEN: usize = 32;
+
+/// Where transactions get signed
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignerKind {
+    /// keypair from `LIQUIDATOR_KEYPAIR` or `keypair_path`
+    Keypair,
+    /// ledger hardware wallet, requires the `ledger` feature
+    Ledger,
+    /// remote signing service at `remote_signer_url`
+    Remote,
+}
+
+/// Which drift program to liquidate on
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProtocolKind {
+    /// the v1 clearing house
+    V1,
+    /// drift v2, perp markets and spot markets with borrows
+    V2,
+}
+
+/// Which solana cluster to liquidate on, picking the rpc and websocket endpoints left at their defaults
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cluster {
+    MainnetBeta,
+    Devnet,
+    /// a solana-test-validator on its default ports
+    Localnet,
+    /// `rpc_url` and `ws_url` as configured, `rpc_url` has to be
+    Custom,
+}
+
+impl Cluster {
+    /// The cluster's rpc and websocket endpoints, None for a custom one
+    pub fn urls(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Cluster::MainnetBeta => Some((CLI_URL, WS_URL)),
+            Cluster::Devnet => Some(("https://api.devnet.solana.com", "wss://api.devnet.solana.com")),
+            Cluster::Localnet => Some(("http://127.0.0.1:8899", "ws://127.0.0.1:8900")),
+            Cluster::Custom => None,
+        }
+    }
+}
+
+/// How log events are written to stdout
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// human readable lines
+    Text,
+    /// one json object per event with its fields, for log collectors
+    Json,
+}
+
+/// How liquidation transactions get to the leader
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SenderKind {
+    /// `sendTransaction` on every rpc endpoint
+    Rpc,
+    /// jito bundles through the block engine at `jito_url`
+    Jito,
+    /// straight to the upcoming leaders' tpu over quic
+    Tpu,
+    /// every path in `multi_sender_paths` at once
+    Multi,
+}
+
+impl SenderKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SenderKind::Rpc => "rpc",
+            SenderKind::Jito => "jito",
+            SenderKind::Tpu => "tpu",
+            SenderKind::Multi => "multi",
+        }
+    }
+}
+
+/// Which compiled in `LiquidationStrategy` picks and sizes the liquidations
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StrategyKind {
+    /// the most valuable reward first, every liquidation as big as the liquidator can take on
+    Reward,
+    /// the lowest margin ratio first, whatever the reward
+    Riskiest,
+}
+
+/// Bounds on how much of a v2 perp position is taken over per liquidation, in base precision (1e9)
+#[derive(Clone, Debug, Deserialize)]
+pub struct PerpSizeCap {
+    pub market_index: u16,
+    /// liquidations smaller than this are skipped
+    #[serde(default)]
+    pub min_base_asset_amount: u64,
+    pub max_base_asset_amount: Option<u64>,
+}
+
+/// Bounds on how much of a v2 borrow is repaid per liquidation, in the borrowed token's own precision
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpotSizeCap {
+    pub market_index: u16,
+    #[serde(default)]
+    pub min_liability_transfer: u64,
+    pub max_liability_transfer: Option<u64>,
+}
+
+/// Policy for one market: one of v1's markets, a v2 perp market, or with `spot` a v2 spot market. Settings a
+/// protocol has no use for are rejected when the config loads.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MarketSettings {
+    pub market_index: u16,
+    /// `market_index` is a v2 spot market's
+    #[serde(default)]
+    pub spot: bool,
+    /// never liquidate in the market. On v1, where a liquidation closes every position the user has, that means
+    /// never liquidating a user with a position in it. A disabled v2 spot market's borrows are never repaid and
+    /// its deposits never taken.
+    #[serde(default)]
+    pub disabled: bool,
+    /// most notional a single v2 liquidation takes over in the market, in quote precision (1e6): of a perp
+    /// position, or of a spot market's borrow. Not for v1, whose liquidations close positions against the amm
+    /// rather than handing them to the liquidator.
+    pub max_notional: Option<u64>,
+    /// replaces `margin_ratio_buffer` for a v1 market, a user's buffer being the largest of its markets'. Not for
+    /// v2, which liquidates at the maintenance requirement.
+    pub margin_ratio_buffer: Option<u128>,
+}
+
+/// Runtime settings of a `Liquidator`. Loaded from a toml file, any key left out falls back to its default.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub protocol: ProtocolKind,
+    /// "mainnet-beta", "devnet", "localnet" or "custom", the endpoints `rpc_url` and `ws_url` default to
+    pub cluster: Cluster,
+    /// the drift program to liquidate on in place of the protocol's own, e.g. one deployed to a local test
+    /// validator. Read once at startup.
+    pub program_id: Option<String>,
+    /// "text" or "json". `RUST_LOG` picks the level, info by default
+    pub log_format: LogFormat,
+    /// export a trace of every liquidation, from detection to landing, to this OTLP gRPC collector
+    pub otlp_endpoint: Option<String>,
+    /// write log lines to this file instead of stdout
+    pub log_path: Option<String>,
+    pub rpc_url: String,
+    /// rpc endpoints failed over to, in order, when `rpc_url` times out or errors
+    pub fallback_rpc_urls: Vec<String>,
+    /// how often every rpc endpoint's latency and slot are measured for routing reads
+    pub rpc_monitor_interval_ms: u64,
+    /// client side request budget per rpc endpoint, 0 disables limiting
+    pub rpc_requests_per_second: u32,
+    /// tries per rpc request, including the first one, before a transient failure is given up on
+    pub rpc_max_attempts: u32,
+    /// backoff between retries starts here and doubles up to the max, with jitter
+    pub rpc_retry_base_delay_ms: u64,
+    pub rpc_retry_max_delay_ms: u64,
+    pub ws_url: String,
+    pub keypair_path: String,
+    pub signer: SignerKind,
+    pub ledger_url: String,
+    /// `account/change` key derivation path on the ledger, `m/44'/501'` if unset
+    pub ledger_derivation_path: Option<String>,
+    pub remote_signer_url: Option<String>,
+    /// bearer token sent to the remote signer
+    pub remote_signer_token: Option<String>,
+    /// how often the program's accounts are listed to pick up accounts the subscription missed while
+    /// reconnecting, 0 disables it
+    pub discovery_interval_secs: u64,
+    /// user accounts to watch exclusively: only these (and the liquidator's own) are loaded, and they are polled
+    /// every `watchlist_poll_interval_ms` instead of streaming every program account. Empty watches everyone.
+    pub watchlist: Vec<String>,
+    pub watchlist_poll_interval_ms: u64,
+    /// where the known user accounts and their margin ratios are saved, so a restart loads the users close to
+    /// liquidation first and the rest in the background. unset disables snapshots
+    pub snapshot_path: Option<String>,
+    pub snapshot_interval_secs: u64,
+    /// stream account updates from a yellowstone grpc geyser endpoint instead of the rpc websocket
+    pub geyser_url: Option<String>,
+    pub geyser_x_token: Option<String>,
+    /// v2: subscribe to the markets' oracle accounts over the websocket and value users at the live oracle price
+    /// instead of the price the market account last recorded
+    pub subscribe_oracles: bool,
+    /// v2: hold back liquidations valued with a streamed oracle that hasn't published for this many slots, the
+    /// program rejects them. 0 disables the check
+    pub max_oracle_staleness_slots: u64,
+    /// v2: hold back liquidations of users whose shortfall the streamed oracles' confidence bands could cover
+    pub oracle_confidence_guard: bool,
+    /// v2: pause liquidations priced off a streamed oracle for `circuit_breaker_pause_secs` after it moves more
+    /// than this many percent from one slot to the next. 0 disables the breaker. v1 reads no oracle prices and
+    /// isn't covered
+    pub circuit_breaker_percent: u64,
+    pub circuit_breaker_pause_secs: u64,
+    /// commitment used for rpc reads and account subscriptions
+    pub commitment: CommitmentLevel,
+    /// commitment used for the send path: preflight checks and when a liquidation counts as landed
+    pub send_commitment: CommitmentLevel,
+    /// send without the rpc node's preflight simulation
+    pub skip_preflight: bool,
+    /// user accounts never to liquidate, besides the liquidator's own
+    pub excluded_accounts: Vec<String>,
+    /// authorities whose user accounts are never liquidated, e.g. the operator's other wallets or partners'
+    pub excluded_authorities: Vec<String>,
+    /// when either is set only these user accounts and the user accounts of these authorities are liquidated
+    pub allowed_accounts: Vec<String>,
+    pub allowed_authorities: Vec<String>,
+    /// take only this share of the users, as "<index>/<count>" with a zero based index, for `count` instances to
+    /// split them between themselves without overlap
+    pub shard: Option<String>,
+    /// v2: also liquidate spot borrows, repaying the user's biggest borrow in exchange for its biggest deposit
+    /// when that is worth more than its biggest perp position
+    pub liquidate_borrows: bool,
+    /// v2: share of the liquidator's free collateral a single liquidation may use, liquidations are sized down
+    /// to fit it
+    pub free_collateral_usage_percent: u64,
+    /// v2: per market caps on liquidation sizes
+    pub perp_size_caps: Vec<PerpSizeCap>,
+    pub spot_size_caps: Vec<SpotSizeCap>,
+    /// per market policy, for telling liquid majors from thin markets
+    pub markets: Vec<MarketSettings>,
+    /// check the compiled account layouts against the program's on-chain idl at startup and refuse to run if
+    /// they differ. v2 always reads its layouts from the idl.
+    pub verify_idl: bool,
+    /// address lookup table holding the static liquidation accounts, liquidations are sent as v0 transactions
+    /// through it when set. Create one with the `lookup-table` command.
+    pub lookup_table: Option<String>,
+    /// only liquidate once the margin ratio is this far below the protocol's partial liquidation ratio, leaving
+    /// headroom for the price to move back before the transaction lands. v1 only.
+    pub margin_ratio_buffer: u128,
+    /// users are tiered by their margin ratio in percent of the ratio they're liquidated at: critical under
+    /// `critical_margin_percent`, watched under `watch_margin_percent`, safe above. A market moving re-evaluates
+    /// critical users every time, watched ones every `watch_interval` moves and safe ones every `safe_interval`.
+    /// A user's own account changing always re-evaluates it.
+    pub critical_margin_percent: u128,
+    pub watch_margin_percent: u128,
+    pub watch_interval: u64,
+    pub safe_interval: u64,
+    /// skip liquidations whose expected reward doesn't cover their transaction fees, priority fee and tip by at
+    /// least this much, in usdc with 6 decimals
+    pub min_reward: u64,
+    /// most lamports of transaction fees and tips to spend per utc day, 0 for no limit. Once spent, sends pause
+    /// until the next day.
+    pub daily_fee_budget_lamports: u64,
+    /// past this percent of the daily budget priority fees are held at `compute_unit_price` instead of
+    /// following the market
+    pub fee_budget_throttle_percent: u64,
+    /// compute units requested per liquidation, a batched transaction requests this times its liquidations
+    pub compute_unit_limit: u32,
+    /// priority fee bid, in micro lamports per compute unit
+    pub compute_unit_price: u64,
+    /// bid this percentile of the recent prioritization fees paid on the liquidation's writable accounts
+    /// instead of the static `compute_unit_price`
+    pub priority_fee_percentile: Option<u8>,
+    /// cap on the dynamic priority fee, in micro lamports per compute unit
+    pub max_compute_unit_price: u64,
+    pub priority_fee_poll_interval_ms: u64,
+    pub sender: SenderKind,
+    /// how candidates are picked, ordered and sized, see `LiquidationStrategy`
+    pub strategy: StrategyKind,
+    /// rhai script accepting, rejecting or ranking the candidates the strategy accepted, see `ScriptFilter`.
+    /// Re-read on a config reload.
+    pub script_path: Option<String>,
+    pub jito_url: String,
+    /// tip paid to jito by every bundled liquidation, in lamports
+    pub jito_tip_lamports: u64,
+    /// number of upcoming leader slots each transaction is sent to by the tpu sender
+    pub tpu_fanout_slots: u64,
+    /// paths the multi sender submits every transaction through
+    pub multi_sender_paths: Vec<SenderKind>,
+    /// liquidations packed into one transaction when several users are liquidatable at once, as many as fit
+    /// the transaction size and compute limits
+    pub max_liquidations_per_transaction: usize,
+    /// simulate every liquidation first and skip the ones the program would reject
+    pub simulate_before_send: bool,
+    /// detect, build, sign and (with `simulate_before_send`) simulate liquidations as usual but log them with
+    /// their expected reward instead of sending them. Nothing else that sends transactions is started either.
+    pub dry_run: bool,
+    /// how often a sent liquidation's signature status is polled
+    pub confirm_poll_interval_ms: u64,
+    /// how often a liquidation that hasn't landed yet is sent again
+    pub rebroadcast_interval_ms: u64,
+    /// times a liquidation is re-signed with a new blockhash after the old one expired
+    pub max_resigns: u32,
+    /// a user whose liquidation failed or never landed is left alone for this long, doubling with every failure
+    /// in a row up to the max. 0 disables cooldowns.
+    pub cooldown_base_ms: u64,
+    pub cooldown_max_ms: u64,
+    /// tokio worker threads, one per cpu by default
+    pub worker_threads: usize,
+    /// rpc requests in flight at once across every endpoint, eight per cpu by default
+    pub max_concurrent_requests: usize,
+    /// liquidation transactions being sent at once, two per cpu by default
+    pub max_concurrent_sends: usize,
+    /// restart the data source when no program account update arrived for this long, e.g. after a connection hung
+    /// without erroring. 0 disables the watchdog, quiet programs need a generous timeout
+    pub watchdog_timeout_secs: u64,
+    /// serve `/healthz` and `/readyz` on this address, e.g. "0.0.0.0:8080"
+    pub health_addr: Option<String>,
+    /// `/readyz` fails once no program account update arrived for this long
+    pub health_stale_secs: u64,
+    /// or once the applied updates trail the rpc's slot by more than this
+    pub health_max_slot_lag: u64,
+    /// serve the status api (tracked users, liquidations, stats) on this address, e.g. "0.0.0.0:8081"
+    pub api_addr: Option<String>,
+    /// serve the admin api (pause, resume, reload, fee and market overrides, cache refresh) on this address,
+    /// requiring `admin_token`, at least `MIN_ADMIN_TOKEN_LEN` characters, as a bearer token
+    pub admin_addr: Option<String>,
+    pub admin_token: Option<String>,
+    /// serve the admin commands on a unix socket at this path as well, for `ctl`, without opening a port
+    pub admin_socket_path: Option<String>,
+    /// post landed liquidations and critical errors to this discord webhook
+    pub discord_webhook_url: Option<String>,
+    /// post landed liquidations and critical errors to this slack incoming webhook
+    pub slack_webhook_url: Option<String>,
+    /// send critical errors to this slack webhook instead, e.g. one posting to an on-call channel
+    pub slack_critical_webhook_url: Option<String>,
+    /// notifications are batched into one message per channel at most this often
+    pub notify_batch_interval_secs: u64,
+    /// alert when the payer's balance drops under this many lamports
+    pub low_balance_alert_lamports: u64,
+    /// halt sends while the payer's balance is under this many lamports, 0 to never halt
+    pub halt_balance_lamports: u64,
+    /// how often the payer's balance is checked against the two thresholds
+    pub balance_check_interval_secs: u64,
+    /// token account the liquidator's own drift account is topped up from when it runs low, None to never deposit
+    pub deposit_token_account: Option<String>,
+    /// how much each top up deposits, in the deposited token's native units: usdc with 6 decimals
+    pub auto_deposit_amount: u64,
+    /// top up once the liquidator's total collateral is under this much, in usdc with 6 decimals, 0 to only go by
+    /// `auto_deposit_margin_percent`
+    pub auto_deposit_min_collateral: u64,
+    /// or once its margin ratio is under this percent of the ratio it would be liquidated at, which nothing is
+    /// withdrawn under either
+    pub auto_deposit_margin_percent: u128,
+    /// v2 spot market the top ups are deposited into and withdrawals taken out of
+    pub auto_deposit_market_index: u16,
+    /// token account whatever the liquidator's own drift account holds over `auto_withdraw_max_collateral` is
+    /// withdrawn into, None to never withdraw
+    pub withdraw_token_account: Option<String>,
+    /// the most collateral the liquidator keeps in the protocol, in usdc with 6 decimals
+    pub auto_withdraw_max_collateral: u64,
+    /// close the perp positions liquidations hand the liquidator's own account
+    pub unwind_positions: bool,
+    /// the most each closing order is worth, in usdc with 6 decimals
+    pub unwind_max_order_value: u64,
+    /// how far off the price the position was valued at a closing order may fill
+    pub unwind_max_slippage_bps: u64,
+    /// perp markets hedged off drift, as `<perp market index>=<venue symbol>`, e.g. `0=SOLUSDT`
+    pub hedge_markets: Vec<String>,
+    /// hedge the liquidator's position in one of them once closing it on drift would move the amm more than this
+    pub hedge_impact_bps: u64,
+    /// hedge on binance usd-m futures with this api key
+    pub binance_api_key: Option<String>,
+    pub binance_api_secret: Option<String>,
+    pub binance_url: String,
+    /// v2: refuse a perp market's liquidations while the liquidator's own position in it is worth more than this,
+    /// in usdc with 6 decimals, 0 for no cap
+    pub max_exposure_notional: u64,
+    /// caps of their own for some markets, as `<perp market index>=<notional>`
+    pub exposure_caps: Vec<String>,
+    /// token of the telegram bot pushing notifications to `telegram_chat_ids` and answering their commands
+    pub telegram_bot_token: Option<String>,
+    /// the only chats the bot talks to
+    pub telegram_chat_ids: Vec<i64>,
+    /// open pagerduty incidents for sustained failures through this events v2 integration
+    pub pagerduty_routing_key: Option<String>,
+    /// or opsgenie alerts through this api key
+    pub opsgenie_api_key: Option<String>,
+    /// page when the risk stage went this long without finishing a round of updates
+    pub incident_stall_secs: u64,
+    /// page when none of the liquidations attempted over this long landed
+    pub incident_window_secs: u64,
+    /// post a json payload for every liquidation attempt and result to this url
+    pub webhook_url: Option<String>,
+    /// sign the payloads with an hmac-sha256 under this secret, sent in the `X-Liquidator-Signature` header
+    pub webhook_secret: Option<String>,
+    /// keep the history of liquidatable users, transactions sent and their results in this sqlite database
+    pub history_path: Option<String>,
+    /// or in this postgres database instead, e.g. one shared by several instances
+    pub history_postgres_url: Option<String>,
+    /// archive every account write the risk stage sees, the startup scan's included, to this gzipped file for
+    /// replaying and debugging offline
+    pub record_path: Option<String>,
+    /// publish the users close to liquidation to this redis server, e.g. "redis://127.0.0.1/"
+    pub redis_url: Option<String>,
+    /// sorted set holding those users' pubkeys, scored by their margin ratio
+    pub redis_key: String,
+    /// channel the whole set is published on as json whenever it changes
+    pub redis_channel: String,
+    /// the users under this percent of their liquidation margin ratio count as close to liquidation
+    pub redis_margin_percent: u128,
+    /// run warm as one of several instances sharing the liquidator account, sending only while holding the lock
+    /// `leader_lock_key` on this redis server, e.g. "redis://127.0.0.1/"
+    pub leader_lock_url: Option<String>,
+    pub leader_lock_key: String,
+    /// how long the lock outlives its holder, about how long failing over takes
+    pub leader_lock_ttl_ms: u64,
+    /// what this instance holds the lock as, a random id by default
+    pub instance_id: Option<String>,
+    /// how long shutdown waits for the liquidations in flight to land before exiting anyway
+    pub shutdown_timeout_secs: u64,
+    /// how often the background task fetches a fresh blockhash for sends
+    pub blockhash_refresh_interval_ms: u64,
+    pub update_channel_size: usize,
+    pub candidate_channel_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            protocol: ProtocolKind::V1,
+            cluster: Cluster::MainnetBeta,
+            program_id: None,
+            log_format: LogFormat::Text,
+            otlp_endpoint: None,
+            log_path: None,
+            rpc_url: CLI_URL.to_string(),
+            fallback_rpc_urls: vec![],
+            rpc_monitor_interval_ms: 1000,
+            rpc_requests_per_second: 10,
+            rpc_max_attempts: 5,
+            rpc_retry_base_delay_ms: 100,
+            rpc_retry_max_delay_ms: 5000,
+            ws_url: WS_URL.to_string(),
+            keypair_path: KEYFILE_PATH.to_string(),
+            signer: SignerKind::Keypair,
+            ledger_url: LEDGER_URL.to_string(),
+            ledger_derivation_path: None,
+            remote_signer_url: None,
+            remote_signer_token: None,
+            discovery_interval_secs: 300,
+            watchlist: vec![],
+            watchlist_poll_interval_ms: 400,
+            snapshot_path: None,
+            snapshot_interval_secs: 60,
+            geyser_url: None,
+            geyser_x_token: None,
+            subscribe_oracles: true,
+            max_oracle_staleness_slots: 120,
+            oracle_confidence_guard: true,
+            circuit_breaker_percent: 10,
+            circuit_breaker_pause_secs: 30,
+            commitment: CommitmentLevel::Processed,
+            send_commitment: CommitmentLevel::Processed,
+            skip_preflight: false,
+            excluded_accounts: vec![],
+            excluded_authorities: vec![],
+            allowed_accounts: vec![],
+            allowed_authorities: vec![],
+            shard: None,
+            liquidate_borrows: true,
+            free_collateral_usage_percent: 80,
+            perp_size_caps: vec![],
+            spot_size_caps: vec![],
+            markets: vec![],
+            verify_idl: true,
+            lookup_table: None,
+            margin_ratio_buffer: 0,
+            critical_margin_percent: 150,
+            watch_margin_percent: 300,
+            watch_interval: 4,
+            safe_interval: 32,
+            min_reward: 0,
+            daily_fee_budget_lamports: 0,
+            fee_budget_throttle_percent: 80,
+            compute_unit_limit: 400_000,
+            compute_unit_price: 10_000,
+            priority_fee_percentile: None,
+            max_compute_unit_price: 1_000_000,
+            priority_fee_poll_interval_ms: 2000,
+            sender: SenderKind::Rpc,
+            strategy: StrategyKind::Reward,
+            script_path: None,
+            jito_url: JITO_URL.to_string(),
+            jito_tip_lamports: 10_000,
+            tpu_fanout_slots: 12,
+            multi_sender_paths: vec![SenderKind::Rpc, SenderKind::Tpu, SenderKind::Jito],
+            max_liquidations_per_transaction: 3,
+            simulate_before_send: true,
+            dry_run: false,
+            confirm_poll_interval_ms: 400,
+            rebroadcast_interval_ms: 2000,
+            max_resigns: 2,
+            cooldown_base_ms: 2000,
+            cooldown_max_ms: 60_000,
+            worker_threads: cpus(),
+            max_concurrent_requests: cpus() * 8,
+            max_concurrent_sends: cpus() * 2,
+            shutdown_timeout_secs: 30,
+            watchdog_timeout_secs: 120,
+            health_addr: None,
+            health_stale_secs: 30,
+            health_max_slot_lag: 150,
+            api_addr: None,
+            admin_addr: None,
+            admin_token: None,
+            admin_socket_path: None,
+            discord_webhook_url: None,
+            slack_webhook_url: None,
+            slack_critical_webhook_url: None,
+            notify_batch_interval_secs: 5,
+            low_balance_alert_lamports: 100_000_000,
+            halt_balance_lamports: 20_000_000,
+            balance_check_interval_secs: 30,
+            deposit_token_account: None,
+            auto_deposit_amount: 100_000_000,
+            auto_deposit_min_collateral: 0,
+            auto_deposit_margin_percent: 200,
+            auto_deposit_market_index: 0,
+            withdraw_token_account: None,
+            auto_withdraw_max_collateral: 10_000_000_000,
+            unwind_positions: false,
+            unwind_max_order_value: 10_000_000_000,
+            unwind_max_slippage_bps: 50,
+            hedge_markets: vec![],
+            max_exposure_notional: 0,
+            exposure_caps: vec![],
+            hedge_impact_bps: 100,
+            binance_api_key: None,
+            binance_api_secret: None,
+            binance_url: "https://fapi.binance.com".to_string(),
+            telegram_bot_token: None,
+            telegram_chat_ids: vec![],
+            pagerduty_routing_key: None,
+            opsgenie_api_key: None,
+            incident_stall_secs: 300,
+            incident_window_secs: 900,
+            webhook_url: None,
+            webhook_secret: None,
+            history_path: None,
+            history_postgres_url: None,
+            record_path: None,
+            redis_url: None,
+            redis_key: "drift-liquidator:at-risk".to_string(),
+            redis_channel: "drift-liquidator:at-risk".to_string(),
+            redis_margin_percent: 150,
+            leader_lock_url: None,
+            leader_lock_key: "drift-liquidator:leader".to_string(),
+            leader_lock_ttl_ms: 5000,
+            instance_id: None,
+            blockhash_refresh_interval_ms: 400,
+            update_channel_size: UPDATE_CHANNEL_SIZE,
+            candidate_channel_size: CANDIDATE_CHANNEL_SIZE,
+        }
+    }
+}
+
+/// One-off commands run instead of the liquidator
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create the address lookup table liquidations are compiled against, or add any missing accounts to
+    /// the configured `lookup_table`
+    LookupTable,
+    /// Dump the recorded liquidation history to csv or parquet files, one per table
+    Export(ExportArgs),
+    /// Print what the recorded liquidations earned and paid by day and market
+    Pnl(PnlArgs),
+    /// Write a csv ledger of the recorded liquidation transactions for tax and accounting
+    Report(ReportArgs),
+    /// Run a recording made with `record_path` through the risk stage and a simulated executor, printing the
+    /// liquidations it would have sent and what they would have made under the given fees
+    Replay(ReplayArgs),
+    /// Re-evaluate every user under a hypothetical oracle move, e.g. `stress --market 0 --move -15%`, and print
+    /// how many become liquidatable and the reward at stake
+    Stress(StressArgs),
+    /// Load every user and print the ones closest to liquidation with their collateral, notional and distance
+    /// to it
+    Scan(ScanArgs),
+    /// Print how a user is valued, position by position, against the liquidation thresholds and why it is or
+    /// isn't liquidatable
+    Check(CheckArgs),
+    /// Evaluate a single user and, if it is liquidatable, send its liquidation right away
+    Liquidate(LiquidateArgs),
+    /// Value a single user at hypothetical prices, e.g. `simulate-price <user> --price 0=95.5`, and print the
+    /// price of each of its markets at which it becomes liquidatable
+    SimulatePrice(SimulatePriceArgs),
+    /// Send an admin command to the running liquidator over its `admin_socket_path`, e.g. `ctl pause`
+    Ctl(CtlArgs),
+}
+
+/// The range of history `report` covers and where it goes
+#[derive(Debug, clap::Args)]
+pub struct ReportArgs {
+    /// only transactions sent from this date (e.g. 2024-01-31, midnight utc) or rfc 3339 time on
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub from: Option<i64>,
+    /// only transactions sent before this date or time
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub to: Option<i64>,
+    /// csv file to write, stdout if not given
+    #[clap(long)]
+    pub out: Option<String>,
+}
+
+/// The range of history `pnl` adds up
+#[derive(Debug, clap::Args)]
+pub struct PnlArgs {
+    /// only liquidations sent from this date (e.g. 2024-01-31, midnight utc) or rfc 3339 time on
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub from: Option<i64>,
+    /// only liquidations sent before this date or time
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub to: Option<i64>,
+    #[clap(long, arg_enum, default_value = "table")]
+    pub output: OutputFormat,
+}
+
+/// What history `export` dumps and where to
+#[derive(Debug, clap::Args)]
+pub struct ExportArgs {
+    #[clap(long, arg_enum, default_value = "csv")]
+    pub format: ExportFormat,
+    /// directory the files are written to
+    #[clap(long, default_value = ".")]
+    pub out: String,
+    /// only rows from this date (e.g. 2024-01-31, midnight utc) or rfc 3339 time on
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub from: Option<i64>,
+    /// only rows before this date or time
+    #[clap(long, parse(try_from_str = parse_time))]
+    pub to: Option<i64>,
+    /// also dump the users and margin ratios of the snapshot at `snapshot_path`
+    #[clap(long)]
+    pub snapshot: bool,
+}
+
+/// The recording `replay` runs and the fees it weighs the liquidations found in it against
+#[derive(Debug, clap::Args)]
+pub struct ReplayArgs {
+    /// recording written to `record_path`
+    pub path: String,
+    /// priority fees to work the profit out under, in micro-lamports per compute unit, `compute_unit_price` if
+    /// none are given. May be repeated or comma separated.
+    #[clap(long = "compute-unit-price", multiple_occurrences = true, use_delimiter = true)]
+    pub compute_unit_prices: Vec<u64>,
+    /// tip paid with every transaction, in lamports
+    #[clap(long, default_value = "0")]
+    pub tip_lamports: u64,
+    /// slots a simulated liquidation takes to land, its user's candidates meanwhile counting as the same one
+    #[clap(long, default_value = "4")]
+    pub landing_slots: u64,
+    /// play the recording back this many times faster than it was recorded, 0 for as fast as it goes
+    #[clap(long, default_value = "0")]
+    pub speed: f64,
+    #[clap(long, arg_enum, default_value = "table")]
+    pub output: OutputFormat,
+}
+
+/// The price move `stress` applies
+#[derive(Debug, clap::Args)]
+pub struct StressArgs {
+    /// market whose oracle moves, a perp market on v2 unless `--spot` is given. Every market moves together if
+    /// none is.
+    #[clap(long)]
+    pub market: Option<u16>,
+    /// take `--market` as a v2 spot market index
+    #[clap(long)]
+    pub spot: bool,
+    /// the move, e.g. -15% or 10%
+    #[clap(long = "move", allow_hyphen_values = true, parse(try_from_str = parse_percent))]
+    pub price_move: f64,
+    #[clap(long, arg_enum, default_value = "table")]
+    pub output: OutputFormat,
+}
+
+/// How much of the leaderboard `scan` prints and how
+#[derive(Debug, clap::Args)]
+pub struct ScanArgs {
+    /// users to print, riskiest first
+    #[clap(long, default_value = "20")]
+    pub top: usize,
+    #[clap(long, arg_enum, default_value = "table")]
+    pub output: OutputFormat,
+}
+
+/// The user `check` breaks down
+#[derive(Debug, clap::Args)]
+pub struct CheckArgs {
+    /// the user account, not its authority
+    pub user: Pubkey,
+    #[clap(long, arg_enum, default_value = "table")]
+    pub output: OutputFormat,
+}
+
+/// The user `liquidate` targets and the fees its transaction pays, the configured ones where not given
+#[derive(Debug, clap::Args)]
+pub struct LiquidateArgs {
+    /// the user account, not its authority
+    pub user: Pubkey,
+    /// priority fee bid, in micro lamports per compute unit, instead of any dynamic fee
+    #[clap(long)]
+    pub compute_unit_price: Option<u64>,
+    #[clap(long)]
+    pub compute_unit_limit: Option<u32>,
+    /// tip paid to jito, in lamports
+    #[clap(long)]
+    pub jito_tip_lamports: Option<u64>,
+    /// send it even if the expected reward doesn't cover the fees by `min_reward`
+    #[clap(long)]
+    pub force: bool,
+}
+
+impl LiquidateArgs {
+    /// The config with the fees given on the command line in place of the configured ones
+    pub fn configure(&self, mut config: Config) -> Config {
+        if let Some(compute_unit_price) = self.compute_unit_price {
+            config.compute_unit_price = compute_unit_price;
+            config.priority_fee_percentile = None;
+        }
+        if let Some(compute_unit_limit) = self.compute_unit_limit {
+            config.compute_unit_limit = compute_unit_limit;
+        }
+        if let Some(jito_tip_lamports) = self.jito_tip_lamports {
+            config.jito_tip_lamports = jito_tip_lamports;
+        }
+        config
+    }
+}
+
+/// The user `simulate-price` values and the prices it values it at
+#[derive(Debug, clap::Args)]
+pub struct SimulatePriceArgs {
+    /// the user account, not its authority
+    pub user: Pubkey,
+    /// `<perp market index>=<price>` to value the market at, in quote per unit of base. May be repeated or comma
+    /// separated, markets not given stay at their current price.
+    #[clap(long = "price", multiple_occurrences = true, use_delimiter = true)]
+    pub prices: Vec<String>,
+    /// `<spot market index>=<price>`, v2 only
+    #[clap(long = "spot-price", multiple_occurrences = true, use_delimiter = true)]
+    pub spot_prices: Vec<String>,
+    #[clap(long, arg_enum, default_value = "table")]
+    pub output: OutputFormat,
+}
+
+/// The command `ctl` sends and the socket it sends it to
+#[derive(Debug, clap::Args)]
+pub struct CtlArgs {
+    #[clap(subcommand)]
+    pub command: CtlCommand,
+    /// the running liquidator's admin socket, `admin_socket_path` if not given
+    #[clap(long)]
+    pub socket: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CtlCommand {
+    /// Print feed health, why sends are paused, the runtime overrides and the counters
+    Status,
+    /// Hold back liquidation sends
+    Pause,
+    /// Send liquidations again, unless they are paused for another reason
+    Resume,
+    /// Re-read the config, like a SIGHUP
+    Reload,
+    /// Refetch every program account into the caches
+    Refresh,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+/// How `scan`, `check`, `pnl`, `stress`, `simulate-price` and `replay` print their results
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+// a percentage like -15% or 10, as a fraction, no further down than -100%
+fn parse_percent(value: &str) -> Result<f64, String> {
+    match value.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent > -100.0 => Ok(percent / 100.0),
+        Ok(_) => Err(format!("{} would take prices under zero", value)),
+        Err(_) => Err(format!("{} isn't a percentage like -15%", value)),
+    }
+}
+
+// a date, midnight utc, or an rfc 3339 time, in milliseconds since the unix epoch
+fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis());
+    }
+    DateTime::parse_from_rfc3339(value).map(|time| time.timestamp_millis()).map_err(|_| format!("{} isn't a date like 2024-01-31 or an rfc 3339 time", value))
+}
+
+/// Command line flags, each overriding the config file. Every flag can also be set through its environment variable.
+#[derive(Debug, Parser)]
+#[clap(version, about)]
+pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    /// show a live dashboard instead of log lines, which go to `log_path` (drift-liquidator.log by default)
+    #[clap(long)]
+    pub tui: bool,
+    /// log the liquidations that would be sent instead of sending them
+    #[clap(long, env = "LIQUIDATOR_DRY_RUN")]
+    pub dry_run: bool,
+    /// toml config file, `config.toml` is picked up if present
+    #[clap(long, env = "LIQUIDATOR_CONFIG")]
+    pub config: Option<String>,
+    #[clap(long, arg_enum, env = "LIQUIDATOR_PROTOCOL")]
+    pub protocol: Option<ProtocolKind>,
+    #[clap(long, arg_enum, env = "LIQUIDATOR_CLUSTER")]
+    pub cluster: Option<Cluster>,
+    #[clap(long, env = "LIQUIDATOR_PROGRAM_ID")]
+    pub program_id: Option<String>,
+    #[clap(long, arg_enum, env = "LIQUIDATOR_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    #[clap(long, env = "LIQUIDATOR_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_LOG_PATH")]
+    pub log_path: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_RPC_URL")]
+    pub rpc_url: Option<String>,
+    /// may be repeated or comma separated
+    #[clap(long = "fallback-rpc-url", env = "LIQUIDATOR_FALLBACK_RPC_URLS", multiple_occurrences = true, use_delimiter = true)]
+    pub fallback_rpc_urls: Vec<String>,
+    #[clap(long, env = "LIQUIDATOR_WS_URL")]
+    pub ws_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_KEYPAIR_PATH")]
+    pub keypair_path: Option<String>,
+    #[clap(long, arg_enum, env = "LIQUIDATOR_SIGNER")]
+    pub signer: Option<SignerKind>,
+    #[clap(long, env = "LIQUIDATOR_LEDGER_URL")]
+    pub ledger_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_LEDGER_DERIVATION_PATH")]
+    pub ledger_derivation_path: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_REMOTE_SIGNER_URL")]
+    pub remote_signer_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_REMOTE_SIGNER_TOKEN", hide_env_values = true)]
+    pub remote_signer_token: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_GEYSER_URL")]
+    pub geyser_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_GEYSER_X_TOKEN")]
+    pub geyser_x_token: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_DISCOVERY_INTERVAL_SECS")]
+    pub discovery_interval_secs: Option<u64>,
+    /// a user account to watch exclusively, e.g. `--watch <pubkey> --watch <pubkey>`. May be repeated or comma
+    /// separated.
+    #[clap(long = "watch", env = "LIQUIDATOR_WATCHLIST", multiple_occurrences = true, use_delimiter = true)]
+    pub watchlist: Vec<String>,
+    #[clap(long, env = "LIQUIDATOR_WATCHLIST_POLL_INTERVAL_MS")]
+    pub watchlist_poll_interval_ms: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_SNAPSHOT_PATH")]
+    pub snapshot_path: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_SNAPSHOT_INTERVAL_SECS")]
+    pub snapshot_interval_secs: Option<u64>,
+    /// processed, confirmed or finalized
+    #[clap(long, env = "LIQUIDATOR_COMMITMENT")]
+    pub commitment: Option<CommitmentLevel>,
+    /// processed, confirmed or finalized
+    #[clap(long, env = "LIQUIDATOR_SEND_COMMITMENT")]
+    pub send_commitment: Option<CommitmentLevel>,
+    #[clap(long, env = "LIQUIDATOR_SKIP_PREFLIGHT")]
+    pub skip_preflight: bool,
+    /// v2: value users at the prices the market accounts record, without subscribing to the oracles
+    #[clap(long, env = "LIQUIDATOR_SKIP_ORACLE_SUBSCRIPTION")]
+    pub skip_oracle_subscription: bool,
+    #[clap(long, env = "LIQUIDATOR_MAX_ORACLE_STALENESS_SLOTS")]
+    pub max_oracle_staleness_slots: Option<u64>,
+    /// v2: liquidate users the oracles' confidence bands leave in doubt too
+    #[clap(long, env = "LIQUIDATOR_SKIP_ORACLE_CONFIDENCE_GUARD")]
+    pub skip_oracle_confidence_guard: bool,
+    #[clap(long, env = "LIQUIDATOR_CIRCUIT_BREAKER_PERCENT")]
+    pub circuit_breaker_percent: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_CIRCUIT_BREAKER_PAUSE_SECS")]
+    pub circuit_breaker_pause_secs: Option<u64>,
+    /// may be repeated or comma separated
+    #[clap(long = "exclude-account", env = "LIQUIDATOR_EXCLUDED_ACCOUNTS", multiple_occurrences = true, use_delimiter = true)]
+    pub excluded_accounts: Vec<String>,
+    /// may be repeated or comma separated
+    #[clap(long = "exclude-authority", env = "LIQUIDATOR_EXCLUDED_AUTHORITIES", multiple_occurrences = true, use_delimiter = true)]
+    pub excluded_authorities: Vec<String>,
+    /// liquidate only this user account and the other allowed ones. May be repeated or comma separated
+    #[clap(long = "allow-account", env = "LIQUIDATOR_ALLOWED_ACCOUNTS", multiple_occurrences = true, use_delimiter = true)]
+    pub allowed_accounts: Vec<String>,
+    /// liquidate only the user accounts of this authority and the other allowed ones. May be repeated or comma
+    /// separated
+    #[clap(long = "allow-authority", env = "LIQUIDATOR_ALLOWED_AUTHORITIES", multiple_occurrences = true, use_delimiter = true)]
+    pub allowed_authorities: Vec<String>,
+    /// liquidate only the users hashing into this shard of `<index>/<count>`, e.g. 0/4 to 3/4 on four instances
+    #[clap(long, env = "LIQUIDATOR_SHARD")]
+    pub shard: Option<String>,
+    /// v2: only liquidate perp positions, leave spot borrows alone
+    #[clap(long, env = "LIQUIDATOR_SKIP_BORROWS")]
+    pub skip_borrows: bool,
+    #[clap(long, env = "LIQUIDATOR_FREE_COLLATERAL_USAGE_PERCENT")]
+    pub free_collateral_usage_percent: Option<u64>,
+    /// skip the startup check of account layouts against the on-chain idl
+    #[clap(long, env = "LIQUIDATOR_SKIP_IDL_CHECK")]
+    pub skip_idl_check: bool,
+    #[clap(long, env = "LIQUIDATOR_LOOKUP_TABLE")]
+    pub lookup_table: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_MARGIN_RATIO_BUFFER")]
+    pub margin_ratio_buffer: Option<u128>,
+    #[clap(long, env = "LIQUIDATOR_CRITICAL_MARGIN_PERCENT")]
+    pub critical_margin_percent: Option<u128>,
+    #[clap(long, env = "LIQUIDATOR_WATCH_MARGIN_PERCENT")]
+    pub watch_margin_percent: Option<u128>,
+    #[clap(long, env = "LIQUIDATOR_WATCH_INTERVAL")]
+    pub watch_interval: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_SAFE_INTERVAL")]
+    pub safe_interval: Option<u64>,
+    /// usdc with 6 decimals
+    #[clap(long, env = "LIQUIDATOR_MIN_REWARD")]
+    pub min_reward: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_DAILY_FEE_BUDGET_LAMPORTS")]
+    pub daily_fee_budget_lamports: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_FEE_BUDGET_THROTTLE_PERCENT")]
+    pub fee_budget_throttle_percent: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_COMPUTE_UNIT_LIMIT")]
+    pub compute_unit_limit: Option<u32>,
+    /// micro lamports per compute unit
+    #[clap(long, env = "LIQUIDATOR_COMPUTE_UNIT_PRICE")]
+    pub compute_unit_price: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_PRIORITY_FEE_PERCENTILE")]
+    pub priority_fee_percentile: Option<u8>,
+    #[clap(long, env = "LIQUIDATOR_MAX_COMPUTE_UNIT_PRICE")]
+    pub max_compute_unit_price: Option<u64>,
+    #[clap(long, arg_enum, env = "LIQUIDATOR_SENDER")]
+    pub sender: Option<SenderKind>,
+    #[clap(long, arg_enum, env = "LIQUIDATOR_STRATEGY")]
+    pub strategy: Option<StrategyKind>,
+    #[clap(long, env = "LIQUIDATOR_SCRIPT_PATH")]
+    pub script_path: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_JITO_URL")]
+    pub jito_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_JITO_TIP_LAMPORTS")]
+    pub jito_tip_lamports: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_TPU_FANOUT_SLOTS")]
+    pub tpu_fanout_slots: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_MAX_LIQUIDATIONS_PER_TRANSACTION")]
+    pub max_liquidations_per_transaction: Option<usize>,
+    #[clap(long, env = "LIQUIDATOR_COOLDOWN_BASE_MS")]
+    pub cooldown_base_ms: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_COOLDOWN_MAX_MS")]
+    pub cooldown_max_ms: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_RPC_REQUESTS_PER_SECOND")]
+    pub rpc_requests_per_second: Option<u32>,
+    #[clap(long, env = "LIQUIDATOR_RPC_MAX_ATTEMPTS")]
+    pub rpc_max_attempts: Option<u32>,
+    #[clap(long, env = "LIQUIDATOR_WORKER_THREADS")]
+    pub worker_threads: Option<usize>,
+    #[clap(long, env = "LIQUIDATOR_MAX_CONCURRENT_REQUESTS")]
+    pub max_concurrent_requests: Option<usize>,
+    #[clap(long, env = "LIQUIDATOR_MAX_CONCURRENT_SENDS")]
+    pub max_concurrent_sends: Option<usize>,
+    #[clap(long, env = "LIQUIDATOR_SHUTDOWN_TIMEOUT_SECS")]
+    pub shutdown_timeout_secs: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_WATCHDOG_TIMEOUT_SECS")]
+    pub watchdog_timeout_secs: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_HEALTH_ADDR")]
+    pub health_addr: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_HEALTH_STALE_SECS")]
+    pub health_stale_secs: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_HEALTH_MAX_SLOT_LAG")]
+    pub health_max_slot_lag: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_API_ADDR")]
+    pub api_addr: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_ADMIN_ADDR")]
+    pub admin_addr: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_ADMIN_SOCKET_PATH")]
+    pub admin_socket_path: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_DISCORD_WEBHOOK_URL")]
+    pub discord_webhook_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_SLACK_WEBHOOK_URL")]
+    pub slack_webhook_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_SLACK_CRITICAL_WEBHOOK_URL")]
+    pub slack_critical_webhook_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_NOTIFY_BATCH_INTERVAL_SECS")]
+    pub notify_batch_interval_secs: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_LOW_BALANCE_ALERT_LAMPORTS")]
+    pub low_balance_alert_lamports: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_HALT_BALANCE_LAMPORTS")]
+    pub halt_balance_lamports: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_BALANCE_CHECK_INTERVAL_SECS")]
+    pub balance_check_interval_secs: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_DEPOSIT_TOKEN_ACCOUNT")]
+    pub deposit_token_account: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_AUTO_DEPOSIT_AMOUNT")]
+    pub auto_deposit_amount: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_AUTO_DEPOSIT_MIN_COLLATERAL")]
+    pub auto_deposit_min_collateral: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_AUTO_DEPOSIT_MARGIN_PERCENT")]
+    pub auto_deposit_margin_percent: Option<u128>,
+    #[clap(long, env = "LIQUIDATOR_AUTO_DEPOSIT_MARKET_INDEX")]
+    pub auto_deposit_market_index: Option<u16>,
+    #[clap(long, env = "LIQUIDATOR_WITHDRAW_TOKEN_ACCOUNT")]
+    pub withdraw_token_account: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_AUTO_WITHDRAW_MAX_COLLATERAL")]
+    pub auto_withdraw_max_collateral: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_UNWIND_POSITIONS")]
+    pub unwind_positions: bool,
+    #[clap(long, env = "LIQUIDATOR_UNWIND_MAX_ORDER_VALUE")]
+    pub unwind_max_order_value: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_UNWIND_MAX_SLIPPAGE_BPS")]
+    pub unwind_max_slippage_bps: Option<u64>,
+    /// may be repeated or comma separated
+    #[clap(long = "hedge-market", env = "LIQUIDATOR_HEDGE_MARKETS", multiple_occurrences = true, use_delimiter = true)]
+    pub hedge_markets: Vec<String>,
+    #[clap(long, env = "LIQUIDATOR_MAX_EXPOSURE_NOTIONAL")]
+    pub max_exposure_notional: Option<u64>,
+    /// may be repeated or comma separated
+    #[clap(long = "exposure-cap", env = "LIQUIDATOR_EXPOSURE_CAPS", multiple_occurrences = true, use_delimiter = true)]
+    pub exposure_caps: Vec<String>,
+    #[clap(long, env = "LIQUIDATOR_HEDGE_IMPACT_BPS")]
+    pub hedge_impact_bps: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_BINANCE_API_KEY")]
+    pub binance_api_key: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_BINANCE_API_SECRET")]
+    pub binance_api_secret: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_BINANCE_URL")]
+    pub binance_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_TELEGRAM_BOT_TOKEN")]
+    pub telegram_bot_token: Option<String>,
+    #[clap(long = "telegram-chat-id", env = "LIQUIDATOR_TELEGRAM_CHAT_IDS", multiple_occurrences = true, use_delimiter = true)]
+    pub telegram_chat_ids: Vec<i64>,
+    #[clap(long, env = "LIQUIDATOR_PAGERDUTY_ROUTING_KEY")]
+    pub pagerduty_routing_key: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_OPSGENIE_API_KEY")]
+    pub opsgenie_api_key: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_INCIDENT_STALL_SECS")]
+    pub incident_stall_secs: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_INCIDENT_WINDOW_SECS")]
+    pub incident_window_secs: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_WEBHOOK_URL")]
+    pub webhook_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_WEBHOOK_SECRET")]
+    pub webhook_secret: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_HISTORY_PATH")]
+    pub history_path: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_HISTORY_POSTGRES_URL")]
+    pub history_postgres_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_RECORD_PATH")]
+    pub record_path: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_REDIS_URL")]
+    pub redis_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_REDIS_KEY")]
+    pub redis_key: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_REDIS_CHANNEL")]
+    pub redis_channel: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_REDIS_MARGIN_PERCENT")]
+    pub redis_margin_percent: Option<u128>,
+    #[clap(long, env = "LIQUIDATOR_LEADER_LOCK_URL")]
+    pub leader_lock_url: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_LEADER_LOCK_KEY")]
+    pub leader_lock_key: Option<String>,
+    #[clap(long, env = "LIQUIDATOR_LEADER_LOCK_TTL_MS")]
+    pub leader_lock_ttl_ms: Option<u64>,
+    #[clap(long, env = "LIQUIDATOR_INSTANCE_ID")]
+    pub instance_id: Option<String>,
+}
+
+impl Config {
+    /// The settings of the perp market, None if it has none of its own
+    pub fn market(&self, market_index: u16) -> Option<&MarketSettings> {
+        self.markets.iter().find(|market| !market.spot && market.market_index == market_index)
+    }
+
+    /// The `[[markets]]` entry of a v2 spot market
+    pub fn spot_market(&self, market_index: u16) -> Option<&MarketSettings> {
+        self.markets.iter().find(|market| market.spot && market.market_index == market_index)
+    }
+
+    /// Reads the config file named in `args` and applies the command line and environment overrides on top
+    pub fn load(args: &Args) -> Config {
+        Config::try_load(args).unwrap()
+    }
+
+    /// Like `load`, but returns an error for an unreadable or invalid config file rather than panicking
+    pub fn try_load(args: &Args) -> Result<Config, Box<dyn Error>> {
+        let mut config = match &args.config {
+            Some(path) => toml::from_str(&fs::read_to_string(path)?)?,
+            None => match fs::read_to_string(CONFIG_PATH) {
+                Ok(contents) => toml::from_str(&contents)?,
+                Err(_) => Config::default(),
+            },
+        };
+
+        if args.dry_run {
+            config.dry_run = true;
+        }
+        if let Some(protocol) = args.protocol {
+            config.protocol = protocol;
+        }
+        if let Some(cluster) = args.cluster {
+            config.cluster = cluster;
+        }
+        if let Some(program_id) = &args.program_id {
+            config.program_id = Some(program_id.clone());
+        }
+        if let Some(log_format) = args.log_format {
+            config.log_format = log_format;
+        }
+        if let Some(otlp_endpoint) = &args.otlp_endpoint {
+            config.otlp_endpoint = Some(otlp_endpoint.clone());
+        }
+        if let Some(log_path) = &args.log_path {
+            config.log_path = Some(log_path.clone());
+        }
+        if let Some(rpc_url) = &args.rpc_url {
+            config.rpc_url = rpc_url.clone();
+        }
+        if !args.fallback_rpc_urls.is_empty() {
+            config.fallback_rpc_urls = args.fallback_rpc_urls.clone();
+        }
+        if let Some(ws_url) = &args.ws_url {
+            config.ws_url = ws_url.clone();
+        }
+        if let Some(keypair_path) = &args.keypair_path {
+            config.keypair_path = keypair_path.clone();
+        }
+        if let Some(signer) = args.signer {
+            config.signer = signer;
+        }
+        if let Some(ledger_url) = &args.ledger_url {
+            config.ledger_url = ledger_url.clone();
+        }
+        if let Some(ledger_derivation_path) = &args.ledger_derivation_path {
+            config.ledger_derivation_path = Some(ledger_derivation_path.clone());
+        }
+        if let Some(remote_signer_url) = &args.remote_signer_url {
+            config.remote_signer_url = Some(remote_signer_url.clone());
+        }
+        if let Some(remote_signer_token) = &args.remote_signer_token {
+            config.remote_signer_token = Some(remote_signer_token.clone());
+        }
+        if let Some(geyser_url) = &args.geyser_url {
+            config.geyser_url = Some(geyser_url.clone());
+        }
+        if let Some(geyser_x_token) = &args.geyser_x_token {
+            config.geyser_x_token = Some(geyser_x_token.clone());
+        }
+        if let Some(discovery_interval_secs) = args.discovery_interval_secs {
+            config.discovery_interval_secs = discovery_interval_secs;
+        }
+        if !args.watchlist.is_empty() {
+            config.watchlist = args.watchlist.clone();
+        }
+        if let Some(watchlist_poll_interval_ms) = args.watchlist_poll_interval_ms {
+            config.watchlist_poll_interval_ms = watchlist_poll_interval_ms;
+        }
+        if let Some(snapshot_path) = &args.snapshot_path {
+            config.snapshot_path = Some(snapshot_path.clone());
+        }
+        if let Some(snapshot_interval_secs) = args.snapshot_interval_secs {
+            config.snapshot_interval_secs = snapshot_interval_secs;
+        }
+        if let Some(commitment) = args.commitment {
+            config.commitment = commitment;
+        }
+        if let Some(send_commitment) = args.send_commitment {
+            config.send_commitment = send_commitment;
+        }
+        if args.skip_preflight {
+            config.skip_preflight = true;
+        }
+        if args.skip_oracle_subscription {
+            config.subscribe_oracles = false;
+        }
+        if let Some(max_oracle_staleness_slots) = args.max_oracle_staleness_slots {
+            config.max_oracle_staleness_slots = max_oracle_staleness_slots;
+        }
+        if args.skip_oracle_confidence_guard {
+            config.oracle_confidence_guard = false;
+        }
+        if let Some(circuit_breaker_percent) = args.circuit_breaker_percent {
+            config.circuit_breaker_percent = circuit_breaker_percent;
+        }
+        if let Some(circuit_breaker_pause_secs) = args.circuit_breaker_pause_secs {
+            config.circuit_breaker_pause_secs = circuit_breaker_pause_secs;
+        }
+        if !args.excluded_accounts.is_empty() {
+            config.excluded_accounts = args.excluded_accounts.clone();
+        }
+        if !args.excluded_authorities.is_empty() {
+            config.excluded_authorities = args.excluded_authorities.clone();
+        }
+        if !args.allowed_accounts.is_empty() {
+            config.allowed_accounts = args.allowed_accounts.clone();
+        }
+        if !args.allowed_authorities.is_empty() {
+            config.allowed_authorities = args.allowed_authorities.clone();
+        }
+        if let Some(shard) = &args.shard {
+            config.shard = Some(shard.clone());
+        }
+        if args.skip_borrows {
+            config.liquidate_borrows = false;
+        }
+        if let Some(free_collateral_usage_percent) = args.free_collateral_usage_percent {
+            config.free_collateral_usage_percent = free_collateral_usage_percent;
+        }
+        if args.skip_idl_check {
+            config.verify_idl = false;
+        }
+        if let Some(lookup_table) = &args.lookup_table {
+            config.lookup_table = Some(lookup_table.clone());
+        }
+        if let Some(margin_ratio_buffer) = args.margin_ratio_buffer {
+            config.margin_ratio_buffer = margin_ratio_buffer;
+        }
+        if let Some(critical_margin_percent) = args.critical_margin_percent {
+            config.critical_margin_percent = critical_margin_percent;
+        }
+        if let Some(watch_margin_percent) = args.watch_margin_percent {
+            config.watch_margin_percent = watch_margin_percent;
+        }
+        if let Some(watch_interval) = args.watch_interval {
+            config.watch_interval = watch_interval;
+        }
+        if let Some(safe_interval) = args.safe_interval {
+            config.safe_interval = safe_interval;
+        }
+        if let Some(min_reward) = args.min_reward {
+            config.min_reward = min_reward;
+        }
+        if let Some(daily_fee_budget_lamports) = args.daily_fee_budget_lamports {
+            config.daily_fee_budget_lamports = daily_fee_budget_lamports;
+        }
+        if let Some(fee_budget_throttle_percent) = args.fee_budget_throttle_percent {
+            config.fee_budget_throttle_percent = fee_budget_throttle_percent;
+        }
+        if let Some(compute_unit_limit) = args.compute_unit_limit {
+            config.compute_unit_limit = compute_unit_limit;
+        }
+        if let Some(compute_unit_price) = args.compute_unit_price {
+            config.compute_unit_price = compute_unit_price;
+        }
+        if let Some(priority_fee_percentile) = args.priority_fee_percentile {
+            config.priority_fee_percentile = Some(priority_fee_percentile);
+        }
+        if let Some(max_compute_unit_price) = args.max_compute_unit_price {
+            config.max_compute_unit_price = max_compute_unit_price;
+        }
+        if let Some(sender) = args.sender {
+            config.sender = sender;
+        }
+        if let Some(strategy) = args.strategy {
+            config.strategy = strategy;
+        }
+        if let Some(script_path) = &args.script_path {
+            config.script_path = Some(script_path.clone());
+        }
+        if let Some(jito_url) = &args.jito_url {
+            config.jito_url = jito_url.clone();
+        }
+        if let Some(jito_tip_lamports) = args.jito_tip_lamports {
+            config.jito_tip_lamports = jito_tip_lamports;
+        }
+        if let Some(tpu_fanout_slots) = args.tpu_fanout_slots {
+            config.tpu_fanout_slots = tpu_fanout_slots;
+        }
+        if let Some(max_liquidations_per_transaction) = args.max_liquidations_per_transaction {
+            config.max_liquidations_per_transaction = max_liquidations_per_transaction;
+        }
+        if let Some(cooldown_base_ms) = args.cooldown_base_ms {
+            config.cooldown_base_ms = cooldown_base_ms;
+        }
+        if let Some(cooldown_max_ms) = args.cooldown_max_ms {
+            config.cooldown_max_ms = cooldown_max_ms;
+        }
+        if let Some(rpc_requests_per_second) = args.rpc_requests_per_second {
+            config.rpc_requests_per_second = rpc_requests_per_second;
+        }
+        if let Some(rpc_max_attempts) = args.rpc_max_attempts {
+            config.rpc_max_attempts = rpc_max_attempts;
+        }
+        if let Some(worker_threads) = args.worker_threads {
+            config.worker_threads = worker_threads;
+        }
+        if let Some(max_concurrent_requests) = args.max_concurrent_requests {
+            config.max_concurrent_requests = max_concurrent_requests;
+        }
+        if let Some(max_concurrent_sends) = args.max_concurrent_sends {
+            config.max_concurrent_sends = max_concurrent_sends;
+        }
+        if let Some(shutdown_timeout_secs) = args.shutdown_timeout_secs {
+            config.shutdown_timeout_secs = shutdown_timeout_secs;
+        }
+        if let Some(watchdog_timeout_secs) = args.watchdog_timeout_secs {
+            config.watchdog_timeout_secs = watchdog_timeout_secs;
+        }
+        if let Some(health_addr) = &args.health_addr {
+            config.health_addr = Some(health_addr.clone());
+        }
+        if let Some(health_stale_secs) = args.health_stale_secs {
+            config.health_stale_secs = health_stale_secs;
+        }
+        if let Some(health_max_slot_lag) = args.health_max_slot_lag {
+            config.health_max_slot_lag = health_max_slot_lag;
+        }
+        if let Some(api_addr) = &args.api_addr {
+            config.api_addr = Some(api_addr.clone());
+        }
+        if let Some(admin_addr) = &args.admin_addr {
+            config.admin_addr = Some(admin_addr.clone());
+        }
+        if let Some(admin_token) = &args.admin_token {
+            config.admin_token = Some(admin_token.clone());
+        }
+        if let Some(admin_socket_path) = &args.admin_socket_path {
+            config.admin_socket_path = Some(admin_socket_path.clone());
+        }
+        if let Some(discord_webhook_url) = &args.discord_webhook_url {
+            config.discord_webhook_url = Some(discord_webhook_url.clone());
+        }
+        if let Some(slack_webhook_url) = &args.slack_webhook_url {
+            config.slack_webhook_url = Some(slack_webhook_url.clone());
+        }
+        if let Some(slack_critical_webhook_url) = &args.slack_critical_webhook_url {
+            config.slack_critical_webhook_url = Some(slack_critical_webhook_url.clone());
+        }
+        if let Some(notify_batch_interval_secs) = args.notify_batch_interval_secs {
+            config.notify_batch_interval_secs = notify_batch_interval_secs;
+        }
+        if let Some(low_balance_alert_lamports) = args.low_balance_alert_lamports {
+            config.low_balance_alert_lamports = low_balance_alert_lamports;
+        }
+        if let Some(halt_balance_lamports) = args.halt_balance_lamports {
+            config.halt_balance_lamports = halt_balance_lamports;
+        }
+        if let Some(balance_check_interval_secs) = args.balance_check_interval_secs {
+            config.balance_check_interval_secs = balance_check_interval_secs;
+        }
+        if let Some(deposit_token_account) = &args.deposit_token_account {
+            config.deposit_token_account = Some(deposit_token_account.clone());
+        }
+        if let Some(auto_deposit_amount) = args.auto_deposit_amount {
+            config.auto_deposit_amount = auto_deposit_amount;
+        }
+        if let Some(auto_deposit_min_collateral) = args.auto_deposit_min_collateral {
+            config.auto_deposit_min_collateral = auto_deposit_min_collateral;
+        }
+        if let Some(auto_deposit_margin_percent) = args.auto_deposit_margin_percent {
+            config.auto_deposit_margin_percent = auto_deposit_margin_percent;
+        }
+        if let Some(auto_deposit_market_index) = args.auto_deposit_market_index {
+            config.auto_deposit_market_index = auto_deposit_market_index;
+        }
+        if let Some(withdraw_token_account) = &args.withdraw_token_account {
+            config.withdraw_token_account = Some(withdraw_token_account.clone());
+        }
+        if let Some(auto_withdraw_max_collateral) = args.auto_withdraw_max_collateral {
+            config.auto_withdraw_max_collateral = auto_withdraw_max_collateral;
+        }
+        if args.unwind_positions {
+            config.unwind_positions = true;
+        }
+        if let Some(unwind_max_order_value) = args.unwind_max_order_value {
+            config.unwind_max_order_value = unwind_max_order_value;
+        }
+        if let Some(unwind_max_slippage_bps) = args.unwind_max_slippage_bps {
+            config.unwind_max_slippage_bps = unwind_max_slippage_bps;
+        }
+        if !args.hedge_markets.is_empty() {
+            config.hedge_markets = args.hedge_markets.clone();
+        }
+        if let Some(max_exposure_notional) = args.max_exposure_notional {
+            config.max_exposure_notional = max_exposure_notional;
+        }
+        if !args.exposure_caps.is_empty() {
+            config.exposure_caps = args.exposure_caps.clone();
+        }
+        if let Some(hedge_impact_bps) = args.hedge_impact_bps {
+            config.hedge_impact_bps = hedge_impact_bps;
+        }
+        if let Some(binance_api_key) = &args.binance_api_key {
+            config.binance_api_key = Some(binance_api_key.clone());
+        }
+        if let Some(binance_api_secret) = &args.binance_api_secret {
+            config.binance_api_secret = Some(binance_api_secret.clone());
+        }
+        if let Some(binance_url) = &args.binance_url {
+            config.binance_url = binance_url.clone();
+        }
+        if let Some(telegram_bot_token) = &args.telegram_bot_token {
+            config.telegram_bot_token = Some(telegram_bot_token.clone());
+        }
+        if !args.telegram_chat_ids.is_empty() {
+            config.telegram_chat_ids = args.telegram_chat_ids.clone();
+        }
+        if let Some(pagerduty_routing_key) = &args.pagerduty_routing_key {
+            config.pagerduty_routing_key = Some(pagerduty_routing_key.clone());
+        }
+        if let Some(opsgenie_api_key) = &args.opsgenie_api_key {
+            config.opsgenie_api_key = Some(opsgenie_api_key.clone());
+        }
+        if let Some(incident_stall_secs) = args.incident_stall_secs {
+            config.incident_stall_secs = incident_stall_secs;
+        }
+        if let Some(incident_window_secs) = args.incident_window_secs {
+            config.incident_window_secs = incident_window_secs;
+        }
+        if let Some(webhook_url) = &args.webhook_url {
+            config.webhook_url = Some(webhook_url.clone());
+        }
+        if let Some(webhook_secret) = &args.webhook_secret {
+            config.webhook_secret = Some(webhook_secret.clone());
+        }
+        if let Some(history_path) = &args.history_path {
+            config.history_path = Some(history_path.clone());
+        }
+        if let Some(history_postgres_url) = &args.history_postgres_url {
+            config.history_postgres_url = Some(history_postgres_url.clone());
+        }
+        if let Some(record_path) = &args.record_path {
+            config.record_path = Some(record_path.clone());
+        }
+        if let Some(redis_url) = &args.redis_url {
+            config.redis_url = Some(redis_url.clone());
+        }
+        if let Some(redis_key) = &args.redis_key {
+            config.redis_key = redis_key.clone();
+        }
+        if let Some(redis_channel) = &args.redis_channel {
+            config.redis_channel = redis_channel.clone();
+        }
+        if let Some(redis_margin_percent) = args.redis_margin_percent {
+            config.redis_margin_percent = redis_margin_percent;
+        }
+        if let Some(leader_lock_url) = &args.leader_lock_url {
+            config.leader_lock_url = Some(leader_lock_url.clone());
+        }
+        if let Some(leader_lock_key) = &args.leader_lock_key {
+            config.leader_lock_key = leader_lock_key.clone();
+        }
+        if let Some(leader_lock_ttl_ms) = args.leader_lock_ttl_ms {
+            config.leader_lock_ttl_ms = leader_lock_ttl_ms;
+        }
+        if let Some(instance_id) = &args.instance_id {
+            config.instance_id = Some(instance_id.clone());
+        }
+
+        // the cluster only replaces the endpoints still at their mainnet defaults, explicit ones win
+        match config.cluster.urls() {
+            Some((rpc_url, ws_url)) => {
+                if config.rpc_url == CLI_URL {
+                    config.rpc_url = rpc_url.to_string();
+                }
+                if config.ws_url == WS_URL {
+                    config.ws_url = ws_url.to_string();
+                }
+            }
+            None if config.rpc_url == CLI_URL => return Err("a custom cluster needs an rpc_url".into()),
+            None => {}
+        }
+        if let Some(program_id) = &config.program_id {
+            Pubkey::from_str(program_id).map_err(|_| format!("program_id {} isn't a pubkey", program_id))?;
+        }
+        if config.admin_addr.is_some() && config.admin_token.as_ref().map_or(true, |token| token.len() < MIN_ADMIN_TOKEN_LEN) {
+            return Err(format!("admin_addr needs an admin_token of at least {} characters", MIN_ADMIN_TOKEN_LEN));
+        }
+        check_markets(&config)?;
+
+        Ok(config)
+    }
+}
+
+// `[[markets]]` settings the protocol doesn't apply, which would otherwise be silently ignored
+fn check_markets(config: &Config) -> Result<(), String> {
+    for market in &config.markets {
+        let name = format!("{}market {}", if market.spot { "spot " } else { "" }, market.market_index);
+        match config.protocol {
+            ProtocolKind::V1 if market.spot => return Err(format!("{} is set but v1 has no spot markets", name)),
+            ProtocolKind::V1 if market.max_notional.is_some() => return Err(format!("max_notional of {} is v2 only, v1 liquidations close positions against the amm", name)),
+            ProtocolKind::V2 if market.margin_ratio_buffer.is_some() => return Err(format!("margin_ratio_buffer of {} is v1 only, v2 liquidates at the maintenance requirement", name)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// cpus available to the process, the base the concurrency defaults scale with
+fn cpus() -> usize {
+    thread::available_parallelism().map_or(1, |cpus| cpus.get())
+}
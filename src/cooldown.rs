@@ -0,0 +1,55 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::retry::RetryPolicy;
+
+struct Cooldown {
+    /// failed attempts in a row
+    failures: u32,
+    until: Instant,
+}
+
+/// Per user cooldowns after failed liquidation attempts, doubling with every failure in a row so a user that
+/// another liquidator already handled isn't hammered with transactions that can only fail
+pub struct Cooldowns {
+    backoff: RetryPolicy,
+    users: Mutex<HashMap<Pubkey, Cooldown>>,
+}
+
+impl Cooldowns {
+    /// A zero `base` disables cooldowns
+    pub fn new(base: Duration, max: Duration) -> Cooldowns {
+        Cooldowns {
+            backoff: RetryPolicy { max_attempts: u32::MAX, base_delay: base, max_delay: max },
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Time left on the user's cooldown, None if it may be liquidated
+    pub fn remaining(&self, user: &Pubkey) -> Option<Duration> {
+        let users = self.users.lock().unwrap();
+        users.get(user)?.until.checked_duration_since(Instant::now()).filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Puts the user on a cooldown twice as long as its last one, returning how long
+    pub fn failed(&self, user: &Pubkey) -> Duration {
+        let now = Instant::now();
+        let mut users = self.users.lock().unwrap();
+        let cooldown = users.entry(*user).or_insert(Cooldown { failures: 0, until: now });
+        // a user that stayed out of trouble for a while starts over
+        if now.saturating_duration_since(cooldown.until) > self.backoff.max_delay {
+            cooldown.failures = 0;
+        }
+        cooldown.failures += 1;
+
+        let duration = self.backoff.backoff(cooldown.failures);
+        cooldown.until = now + duration;
+        duration
+    }
+
+    /// Forgets the user's failures once a liquidation of it landed
+    pub fn succeeded(&self, user: &Pubkey) {
+        self.users.lock().unwrap().remove(user);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{error::Error, fs, io, os::unix::fs::{DirBuilderExt, PermissionsExt}, process, sync::Arc, time::Duration};
+
+use serde_json::{json, Value};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::{UnixListener, UnixStream}, task::JoinHandle, time::sleep};
+use tracing::{info, warn};
+
+use crate::{admin::{Admin, AdminCommand}, config::{Config, CtlArgs, CtlCommand}};
+
+// backoff after a failed accept
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Serves the admin commands on a unix socket at `path` in the background, for operators who'd rather not open
+/// a port. Each line a client writes is a command (`status`, `pause`, `resume`, `reload` or `refresh`) answered
+/// with a line of json. The socket is created readable and writable by its owner only, which is all the
+/// authentication it has.
+pub fn serve(admin: Arc<Admin>, path: &str) -> io::Result<JoinHandle<()>> {
+    // a socket left behind by a previous run would fail the bind
+    let _ = fs::remove_file(path);
+    // bound inside a directory only the owner can enter, so no one can connect before the socket's own
+    // permissions are set, then moved into place
+    let private = format!("{}.{}", path, process::id());
+    let _ = fs::remove_dir_all(&private);
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let bound = format!("{}/socket", private);
+    let placed = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&private);
+    let listener = placed?;
+    info!(%path, "serving admin socket");
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(answer(admin.clone(), stream));
+                }
+                Err(err) => {
+                    warn!(error = %err, "failed to accept an admin socket connection");
+                    sleep(ACCEPT_RETRY_DELAY).await;
+                }
+            }
+        }
+    }))
+}
+
+async fn answer(admin: Arc<Admin>, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let answer = match parse(line.trim()) {
+            Some(command) => admin.execute(command).unwrap_or_else(|(_, err)| json!({ "error": err })),
+            None => json!({ "error": format!("unknown command {}", line.trim()) }),
+        };
+        if writer.write_all(format!("{}\n", answer).as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn parse(line: &str) -> Option<AdminCommand> {
+    match line {
+        "status" => Some(AdminCommand::Status),
+        "pause" => Some(AdminCommand::Pause),
+        "resume" => Some(AdminCommand::Resume),
+        "reload" => Some(AdminCommand::Reload),
+        "refresh" => Some(AdminCommand::Refresh),
+        _ => None,
+    }
+}
+
+impl CtlCommand {
+    fn line(&self) -> &'static str {
+        match self {
+            CtlCommand::Status => "status",
+            CtlCommand::Pause => "pause",
+            CtlCommand::Resume => "resume",
+            CtlCommand::Reload => "reload",
+            CtlCommand::Refresh => "refresh",
+        }
+    }
+}
+
+/// Sends the `ctl` command to the running liquidator's admin socket and prints its answer
+pub async fn ctl(config: &Config, args: &CtlArgs) -> Result<(), Box<dyn Error>> {
+    let path = args.socket.as_ref().or(config.admin_socket_path.as_ref()).ok_or("no --socket given and no admin_socket_path configured")?;
+    let stream = UnixStream::connect(path).await.map_err(|err| format!("failed to connect to {}: {}", path, err))?;
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(format!("{}\n", args.command.line()).as_bytes()).await?;
+    let line = BufReader::new(reader).lines().next_line().await?.ok_or("the liquidator closed the socket without answering")?;
+    let answer: Value = serde_json::from_str(&line)?;
+    if let Some(err) = answer.get("error").and_then(Value::as_str) {
+        return Err(err.into());
+    }
+    println!("{}", serde_json::to_string_pretty(&answer)?);
+    Ok(())
+}
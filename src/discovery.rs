@@ -0,0 +1,94 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::mpsc::Sender, task::JoinHandle, time::sleep};
+use tracing::{info, warn};
+
+use crate::{rpc::{RpcPool, MAX_MULTIPLE_ACCOUNTS}, source::AccountUpdate};
+
+/// Lists the program's accounts every `interval` and feeds the ones that weren't there the last time into the
+/// update stream, along with closures of the ones that disappeared. The program subscription already sees
+/// accounts created while it is connected, this catches the ones created while it was reconnecting and the
+/// closes it never reports, as a closed account no longer belongs to the program. Listings skip the account
+/// data, only new accounts are fetched in full.
+pub fn spawn_discovery(rpc: Arc<RpcPool>, program_id: Pubkey, interval: Duration, sender: Sender<AccountUpdate>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // the first listing is the baseline, the startup scan already loaded everything in it
+        let mut known: Option<HashSet<Pubkey>> = None;
+        loop {
+            match list_accounts(&rpc, &program_id).await {
+                Ok(listed) => {
+                    if let Some(known) = &known {
+                        let new: Vec<Pubkey> = listed.difference(known).copied().collect();
+                        if !new.is_empty() {
+                            info!(accounts = new.len(), "discovered new program accounts");
+                        }
+                        if fetch_into(&rpc, &new, &sender).await.is_err() {
+                            return;
+                        }
+                        for pubkey in known.difference(&listed) {
+                            if sender.send(AccountUpdate::closed(*pubkey, 0)).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    known = Some(listed);
+                }
+                Err(err) => warn!(error = %err, "failed to list program accounts"),
+            }
+            sleep(interval).await;
+        }
+    })
+}
+
+// every account the program owns, without data
+async fn list_accounts(rpc: &RpcPool, program_id: &Pubkey) -> solana_client::client_error::Result<HashSet<Pubkey>> {
+    let accounts = rpc
+        .call(|client| async move {
+            let config = RpcProgramAccountsConfig {
+                filters: None,
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    data_slice: Some(UiDataSliceConfig { offset: 0, length: 0 }),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            };
+            client.get_program_accounts_with_config(program_id, config).await
+        })
+        .await?;
+
+    Ok(accounts.into_iter().map(|(pubkey, _)| pubkey).collect())
+}
+
+/// Lists every account the program owns and fetches them all in full into `sender`, for the caches to catch
+/// up on anything the stream missed. Returns how many were listed.
+pub async fn refresh(rpc: &RpcPool, program_id: &Pubkey, sender: &Sender<AccountUpdate>) -> Result<usize, String> {
+    let listed: Vec<Pubkey> = list_accounts(rpc, program_id).await.map_err(|err| err.to_string())?.into_iter().collect();
+    fetch_into(rpc, &listed, sender).await.map_err(|_| "the risk stage is gone".to_string())?;
+    Ok(listed.len())
+}
+
+/// Fetches the accounts in full and sends them on, erroring only once the receiving end is gone
+pub async fn fetch_into(rpc: &RpcPool, pubkeys: &[Pubkey], sender: &Sender<AccountUpdate>) -> Result<(), ()> {
+    for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let response = match rpc.call(|client| async move { client.get_multiple_accounts_with_commitment(chunk, client.commitment()).await }).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!(accounts = chunk.len(), error = %err, "failed to fetch program accounts");
+                continue;
+            }
+        };
+
+        for (pubkey, account) in chunk.iter().zip(response.value) {
+            if let Some(account) = account {
+                let update = AccountUpdate { pubkey: *pubkey, owner: account.owner, data: account.data, lamports: account.lamports, slot: response.context.slot };
+                sender.send(update).await.map_err(|_| ())?;
+            }
+        }
+    }
+
+    Ok(())
+}
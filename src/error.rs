@@ -0,0 +1,25 @@
+use clearing_house::error::ErrorCode;
+use thiserror::Error;
+
+/// What can go wrong evaluating a single user. These are logged and counted per user, never fatal, so one
+/// malformed account can't take the risk stage down with it.
+#[derive(Debug, Error)]
+pub enum LiquidatorError {
+    /// the clearing house's own math rejected the user's positions
+    #[error("clearing house math failed: {0:?}")]
+    ClearingHouse(ErrorCode),
+    #[error("math overflow {0}")]
+    MathOverflow(&'static str),
+    /// a position refers to a market index the markets account doesn't have
+    #[error("unknown market {0}")]
+    UnknownMarket(u64),
+}
+
+// anchor's error codes don't implement `std::error::Error`, so no `#[from]`
+impl From<ErrorCode> for LiquidatorError {
+    fn from(err: ErrorCode) -> LiquidatorError {
+        LiquidatorError::ClearingHouse(err)
+    }
+}
+
+pub type Result<T, E = LiquidatorError> = std::result::Result<T, E>;
@@ -0,0 +1,590 @@
+use std::{collections::HashMap, error::Error, sync::{atomic::Ordering, Arc, Mutex, MutexGuard, PoisonError}, time::{Duration, Instant}};
+
+use clearing_house::state::state::State;
+use solana_sdk::{address_lookup_table_account::AddressLookupTableAccount, commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction, hash::Hash, instruction::Instruction, message::{v0, Message, VersionedMessage}, native_token::LAMPORTS_PER_SOL, packet::PACKET_DATA_SIZE, pubkey::Pubkey, signature::Signature, signer::Signer, transaction::{TransactionError, VersionedTransaction}};
+use futures::future::join_all;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_transaction_status::UiTransactionEncoding;
+use tokio::{sync::{mpsc::Receiver, watch, Semaphore}, time::sleep};
+use tracing::{info, info_span, instrument, warn, Instrument, Span};
+
+use crate::{blockhash::BlockhashCache, budget, config::Config, cooldown::Cooldowns, fees::FeeOracle, history, instructions, metrics::{self, METRICS, QUOTE_PRECISION}, notify::{self, Event}, pause, pnl::{self, Settled, PNL}, risk::{Candidate, CandidateQueue, Liquidation}, reload, rpc::RpcPool, sender::TxSender, shutdown, signer::SharedSigner, simulation, status::STATUS, v2, webhook};
+
+// most compute units a single transaction can request
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// Base fee of a transaction with a single signature
+pub const SIGNATURE_FEE_LAMPORTS: u64 = 5000;
+
+/// How a sent liquidation transaction ended up
+pub enum Outcome {
+    Landed(Signature),
+    /// landed but the program rejected it, e.g. another liquidator got there first
+    Failed(Signature, TransactionError),
+    /// its blockhash expired before it landed
+    Expired,
+}
+
+/// Executor stage: turns liquidation candidates into signed transactions, sends them and follows them until they
+/// land
+#[derive(Clone)]
+pub struct Executor {
+    pub config: Config,
+    pub rpc: Arc<RpcPool>,
+    pub payer: SharedSigner,
+    pub liquidator_drift_account: Pubkey,
+    pub state: (Pubkey, State),
+    pub blockhash: BlockhashCache,
+    pub fees: FeeOracle,
+    pub sender: Arc<dyn TxSender>,
+    /// when set, transactions are sent as v0 messages resolving the static accounts through this table
+    pub lookup_table: Option<AddressLookupTableAccount>,
+    /// caps the number of liquidations being sent at once
+    pub send_permits: Arc<Semaphore>,
+    /// users with a liquidation in flight and the signature of its latest transaction, if one was sent yet.
+    /// No second liquidation is sent for them until the first lands or gives up.
+    pub in_flight: Arc<Mutex<HashMap<Pubkey, Option<Signature>>>>,
+    /// users left alone for a while after their liquidation failed
+    pub cooldowns: Arc<Cooldowns>,
+}
+
+impl Executor {
+    /// Sends a liquidation for every candidate received, packing candidates that arrive together into shared
+    /// transactions with the most valuable sent first. Returns once the risk stage hangs up or shutdown began,
+    /// after the liquidations in flight were given a chance to land. Reloaded configs apply from the next
+    /// candidates on.
+    pub async fn run(self, mut candidates: Receiver<Candidate>, mut shutdown: watch::Receiver<bool>, mut reloads: watch::Receiver<Config>) {
+        let mut executor = Arc::new(self);
+        let mut queue = CandidateQueue::new(executor.config.strategy.strategy());
+        while let Some(candidate) = shutdown::recv(&mut candidates, &mut shutdown).await {
+            // liquidations already being sent keep the config they started with. the queue is empty between
+            // batches, so it can switch strategies
+            if let Some(config) = reload::changed(&mut reloads) {
+                queue = CandidateQueue::new(config.strategy.strategy());
+                executor = Arc::new(Executor { config, ..Executor::clone(&executor) });
+            }
+            // whatever else is already queued gets a chance to share the transaction
+            queue.push(candidate);
+            while let Ok(candidate) = candidates.try_recv() {
+                queue.push(candidate);
+            }
+            let paused = pause::reasons();
+            if !paused.is_empty() {
+                // the users are evaluated again on the next market move
+                info!(skipped = queue.drain().count(), reasons = %paused.join(", "), "skipping liquidations, sends are paused");
+                continue;
+            }
+            let queued: Vec<Candidate> = queue
+                .drain()
+                .filter(|candidate| !executor.pending(candidate) && !executor.cooling_down(candidate) && executor.profitable(candidate))
+                .collect();
+
+            for batch in executor.pack(queued) {
+                // claimed before spawning, so the next candidates for these users see them in flight
+                executor.claim(&batch);
+                // send without holding up the candidates queued behind this one
+                let executor = executor.clone();
+                tokio::spawn(async move {
+                    executor.liquidate_batch(&batch).await;
+                    executor.release(&batch);
+                });
+            }
+        }
+        executor.drain().await;
+    }
+
+    /// Waits up to `shutdown_timeout_secs` for the liquidations in flight to land or give up, logging the ones
+    /// still out once it runs out so there is a record of what was submitted
+    async fn drain(&self) {
+        let deadline = Instant::now() + Duration::from_secs(self.config.shutdown_timeout_secs);
+        loop {
+            let in_flight: Vec<(Pubkey, Option<Signature>)> = self.in_flight().iter().map(|(user, signature)| (*user, *signature)).collect();
+            if in_flight.is_empty() {
+                return;
+            }
+            if Instant::now() >= deadline {
+                for (user, signature) in in_flight {
+                    let user = bs58::encode(user.to_bytes()).into_string();
+                    match signature {
+                        Some(signature) => warn!(%user, %signature, "exiting with a liquidation unconfirmed"),
+                        None => warn!(%user, "exiting with a liquidation not sent yet"),
+                    }
+                }
+                return;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Whether a liquidation of the candidate's user is still in flight
+    pub fn pending(&self, candidate: &Candidate) -> bool {
+        let in_flight = self.in_flight();
+        let signature = match in_flight.get(&candidate.user) {
+            Some(signature) => signature,
+            None => return false,
+        };
+
+        let user = bs58::encode(candidate.user.to_bytes()).into_string();
+        match signature {
+            Some(signature) => info!(%user, %signature, "skipping liquidation, still in flight"),
+            None => info!(%user, "skipping liquidation, already being liquidated"),
+        }
+        true
+    }
+
+    /// Whether the candidate's user is on a cooldown after a failed liquidation
+    pub fn cooling_down(&self, candidate: &Candidate) -> bool {
+        match self.cooldowns.remaining(&candidate.user) {
+            Some(remaining) => {
+                info!(user = %bs58::encode(candidate.user.to_bytes()).into_string(), remaining_ms = remaining.as_millis() as u64, "skipping liquidation, cooling down");
+                true
+            }
+            None => false,
+        }
+    }
+
+    // backs off the candidates' users after a failed attempt
+    fn cool_down(&self, candidates: &[Candidate]) {
+        for candidate in candidates {
+            let duration = self.cooldowns.failed(&candidate.user);
+            info!(user = %bs58::encode(candidate.user.to_bytes()).into_string(), cooldown_ms = duration.as_millis() as u64, "cooling down account");
+        }
+    }
+
+    // a send that panicked while holding the lock left the map no less consistent than between any two sends
+    fn in_flight(&self) -> MutexGuard<'_, HashMap<Pubkey, Option<Signature>>> {
+        self.in_flight.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn claim(&self, candidates: &[Candidate]) {
+        let mut in_flight = self.in_flight();
+        for candidate in candidates {
+            in_flight.insert(candidate.user, None);
+        }
+    }
+
+    fn release(&self, candidates: &[Candidate]) {
+        let mut in_flight = self.in_flight();
+        for candidate in candidates {
+            in_flight.remove(&candidate.user);
+        }
+    }
+
+    /// Whether the candidate's expected reward beats what landing its liquidation costs (the signature fee,
+    /// priority fee and tip of a transaction of its own) by at least `min_reward`. Bankruptcies pay no reward
+    /// and are always resolved.
+    pub fn profitable(&self, candidate: &Candidate) -> bool {
+        if matches!(candidate.liquidation, Liquidation::PerpBankruptcy { .. } | Liquidation::SpotBankruptcy { .. }) {
+            return true;
+        }
+
+        let fees = (self.fee_lamports() as u128 * candidate.sol_price as u128 / LAMPORTS_PER_SOL as u128) as u64;
+        if candidate.reward.liquidator >= fees.saturating_add(self.config.min_reward) {
+            return true;
+        }
+
+        metrics::increment(&METRICS.unprofitable_candidates);
+        info!(
+            user = %bs58::encode(candidate.user.to_bytes()).into_string(),
+            reward = candidate.reward.liquidator as f64 / QUOTE_PRECISION,
+            fees = fees as f64 / QUOTE_PRECISION,
+            "skipping liquidation, expected reward doesn't cover the fees"
+        );
+        false
+    }
+
+    // the priority fee bid, held at the static price while the fee budget is running out
+    fn compute_unit_price(&self) -> u64 {
+        if budget::throttled() {
+            self.fees.price().min(self.config.compute_unit_price)
+        } else {
+            self.fees.price()
+        }
+    }
+
+    // what landing a liquidation transaction costs at the current priority fee
+    fn fee_lamports(&self) -> u64 {
+        SIGNATURE_FEE_LAMPORTS + self.compute_unit_price() * self.config.compute_unit_limit as u64 / 1_000_000 + self.sender.tip_lamports()
+    }
+
+    /// Greedily packs candidates into batches of up to `max_liquidations_per_transaction` that still fit a
+    /// single transaction's size and compute limits. Duplicate candidates for the same user are dropped, and
+    /// batches keep the order of the candidates they start with.
+    pub fn pack(&self, candidates: Vec<Candidate>) -> Vec<Vec<Candidate>> {
+        let mut batches: Vec<Vec<Candidate>> = vec![];
+        'candidates: for mut candidate in candidates {
+            if batches.iter().flatten().any(|batched| batched.user == candidate.user) {
+                continue;
+            }
+
+            for batch in batches.iter_mut().filter(|batch| batch.len() < self.config.max_liquidations_per_transaction) {
+                batch.push(candidate);
+                if self.fits(batch) {
+                    continue 'candidates;
+                }
+                candidate = batch.pop().unwrap();
+            }
+            batches.push(vec![candidate]);
+        }
+
+        batches
+    }
+
+    /// Liquidates several candidates in one transaction. If the program would reject the batch, e.g. because one
+    /// of the users is no longer liquidatable, they are retried one transaction each.
+    pub async fn liquidate_batch(&self, candidates: &[Candidate]) {
+        if candidates.len() == 1 {
+            return self.liquidate(&candidates[0]).await;
+        }
+
+        if let Err(reason) = self.execute(candidates).await {
+            warn!(users = %users(candidates), %reason, "batch liquidation rejected, liquidating individually");
+            join_all(candidates.iter().map(|candidate| self.liquidate(candidate))).await;
+        }
+    }
+
+    /// Builds, signs and sends the liquidation transaction for a candidate, rebroadcasting it until it lands and
+    /// re-signing it with a fresh blockhash if the old one expires first
+    pub async fn liquidate(&self, candidate: &Candidate) {
+        if let Err(reason) = self.execute(std::slice::from_ref(candidate)).await {
+            warn!(user = %bs58::encode(candidate.user.to_bytes()).into_string(), %reason, "skipping liquidation");
+            self.cool_down(std::slice::from_ref(candidate));
+        }
+    }
+
+    // sends and follows one transaction liquidating all the candidates, only errors if simulation rejects it. traced
+    // under the first candidate's detection, batched ones link to theirs
+    #[instrument(name = "execute", parent = candidates.first().and_then(|candidate| candidate.span.id()), skip_all, fields(users = %users(candidates)))]
+    async fn execute(&self, candidates: &[Candidate]) -> Result<(), String> {
+        for candidate in candidates.iter().skip(1) {
+            Span::current().follows_from(&candidate.span);
+        }
+        let started = Instant::now();
+        let mut expired_blockhash = None;
+        for _ in 0..=self.config.max_resigns {
+            let recent_blockhash = match self.fresh_blockhash(expired_blockhash).instrument(info_span!("blockhash")).await {
+                Ok(recent_blockhash) => recent_blockhash,
+                Err(err) => {
+                    warn!(error = %err, "failed to get blockhash");
+                    return Ok(());
+                }
+            };
+
+            let liquidate_transaction = match self.sign(candidates, recent_blockhash) {
+                Ok(liquidate_transaction) => liquidate_transaction,
+                Err(err) => {
+                    warn!(error = %err, "failed to sign liquidation");
+                    return Ok(());
+                }
+            };
+            if self.config.simulate_before_send {
+                self.simulate(&liquidate_transaction).instrument(info_span!("simulate")).await?;
+            }
+            if self.config.dry_run {
+                dry_run(candidates, &liquidate_transaction.signatures[0], self.config.simulate_before_send);
+                return Ok(());
+            }
+            let permit = match self.send_permits.acquire().await {
+                Ok(permit) => permit,
+                Err(err) => {
+                    warn!(error = %err, "failed to wait for a send slot");
+                    return Ok(());
+                }
+            };
+            let sent = self.sender.send(&liquidate_transaction).instrument(info_span!("send")).await;
+            drop(permit);
+            if let Err(err) = sent {
+                warn!(error = %err, "failed to send liquidation");
+                return Ok(());
+            }
+            webhook::publish(candidates, "attempt", Some(&liquidate_transaction.signatures[0]));
+            history::sent(candidates, &liquidate_transaction.signatures[0]);
+            let mut in_flight = self.in_flight();
+            for candidate in candidates {
+                // only tracked for candidates `run` claimed
+                if let Some(signature) = in_flight.get_mut(&candidate.user) {
+                    *signature = Some(liquidate_transaction.signatures[0]);
+                }
+            }
+            drop(in_flight);
+
+            match self.confirm(&liquidate_transaction).instrument(info_span!("confirm")).await {
+                Outcome::Landed(signature) => {
+                    record(candidates, "landed", Some(&signature), started);
+                    self.settle(candidates, signature, "landed", started);
+                    for candidate in candidates {
+                        self.cooldowns.succeeded(&candidate.user);
+                        METRICS.landed(&candidate.liquidation);
+                        if let Some((market, bad_debt)) = candidate.liquidation.bad_debt() {
+                            warn!(
+                                user = %bs58::encode(candidate.user.to_bytes()).into_string(),
+                                bad_debt = bad_debt as f64 / QUOTE_PRECISION,
+                                %market,
+                                "liquidation left bad debt (total {})",
+                                METRICS.bad_debt_summary()
+                            );
+                        }
+                    }
+                    let liquidator: u64 = candidates.iter().map(|candidate| candidate.reward.liquidator).sum();
+                    let insurance_fund: u64 = candidates.iter().map(|candidate| candidate.reward.insurance_fund).sum();
+                    METRICS.rewards.fetch_add(liquidator, Ordering::Relaxed);
+                    notify::notify(Event::info(format!(
+                        "liquidated {} for ${:.2}: https://solscan.io/tx/{}",
+                        markets(candidates),
+                        liquidator as f64 / QUOTE_PRECISION,
+                        signature
+                    )));
+                    info!(
+                        %signature,
+                        reward = liquidator as f64 / QUOTE_PRECISION,
+                        insurance_fund = insurance_fund as f64 / QUOTE_PRECISION,
+                        latency_ms = started.elapsed().as_millis() as u64,
+                        "liquidated ({})",
+                        METRICS.summary()
+                    );
+                    return Ok(());
+                }
+                Outcome::Failed(signature, err) => {
+                    metrics::increment(&METRICS.failed_liquidations);
+                    record(candidates, "failed", Some(&signature), started);
+                    self.settle(candidates, signature, "failed", started);
+                    let reason = simulation::describe_failure(&err, &[]);
+                    warn!(%signature, %reason, latency_ms = started.elapsed().as_millis() as u64, "liquidation failed");
+                    self.cool_down(candidates);
+                    return Ok(());
+                }
+                Outcome::Expired => {
+                    history::resolved(&liquidate_transaction.signatures[0], "expired", started.elapsed().as_millis() as u64, 0, 0, 0);
+                    warn!(latency_ms = started.elapsed().as_millis() as u64, "liquidation expired before landing");
+                    expired_blockhash = Some(recent_blockhash);
+                }
+            }
+        }
+
+        metrics::increment(&METRICS.expired_liquidations);
+        record(candidates, "expired", None, started);
+        self.cool_down(candidates);
+        Ok(())
+    }
+
+    // books what a landed or failed transaction paid and earned with the metrics, the history and the pnl. The fee
+    // is read back from the transaction in the background, the estimate standing in if that fails
+    fn settle(&self, candidates: &[Candidate], signature: Signature, outcome: &'static str, started: Instant) {
+        let landed = outcome == "landed";
+        let liquidations: Vec<Settled> = candidates
+            .iter()
+            .map(|candidate| Settled {
+                market: pnl::market_name(candidate.liquidation.kind(), candidate.liquidation.market_index()),
+                perp_market_index: match candidate.liquidation {
+                    Liquidation::Perp { market_index, .. } => Some(market_index),
+                    _ => None,
+                },
+                landed,
+                reward: if landed { candidate.reward.liquidator } else { 0 },
+                sol_price: candidate.sol_price,
+            })
+            .collect();
+        let reward = liquidations.iter().map(|liquidation| liquidation.reward).sum();
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let tip_lamports = self.sender.tip_lamports();
+        let estimate = self.fee_lamports() - tip_lamports;
+        let rpc = self.rpc.clone();
+        let (budget_lamports, throttle_percent) = (self.config.daily_fee_budget_lamports, self.config.fee_budget_throttle_percent);
+        tokio::spawn(async move {
+            let config = RpcTransactionConfig { encoding: Some(UiTransactionEncoding::Base64), commitment: Some(CommitmentConfig::confirmed()), max_supported_transaction_version: Some(0) };
+            let fee_lamports = match rpc.call(|client| async move { client.get_transaction_with_config(&signature, config).await }).await {
+                Ok(transaction) => transaction.transaction.meta.map_or(estimate, |meta| meta.fee),
+                Err(err) => {
+                    warn!(%signature, error = %err, "failed to fetch the liquidation's fee, booking the estimate");
+                    estimate
+                }
+            };
+            METRICS.fees_lamports.fetch_add(fee_lamports + tip_lamports, Ordering::Relaxed);
+            METRICS.tips_lamports.fetch_add(tip_lamports, Ordering::Relaxed);
+            budget::book(fee_lamports + tip_lamports, budget_lamports, throttle_percent);
+            history::resolved(&signature, outcome, latency_ms, fee_lamports, tip_lamports, reward);
+            PNL.settle(&liquidations, fee_lamports, tip_lamports);
+        });
+    }
+
+    /// Simulates the transaction, returning the decoded failure reason if the program would reject it. A failed
+    /// simulation request itself doesn't block the send.
+    pub async fn simulate(&self, transaction: &VersionedTransaction) -> Result<(), String> {
+        let simulation = match self.rpc.call(|client| async move { client.simulate_transaction(transaction).await }).await {
+            Ok(simulation) => simulation.value,
+            Err(err) => {
+                warn!(signature = %transaction.signatures[0], error = %err, "failed to simulate");
+                return Ok(());
+            }
+        };
+
+        match simulation.err {
+            Some(err) => Err(simulation::describe_failure(&err, &simulation.logs.unwrap_or_default())),
+            None => Ok(()),
+        }
+    }
+
+    /// Polls the transaction's signature status, rebroadcasting it every so often, until it lands or its
+    /// blockhash expires
+    pub async fn confirm(&self, transaction: &VersionedTransaction) -> Outcome {
+        let signature = transaction.signatures[0];
+        let recent_blockhash = *transaction.message.recent_blockhash();
+        let mut last_broadcast = Instant::now();
+
+        loop {
+            sleep(Duration::from_millis(self.config.confirm_poll_interval_ms)).await;
+
+            let statuses = self.rpc.call(|client| async move { client.get_signature_statuses(&[signature]).await }).await;
+            if let Some(status) = statuses.ok().and_then(|statuses| statuses.value.into_iter().next().flatten()) {
+                match status.err {
+                    Some(err) => return Outcome::Failed(signature, err),
+                    None if status.satisfies_commitment(CommitmentConfig { commitment: self.config.send_commitment }) => {
+                        return Outcome::Landed(signature)
+                    }
+                    // landed but not yet at the send commitment, keep polling
+                    None => {}
+                }
+            }
+
+            if last_broadcast.elapsed() >= Duration::from_millis(self.config.rebroadcast_interval_ms) {
+                let valid = self
+                    .rpc
+                    .call(|client| async move { client.is_blockhash_valid(&recent_blockhash, CommitmentConfig::processed()).await })
+                    .await;
+                if let Ok(false) = valid {
+                    return Outcome::Expired;
+                }
+
+                if let Err(err) = self.sender.send(transaction).await {
+                    warn!(%signature, error = %err, "failed to rebroadcast");
+                }
+                last_broadcast = Instant::now();
+            }
+        }
+    }
+
+    /// The cached blockhash, unless it is the one that just expired
+    async fn fresh_blockhash(&self, expired: Option<Hash>) -> solana_client::client_error::Result<Hash> {
+        let recent_blockhash = self.blockhash.get(&self.rpc).await?;
+        if Some(recent_blockhash) != expired {
+            return Ok(recent_blockhash);
+        }
+
+        self.rpc.call(|client| async move { client.get_latest_blockhash().await }).await
+    }
+
+    fn sign(&self, candidates: &[Candidate], recent_blockhash: Hash) -> Result<VersionedTransaction, Box<dyn Error + Send + Sync>> {
+        let message = info_span!("build").in_scope(|| self.compile(candidates, recent_blockhash))?;
+        let signer: &dyn Signer = &*self.payer;
+        Ok(info_span!("sign").in_scope(|| VersionedTransaction::try_new(message, &[signer]))?)
+    }
+
+    fn compile(&self, candidates: &[Candidate], recent_blockhash: Hash) -> Result<VersionedMessage, Box<dyn Error + Send + Sync>> {
+        let compute_unit_limit = (self.config.compute_unit_limit * candidates.len() as u32).min(MAX_COMPUTE_UNIT_LIMIT);
+        let mut instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(self.compute_unit_price()),
+        ];
+        instructions.extend(candidates.iter().map(|candidate| self.liquidate_instruction(candidate)));
+        instructions.extend(self.sender.extra_instructions(&self.payer.pubkey()));
+
+        let payer = self.payer.pubkey();
+        Ok(match &self.lookup_table {
+            Some(lookup_table) => VersionedMessage::V0(v0::Message::try_compile(&payer, &instructions, &[lookup_table.clone()], recent_blockhash)?),
+            None => VersionedMessage::Legacy(Message::new_with_blockhash(&instructions, Some(&payer), &recent_blockhash)),
+        })
+    }
+
+    // whether a transaction liquidating the whole batch stays within the packet size and compute limits
+    fn fits(&self, candidates: &[Candidate]) -> bool {
+        if self.config.compute_unit_limit as u64 * candidates.len() as u64 > MAX_COMPUTE_UNIT_LIMIT as u64 {
+            return false;
+        }
+
+        match self.compile(candidates, Hash::default()) {
+            // the payer's signature plus its length prefix
+            Ok(message) => 1 + 64 + message.serialize().len() <= PACKET_DATA_SIZE,
+            Err(_) => false,
+        }
+    }
+
+    fn liquidate_instruction(&self, candidate: &Candidate) -> Instruction {
+        let payer = self.payer.pubkey();
+        match &candidate.liquidation {
+            Liquidation::V1 { user_positions, oracles, .. } => {
+                instructions::liquidate(&self.state, &payer, &self.liquidator_drift_account, &candidate.user, user_positions, oracles)
+            }
+            Liquidation::Perp { user_authority, market_index, max_base_asset_amount, remaining_accounts } => v2::liquidate_perp(
+                &payer,
+                &self.liquidator_drift_account,
+                &candidate.user,
+                user_authority,
+                *market_index,
+                *max_base_asset_amount,
+                remaining_accounts,
+            ),
+            Liquidation::Spot { user_authority, asset_market_index, liability_market_index, max_liability_transfer, remaining_accounts } => v2::liquidate_spot(
+                &payer,
+                &self.liquidator_drift_account,
+                &candidate.user,
+                user_authority,
+                *asset_market_index,
+                *liability_market_index,
+                *max_liability_transfer,
+                remaining_accounts,
+            ),
+            Liquidation::PerpBankruptcy { user_authority, market_index, remaining_accounts, .. } => {
+                v2::resolve_perp_bankruptcy(&payer, &self.liquidator_drift_account, &candidate.user, user_authority, *market_index, remaining_accounts)
+            }
+            Liquidation::SpotBankruptcy { user_authority, market_index, remaining_accounts, .. } => {
+                v2::resolve_spot_bankruptcy(&payer, &self.liquidator_drift_account, &candidate.user, user_authority, *market_index, remaining_accounts)
+            }
+        }
+    }
+}
+
+// files the outcome of the candidates' liquidation for the status api and the webhook
+fn record(candidates: &[Candidate], outcome: &'static str, signature: Option<&Signature>, started: Instant) {
+    STATUS.record(candidates, outcome, signature.map(Signature::to_string), started.elapsed());
+    webhook::publish(candidates, outcome, signature);
+}
+
+// the candidates' users with the types and markets of their liquidations
+fn markets(candidates: &[Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| {
+            let user = bs58::encode(candidate.user.to_bytes()).into_string();
+            match candidate.liquidation.market_index() {
+                Some(market_index) => format!("{} ({} on market {})", user, candidate.liquidation.kind(), market_index),
+                None => format!("{} ({})", user, candidate.liquidation.kind()),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// logs what a sent transaction would have liquidated, in place of sending it
+fn dry_run(candidates: &[Candidate], signature: &Signature, simulated: bool) {
+    for candidate in candidates {
+        metrics::increment(&METRICS.dry_run_liquidations);
+        info!(
+            user = %bs58::encode(candidate.user.to_bytes()).into_string(),
+            liquidation = candidate.liquidation.kind(),
+            margin_ratio = candidate.margin_ratio as u64,
+            reward = candidate.reward.liquidator as f64 / QUOTE_PRECISION,
+            insurance_fund = candidate.reward.insurance_fund as f64 / QUOTE_PRECISION,
+            simulated,
+            %signature,
+            "dry run, would have liquidated"
+        );
+    }
+}
+
+// comma separated user accounts of a batch with their liquidation types, for logging
+fn users(candidates: &[Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| format!("{} ({})", bs58::encode(candidate.user.to_bytes()).into_string(), candidate.liquidation.kind()))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
@@ -0,0 +1,166 @@
+use std::{error::Error, fs::{self, File}, path::Path, sync::Arc};
+
+use arrow::{array::{ArrayRef, Int64Array, StringArray}, datatypes::{DataType, Field, Schema}, record_batch::RecordBatch};
+use parquet::arrow::ArrowWriter;
+use tracing::info;
+
+use crate::{config::{Config, ExportArgs, ExportFormat}, history, snapshot::Snapshot};
+
+type ExportResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// a table's values, one per row
+enum Column {
+    Int(Vec<Option<i64>>),
+    Text(Vec<Option<String>>),
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Int(values) => values.len(),
+            Column::Text(values) => values.len(),
+        }
+    }
+
+    // empty for null
+    fn cell(&self, row: usize) -> String {
+        match self {
+            Column::Int(values) => values[row].map_or(String::new(), |value| value.to_string()),
+            Column::Text(values) => values[row].clone().unwrap_or_default(),
+        }
+    }
+}
+
+struct Table {
+    name: &'static str,
+    columns: Vec<(&'static str, Column)>,
+}
+
+impl Table {
+    fn rows(&self) -> usize {
+        self.columns.first().map_or(0, |(_, column)| column.len())
+    }
+
+    fn write_csv(&self, path: &Path) -> ExportResult<()> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(self.columns.iter().map(|(name, _)| *name))?;
+        for row in 0..self.rows() {
+            writer.write_record(self.columns.iter().map(|(_, column)| column.cell(row)))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_parquet(&self, path: &Path) -> ExportResult<()> {
+        let fields: Vec<Field> = self
+            .columns
+            .iter()
+            .map(|(name, column)| {
+                let data_type = match column {
+                    Column::Int(_) => DataType::Int64,
+                    Column::Text(_) => DataType::Utf8,
+                };
+                Field::new(*name, data_type, true)
+            })
+            .collect();
+        let arrays: Vec<ArrayRef> = self
+            .columns
+            .iter()
+            .map(|(_, column)| match column {
+                Column::Int(values) => Arc::new(Int64Array::from(values.clone())) as ArrayRef,
+                Column::Text(values) => Arc::new(StringArray::from(values.clone())) as ArrayRef,
+            })
+            .collect();
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?;
+        let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+fn ints<T>(rows: &[T], value: impl Fn(&T) -> Option<i64>) -> Column {
+    Column::Int(rows.iter().map(value).collect())
+}
+
+fn texts<T>(rows: &[T], value: impl Fn(&T) -> Option<String>) -> Column {
+    Column::Text(rows.iter().map(value).collect())
+}
+
+/// Dumps the history recorded in the configured store, and the snapshot if asked to, into one file per table
+/// under `args.out`, for pandas, duckdb and the like
+pub async fn export(config: &Config, args: &ExportArgs) -> ExportResult<()> {
+    let store = history::store(config).await.ok_or("set history_path or history_postgres_url to export the history")?;
+    let (from_ms, to_ms) = (args.from.unwrap_or(0), args.to.unwrap_or(i64::MAX));
+
+    let evaluations = store.evaluations(from_ms, to_ms).await?;
+    let transactions = store.transactions(from_ms, to_ms).await?;
+    let attempts = store.attempts(from_ms, to_ms).await?;
+    let mut tables = vec![
+        Table {
+            name: "evaluations",
+            columns: vec![
+                ("time_ms", ints(&evaluations, |row| Some(row.time_ms))),
+                ("slot", ints(&evaluations, |row| Some(row.slot))),
+                ("user", texts(&evaluations, |row| Some(row.user.clone()))),
+                ("liquidation", texts(&evaluations, |row| Some(row.liquidation.clone()))),
+                ("market_index", ints(&evaluations, |row| row.market_index.map(i64::from))),
+                ("margin_ratio", ints(&evaluations, |row| Some(row.margin_ratio))),
+                ("expected_reward", ints(&evaluations, |row| Some(row.expected_reward))),
+            ],
+        },
+        Table {
+            name: "transactions",
+            columns: vec![
+                ("signature", texts(&transactions, |row| Some(row.signature.clone()))),
+                ("sent_ms", ints(&transactions, |row| Some(row.sent_ms))),
+                ("slot", ints(&transactions, |row| Some(row.slot))),
+                ("outcome", texts(&transactions, |row| row.outcome.clone())),
+                ("resolved_ms", ints(&transactions, |row| row.resolved_ms)),
+                ("latency_ms", ints(&transactions, |row| row.latency_ms)),
+                ("fee_lamports", ints(&transactions, |row| Some(row.fee_lamports))),
+                ("tip_lamports", ints(&transactions, |row| Some(row.tip_lamports))),
+                ("reward", ints(&transactions, |row| Some(row.reward))),
+                ("sol_price", ints(&transactions, |row| Some(row.sol_price))),
+            ],
+        },
+        Table {
+            name: "attempts",
+            columns: vec![
+                ("signature", texts(&attempts, |row| Some(row.signature.clone()))),
+                ("sent_ms", ints(&attempts, |row| Some(row.sent_ms))),
+                ("user", texts(&attempts, |row| Some(row.user.clone()))),
+                ("liquidation", texts(&attempts, |row| Some(row.liquidation.clone()))),
+                ("market_index", ints(&attempts, |row| row.market_index.map(i64::from))),
+                ("margin_ratio", ints(&attempts, |row| Some(row.margin_ratio))),
+                ("expected_reward", ints(&attempts, |row| Some(row.expected_reward))),
+            ],
+        },
+    ];
+    if args.snapshot {
+        let path = config.snapshot_path.as_ref().ok_or("set snapshot_path to export the snapshot")?;
+        let snapshot = Snapshot::load(path).ok_or("no readable snapshot at snapshot_path")?;
+        tables.push(Table {
+            name: "snapshot",
+            columns: vec![
+                ("user", texts(&snapshot.users, |user| Some(user.user.clone()))),
+                ("positions", texts(&snapshot.users, |user| user.positions.clone())),
+                ("margin_ratio", ints(&snapshot.users, |user| user.margin_ratio.map(|margin_ratio| margin_ratio.min(i64::MAX as u128) as i64))),
+            ],
+        });
+    }
+
+    fs::create_dir_all(&args.out)?;
+    for table in &tables {
+        let path = match args.format {
+            ExportFormat::Csv => Path::new(&args.out).join(format!("{}.csv", table.name)),
+            ExportFormat::Parquet => Path::new(&args.out).join(format!("{}.parquet", table.name)),
+        };
+        match args.format {
+            ExportFormat::Csv => table.write_csv(&path)?,
+            ExportFormat::Parquet => table.write_parquet(&path)?,
+        }
+        info!(path = %path.display(), rows = table.rows(), "exported {}", table.name);
+    }
+    Ok(())
+}
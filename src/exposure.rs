@@ -0,0 +1,74 @@
+use std::{collections::{BTreeMap, BTreeSet}, sync::{Mutex, OnceLock}};
+
+use tracing::{info, warn};
+
+use crate::{metrics::QUOTE_PRECISION, notify::{self, Event}, risk::Liquidation};
+
+/// Notional caps on the liquidator's own perp positions per market, in quote precision: past its cap a market's
+/// perp liquidations are refused, as they would hand the liquidator more of the same position, until enough of it
+/// is unwound
+pub struct Caps {
+    /// the cap of markets without one of their own, 0 for none
+    pub default: u64,
+    pub markets: BTreeMap<u16, u64>,
+}
+
+impl Caps {
+    /// Reads `exposure_caps`' `<perp market index>=<notional>` entries on top of the default cap
+    pub fn parse(default: u64, entries: &[String]) -> Result<Caps, String> {
+        let markets = entries
+            .iter()
+            .map(|entry| {
+                let (market_index, cap) = entry.split_once('=').ok_or_else(|| format!("exposure cap {} isn't <perp market index>=<notional>", entry))?;
+                let market_index = market_index.trim().parse().map_err(|_| format!("exposure cap {} has no valid market index", entry))?;
+                let cap = cap.trim().parse().map_err(|_| format!("exposure cap {} has no valid notional", entry))?;
+                Ok((market_index, cap))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Caps { default, markets })
+    }
+
+    fn cap(&self, market_index: u16) -> Option<u64> {
+        Some(self.markets.get(&market_index).copied().unwrap_or(self.default)).filter(|cap| *cap > 0)
+    }
+}
+
+static CAPS: OnceLock<Caps> = OnceLock::new();
+// markets the liquidator's position is over its cap in
+static CAPPED: Mutex<BTreeSet<u16>> = Mutex::new(BTreeSet::new());
+
+/// Enforces the caps from here on
+pub fn limit(caps: Caps) {
+    if CAPS.set(caps).is_err() {
+        warn!("exposure caps were already set");
+    }
+}
+
+/// Publishes the notional of the liquidator's perp position per market, in quote precision, the markets it
+/// holds none in left out
+pub fn observe(notionals: &BTreeMap<u16, u64>) {
+    let caps = match CAPS.get() {
+        Some(caps) => caps,
+        None => return,
+    };
+    let mut capped = CAPPED.lock().unwrap();
+    let over: BTreeSet<u16> = notionals.iter().filter(|(market_index, notional)| caps.cap(**market_index).map_or(false, |cap| **notional > cap)).map(|(market_index, _)| *market_index).collect();
+    for market_index in over.difference(&capped) {
+        let notional = notionals[market_index] as f64 / QUOTE_PRECISION;
+        warn!(market_index, notional, "liquidator position over its exposure cap, refusing the market's perp liquidations");
+        notify::notify(Event::critical(format!("liquidator holds ${:.2} in perp market {}, over its cap, refusing its liquidations until it is unwound", notional, market_index)));
+    }
+    for market_index in capped.difference(&over) {
+        info!(market_index, "liquidator position back under its exposure cap");
+        notify::notify(Event::info(format!("liquidator position in perp market {} is back under its cap, liquidating it again", market_index)));
+    }
+    *capped = over;
+}
+
+/// Whether the liquidation would add to a position already over its cap
+pub fn refuses(liquidation: &Liquidation) -> bool {
+    match liquidation {
+        Liquidation::Perp { market_index, .. } => CAPPED.lock().unwrap().contains(market_index),
+        _ => false,
+    }
+}
@@ -0,0 +1,71 @@
+use std::{sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+use solana_sdk::pubkey::Pubkey;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::warn;
+
+use crate::rpc::RpcPool;
+
+/// Priority fee bid for liquidation transactions, in micro lamports per compute unit. Holds the static
+/// `compute_unit_price` unless a poll task keeps it in line with recent prioritization fees.
+#[derive(Clone)]
+pub struct FeeOracle {
+    price: Arc<AtomicU64>,
+    // cap on the polled price
+    max_price: Arc<AtomicU64>,
+}
+
+impl FeeOracle {
+    pub fn new(compute_unit_price: u64) -> FeeOracle {
+        FeeOracle {
+            price: Arc::new(AtomicU64::new(compute_unit_price)),
+            max_price: Arc::new(AtomicU64::new(u64::MAX)),
+        }
+    }
+
+    pub fn price(&self) -> u64 {
+        self.price.load(Ordering::Relaxed)
+    }
+
+    /// Replaces the static price, e.g. after a config reload. A running poll overwrites it on its next round.
+    pub fn set(&self, compute_unit_price: u64) {
+        self.price.store(compute_unit_price, Ordering::Relaxed);
+    }
+
+    /// Replaces the cap on the polled price, e.g. after a config reload, from the poll's next round on
+    pub fn set_max(&self, max_price: u64) {
+        self.max_price.store(max_price, Ordering::Relaxed);
+    }
+
+    /// Polls `getRecentPrioritizationFees` for `accounts` (the writable accounts every liquidation locks) and
+    /// bids the given percentile of the fees paid over the recent slots, capped at `max_price`
+    pub fn spawn_poll(&self, rpc: Arc<RpcPool>, accounts: Vec<Pubkey>, percentile: u8, max_price: u64, interval: Duration) -> JoinHandle<()> {
+        let (price, cap) = (self.price.clone(), self.max_price.clone());
+        cap.store(max_price, Ordering::Relaxed);
+        tokio::spawn(async move {
+            loop {
+                let accounts = &accounts;
+                match rpc.call(|client| async move { client.get_recent_prioritization_fees(accounts).await }).await {
+                    Ok(fees) => {
+                        let mut fees: Vec<u64> = fees.iter().map(|fee| fee.prioritization_fee).collect();
+                        if let Some(fee) = percentile_of(&mut fees, percentile) {
+                            price.store(fee.min(cap.load(Ordering::Relaxed)), Ordering::Relaxed);
+                        }
+                    }
+                    Err(err) => warn!(error = %err, "failed to get recent prioritization fees"),
+                }
+                sleep(interval).await;
+            }
+        })
+    }
+}
+
+fn percentile_of(values: &mut [u64], percentile: u8) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_unstable();
+    let index = (values.len() - 1) * percentile.min(100) as usize / 100;
+    Some(values[index])
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use solana_sdk::{hash::hash, pubkey::Pubkey};
+use tracing::warn;
+
+use crate::{config::Config, metrics::METRICS};
+
+/// One of `count` disjoint shares of the users, the ones whose pubkey hashes to `index` modulo `count`. Every
+/// instance given the same `count` and its own `index` agrees on where each user belongs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    /// Whether the user account falls into the shard
+    pub fn contains(&self, user: &Pubkey) -> bool {
+        let hash = hash(user.as_ref()).to_bytes();
+        u64::from_le_bytes(hash[..8].try_into().unwrap()) % self.count == self.index
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    /// Reads `<index>/<count>`, e.g. 0/4
+    fn from_str(shard: &str) -> Result<Shard, String> {
+        let (index, count) = shard.split_once('/').ok_or_else(|| format!("shard {} isn't <index>/<count>", shard))?;
+        let index = index.trim().parse().map_err(|_| format!("shard {} has no valid index", shard))?;
+        let count = count.trim().parse().map_err(|_| format!("shard {} has no valid count", shard))?;
+        if index >= count {
+            return Err(format!("shard {} needs an index under its count", shard));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Which users the risk stage may liquidate. Blocked accounts and authorities never are, the liquidator's own
+/// account included, and with any allowed accounts or authorities configured only the users matching one of them
+/// are. With a shard configured the users of other shards never are either. Checked before a candidate is
+/// built, so a filtered user never gets an instruction.
+#[derive(Debug, Default)]
+pub struct UserFilter {
+    blocked_accounts: HashSet<Pubkey>,
+    blocked_authorities: HashSet<Pubkey>,
+    allowed_accounts: HashSet<Pubkey>,
+    allowed_authorities: HashSet<Pubkey>,
+    shard: Option<Shard>,
+}
+
+impl UserFilter {
+    /// The config's lists and shard, with the liquidator's own account blocked as well. The shard goes into the
+    /// metrics.
+    pub fn new(config: &Config, liquidator: Option<Pubkey>) -> Result<UserFilter, String> {
+        let mut filter = UserFilter {
+            blocked_accounts: parse(&config.excluded_accounts)?,
+            blocked_authorities: parse(&config.excluded_authorities)?,
+            allowed_accounts: parse(&config.allowed_accounts)?,
+            allowed_authorities: parse(&config.allowed_authorities)?,
+            shard: config.shard.as_deref().map(Shard::from_str).transpose()?,
+        };
+        // the liquidator's own drift account can't liquidate itself
+        filter.blocked_accounts.extend(liquidator);
+        *METRICS.shard.lock().unwrap() = filter.shard.map(|shard| shard.to_string());
+        Ok(filter)
+    }
+
+    /// Picks up the lists and shard of a reloaded config, keeping the current ones if any of them is invalid
+    pub fn reconfigure(&mut self, config: &Config, liquidator: Option<Pubkey>) {
+        match UserFilter::new(config, liquidator) {
+            Ok(filter) => *self = filter,
+            Err(err) => warn!(error = %err, "invalid block or allow lists or shard, keeping the current ones"),
+        }
+    }
+
+    /// Whether the user account falls into this instance's shard, always without one
+    pub fn in_shard(&self, user: &Pubkey) -> bool {
+        self.shard.map_or(true, |shard| shard.contains(user))
+    }
+
+    /// Whether the user account, owned by `authority`, may be liquidated
+    pub fn allows(&self, user: &Pubkey, authority: &Pubkey) -> bool {
+        if !self.in_shard(user) || self.blocked_accounts.contains(user) || self.blocked_authorities.contains(authority) {
+            return false;
+        }
+        let allowlist = !self.allowed_accounts.is_empty() || !self.allowed_authorities.is_empty();
+        !allowlist || self.allowed_accounts.contains(user) || self.allowed_authorities.contains(authority)
+    }
+}
+
+fn parse(accounts: &[String]) -> Result<HashSet<Pubkey>, String> {
+    accounts.iter().map(|account| Pubkey::from_str(account).map_err(|_| format!("{} isn't a pubkey", account))).collect()
+}
@@ -0,0 +1,102 @@
+use clearing_house::state::state::State;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{reward::Reward, risk::LiquidationType, v2::{PerpMarket, SpotMarket}};
+
+/// The clearing house's v1 liquidation parameters: partial liquidations under 6.25% closing a quarter for a
+/// 2.5% penalty, full ones under 5% for 5%, each split evenly with the insurance vault
+pub fn state() -> State {
+    State {
+        margin_ratio_partial: 625,
+        margin_ratio_maintenance: 500,
+        partial_liquidation_close_percentage_numerator: 25,
+        partial_liquidation_close_percentage_denominator: 100,
+        partial_liquidation_penalty_percentage_numerator: 1,
+        partial_liquidation_penalty_percentage_denominator: 40,
+        partial_liquidation_liquidator_share_denominator: 2,
+        full_liquidation_penalty_percentage_numerator: 1,
+        full_liquidation_penalty_percentage_denominator: 20,
+        full_liquidation_liquidator_share_denominator: 2,
+        ..State::default()
+    }
+}
+
+/// The fields of a clearing house `LiquidationRecord` a prediction or a reward can be checked against, in
+/// quote precision
+#[derive(Clone, Copy, Debug)]
+pub struct Record {
+    pub partial: bool,
+    pub total_collateral: u128,
+    pub base_asset_value_closed: u128,
+    pub fee_to_liquidator: u64,
+    pub fee_to_insurance_fund: u64,
+}
+
+impl Record {
+    pub fn liquidation_type(&self) -> LiquidationType {
+        if self.partial {
+            LiquidationType::Partial
+        } else {
+            LiquidationType::Full
+        }
+    }
+
+    pub fn reward(&self) -> Reward {
+        Reward { liquidator: self.fee_to_liquidator, insurance_fund: self.fee_to_insurance_fund }
+    }
+}
+
+/// A long of 1000 base worth $999.000999 on $39.000999 of total collateral, a 3.9% margin ratio: closed
+/// outright, the odd lamport of the 5% penalty to the insurance vault
+pub const FULL: Record = Record {
+    partial: false,
+    total_collateral: 39_000_999,
+    base_asset_value_closed: 999_000_999,
+    fee_to_liquidator: 975_024,
+    fee_to_insurance_fund: 975_025,
+};
+
+/// The same long on $59.000999, a 5.9% margin ratio: a quarter of it closed for 2.5%
+pub const PARTIAL: Record = Record {
+    partial: true,
+    total_collateral: 59_000_999,
+    base_asset_value_closed: 249_750_249,
+    fee_to_liquidator: 737_512,
+    fee_to_insurance_fund: 737_512,
+};
+
+/// A perp market at `oracle_price` in price precision, fees in liquidation fee precision
+pub fn perp_market(oracle_price: i64, liquidator_fee: u32, if_liquidation_fee: u32) -> PerpMarket {
+    PerpMarket {
+        pubkey: Pubkey::default(),
+        market_index: 0,
+        oracle: Pubkey::default(),
+        oracle_price,
+        base_asset_reserve: 0,
+        margin_ratio_initial: 1000,
+        margin_ratio_maintenance: 500,
+        unrealized_pnl_initial_asset_weight: 10_000,
+        unrealized_pnl_maintenance_asset_weight: 10_000,
+        liquidator_fee,
+        if_liquidation_fee,
+    }
+}
+
+/// A spot market weighted at par with no interest accrued
+pub fn spot_market(oracle_price: i64, decimals: u32, liquidator_fee: u32, if_liquidation_fee: u32) -> SpotMarket {
+    SpotMarket {
+        pubkey: Pubkey::default(),
+        market_index: 0,
+        oracle: Pubkey::default(),
+        oracle_price,
+        decimals,
+        cumulative_deposit_interest: 10_000_000_000,
+        cumulative_borrow_interest: 10_000_000_000,
+        initial_asset_weight: 10_000,
+        initial_liability_weight: 10_000,
+        maintenance_asset_weight: 10_000,
+        maintenance_liability_weight: 10_000,
+        liquidator_fee,
+        if_liquidation_fee,
+    }
+}
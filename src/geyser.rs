@@ -0,0 +1,147 @@
+use std::{collections::HashMap, convert::TryFrom, time::Duration};
+
+use futures::{future::join_all, StreamExt};
+use solana_sdk::{commitment_config, pubkey::Pubkey};
+use tokio::{sync::mpsc::Sender, task::JoinHandle, time::sleep};
+use tracing::warn;
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::prelude::{subscribe_request_filter_accounts_filter::Filter, subscribe_request_filter_accounts_filter_memcmp::Data, subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest, SubscribeRequestAccountsDataSlice, SubscribeRequestFilterAccounts, SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterMemcmp};
+
+use crate::source::{AccountFilter, AccountSlices, AccountUpdate, DataSource};
+
+/// Streams program account writes from a Yellowstone gRPC geyser endpoint. Updates arrive straight from
+/// the validator's accounts db, skipping the rpc node's websocket fan out.
+pub struct GeyserSource {
+    pub url: String,
+    pub x_token: Option<String>,
+    pub program_id: Pubkey,
+    pub commitment: commitment_config::CommitmentLevel,
+    /// account types to stream, every program account if empty. Sliced types get a subscription of their own
+    /// as data slices apply to a whole subscription.
+    pub filters: Vec<AccountFilter>,
+}
+
+impl DataSource for GeyserSource {
+    fn spawn(&self, sender: Sender<AccountUpdate>) -> JoinHandle<()> {
+        let commitment = match self.commitment {
+            commitment_config::CommitmentLevel::Finalized => CommitmentLevel::Finalized,
+            commitment_config::CommitmentLevel::Confirmed => CommitmentLevel::Confirmed,
+            _ => CommitmentLevel::Processed,
+        };
+
+        let (sliced, whole): (Vec<&AccountFilter>, Vec<&AccountFilter>) = self.filters.iter().partition(|filter| filter.slices.is_some());
+        let mut subscriptions: Vec<(Vec<[u8; 8]>, Option<AccountSlices>)> = sliced.iter().map(|filter| (vec![filter.discriminator], filter.slices.clone())).collect();
+        if !whole.is_empty() || self.filters.is_empty() {
+            subscriptions.push((whole.iter().map(|filter| filter.discriminator).collect(), None));
+        }
+
+        // run on the one task rather than a task each, so aborting it ends every subscription
+        let streams: Vec<_> = subscriptions
+            .into_iter()
+            .map(|(discriminators, slices)| {
+                let url = self.url.clone();
+                let x_token = self.x_token.clone();
+                let program_id = self.program_id;
+                let sender = sender.clone();
+                async move {
+                    loop {
+                        match stream_accounts(&url, x_token.clone(), &program_id, &discriminators, slices.as_ref(), commitment, &sender).await {
+                            // the receiving end is gone, nothing left to stream to
+                            Ok(()) => return,
+                            Err(err) => warn!(error = %err, "geyser subscription failed"),
+                        }
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            })
+            .collect();
+        tokio::spawn(async move {
+            join_all(streams).await;
+        })
+    }
+}
+
+/// Runs a single geyser subscription to the program's accounts with one of the `discriminators` (any account if
+/// empty) until the stream errors or `sender` is closed. Sliced accounts are expanded back to full size.
+async fn stream_accounts(
+    url: &str,
+    x_token: Option<String>,
+    program_id: &Pubkey,
+    discriminators: &[[u8; 8]],
+    slices: Option<&AccountSlices>,
+    commitment: CommitmentLevel,
+    sender: &Sender<AccountUpdate>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut client = GeyserGrpcClient::connect(url.to_string(), x_token, None)?;
+
+    let mut accounts = HashMap::new();
+    if discriminators.is_empty() {
+        accounts.insert(
+            "program".to_string(),
+            SubscribeRequestFilterAccounts {
+                owner: vec![program_id.to_string()],
+                ..SubscribeRequestFilterAccounts::default()
+            },
+        );
+    }
+    // named filters match if any of them does
+    for (i, discriminator) in discriminators.iter().enumerate() {
+        let memcmp = SubscribeRequestFilterAccountsFilterMemcmp { offset: 0, data: Some(Data::Bytes(discriminator.to_vec())) };
+        accounts.insert(
+            format!("program{}", i),
+            SubscribeRequestFilterAccounts {
+                owner: vec![program_id.to_string()],
+                filters: vec![SubscribeRequestFilterAccountsFilter { filter: Some(Filter::Memcmp(memcmp)) }],
+                ..SubscribeRequestFilterAccounts::default()
+            },
+        );
+    }
+    let accounts_data_slice = slices.map_or(vec![], |slices| {
+        slices.ranges.iter().map(|(offset, length)| SubscribeRequestAccountsDataSlice { offset: *offset as u64, length: *length as u64 }).collect()
+    });
+    let request = SubscribeRequest {
+        accounts,
+        accounts_data_slice,
+        commitment: Some(commitment as i32),
+        ..SubscribeRequest::default()
+    };
+    // the sink has to outlive the stream, dropping it closes the subscription
+    let (_subscribe_tx, mut stream) = client.subscribe_with_request(Some(request)).await?;
+
+    while let Some(message) = stream.next().await {
+        let account_update = match message?.update_oneof {
+            Some(UpdateOneof::Account(account_update)) => account_update,
+            _ => continue,
+        };
+        let account = match account_update.account {
+            Some(account) => account,
+            None => continue,
+        };
+        let (pubkey, owner) = match (Pubkey::try_from(account.pubkey.as_slice()), Pubkey::try_from(account.owner.as_slice())) {
+            (Ok(pubkey), Ok(owner)) => (pubkey, owner),
+            _ => continue,
+        };
+
+        let data = match slices {
+            // closed accounts come through empty
+            Some(slices) if !account.data.is_empty() => match slices.expand(&account.data) {
+                Some(data) => data,
+                None => continue,
+            },
+            _ => account.data,
+        };
+
+        let update = AccountUpdate {
+            pubkey,
+            owner,
+            data,
+            lamports: account.lamports,
+            slot: account_update.slot,
+        };
+        if sender.send(update).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    Err("geyser stream ended".into())
+}
@@ -0,0 +1,111 @@
+use std::{net::SocketAddr, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::{config::Config, rpc::{EndpointStatus, RpcPool}, watchdog};
+
+// when the risk stage last finished a round of updates, in milliseconds since the unix epoch, 0 before the first
+static LAST_ITERATION_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Marks that the risk stage finished applying and evaluating a round of updates
+pub fn iterated() {
+    LAST_ITERATION_MS.store(watchdog::now_ms(), Ordering::Relaxed);
+}
+
+/// How long ago the risk stage last finished a round of updates, None before the first one
+pub fn since_last_iteration() -> Option<Duration> {
+    match LAST_ITERATION_MS.load(Ordering::Relaxed) {
+        0 => None,
+        last_iteration => Some(Duration::from_millis(watchdog::now_ms().saturating_sub(last_iteration))),
+    }
+}
+
+/// What `/healthz` and `/readyz` report, the same body for both
+#[derive(Serialize)]
+pub struct Report {
+    pub ready: bool,
+    /// why the liquidator isn't ready, empty when it is
+    pub reasons: Vec<String>,
+    /// most current slot any rpc endpoint reported
+    pub rpc_slot: u64,
+    /// highest slot of the program account updates applied so far
+    pub slot: u64,
+    pub slot_lag: u64,
+    /// time since the last program account update, None before the first one
+    pub last_update_age_ms: Option<u64>,
+    /// when the risk stage last finished a round of updates, in milliseconds since the unix epoch
+    pub last_iteration_ms: Option<u64>,
+    pub rpc_endpoints: Vec<EndpointStatus>,
+}
+
+/// Serves the liquidator's health to orchestrators and uptime monitors. `/healthz` answers as long as the
+/// process does, `/readyz` answers 503 while the data feed is stale, the applied updates trail the rpc by too
+/// many slots or no rpc endpoint is healthy.
+pub struct Health {
+    rpc: Arc<RpcPool>,
+    stale: Duration,
+    max_slot_lag: u64,
+}
+
+impl Health {
+    pub fn new(config: &Config, rpc: Arc<RpcPool>) -> Health {
+        Health { rpc, stale: Duration::from_secs(config.health_stale_secs), max_slot_lag: config.health_max_slot_lag }
+    }
+
+    pub fn report(&self) -> Report {
+        let rpc_endpoints = self.rpc.status();
+        let rpc_slot = rpc_endpoints.iter().map(|endpoint| endpoint.slot).max().unwrap_or(0);
+        let slot = watchdog::last_slot();
+        // 0 until the monitor measured the endpoints
+        let slot_lag = if rpc_slot == 0 { 0 } else { rpc_slot.saturating_sub(slot) };
+        let last_update_age = watchdog::since_last_update();
+
+        let mut reasons = vec![];
+        if !rpc_endpoints.iter().any(|endpoint| endpoint.healthy) {
+            reasons.push("no healthy rpc endpoint".to_string());
+        }
+        match last_update_age {
+            None => reasons.push("no program account update yet".to_string()),
+            Some(age) if age > self.stale => reasons.push(format!("no program account update in {:.0?}", age)),
+            Some(_) => {}
+        }
+        if slot_lag > self.max_slot_lag {
+            reasons.push(format!("{} slots behind the rpc", slot_lag));
+        }
+
+        Report {
+            ready: reasons.is_empty(),
+            reasons,
+            rpc_slot,
+            slot,
+            slot_lag,
+            last_update_age_ms: last_update_age.map(|age| age.as_millis() as u64),
+            last_iteration_ms: Some(LAST_ITERATION_MS.load(Ordering::Relaxed)).filter(|last_iteration| *last_iteration != 0),
+            rpc_endpoints,
+        }
+    }
+
+    /// Serves the endpoints on `addr` in the background
+    pub fn spawn(self, addr: SocketAddr) -> JoinHandle<()> {
+        let app = Router::new().route("/healthz", get(healthz)).route("/readyz", get(readyz)).with_state(Arc::new(self));
+        tokio::spawn(async move {
+            info!(%addr, "serving health endpoints");
+            if let Err(err) = axum::Server::bind(&addr).serve(app.into_make_service()).await {
+                warn!(error = %err, "health server stopped");
+            }
+        })
+    }
+}
+
+async fn healthz(State(health): State<Arc<Health>>) -> Json<Report> {
+    Json(health.report())
+}
+
+async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<Report>) {
+    let report = health.report();
+    let status = if report.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
+}
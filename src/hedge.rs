@@ -0,0 +1,231 @@
+use std::{collections::{BTreeMap, HashMap}, error::Error, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{info, warn};
+
+use crate::{metrics::{self, METRICS}, notify::{self, Event}, pause, watchdog};
+
+// how often the hedges are brought in line with the exposures the risk stage published
+const HEDGE_INTERVAL: Duration = Duration::from_secs(10);
+// hedge differences under this share of the target, in percent, are left be rather than traded back and forth
+const TOLERANCE_PERCENT: f64 = 5.0;
+
+pub type HedgeResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A venue off drift that the liquidator's inherited exposure can be offset on
+#[async_trait]
+pub trait Hedger: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The venue's position in `symbol`, in base units, negative when short
+    async fn position(&self, symbol: &str) -> HedgeResult<f64>;
+
+    /// Trades `base_amount` of `symbol` at market, buying when it is positive and selling when it is negative.
+    /// Returns the venue's id of the order, None if the amount rounds down to nothing the venue trades.
+    async fn trade(&self, symbol: &str, base_amount: f64) -> HedgeResult<Option<String>>;
+}
+
+/// A perp position of the liquidator's own account, as the risk stage last valued it
+#[derive(Clone, Copy, Debug)]
+pub struct Exposure {
+    /// in base units, negative when short
+    pub base_amount: f64,
+    /// how far closing all of it on drift would move the amm's price, in basis points
+    pub impact_bps: u64,
+}
+
+// the liquidator's positions by perp market index, replaced on every round of updates
+static EXPOSURES: Mutex<BTreeMap<u16, Exposure>> = Mutex::new(BTreeMap::new());
+
+/// Publishes the liquidator's perp positions, the markets it holds none in left out
+pub fn observe(exposures: BTreeMap<u16, Exposure>) {
+    *EXPOSURES.lock().unwrap() = exposures;
+}
+
+/// Parses `hedge_markets`' `<perp market index>=<venue symbol>` entries
+pub fn symbols(entries: &[String]) -> Result<BTreeMap<u16, String>, String> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (market_index, symbol) = entry.split_once('=').ok_or_else(|| format!("hedge market {} isn't <perp market index>=<symbol>", entry))?;
+            let market_index = market_index.trim().parse().map_err(|_| format!("hedge market {} has no valid market index", entry))?;
+            Ok((market_index, symbol.trim().to_string()))
+        })
+        .collect()
+}
+
+/// How far closing a position of `base_asset_amount` against an amm holding `base_asset_reserve` moves its
+/// price, in basis points, both in the same precision. The amm is taken to be constant product, which ignores
+/// its spreads and concentration but is good enough to tell a position the amm can't take from one it can.
+pub fn price_impact_bps(base_asset_reserve: u128, base_asset_amount: i128) -> u64 {
+    let (reserve, amount) = (base_asset_reserve as f64, base_asset_amount.unsigned_abs() as f64);
+    if reserve == 0.0 {
+        return u64::MAX;
+    }
+    // closing a long sells base into the amm, closing a short buys it out
+    let ratio = if base_asset_amount > 0 {
+        1.0 - (reserve / (reserve + amount)).powi(2)
+    } else if amount < reserve {
+        (reserve / (reserve - amount)).powi(2) - 1.0
+    } else {
+        return u64::MAX;
+    };
+    (ratio * 10_000.0).min(u64::MAX as f64) as u64
+}
+
+/// Offsets the liquidator's positions that closing on drift would move the amm more than `impact_bps` for, with
+/// the opposite position on `hedger`, and closes the offset again once the position on drift is down to
+/// something the amm can take. Only the markets in `symbols`, perp market index to the venue's symbol, are
+/// hedged, and nothing is traded while sends are paused.
+pub fn spawn(hedger: Box<dyn Hedger>, symbols: BTreeMap<u16, String>, impact_bps: u64) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            sleep(HEDGE_INTERVAL).await;
+            // nothing published before the startup scan is through
+            if watchdog::last_slot() == 0 || !pause::reasons().is_empty() {
+                continue;
+            }
+            let exposures = EXPOSURES.lock().unwrap().clone();
+            for (market_index, symbol) in &symbols {
+                let target = match exposures.get(market_index) {
+                    Some(exposure) if exposure.impact_bps > impact_bps => -exposure.base_amount,
+                    _ => 0.0,
+                };
+                if let Err(err) = rebalance(&*hedger, symbol, target).await {
+                    warn!(hedger = hedger.name(), symbol = symbol.as_str(), error = %err, "failed to hedge");
+                }
+            }
+        }
+    })
+}
+
+// trades the venue's position in `symbol` to `target`
+async fn rebalance(hedger: &dyn Hedger, symbol: &str, target: f64) -> HedgeResult<()> {
+    let position = hedger.position(symbol).await?;
+    let difference = target - position;
+    if difference.abs() <= target.abs().max(position.abs()) * TOLERANCE_PERCENT / 100.0 {
+        return Ok(());
+    }
+    let order = match hedger.trade(symbol, difference).await? {
+        Some(order) => order,
+        None => return Ok(()),
+    };
+    metrics::increment(&METRICS.hedge_orders);
+    info!(hedger = hedger.name(), symbol, position, target, order = order.as_str(), "hedged");
+    if target == 0.0 {
+        notify::notify(Event::info(format!("closed the {} hedge of {:.4} on {}", symbol, position, hedger.name())));
+    } else {
+        notify::notify(Event::info(format!("hedged {} on {}, position {:.4} -> {:.4}", symbol, hedger.name(), position, target)));
+    }
+    Ok(())
+}
+
+/// Hedges on binance usd-m futures through its rest api, at market
+pub struct Binance {
+    pub api_key: String,
+    pub api_secret: String,
+    /// https://fapi.binance.com, or the testnet's
+    pub url: String,
+    pub http: reqwest::Client,
+    // lot step size per symbol, fetched on first use
+    step_sizes: Mutex<HashMap<String, f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PositionRisk {
+    position_amt: String,
+}
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SymbolInfo {
+    symbol: String,
+    filters: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderResponse {
+    order_id: u64,
+}
+
+impl Binance {
+    pub fn new(api_key: String, api_secret: String, url: String, http: reqwest::Client) -> Binance {
+        Binance { api_key, api_secret, url, http, step_sizes: Mutex::new(HashMap::new()) }
+    }
+
+    // the query with its timestamp and hmac-sha256 signature appended, the way signed endpoints want it
+    fn signed(&self, query: &str) -> String {
+        let query = format!("{}{}timestamp={}", query, if query.is_empty() { "" } else { "&" }, watchdog::now_ms());
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.api_secret.as_bytes()).expect("hmac takes keys of any size");
+        mac.update(query.as_bytes());
+        format!("{}&signature={}", query, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    async fn step_size(&self, symbol: &str) -> HedgeResult<f64> {
+        if let Some(step_size) = self.step_sizes.lock().unwrap().get(symbol) {
+            return Ok(*step_size);
+        }
+        let info: ExchangeInfo = self.http.get(format!("{}/fapi/v1/exchangeInfo", self.url)).send().await?.error_for_status()?.json().await?;
+        let mut step_sizes = self.step_sizes.lock().unwrap();
+        for symbol in info.symbols {
+            let lot_size = symbol.filters.iter().find(|filter| filter["filterType"] == "MARKET_LOT_SIZE").or_else(|| symbol.filters.iter().find(|filter| filter["filterType"] == "LOT_SIZE"));
+            if let Some(step_size) = lot_size.and_then(|filter| filter["stepSize"].as_str()).and_then(|step_size| step_size.parse().ok()) {
+                step_sizes.insert(symbol.symbol, step_size);
+            }
+        }
+        step_sizes.get(symbol).copied().ok_or_else(|| format!("binance doesn't list {}", symbol).into())
+    }
+}
+
+#[async_trait]
+impl Hedger for Binance {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    async fn position(&self, symbol: &str) -> HedgeResult<f64> {
+        let positions: Vec<PositionRisk> = self
+            .http
+            .get(format!("{}/fapi/v2/positionRisk?{}", self.url, self.signed(&format!("symbol={}", symbol))))
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // one entry in one-way mode
+        Ok(positions.iter().map(|position| position.position_amt.parse::<f64>().unwrap_or(0.0)).sum())
+    }
+
+    async fn trade(&self, symbol: &str, base_amount: f64) -> HedgeResult<Option<String>> {
+        let step_size = self.step_size(symbol).await?;
+        let steps = (base_amount.abs() / step_size).floor();
+        if steps == 0.0 {
+            return Ok(None);
+        }
+        // as many decimals as the step size has
+        let decimals = (-step_size.log10()).ceil().max(0.0) as usize;
+        let query = format!("symbol={}&side={}&type=MARKET&quantity={:.*}", symbol, if base_amount > 0.0 { "BUY" } else { "SELL" }, decimals, steps * step_size);
+        let order: OrderResponse = self
+            .http
+            .post(format!("{}/fapi/v1/order?{}", self.url, self.signed(&query)))
+            .header("X-MBX-APIKEY", &self.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(Some(order.order_id.to_string()))
+    }
+}
@@ -0,0 +1,456 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use solana_sdk::signature::Signature;
+use sqlx::{postgres::{PgPool, PgPoolOptions}, sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions}};
+use tokio::{sync::mpsc::{channel, error::TrySendError, Receiver, Sender}, task::JoinHandle};
+use tracing::{info, warn};
+
+use crate::{config::Config, risk::Candidate, watchdog};
+
+// records waiting to be written, more are dropped rather than holding up the stages
+const RECORD_CHANNEL_SIZE: usize = 4096;
+
+const SQLITE_SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS evaluations (
+        time_ms INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        user TEXT NOT NULL,
+        liquidation TEXT NOT NULL,
+        market_index INTEGER,
+        margin_ratio INTEGER NOT NULL,
+        expected_reward INTEGER NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS evaluations_time ON evaluations (time_ms)",
+    "CREATE TABLE IF NOT EXISTS transactions (
+        signature TEXT PRIMARY KEY,
+        sent_ms INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        outcome TEXT,
+        resolved_ms INTEGER,
+        latency_ms INTEGER,
+        fee_lamports INTEGER NOT NULL DEFAULT 0,
+        tip_lamports INTEGER NOT NULL DEFAULT 0,
+        reward INTEGER NOT NULL DEFAULT 0,
+        sol_price INTEGER NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS transactions_sent ON transactions (sent_ms)",
+    "CREATE TABLE IF NOT EXISTS attempts (
+        signature TEXT NOT NULL REFERENCES transactions (signature),
+        user TEXT NOT NULL,
+        liquidation TEXT NOT NULL,
+        market_index INTEGER,
+        margin_ratio INTEGER NOT NULL,
+        expected_reward INTEGER NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS attempts_user ON attempts (user)",
+];
+
+// columns added after a table was first created, which `CREATE TABLE IF NOT EXISTS` leaves out of an existing
+// database. Rows resolved before `tip_lamports` existed keep their tips in `fee_lamports` with a tip of 0, so
+// fee plus tip still adds up to what they spent, only not split.
+const SQLITE_MIGRATIONS: &[(&str, &str, &str)] = &[("transactions", "tip_lamports", "ALTER TABLE transactions ADD COLUMN tip_lamports INTEGER NOT NULL DEFAULT 0")];
+
+// the same tables, `user` being reserved in postgres
+const POSTGRES_SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS evaluations (
+        time_ms BIGINT NOT NULL,
+        slot BIGINT NOT NULL,
+        \"user\" TEXT NOT NULL,
+        liquidation TEXT NOT NULL,
+        market_index INTEGER,
+        margin_ratio BIGINT NOT NULL,
+        expected_reward BIGINT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS evaluations_time ON evaluations (time_ms)",
+    "CREATE TABLE IF NOT EXISTS transactions (
+        signature TEXT PRIMARY KEY,
+        sent_ms BIGINT NOT NULL,
+        slot BIGINT NOT NULL,
+        outcome TEXT,
+        resolved_ms BIGINT,
+        latency_ms BIGINT,
+        fee_lamports BIGINT NOT NULL DEFAULT 0,
+        tip_lamports BIGINT NOT NULL DEFAULT 0,
+        reward BIGINT NOT NULL DEFAULT 0,
+        sol_price BIGINT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS transactions_sent ON transactions (sent_ms)",
+    "CREATE TABLE IF NOT EXISTS attempts (
+        signature TEXT NOT NULL REFERENCES transactions (signature),
+        \"user\" TEXT NOT NULL,
+        liquidation TEXT NOT NULL,
+        market_index INTEGER,
+        margin_ratio BIGINT NOT NULL,
+        expected_reward BIGINT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS attempts_user ON attempts (\"user\")",
+    // added later, see `SQLITE_MIGRATIONS`
+    "ALTER TABLE transactions ADD COLUMN IF NOT EXISTS tip_lamports BIGINT NOT NULL DEFAULT 0",
+];
+
+/// A user found liquidatable, or one of the users a transaction liquidates
+pub struct Liquidatable {
+    pub user: String,
+    pub liquidation: &'static str,
+    pub market_index: Option<u16>,
+    pub margin_ratio: u128,
+    /// the liquidator's expected share of the liquidation fee, in quote precision
+    pub expected_reward: u64,
+}
+
+impl Liquidatable {
+    fn new(candidate: &Candidate) -> Liquidatable {
+        Liquidatable {
+            user: bs58::encode(candidate.user.to_bytes()).into_string(),
+            liquidation: candidate.liquidation.kind(),
+            market_index: candidate.liquidation.market_index(),
+            margin_ratio: candidate.margin_ratio,
+            expected_reward: candidate.reward.liquidator,
+        }
+    }
+
+    // the databases' integers are signed 64 bits
+    fn margin_ratio(&self) -> i64 {
+        self.margin_ratio.min(i64::MAX as u128) as i64
+    }
+}
+
+/// What gets written to the history
+pub enum Record {
+    /// the risk stage found a user under its liquidation margin ratio
+    Evaluated { time_ms: u64, slot: u64, candidate: Liquidatable },
+    /// a liquidation transaction was sent, `sol_price` valuing its fee in quote precision
+    Sent { time_ms: u64, slot: u64, signature: String, sol_price: u64, candidates: Vec<Liquidatable> },
+    /// a sent transaction "landed", "failed" or "expired", paying `fee_lamports` in signature and priority fees
+    /// and `tip_lamports` in tips, and earning `reward`
+    Resolved { time_ms: u64, signature: String, outcome: &'static str, latency_ms: u64, fee_lamports: u64, tip_lamports: u64, reward: u64 },
+}
+
+static RECORDS: OnceLock<Sender<Record>> = OnceLock::new();
+
+fn write(record: Record) {
+    if let Some(records) = RECORDS.get() {
+        if let Err(TrySendError::Full(_)) = records.try_send(record) {
+            warn!("dropping history record, too many queued");
+        }
+    }
+}
+
+/// Records the risk stage finding a candidate, if the history is kept
+pub fn evaluated(candidate: &Candidate) {
+    write(Record::Evaluated { time_ms: watchdog::now_ms(), slot: watchdog::last_slot(), candidate: Liquidatable::new(candidate) });
+}
+
+/// Records a liquidation transaction for the candidates being sent
+pub fn sent(candidates: &[Candidate], signature: &Signature) {
+    write(Record::Sent {
+        time_ms: watchdog::now_ms(),
+        slot: watchdog::last_slot(),
+        signature: signature.to_string(),
+        sol_price: candidates.first().map_or(0, |candidate| candidate.sol_price),
+        candidates: candidates.iter().map(Liquidatable::new).collect(),
+    });
+}
+
+/// Records how a sent transaction ended up
+pub fn resolved(signature: &Signature, outcome: &'static str, latency_ms: u64, fee_lamports: u64, tip_lamports: u64, reward: u64) {
+    write(Record::Resolved { time_ms: watchdog::now_ms(), signature: signature.to_string(), outcome, latency_ms, fee_lamports, tip_lamports, reward });
+}
+
+/// A row of the `evaluations` table
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Evaluation {
+    pub time_ms: i64,
+    pub slot: i64,
+    pub user: String,
+    pub liquidation: String,
+    pub market_index: Option<i32>,
+    pub margin_ratio: i64,
+    pub expected_reward: i64,
+}
+
+/// A row of the `transactions` table, the outcome and what follows None until the transaction resolved
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SentTransaction {
+    pub signature: String,
+    pub sent_ms: i64,
+    pub slot: i64,
+    pub outcome: Option<String>,
+    pub resolved_ms: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub fee_lamports: i64,
+    pub tip_lamports: i64,
+    pub reward: i64,
+    /// quote price of one SOL when it was sent
+    pub sol_price: i64,
+}
+
+/// A row of the `attempts` table, with when its transaction was sent
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Attempt {
+    pub signature: String,
+    pub sent_ms: i64,
+    pub user: String,
+    pub liquidation: String,
+    pub market_index: Option<i32>,
+    pub margin_ratio: i64,
+    pub expected_reward: i64,
+}
+
+/// Where the history is kept. The readers return the rows from `from_ms` up to but excluding `to_ms`, oldest
+/// first.
+#[async_trait]
+pub trait Store: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Writes the records in one transaction, all or none of them
+    async fn insert(&self, records: &[Record]) -> Result<(), sqlx::Error>;
+
+    async fn evaluations(&self, from_ms: i64, to_ms: i64) -> Result<Vec<Evaluation>, sqlx::Error>;
+
+    async fn transactions(&self, from_ms: i64, to_ms: i64) -> Result<Vec<SentTransaction>, sqlx::Error>;
+
+    async fn attempts(&self, from_ms: i64, to_ms: i64) -> Result<Vec<Attempt>, sqlx::Error>;
+}
+
+/// Keeps the history in a local sqlite database
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it and its tables if needed
+    pub async fn open(path: &str) -> Result<SqliteStore, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(path)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new().max_connections(1).connect_with(options).await?;
+        for statement in SQLITE_SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        // sqlite has no ADD COLUMN IF NOT EXISTS
+        for (table, column, statement) in SQLITE_MIGRATIONS {
+            let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)").bind(*table).fetch_all(&pool).await?;
+            if !columns.iter().any(|name| name == column) {
+                sqlx::query(statement).execute(&pool).await?;
+            }
+        }
+        Ok(SqliteStore { pool })
+    }
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn insert(&self, records: &[Record]) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        for record in records {
+            match record {
+                Record::Evaluated { time_ms, slot, candidate } => {
+                    sqlx::query("INSERT INTO evaluations (time_ms, slot, user, liquidation, market_index, margin_ratio, expected_reward) VALUES (?, ?, ?, ?, ?, ?, ?)")
+                        .bind(*time_ms as i64)
+                        .bind(*slot as i64)
+                        .bind(&candidate.user)
+                        .bind(candidate.liquidation)
+                        .bind(candidate.market_index.map(i32::from))
+                        .bind(candidate.margin_ratio())
+                        .bind(candidate.expected_reward as i64)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+                Record::Sent { time_ms, slot, signature, sol_price, candidates } => {
+                    sqlx::query("INSERT OR IGNORE INTO transactions (signature, sent_ms, slot, sol_price) VALUES (?, ?, ?, ?)")
+                        .bind(signature)
+                        .bind(*time_ms as i64)
+                        .bind(*slot as i64)
+                        .bind(*sol_price as i64)
+                        .execute(&mut *transaction)
+                        .await?;
+                    for candidate in candidates {
+                        sqlx::query("INSERT INTO attempts (signature, user, liquidation, market_index, margin_ratio, expected_reward) VALUES (?, ?, ?, ?, ?, ?)")
+                            .bind(signature)
+                            .bind(&candidate.user)
+                            .bind(candidate.liquidation)
+                            .bind(candidate.market_index.map(i32::from))
+                            .bind(candidate.margin_ratio())
+                            .bind(candidate.expected_reward as i64)
+                            .execute(&mut *transaction)
+                            .await?;
+                    }
+                }
+                Record::Resolved { time_ms, signature, outcome, latency_ms, fee_lamports, tip_lamports, reward } => {
+                    sqlx::query("UPDATE transactions SET outcome = ?, resolved_ms = ?, latency_ms = ?, fee_lamports = ?, tip_lamports = ?, reward = ? WHERE signature = ?")
+                        .bind(*outcome)
+                        .bind(*time_ms as i64)
+                        .bind(*latency_ms as i64)
+                        .bind(*fee_lamports as i64)
+                        .bind(*tip_lamports as i64)
+                        .bind(*reward as i64)
+                        .bind(signature)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+            }
+        }
+        transaction.commit().await
+    }
+
+    async fn evaluations(&self, from_ms: i64, to_ms: i64) -> Result<Vec<Evaluation>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM evaluations WHERE time_ms >= ? AND time_ms < ? ORDER BY time_ms").bind(from_ms).bind(to_ms).fetch_all(&self.pool).await
+    }
+
+    async fn transactions(&self, from_ms: i64, to_ms: i64) -> Result<Vec<SentTransaction>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM transactions WHERE sent_ms >= ? AND sent_ms < ? ORDER BY sent_ms").bind(from_ms).bind(to_ms).fetch_all(&self.pool).await
+    }
+
+    async fn attempts(&self, from_ms: i64, to_ms: i64) -> Result<Vec<Attempt>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT attempts.signature, transactions.sent_ms, attempts.user, attempts.liquidation, attempts.market_index, attempts.margin_ratio, attempts.expected_reward
+            FROM attempts JOIN transactions ON transactions.signature = attempts.signature
+            WHERE transactions.sent_ms >= ? AND transactions.sent_ms < ? ORDER BY transactions.sent_ms",
+        )
+        .bind(from_ms)
+        .bind(to_ms)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+/// Keeps the history in a postgres database, for deployments of several instances that want it in one place
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    /// Connects to the database at `url`, creating the tables if needed
+    pub async fn connect(url: &str) -> Result<PostgresStore, sqlx::Error> {
+        let pool = PgPoolOptions::new().max_connections(2).connect(url).await?;
+        for statement in POSTGRES_SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        Ok(PostgresStore { pool })
+    }
+}
+
+#[async_trait]
+impl Store for PostgresStore {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn insert(&self, records: &[Record]) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        for record in records {
+            match record {
+                Record::Evaluated { time_ms, slot, candidate } => {
+                    sqlx::query("INSERT INTO evaluations (time_ms, slot, \"user\", liquidation, market_index, margin_ratio, expected_reward) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+                        .bind(*time_ms as i64)
+                        .bind(*slot as i64)
+                        .bind(&candidate.user)
+                        .bind(candidate.liquidation)
+                        .bind(candidate.market_index.map(i32::from))
+                        .bind(candidate.margin_ratio())
+                        .bind(candidate.expected_reward as i64)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+                Record::Sent { time_ms, slot, signature, sol_price, candidates } => {
+                    sqlx::query("INSERT INTO transactions (signature, sent_ms, slot, sol_price) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING")
+                        .bind(signature)
+                        .bind(*time_ms as i64)
+                        .bind(*slot as i64)
+                        .bind(*sol_price as i64)
+                        .execute(&mut *transaction)
+                        .await?;
+                    for candidate in candidates {
+                        sqlx::query("INSERT INTO attempts (signature, \"user\", liquidation, market_index, margin_ratio, expected_reward) VALUES ($1, $2, $3, $4, $5, $6)")
+                            .bind(signature)
+                            .bind(&candidate.user)
+                            .bind(candidate.liquidation)
+                            .bind(candidate.market_index.map(i32::from))
+                            .bind(candidate.margin_ratio())
+                            .bind(candidate.expected_reward as i64)
+                            .execute(&mut *transaction)
+                            .await?;
+                    }
+                }
+                Record::Resolved { time_ms, signature, outcome, latency_ms, fee_lamports, tip_lamports, reward } => {
+                    sqlx::query("UPDATE transactions SET outcome = $1, resolved_ms = $2, latency_ms = $3, fee_lamports = $4, tip_lamports = $5, reward = $6 WHERE signature = $7")
+                        .bind(*outcome)
+                        .bind(*time_ms as i64)
+                        .bind(*latency_ms as i64)
+                        .bind(*fee_lamports as i64)
+                        .bind(*tip_lamports as i64)
+                        .bind(*reward as i64)
+                        .bind(signature)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+            }
+        }
+        transaction.commit().await
+    }
+
+    async fn evaluations(&self, from_ms: i64, to_ms: i64) -> Result<Vec<Evaluation>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM evaluations WHERE time_ms >= $1 AND time_ms < $2 ORDER BY time_ms").bind(from_ms).bind(to_ms).fetch_all(&self.pool).await
+    }
+
+    async fn transactions(&self, from_ms: i64, to_ms: i64) -> Result<Vec<SentTransaction>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM transactions WHERE sent_ms >= $1 AND sent_ms < $2 ORDER BY sent_ms").bind(from_ms).bind(to_ms).fetch_all(&self.pool).await
+    }
+
+    async fn attempts(&self, from_ms: i64, to_ms: i64) -> Result<Vec<Attempt>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT attempts.signature, transactions.sent_ms, attempts.\"user\", attempts.liquidation, attempts.market_index, attempts.margin_ratio, attempts.expected_reward
+            FROM attempts JOIN transactions ON transactions.signature = attempts.signature
+            WHERE transactions.sent_ms >= $1 AND transactions.sent_ms < $2 ORDER BY transactions.sent_ms",
+        )
+        .bind(from_ms)
+        .bind(to_ms)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+/// Opens the configured store, postgres if both are configured and None if neither is
+pub async fn store(config: &Config) -> Option<Box<dyn Store>> {
+    if let Some(url) = &config.history_postgres_url {
+        return Some(Box::new(PostgresStore::connect(url).await.expect("failed to connect to the history database")));
+    }
+    let path = config.history_path.as_ref()?;
+    Some(Box::new(SqliteStore::open(path).await.expect("failed to open the history database")))
+}
+
+/// Keeps every liquidatable user found, transaction sent, its result, the fee it paid and the reward it earned
+/// in a store, to audit what the liquidator did after the fact
+pub struct History {
+    pub store: Box<dyn Store>,
+}
+
+impl History {
+    /// Writes the records the stages hand over in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        let (sender, receiver) = channel(RECORD_CHANNEL_SIZE);
+        if RECORDS.set(sender).is_err() {
+            warn!("the history was already set up");
+        }
+        info!(store = self.store.name(), "keeping liquidation history");
+        tokio::spawn(self.persist(receiver))
+    }
+
+    async fn persist(self, mut records: Receiver<Record>) {
+        while let Some(record) = records.recv().await {
+            // whatever queued up meanwhile goes in the same transaction
+            let mut batch = vec![record];
+            while let Ok(record) = records.try_recv() {
+                batch.push(record);
+            }
+            if let Err(err) = self.store.insert(&batch).await {
+                warn!(store = self.store.name(), records = batch.len(), error = %err, "failed to write liquidation history");
+            }
+        }
+    }
+}
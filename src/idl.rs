@@ -0,0 +1,201 @@
+use std::{collections::HashMap, error::Error, io::Read, mem::size_of};
+
+use anchor_lang::AnchorSerialize;
+use clearing_house::state::{market::Markets, user::UserPositions};
+use flate2::read::ZlibDecoder;
+use serde::Deserialize;
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{cache::AccountCache, rpc::RpcPool};
+
+/// The parts of an anchor IDL needed to work out account layouts
+#[derive(Deserialize)]
+pub struct Idl {
+    #[serde(default)]
+    pub accounts: Vec<TypeDef>,
+    #[serde(default)]
+    pub types: Vec<TypeDef>,
+}
+
+#[derive(Deserialize)]
+pub struct TypeDef {
+    pub name: String,
+    /// newer idls only list the discriminator under `accounts`, with the layout under `types`
+    #[serde(rename = "type")]
+    pub ty: Option<TypeDefTy>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TypeDefTy {
+    Struct { fields: Vec<Field> },
+    Enum { variants: Vec<Variant> },
+}
+
+#[derive(Deserialize)]
+pub struct Field {
+    pub name: String,
+    /// `"u64"`, `{"array": [ty, len]}`, `{"defined": "Name"}` or `{"defined": {"name": "Name"}}`, ...
+    #[serde(rename = "type")]
+    pub ty: Value,
+}
+
+#[derive(Deserialize)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Option<Value>,
+}
+
+/// Where anchor keeps a program's IDL: a seeded account off the program's signer pda
+pub fn idl_address(program_id: &Pubkey) -> Pubkey {
+    let base = Pubkey::find_program_address(&[], program_id).0;
+    Pubkey::create_with_seed(&base, "anchor:idl", program_id).unwrap()
+}
+
+/// Fetches and decompresses the IDL the program published on chain
+pub async fn fetch(rpc: &RpcPool, program_id: &Pubkey) -> Result<Idl, Box<dyn Error + Send + Sync>> {
+    let address = idl_address(program_id);
+    let account = rpc.call(|client| async move { client.get_account(&address).await }).await?;
+
+    // discriminator, authority, then the length prefixed zlib compressed json
+    let data = &account.data;
+    if data.len() < 44 {
+        return Err(format!("idl account {} is too short", address).into());
+    }
+    let len = u32::from_le_bytes(data[40..44].try_into().unwrap()) as usize;
+    let compressed = data.get(44..44 + len).ok_or("idl account data is truncated")?;
+
+    let mut json = String::new();
+    ZlibDecoder::new(compressed).read_to_string(&mut json)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+impl Idl {
+    /// Serialized size of a fixed size account or type, without the account discriminator. None if the type
+    /// is unknown or variable length.
+    pub fn size_of(&self, name: &str) -> Option<usize> {
+        size_of_def(&self.definitions(), self.definition(name)?)
+    }
+
+    /// Byte offset of a possibly nested field, e.g. `amm.historical_oracle_data.last_oracle_price`, from the
+    /// start of the type, without the account discriminator. Field names match whether the idl spells them in
+    /// camel or snake case.
+    pub fn offset_of(&self, name: &str, path: &str) -> Option<usize> {
+        let definitions = self.definitions();
+        let mut def = self.definition(name)?;
+        let mut offset = 0;
+        let mut fields = path.split('.').peekable();
+        while let Some(field_name) = fields.next() {
+            let field_types = match def.ty.as_ref()? {
+                TypeDefTy::Struct { fields } => fields,
+                TypeDefTy::Enum { .. } => return None,
+            };
+            let mut field_ty = None;
+            for field in field_types {
+                if normalize(&field.name) == normalize(field_name) {
+                    field_ty = Some(&field.ty);
+                    break;
+                }
+                offset += size_of_ty(&definitions, &field.ty)?;
+            }
+
+            if fields.peek().is_some() {
+                def = *definitions.get(defined_name(field_ty?)?.as_str())?;
+            } else {
+                field_ty?;
+            }
+        }
+
+        Some(offset)
+    }
+
+    /// Length of a fixed size array field
+    pub fn array_len(&self, name: &str, field_name: &str) -> Option<usize> {
+        match self.definition(name)?.ty.as_ref()? {
+            TypeDefTy::Struct { fields } => {
+                let field = fields.iter().find(|field| normalize(&field.name) == normalize(field_name))?;
+                field.ty.get("array")?.get(1)?.as_u64().map(|len| len as usize)
+            }
+            TypeDefTy::Enum { .. } => None,
+        }
+    }
+
+    fn definition(&self, name: &str) -> Option<&TypeDef> {
+        self.definitions().get(name).copied()
+    }
+
+    fn definitions(&self) -> HashMap<&str, &TypeDef> {
+        self.accounts.iter().chain(self.types.iter()).filter(|def| def.ty.is_some()).map(|def| (def.name.as_str(), def)).collect()
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| *c != '_').flat_map(|c| c.to_lowercase()).collect()
+}
+
+// the name inside `{"defined": "Name"}` or `{"defined": {"name": "Name"}}`
+fn defined_name(ty: &Value) -> Option<String> {
+    match ty.get("defined")? {
+        Value::String(name) => Some(name.clone()),
+        defined => defined.get("name")?.as_str().map(|name| name.to_string()),
+    }
+}
+
+fn size_of_def(definitions: &HashMap<&str, &TypeDef>, def: &TypeDef) -> Option<usize> {
+    match def.ty.as_ref()? {
+        TypeDefTy::Struct { fields } => fields.iter().map(|field| size_of_ty(definitions, &field.ty)).sum(),
+        // only fieldless enums have a fixed size, borsh writes them as a single byte
+        TypeDefTy::Enum { variants } if variants.iter().all(|variant| variant.fields.is_none()) => Some(1),
+        TypeDefTy::Enum { .. } => None,
+    }
+}
+
+fn size_of_ty(definitions: &HashMap<&str, &TypeDef>, ty: &Value) -> Option<usize> {
+    match ty {
+        Value::String(primitive) => match primitive.as_str() {
+            "bool" | "u8" | "i8" => Some(1),
+            "u16" | "i16" => Some(2),
+            "u32" | "i32" | "f32" => Some(4),
+            "u64" | "i64" | "f64" => Some(8),
+            "u128" | "i128" => Some(16),
+            "publicKey" | "pubkey" => Some(32),
+            _ => None,
+        },
+        Value::Object(object) => {
+            if let Some(Value::Array(array)) = object.get("array") {
+                let len = array.get(1)?.as_u64()? as usize;
+                return Some(size_of_ty(definitions, array.get(0)?)? * len);
+            }
+            size_of_def(definitions, definitions.get(defined_name(ty)?.as_str())?)
+        }
+        _ => None,
+    }
+}
+
+/// Compares the layouts the liquidator was compiled against with the ones in the on-chain IDL, returning a
+/// description of every account type that differs. Borsh accounts are measured by reserializing a cached
+/// account, zero copy ones by their in-memory size.
+pub fn check_layouts(idl: &Idl, cache: &AccountCache) -> Vec<String> {
+    let mut local = vec![
+        ("Markets", Some(size_of::<Markets>())),
+        ("UserPositions", Some(size_of::<UserPositions>())),
+        ("State", cache.state.1.try_to_vec().ok().map(|data| data.len())),
+    ];
+    if let Some(user) = cache.users.values().next() {
+        local.push(("User", user.try_to_vec().ok().map(|data| data.len())));
+    }
+
+    let mut mismatches = vec![];
+    for (name, local_size) in local {
+        match (local_size, idl.size_of(name)) {
+            (Some(local_size), Some(idl_size)) if local_size != idl_size => {
+                mismatches.push(format!("{} is {} bytes on chain but {} bytes in this build", name, idl_size, local_size))
+            }
+            (_, None) => mismatches.push(format!("{} is missing from the idl or has no fixed size", name)),
+            _ => {}
+        }
+    }
+
+    mismatches
+}
@@ -0,0 +1,171 @@
+use std::{collections::VecDeque, sync::{atomic::Ordering, Arc}, time::Duration};
+
+use async_trait::async_trait;
+use serde_json::json;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use tokio::{task::JoinHandle, time::{sleep, Instant}};
+use tracing::{info, warn};
+
+use crate::{config::Config, health, metrics::METRICS, notify::NotifyResult, rpc::RpcPool};
+
+// how often the incident conditions are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An incident management service that pages whoever is on call
+#[async_trait]
+pub trait Pager: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Opens the incident for `key`, or adds to the open one
+    async fn trigger(&self, key: &str, summary: &str) -> NotifyResult;
+
+    /// Closes the incident for `key`
+    async fn resolve(&self, key: &str) -> NotifyResult;
+}
+
+/// Opens pagerduty incidents through the events v2 api
+pub struct PagerDuty {
+    pub routing_key: String,
+    pub http: reqwest::Client,
+}
+
+impl PagerDuty {
+    async fn enqueue(&self, event: serde_json::Value) -> NotifyResult {
+        self.http.post("https://events.pagerduty.com/v2/enqueue").json(&event).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Pager for PagerDuty {
+    fn name(&self) -> &'static str {
+        "pagerduty"
+    }
+
+    async fn trigger(&self, key: &str, summary: &str) -> NotifyResult {
+        self.enqueue(json!({
+            "routing_key": self.routing_key,
+            "event_action": "trigger",
+            "dedup_key": key,
+            "payload": { "summary": summary, "source": "drift-liquidator", "severity": "critical" },
+        }))
+        .await
+    }
+
+    async fn resolve(&self, key: &str) -> NotifyResult {
+        self.enqueue(json!({ "routing_key": self.routing_key, "event_action": "resolve", "dedup_key": key })).await
+    }
+}
+
+/// Opens opsgenie alerts, deduplicated by their alias
+pub struct Opsgenie {
+    pub api_key: String,
+    pub http: reqwest::Client,
+}
+
+#[async_trait]
+impl Pager for Opsgenie {
+    fn name(&self) -> &'static str {
+        "opsgenie"
+    }
+
+    async fn trigger(&self, key: &str, summary: &str) -> NotifyResult {
+        self.http
+            .post("https://api.opsgenie.com/v2/alerts")
+            .header("Authorization", format!("GenieKey {}", self.api_key))
+            .json(&json!({ "message": summary, "alias": key, "source": "drift-liquidator", "priority": "P1" }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn resolve(&self, key: &str) -> NotifyResult {
+        self.http
+            .post(format!("https://api.opsgenie.com/v2/alerts/{}/close?identifierType=alias", key))
+            .header("Authorization", format!("GenieKey {}", self.api_key))
+            .json(&json!({ "source": "drift-liquidator" }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+// counters sampled on every check, to tell how many liquidations landed over the window
+struct Sample {
+    at: Instant,
+    candidates: u64,
+    attempts: u64,
+    landed: u64,
+}
+
+/// Pages through every pager while a failure is sustained, resolving the incident once it clears: the risk stage
+/// going `incident_stall_secs` without finishing a round of updates, the payer's balance under
+/// `low_balance_alert_lamports`, or none of the liquidations attempted over `incident_window_secs` landing
+pub fn spawn(pagers: Vec<Box<dyn Pager>>, rpc: Arc<RpcPool>, payer: Pubkey, config: &Config) -> JoinHandle<()> {
+    let stall = Duration::from_secs(config.incident_stall_secs);
+    let window = Duration::from_secs(config.incident_window_secs);
+    let min_balance_lamports = config.low_balance_alert_lamports;
+    tokio::spawn(async move {
+        let mut open: Vec<&'static str> = vec![];
+        let mut samples: VecDeque<Sample> = VecDeque::new();
+        loop {
+            sleep(CHECK_INTERVAL).await;
+            let mut failing: Vec<(&'static str, String)> = vec![];
+
+            // none before the startup scan is through, which can take a while
+            if let Some(age) = health::since_last_iteration().filter(|age| *age >= stall) {
+                failing.push(("drift-liquidator-stalled", format!("drift-liquidator hasn't processed an update in {:.0?}", age)));
+            }
+
+            match rpc.call(|client| async move { client.get_balance(&payer).await }).await {
+                Ok(balance) if balance < min_balance_lamports => {
+                    failing.push(("drift-liquidator-low-balance", format!("drift-liquidator payer holds {:.4} SOL", balance as f64 / LAMPORTS_PER_SOL as f64)));
+                }
+                Ok(_) => {}
+                // unknown, the incident stays however it was
+                Err(_) if open.contains(&"drift-liquidator-low-balance") => failing.push(("drift-liquidator-low-balance", String::new())),
+                Err(_) => {}
+            }
+
+            samples.push_back(Sample {
+                at: Instant::now(),
+                candidates: METRICS.candidates.load(Ordering::Relaxed),
+                attempts: METRICS.landed_liquidations() + METRICS.failed_liquidations.load(Ordering::Relaxed) + METRICS.expired_liquidations.load(Ordering::Relaxed),
+                landed: METRICS.landed_liquidations(),
+            });
+            while samples.len() > 1 && samples[1].at.elapsed() >= window {
+                samples.pop_front();
+            }
+            let (first, last) = (&samples[0], &samples[samples.len() - 1]);
+            let attempts = last.attempts - first.attempts;
+            if first.at.elapsed() >= window && last.candidates > first.candidates && attempts > 0 && last.landed == first.landed {
+                failing.push(("drift-liquidator-not-landing", format!("none of the last {} drift-liquidator liquidations landed in {:.0?}", attempts, window)));
+            }
+
+            for (key, summary) in &failing {
+                if summary.is_empty() {
+                    continue;
+                }
+                if !open.contains(key) {
+                    warn!(incident = *key, %summary, "opening incident");
+                }
+                for pager in &pagers {
+                    if let Err(err) = pager.trigger(key, summary).await {
+                        warn!(pager = pager.name(), incident = *key, error = %err, "failed to trigger incident");
+                    }
+                }
+            }
+            for key in open.iter().filter(|key| !failing.iter().any(|(failing, _)| failing == *key)) {
+                info!(incident = *key, "resolving incident");
+                for pager in &pagers {
+                    if let Err(err) = pager.resolve(key).await {
+                        warn!(pager = pager.name(), incident = *key, error = %err, "failed to resolve incident");
+                    }
+                }
+            }
+            open = failing.into_iter().map(|(key, _)| key).collect();
+        }
+    })
+}
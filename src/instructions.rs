@@ -0,0 +1,215 @@
+use clearing_house::state::state::State;
+use solana_sdk::{hash, instruction::{AccountMeta, Instruction}, pubkey::Pubkey, system_program, sysvar};
+
+use crate::program;
+
+/// Anchor's instruction discriminator: the first 8 bytes of sha256("global:<instruction name>")
+pub fn sighash(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash::hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+/// The clearing house user account of an authority, a pda of the authority
+pub fn user_address(authority: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"user", authority.as_ref()], &program::v1()).0
+}
+
+/// Creates the authority's user account, paid for by the authority, along with its positions account at
+/// `user_positions`, a fresh keypair that has to sign as well
+pub fn initialize_user(state: &(Pubkey, State), authority: &Pubkey, user_positions: &Pubkey) -> Instruction {
+    let (user, user_nonce) = Pubkey::find_program_address(&[b"user", authority.as_ref()], &program::v1());
+    let mut data = sighash("initialize_user").to_vec();
+    data.push(user_nonce);
+    // no whitelist token
+    data.push(0);
+
+    Instruction {
+        program_id: program::v1(),
+        accounts: vec![
+            AccountMeta::new(user, false),
+            AccountMeta::new_readonly(state.0, false),
+            AccountMeta::new(*user_positions, true),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    }
+}
+
+/// Liquidates `user`, paying the reward into the liquidator's drift account. `oracles` are the oracles of
+/// every market the user has a position in.
+pub fn liquidate(state: &(Pubkey, State), liquidator: &Pubkey, liquidator_drift_account: &Pubkey, user: &Pubkey, user_positions: &Pubkey, oracles: &[Pubkey]) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(state.0, false),
+        AccountMeta::new(*liquidator, true),
+        AccountMeta::new(*liquidator_drift_account, false),
+        AccountMeta::new(*user, false),
+        AccountMeta::new(state.1.collateral_vault, false),
+        AccountMeta::new_readonly(state.1.collateral_vault_authority, false),
+        AccountMeta::new(state.1.insurance_vault, false),
+        AccountMeta::new_readonly(state.1.insurance_vault_authority, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(state.1.markets, false),
+        AccountMeta::new(*user_positions, false),
+        AccountMeta::new(state.1.trade_history, false),
+        AccountMeta::new(state.1.liquidation_history, false),
+        AccountMeta::new(state.1.funding_payment_history, false),
+    ];
+
+    for oracle in oracles {
+        accounts.push(AccountMeta::new_readonly(*oracle, false));
+    }
+
+    Instruction {
+        program_id: program::v1(),
+        accounts,
+        data: sighash("liquidate").to_vec(),
+    }
+}
+
+/// Settles the user's outstanding funding payments into its collateral
+pub fn settle_funding_payment(state: &(Pubkey, State), user: &Pubkey, user_positions: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: program::v1(),
+        accounts: vec![
+            AccountMeta::new_readonly(state.0, false),
+            AccountMeta::new(*user, false),
+            AccountMeta::new_readonly(state.1.markets, false),
+            AccountMeta::new(*user_positions, false),
+            AccountMeta::new(state.1.funding_payment_history, false),
+        ],
+        data: sighash("settle_funding_payment").to_vec(),
+    }
+}
+
+/// Deposits `amount` of usdc from `user_collateral_account` into the user's collateral, signed by its authority
+pub fn deposit_collateral(state: &(Pubkey, State), user: &Pubkey, authority: &Pubkey, user_positions: &Pubkey, user_collateral_account: &Pubkey, amount: u64) -> Instruction {
+    let mut data = sighash("deposit_collateral").to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+
+    Instruction {
+        program_id: program::v1(),
+        accounts: vec![
+            AccountMeta::new_readonly(state.0, false),
+            AccountMeta::new(*user, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(state.1.collateral_vault, false),
+            AccountMeta::new(*user_collateral_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(state.1.markets, false),
+            AccountMeta::new(*user_positions, false),
+            AccountMeta::new(state.1.funding_payment_history, false),
+            AccountMeta::new(state.1.deposit_history, false),
+        ],
+        data,
+    }
+}
+
+/// Withdraws `amount` of usdc from the user's collateral into `user_collateral_account`, signed by its authority.
+/// The program refuses withdrawals that would leave the user under its initial margin.
+pub fn withdraw_collateral(state: &(Pubkey, State), user: &Pubkey, authority: &Pubkey, user_positions: &Pubkey, user_collateral_account: &Pubkey, amount: u64) -> Instruction {
+    let mut data = sighash("withdraw_collateral").to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+
+    Instruction {
+        program_id: program::v1(),
+        accounts: vec![
+            AccountMeta::new_readonly(state.0, false),
+            AccountMeta::new(*user, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(state.1.collateral_vault, false),
+            AccountMeta::new_readonly(state.1.collateral_vault_authority, false),
+            AccountMeta::new(state.1.insurance_vault, false),
+            AccountMeta::new_readonly(state.1.insurance_vault_authority, false),
+            AccountMeta::new(*user_collateral_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(state.1.markets, false),
+            AccountMeta::new(*user_positions, false),
+            AccountMeta::new(state.1.funding_payment_history, false),
+            AccountMeta::new(state.1.deposit_history, false),
+        ],
+        data,
+    }
+}
+
+/// Closes the user's whole position in the market against its amm, at whatever price that takes
+pub fn close_position(state: &(Pubkey, State), user: &Pubkey, authority: &Pubkey, user_positions: &Pubkey, market_index: u64, oracle: &Pubkey) -> Instruction {
+    let mut data = sighash("close_position").to_vec();
+    data.extend_from_slice(&market_index.to_le_bytes());
+    // no discount token or referrer
+    data.extend_from_slice(&[0, 0]);
+
+    Instruction {
+        program_id: program::v1(),
+        accounts: vec![
+            AccountMeta::new_readonly(state.0, false),
+            AccountMeta::new(*user, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(state.1.markets, false),
+            AccountMeta::new(*user_positions, false),
+            AccountMeta::new(state.1.trade_history, false),
+            AccountMeta::new(state.1.funding_payment_history, false),
+            AccountMeta::new(state.1.funding_rate_history, false),
+            AccountMeta::new_readonly(*oracle, false),
+        ],
+        data,
+    }
+}
+
+/// Trades `quote_asset_amount` of the market against its amm, short or long, refused if the price goes past
+/// `limit_price` (mark price precision). Against the direction of an open position it reduces the position.
+#[allow(clippy::too_many_arguments)]
+pub fn open_position(
+    state: &(Pubkey, State),
+    user: &Pubkey,
+    authority: &Pubkey,
+    user_positions: &Pubkey,
+    market_index: u64,
+    short: bool,
+    quote_asset_amount: u128,
+    limit_price: u128,
+    oracle: &Pubkey,
+) -> Instruction {
+    let mut data = sighash("open_position").to_vec();
+    data.push(short as u8);
+    data.extend_from_slice(&quote_asset_amount.to_le_bytes());
+    data.extend_from_slice(&market_index.to_le_bytes());
+    data.extend_from_slice(&limit_price.to_le_bytes());
+    // no discount token or referrer
+    data.extend_from_slice(&[0, 0]);
+
+    Instruction {
+        program_id: program::v1(),
+        accounts: vec![
+            AccountMeta::new_readonly(state.0, false),
+            AccountMeta::new(*user, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(state.1.markets, false),
+            AccountMeta::new(*user_positions, false),
+            AccountMeta::new(state.1.trade_history, false),
+            AccountMeta::new(state.1.funding_payment_history, false),
+            AccountMeta::new(state.1.funding_rate_history, false),
+            AccountMeta::new_readonly(*oracle, false),
+        ],
+        data,
+    }
+}
+
+/// Updates a market's funding rate from its oracle, permissionless once the funding period has passed
+pub fn update_funding_rate(state: &(Pubkey, State), market_index: u64, oracle: &Pubkey) -> Instruction {
+    let mut data = sighash("update_funding_rate").to_vec();
+    data.extend_from_slice(&market_index.to_le_bytes());
+
+    Instruction {
+        program_id: program::v1(),
+        accounts: vec![
+            AccountMeta::new_readonly(state.0, false),
+            AccountMeta::new(state.1.markets, false),
+            AccountMeta::new_readonly(*oracle, false),
+            AccountMeta::new(state.1.funding_rate_history, false),
+        ],
+        data,
+    }
+}
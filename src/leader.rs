@@ -0,0 +1,101 @@
+use std::{sync::atomic::{AtomicBool, Ordering}, time::Duration};
+
+use redis::{aio::ConnectionManager, Client, RedisResult};
+use tokio::{task::JoinHandle, time::{sleep, timeout}};
+use tracing::{info, warn};
+
+use crate::{notify::{self, Event}, pause};
+
+// what a standby instance holds sends back for
+const PAUSE_REASON: &str = "standing by, another instance is the leader";
+// backoff while redis can't be reached at startup
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(5);
+// extends the lock only while this instance still holds it, a lapsed one may already be another's
+const RENEW: &str = r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("PEXPIRE", KEYS[1], ARGV[2]) else return 0 end"#;
+
+// whether this instance may send, true without an election
+static LEADING: AtomicBool = AtomicBool::new(true);
+
+/// Whether this instance is the leader, always without `leader_lock_url`
+pub fn leading() -> bool {
+    LEADING.load(Ordering::Relaxed)
+}
+
+/// Elects one of several instances running warm against the same liquidator account to send its transactions,
+/// the others following every account and pausing only their sends. The leader holds the redis lock `key`, set
+/// to its `instance_id` for `ttl` and renewed every third of it, while the others try to take it as often. A
+/// leader that fails to renew steps down at once, before the lock could lapse to another instance, so failing
+/// over takes a little over `ttl`.
+pub struct LeaderElection {
+    /// the redis server at `leader_lock_url`
+    pub client: Client,
+    pub key: String,
+    pub instance_id: String,
+    pub ttl: Duration,
+}
+
+impl LeaderElection {
+    /// Starts out standing by, sends are held back until this instance has won the lock
+    pub fn spawn(self) -> JoinHandle<()> {
+        LEADING.store(false, Ordering::Relaxed);
+        pause::pause(PAUSE_REASON);
+        tokio::spawn(async move {
+            let mut connection = loop {
+                match ConnectionManager::new(self.client.clone()).await {
+                    Ok(connection) => break connection,
+                    Err(err) => {
+                        warn!(error = %err, "failed to connect to redis for the leader election");
+                        sleep(CONNECT_RETRY_DELAY).await;
+                    }
+                }
+            };
+            info!(key = self.key.as_str(), instance = self.instance_id.as_str(), "standing by for the leader lock");
+
+            let renew = redis::Script::new(RENEW);
+            let interval = self.ttl / 3;
+            loop {
+                let leading = leading();
+                let attempt = async {
+                    if leading {
+                        renew.key(&self.key).arg(&self.instance_id).arg(self.ttl.as_millis() as u64).invoke_async::<_, i64>(&mut connection).await.map(|renewed| renewed == 1)
+                    } else {
+                        self.acquire(&mut connection).await
+                    }
+                };
+                // an answer later than this may come after the lock lapsed
+                let held = match timeout(interval, attempt).await {
+                    Ok(Ok(held)) => held,
+                    Ok(Err(err)) => {
+                        warn!(error = %err, leading, "failed to reach redis for the leader lock");
+                        false
+                    }
+                    Err(_) => {
+                        warn!(leading, "timed out on redis for the leader lock");
+                        false
+                    }
+                };
+                match (leading, held) {
+                    (false, true) => {
+                        LEADING.store(true, Ordering::Relaxed);
+                        pause::resume(PAUSE_REASON);
+                        info!(instance = self.instance_id.as_str(), "took the leader lock, sending liquidations");
+                        notify::notify(Event::info(format!("instance {} is the leader now and sends liquidations", self.instance_id)));
+                    }
+                    (true, false) => {
+                        LEADING.store(false, Ordering::Relaxed);
+                        pause::pause(PAUSE_REASON);
+                        warn!(instance = self.instance_id.as_str(), "lost the leader lock, standing by");
+                        notify::notify(Event::critical(format!("instance {} lost the leader lock and stands by", self.instance_id)));
+                    }
+                    _ => {}
+                }
+                sleep(interval).await;
+            }
+        })
+    }
+
+    async fn acquire(&self, connection: &mut ConnectionManager) -> RedisResult<bool> {
+        let set: Option<String> = redis::cmd("SET").arg(&self.key).arg(&self.instance_id).arg("NX").arg("PX").arg(self.ttl.as_millis() as u64).query_async(connection).await?;
+        Ok(set.is_some())
+    }
+}
@@ -0,0 +1,99 @@
+use clearing_house::math::constants::MARGIN_PRECISION;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{cache::AccountCache, config::{OutputFormat, ScanArgs}, metrics::QUOTE_PRECISION, output, risk, v2::{self, MarginType}};
+
+/// A user with open positions and how close it is to liquidation, dollar amounts in quote
+#[derive(Serialize)]
+pub struct Entry {
+    pub user: String,
+    pub authority: String,
+    pub collateral: f64,
+    /// value of the perp positions, plus the borrows on v2
+    pub notional: f64,
+    /// as the protocol measures it: collateral over notional on v1, collateral over the maintenance requirement
+    /// on v2
+    pub margin_ratio: f64,
+    /// the margin ratio the user is liquidatable at or below
+    pub liquidation_margin_ratio: f64,
+    /// collateral the user can lose before it is liquidatable, negative once it is
+    pub distance: f64,
+    /// how far the margin ratio is above the liquidation margin ratio, in percent of it
+    pub distance_percent: f64,
+}
+
+impl Entry {
+    fn new(user: &Pubkey, authority: &Pubkey, collateral: i128, notional: i128, margin_ratio: f64, liquidation_margin_ratio: f64, distance: i128) -> Entry {
+        Entry {
+            user: bs58::encode(user.to_bytes()).into_string(),
+            authority: bs58::encode(authority.to_bytes()).into_string(),
+            collateral: collateral as f64 / QUOTE_PRECISION,
+            notional: notional as f64 / QUOTE_PRECISION,
+            margin_ratio,
+            liquidation_margin_ratio,
+            distance: distance as f64 / QUOTE_PRECISION,
+            distance_percent: (margin_ratio / liquidation_margin_ratio - 1.0) * 100.0,
+        }
+    }
+}
+
+/// Every v1 user with open positions, funding settled, riskiest first
+pub fn v1(cache: &AccountCache) -> Vec<Entry> {
+    let (markets, state) = (&cache.markets.1, &cache.state.1);
+    let liquidation_margin_ratio = state.margin_ratio_partial as f64 / MARGIN_PRECISION as f64;
+    let mut entries: Vec<Entry> = cache
+        .users
+        .iter()
+        .filter_map(|(pubkey, user)| {
+            let mut user = user.clone();
+            let mut user_positions = *cache.positions.get(&user.positions)?;
+            risk::settle_funding_payment(&mut user, &mut user_positions, markets).ok()?;
+            let (total_collateral, _, base_asset_value, margin_ratio) = risk::calculate_margin_ratio(&user, &mut user_positions, markets).ok()?;
+            if margin_ratio == u128::MAX {
+                return None;
+            }
+            let partial_requirement = base_asset_value * state.margin_ratio_partial / MARGIN_PRECISION;
+            let distance = total_collateral as i128 - partial_requirement as i128;
+            Some(Entry::new(pubkey, &user.authority, total_collateral as i128, base_asset_value as i128, margin_ratio as f64 / MARGIN_PRECISION as f64, liquidation_margin_ratio, distance))
+        })
+        .collect();
+    rank(&mut entries);
+    entries
+}
+
+/// Every v2 user with a maintenance margin requirement, valued at the last oracle prices, riskiest first
+pub fn v2(cache: &v2::Cache) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = cache
+        .users
+        .iter()
+        .filter_map(|(pubkey, user)| {
+            let (total_collateral, margin_requirement) = v2::margin(cache, user, MarginType::Maintenance).filter(|(_, margin_requirement)| *margin_requirement > 0)?;
+            let mut notional: i128 = 0;
+            for position in &user.perp_positions {
+                notional += v2::perp_value(cache.perp_markets.get(&position.market_index)?, position).abs();
+            }
+            for position in user.spot_positions.iter().filter(|position| position.borrow) {
+                notional += v2::spot_value(cache.spot_markets.get(&position.market_index)?, position).abs();
+            }
+            let margin_ratio = total_collateral.max(0) as f64 / margin_requirement as f64;
+            Some(Entry::new(pubkey, &user.authority, total_collateral, notional, margin_ratio, 1.0, total_collateral - margin_requirement))
+        })
+        .collect();
+    rank(&mut entries);
+    entries
+}
+
+// riskiest first: the smallest margin ratio relative to the one liquidation starts at
+fn rank(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| a.distance_percent.total_cmp(&b.distance_percent));
+}
+
+/// Prints the `args.top` riskiest entries in the `args.output` format
+pub fn print(entries: &[Entry], args: &ScanArgs) {
+    let top = &entries[..entries.len().min(args.top)];
+    output::rows(args.output, top);
+    if args.output == OutputFormat::Table {
+        println!("{} of {} users with open positions", top.len(), entries.len());
+    }
+}
@@ -0,0 +1,943 @@
+use std::{collections::HashSet, error::Error, io::Write, mem::size_of, str::FromStr, sync::Arc, time::{Duration, Instant}};
+
+use admin::Admin;
+use anchor_lang::Discriminator;
+use blockhash::BlockhashCache;
+use cache::AccountCache;
+use clearing_house::state::{market::Markets, state::State, user::{User, UserPositions}};
+use collateral::{Deposits, Keeper, Withdrawals};
+use config::{Args, CheckArgs, Config, LiquidateArgs, ProtocolKind, ScanArgs, SenderKind, SimulatePriceArgs, StressArgs};
+use cooldown::Cooldowns;
+use executor::Executor;
+use exposure::Caps;
+use fees::FeeOracle;
+use filter::UserFilter;
+use futures::future::join_all;
+use geyser::GeyserSource;
+use health::Health;
+use hedge::Binance;
+use history::History;
+use incident::{Opsgenie, Pager, PagerDuty};
+use leader::LeaderElection;
+use metrics::{METRICS, QUOTE_PRECISION};
+use notify::{DiscordNotifier, Notifier, SlackNotifier};
+use oracle::OracleSource;
+use recording::Recorder;
+use reload::Reloader;
+use risk::Candidate;
+use retry::RetryPolicy;
+use risk_feed::RiskFeed;
+use rpc::RpcPool;
+use script::ScriptFilter;
+use sender::{JitoSender, MultiSender, PathStats, RpcSender, SendPath, TpuSender, TxSender};
+use signer::SharedSigner;
+use snapshot::Snapshot;
+use solana_client::client_error::Result as ClientResult;
+use solana_sdk::{account::Account, address_lookup_table_account::AddressLookupTableAccount, commitment_config::{CommitmentConfig}, pubkey::Pubkey, signature::Keypair, signer::Signer};
+use source::{AccountFilter, AccountUpdate, DataSource};
+use subscription::WebsocketSource;
+use telegram::Telegram;
+use tokio::sync::{mpsc::{channel, Sender}, watch, Semaphore};
+use tracing::{info, warn};
+use unwind::Unwinder;
+use watchlist::WatchlistSource;
+use webhook::Webhook;
+
+pub mod admin;
+pub mod api;
+pub mod balance;
+pub mod blockhash;
+pub mod breaker;
+pub mod budget;
+pub mod cache;
+pub mod check;
+pub mod collateral;
+pub mod config;
+pub mod cooldown;
+pub mod ctl;
+pub mod discovery;
+pub mod error;
+pub mod executor;
+pub mod export;
+pub mod exposure;
+pub mod fees;
+pub mod filter;
+pub mod geyser;
+pub mod health;
+pub mod hedge;
+pub mod history;
+pub mod idl;
+pub mod incident;
+pub mod instructions;
+pub mod leader;
+pub mod leaderboard;
+pub mod logging;
+pub mod lookup_table;
+pub mod metrics;
+pub mod notify;
+pub mod oracle;
+pub mod output;
+pub mod pause;
+pub mod pnl;
+pub mod prediction;
+pub mod program;
+pub mod rate_limit;
+pub mod recording;
+pub mod reload;
+pub mod replay;
+pub mod report;
+pub mod retry;
+pub mod reward;
+pub mod risk;
+pub mod risk_feed;
+pub mod rpc;
+pub mod scenario;
+pub mod script;
+pub mod sender;
+pub mod shutdown;
+pub mod signer;
+pub mod simulation;
+pub mod snapshot;
+pub mod source;
+pub mod status;
+pub mod strategy;
+pub mod stress;
+pub mod subscription;
+pub mod telegram;
+pub mod tiers;
+pub mod triggers;
+pub mod tui;
+pub mod unwind;
+pub mod v2;
+pub mod watchdog;
+pub mod watchlist;
+pub mod webhook;
+
+#[cfg(test)]
+mod fixtures;
+
+// how often the rpc endpoints are checked for alerts
+const NOTIFY_WATCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What the scans, `run` and the commands fail with: the rpc failing them or a setting that doesn't parse
+pub type CommandResult<T = ()> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Why `liquidate_user` sent nothing for a user it evaluated, each exiting the command with a status of its own
+#[derive(Debug, thiserror::Error)]
+pub enum NotSent {
+    #[error("user {0} isn't liquidatable, nothing sent")]
+    NotLiquidatable(String),
+    #[error("user {0}'s reward doesn't cover the fees, pass --force to liquidate it anyway")]
+    Unprofitable(String),
+}
+
+impl NotSent {
+    /// 2 for a user that isn't liquidatable and 3 for an unprofitable one, any other failure exiting with 1
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            NotSent::NotLiquidatable(_) => 2,
+            NotSent::Unprofitable(_) => 3,
+        }
+    }
+}
+
+/// The liquidation engine. `run` drives the full fetcher → risk → executor pipeline, while `scan`,
+/// `evaluate` and `liquidate` expose the individual steps to bots embedding the engine.
+pub struct Liquidator {
+    pub config: Config,
+    pub rpc: Arc<RpcPool>,
+    // fee payer and transaction signer
+    pub payer: SharedSigner,
+    pub cache: AccountCache,
+    pub liquidator_drift_account: Pubkey,
+    pub blockhash: BlockhashCache,
+    pub fees: FeeOracle,
+    pub sender: Arc<dyn TxSender>,
+    /// loaded by `scan` when `lookup_table` is configured
+    pub lookup_table: Option<AddressLookupTableAccount>,
+    // caps the number of liquidations being sent at once
+    send_permits: Arc<Semaphore>,
+    cooldowns: Arc<Cooldowns>,
+    // user accounts a snapshot let the startup scan skip, streamed in once the pipeline is up
+    deferred_accounts: Vec<Pubkey>,
+    // reloaded configs, never updated unless `reload_on_sighup` was called
+    reloads: watch::Receiver<Config>,
+    // publishes them, for the admin api to reload and override settings through
+    reloader: Option<Arc<Reloader>>,
+}
+
+impl Liquidator {
+    /// Errs if the rpc pool can't be built from the config
+    pub fn new(config: Config) -> CommandResult<Liquidator> {
+        let rpc = rpc_pool(&config)?;
+        let payer = signer::load_signer(&config);
+        info!(account = %bs58::encode(payer.pubkey().to_bytes()).into_string(), "liquidator account");
+
+        let sender = build_sender(config.sender, &config, &rpc);
+
+        Ok(Liquidator {
+            fees: FeeOracle::new(config.compute_unit_price),
+            sender,
+            send_permits: Arc::new(Semaphore::new(config.max_concurrent_sends)),
+            cooldowns: Arc::new(Cooldowns::new(Duration::from_millis(config.cooldown_base_ms), Duration::from_millis(config.cooldown_max_ms))),
+            // the sender is dropped right away, so this one never changes
+            reloads: watch::channel(config.clone()).1,
+            reloader: None,
+            config,
+            rpc,
+            payer,
+            cache: AccountCache::default(),
+            liquidator_drift_account: Pubkey::default(),
+            blockhash: BlockhashCache::default(),
+            lookup_table: None,
+            deferred_accounts: vec![],
+        })
+    }
+
+    /// Re-reads the config on SIGHUP, or an admin api reload, while `run` is running. Thresholds, size caps,
+    /// oracle guards, tiers, fees and the rpc endpoints take effect right away, everything else needs a restart.
+    pub fn reload_on_sighup(&mut self, args: Args) {
+        let (reloader, reloads) = reload::listen(args, self.config.clone());
+        self.reloads = reloads;
+        self.reloader = Some(reloader);
+    }
+
+    /// Loads the clearing house's user, positions, markets and state accounts into the cache and looks up the
+    /// liquidator's own drift account. Errs if the rpc fails it or a setting it needs doesn't parse.
+    pub async fn scan(&mut self) -> CommandResult {
+        self.scan_with(false).await
+    }
+
+    async fn scan_with(&mut self, defer: bool) -> CommandResult {
+        let now = Instant::now();
+
+        // one filtered request per account type rather than every program account, histories included
+        let program_id = program::v1();
+        let (markets, state) = tokio::join!(
+            self.rpc.get_accounts_by_discriminator(&program_id, Markets::discriminator(), Some(8 + size_of::<Markets>())),
+            self.rpc.get_accounts_by_discriminator(&program_id, State::discriminator(), None),
+        );
+        let mut all_accounts: Vec<(Pubkey, Account)> = markets?.into_iter().chain(state?).collect();
+        for account in &all_accounts {
+            self.cache.apply(account.0, &account.1.data);
+        }
+
+        // users under twice the partial liquidation ratio are the ones worth having before the stream starts
+        let threshold = self.cache.state.1.margin_ratio_partial * 2;
+        let own_accounts = [instructions::user_address(&self.payer.pubkey())];
+        let user_accounts = if !self.config.watchlist.is_empty() {
+            Some(self.watchlist_accounts(&own_accounts).await?)
+        } else if defer {
+            self.snapshot_accounts(&program_id, &[User::discriminator(), UserPositions::discriminator()], threshold, &own_accounts).await?
+        } else {
+            None
+        };
+        let user_accounts = match user_accounts {
+            Some(accounts) => accounts,
+            None => {
+                let (users, positions) = tokio::join!(
+                    self.rpc.get_accounts_by_discriminator(&program_id, User::discriminator(), None),
+                    self.rpc.get_accounts_by_discriminator(&program_id, UserPositions::discriminator(), Some(8 + size_of::<UserPositions>())),
+                );
+                users?.into_iter().chain(positions?).collect()
+            }
+        };
+        for account in &user_accounts {
+            self.cache.apply(account.0, &account.1.data);
+        }
+        all_accounts.extend(user_accounts);
+        recording::accounts(&all_accounts);
+
+        for (pubkey, user) in &self.cache.users {
+            if user.authority == self.payer.pubkey() {
+                self.liquidator_drift_account = *pubkey;
+                self.cache.liquidator = Some(*pubkey);
+                info!(account = %bs58::encode(pubkey.to_bytes()).into_string(), "liquidator drift account");
+            }
+        }
+        if self.cache.liquidator.is_none() {
+            if self.config.dry_run {
+                warn!("the payer has no drift account, dry run liquidations will fail simulation");
+            } else {
+                self.initialize_user().await?;
+            }
+        }
+        self.cache.filter = UserFilter::new(&self.config, self.cache.liquidator)?;
+
+        if self.config.verify_idl {
+            self.verify_layouts().await?;
+        }
+        self.load_lookup_table().await?;
+
+        let elapsed = now.elapsed();
+        info!("loaded {} user accounts from a total of {} accounts in {:.2?}", self.cache.users.len(), all_accounts.len(), elapsed);
+        Ok(())
+    }
+
+    /// The v2 counterpart of `scan`: reads the account layouts from the program's idl, loads the v2 user and
+    /// market accounts and looks up the liquidator's own user account (sub account 0)
+    pub async fn scan_v2(&mut self) -> CommandResult<v2::Cache> {
+        let layout = self.v2_layout().await?;
+        self.scan_v2_with(layout, false).await
+    }
+
+    async fn v2_layout(&self) -> CommandResult<v2::Layout> {
+        let idl = idl::fetch(&self.rpc, &v2::program_id()).await?;
+        Ok(v2::Layout::from_idl(&idl)?)
+    }
+
+    async fn scan_v2_with(&mut self, layout: v2::Layout, defer: bool) -> CommandResult<v2::Cache> {
+        let now = Instant::now();
+
+        let mut cache = v2::Cache::new(layout);
+        let program_id = v2::program_id();
+        let [user_discriminator, market_discriminators @ ..] = cache.layout.discriminators();
+        let requests = market_discriminators.map(|discriminator| self.rpc.get_accounts_by_discriminator(&program_id, discriminator, None));
+        let mut all_accounts: Vec<(Pubkey, Account)> = join_all(requests).await.into_iter().collect::<Result<Vec<_>, _>>()?.into_iter().flatten().collect();
+
+        // users under twice the maintenance margin requirement are the ones worth having before the stream starts
+        let own_accounts = [v2::user_address(&self.payer.pubkey(), 0)];
+        let user_accounts = if !self.config.watchlist.is_empty() {
+            let watched: Vec<Pubkey> = watchlist::parse(&self.config.watchlist).into_iter().chain(own_accounts).collect();
+            Some(self.rpc.get_multiple_accounts(&watched).await?)
+        } else if defer {
+            self.snapshot_accounts(&program_id, &[user_discriminator], 20_000, &own_accounts).await?
+        } else {
+            None
+        };
+        match user_accounts {
+            Some(accounts) => all_accounts.extend(accounts),
+            None => all_accounts.extend(self.rpc.get_accounts_by_discriminator(&program_id, user_discriminator, None).await?),
+        }
+
+        for account in &all_accounts {
+            cache.apply(account.0, &account.1.data);
+        }
+        recording::accounts(&all_accounts);
+
+        for (pubkey, user) in &cache.users {
+            if user.authority == self.payer.pubkey() && user.sub_account_id == 0 {
+                self.liquidator_drift_account = *pubkey;
+                cache.liquidator = Some(*pubkey);
+                info!(account = %bs58::encode(pubkey.to_bytes()).into_string(), "liquidator drift account");
+            }
+        }
+        if cache.liquidator.is_none() {
+            if self.config.dry_run {
+                warn!("the payer has no drift account, dry run liquidations will fail simulation");
+            } else {
+                self.initialize_user_v2(&mut cache).await?;
+            }
+        }
+        cache.filter = UserFilter::new(&self.config, cache.liquidator)?;
+        self.load_lookup_table().await?;
+
+        let elapsed = now.elapsed();
+        info!(
+            "loaded {} user accounts, {} perp markets and {} spot markets from a total of {} accounts in {:.2?}",
+            cache.users.len(),
+            cache.perp_markets.len(),
+            cache.spot_markets.len(),
+            all_accounts.len(),
+            elapsed
+        );
+        Ok(cache)
+    }
+
+    // creates the payer's clearing house user account when the scan found none, rather than sending liquidations
+    // with nowhere to pay their rewards into, and loads it into the cache
+    async fn initialize_user(&mut self) -> CommandResult {
+        let authority = self.payer.pubkey();
+        warn!(authority = %bs58::encode(authority.to_bytes()).into_string(), "the payer has no drift account, initializing one");
+        let user_positions = Keypair::new();
+        let instruction = instructions::initialize_user(&self.cache.state, &authority, &user_positions.pubkey());
+        let signers: [&dyn Signer; 2] = [&*self.payer, &user_positions];
+        let signature = self.rpc.send_and_confirm(&[instruction], &signers).await.map_err(|err| format!("failed to initialize the liquidator's drift account: {}", err))?;
+
+        let user = instructions::user_address(&authority);
+        for (pubkey, account) in self.rpc.get_multiple_accounts(&[user, user_positions.pubkey()]).await? {
+            self.cache.apply(pubkey, &account.data);
+        }
+        self.liquidator_drift_account = user;
+        self.cache.liquidator = Some(user);
+        info!(account = %bs58::encode(user.to_bytes()).into_string(), %signature, "initialized liquidator drift account");
+        Ok(())
+    }
+
+    // the v2 counterpart of `initialize_user`, creating sub account 0 and the user stats account it needs if the
+    // payer has none yet
+    async fn initialize_user_v2(&mut self, cache: &mut v2::Cache) -> CommandResult {
+        let authority = self.payer.pubkey();
+        warn!(authority = %bs58::encode(authority.to_bytes()).into_string(), "the payer has no drift account, initializing one");
+        let mut instructions = vec![];
+        if self.rpc.get_multiple_accounts(&[v2::user_stats_address(&authority)]).await?.is_empty() {
+            instructions.push(v2::initialize_user_stats(&authority));
+        }
+        instructions.push(v2::initialize_user(&authority, 0, "liquidator"));
+        let signer: &dyn Signer = &*self.payer;
+        let signature = self.rpc.send_and_confirm(&instructions, &[signer]).await.map_err(|err| format!("failed to initialize the liquidator's drift account: {}", err))?;
+
+        let user = v2::user_address(&authority, 0);
+        for (pubkey, account) in self.rpc.get_multiple_accounts(&[user]).await? {
+            cache.apply(pubkey, &account.data);
+        }
+        self.liquidator_drift_account = user;
+        cache.liquidator = Some(user);
+        info!(account = %bs58::encode(user.to_bytes()).into_string(), %signature, "initialized liquidator drift account");
+        Ok(())
+    }
+
+    // the watched v1 users and the liquidator's own, then their positions accounts, which the users point to
+    async fn watchlist_accounts(&mut self, own_accounts: &[Pubkey]) -> CommandResult<Vec<(Pubkey, Account)>> {
+        let watched: Vec<Pubkey> = watchlist::parse(&self.config.watchlist).into_iter().chain(own_accounts.iter().copied()).collect();
+        let mut accounts = self.rpc.get_multiple_accounts(&watched).await?;
+        for (pubkey, account) in &accounts {
+            self.cache.apply(*pubkey, &account.data);
+        }
+        let positions: Vec<Pubkey> = accounts.iter().filter_map(|(pubkey, _)| self.cache.users.get(pubkey)).map(|user| user.positions).collect();
+        accounts.extend(self.rpc.get_multiple_accounts(&positions).await?);
+        info!(users = watched.len(), "loaded the watchlist instead of every user");
+        Ok(accounts)
+    }
+
+    // with a snapshot, only the users that were close to liquidation, the liquidator's own and the accounts
+    // created since are fetched up front. the rest is left in `deferred_accounts` for `run` to stream in. the
+    // snapshot is checked against a listing of the program's accounts without their data, so accounts
+    // closed since are dropped
+    async fn snapshot_accounts(&mut self, program_id: &Pubkey, discriminators: &[[u8; 8]], threshold: u128, own_accounts: &[Pubkey]) -> CommandResult<Option<Vec<(Pubkey, Account)>>> {
+        let snapshot = match self.config.snapshot_path.as_deref().and_then(Snapshot::load) {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+        let requests = discriminators.iter().map(|discriminator| self.rpc.get_pubkeys_by_discriminator(program_id, *discriminator));
+        let listed: HashSet<Pubkey> = join_all(requests).await.into_iter().collect::<Result<Vec<_>, _>>()?.into_iter().flatten().collect();
+
+        let known = snapshot.pubkeys();
+        let new: Vec<Pubkey> = listed.difference(&known).copied().collect();
+        let load_now: HashSet<Pubkey> = snapshot
+            .at_risk(threshold)
+            .into_iter()
+            .chain(own_accounts.iter().copied())
+            .filter(|pubkey| listed.contains(pubkey))
+            .chain(new.iter().copied())
+            .collect();
+        self.deferred_accounts = listed.difference(&load_now).copied().collect();
+        info!(
+            "snapshot lists {} of {} program accounts, {} new and {} closed since. loading {} now and {} in the background",
+            listed.len() - new.len(),
+            listed.len(),
+            new.len(),
+            known.difference(&listed).count(),
+            load_now.len(),
+            self.deferred_accounts.len()
+        );
+
+        let load_now: Vec<Pubkey> = load_now.into_iter().collect();
+        Ok(Some(self.rpc.get_multiple_accounts(&load_now).await?))
+    }
+
+    // feeds the accounts the startup scan deferred into the update stream
+    fn spawn_deferred_load(&mut self, sender: Sender<AccountUpdate>) {
+        let deferred = std::mem::take(&mut self.deferred_accounts);
+        if deferred.is_empty() {
+            return;
+        }
+
+        let rpc = self.rpc.clone();
+        tokio::spawn(async move {
+            let now = Instant::now();
+            if discovery::fetch_into(&rpc, &deferred, &sender).await.is_ok() {
+                info!("loaded {} deferred accounts in {:.2?}", deferred.len(), now.elapsed());
+            }
+        });
+    }
+
+    async fn load_lookup_table(&mut self) -> CommandResult {
+        if let Some(address) = &self.config.lookup_table {
+            let pubkey = Pubkey::from_str(address).map_err(|_| format!("lookup_table {} isn't a pubkey", address))?;
+            let table = lookup_table::load(&self.rpc, pubkey).await?;
+            info!("using lookup table {} with {} addresses", address, table.addresses.len());
+            self.lookup_table = Some(table);
+        }
+        Ok(())
+    }
+
+    // a program upgrade that changed an account layout would otherwise go unnoticed, with accounts failing to
+    // deserialize or, worse, decoding into garbage margins
+    async fn verify_layouts(&self) -> CommandResult {
+        let idl = match idl::fetch(&self.rpc, &program::v1()).await {
+            Ok(idl) => idl,
+            Err(err) => {
+                warn!(error = %err, "skipping account layout check, failed to fetch the program idl");
+                return Ok(());
+            }
+        };
+
+        let mismatches = idl::check_layouts(&idl, &self.cache);
+        if !mismatches.is_empty() {
+            return Err(format!("account layouts don't match the on-chain program idl, rebuild against the deployed program: {}", mismatches.join(", ")).into());
+        }
+        info!("account layouts match the on-chain program idl");
+        Ok(())
+    }
+
+    /// Returns a liquidation candidate if the cached user is in liquidation territory
+    pub fn evaluate(&self, user: &Pubkey) -> error::Result<Option<Candidate>> {
+        risk::evaluate(&self.cache, &self.config, user)
+    }
+
+    /// Builds, signs and sends the liquidation transaction for a candidate
+    pub async fn liquidate(&self, candidate: &Candidate) {
+        self.executor().liquidate(candidate).await;
+    }
+
+    /// Scans the accounts and prints what the price move in `args` would leave liquidatable
+    pub async fn stress(mut self, args: &StressArgs) -> CommandResult {
+        let stress = match self.config.protocol {
+            ProtocolKind::V1 => {
+                self.scan().await?;
+                stress::v1(&mut self.cache, &self.config, args)?
+            }
+            ProtocolKind::V2 => {
+                let mut cache = self.scan_v2().await?;
+                stress::v2(&mut cache, &self.config, args)?
+            }
+        };
+        stress.print(&self.config, args);
+        Ok(())
+    }
+
+    /// Scans the accounts and prints the users closest to liquidation
+    pub async fn leaderboard(mut self, args: &ScanArgs) -> CommandResult {
+        let entries = match self.config.protocol {
+            ProtocolKind::V1 => {
+                self.scan().await?;
+                leaderboard::v1(&self.cache)
+            }
+            ProtocolKind::V2 => leaderboard::v2(&self.scan_v2().await?),
+        };
+        leaderboard::print(&entries, args);
+        Ok(())
+    }
+
+    /// Scans the accounts and prints the margin breakdown of the user in `args`
+    pub async fn check(mut self, args: &CheckArgs) -> CommandResult {
+        let checked = match self.config.protocol {
+            ProtocolKind::V1 => {
+                self.scan().await?;
+                check::v1(&self.cache, &self.config, &args.user).map(|check| output::record(args.output, &check))
+            }
+            ProtocolKind::V2 => check::v2(&self.scan_v2().await?, &self.config, &args.user).map(|check| output::record(args.output, &check)),
+        };
+        checked.map_err(|err| format!("user {}: {}", bs58::encode(args.user.to_bytes()).into_string(), err).into())
+    }
+
+    /// Scans the accounts, evaluates the user in `args` and sends its liquidation if it is liquidatable and, unless
+    /// forced, profitable, following the transaction until it lands. Errs with `NotSent` if it is neither.
+    pub async fn liquidate_user(mut self, args: &LiquidateArgs) -> CommandResult {
+        let user = bs58::encode(args.user.to_bytes()).into_string();
+        let candidate = match self.config.protocol {
+            ProtocolKind::V1 => {
+                self.scan().await?;
+                self.evaluate(&args.user)
+            }
+            ProtocolKind::V2 => v2::evaluate(&self.scan_v2().await?, &self.config, &args.user),
+        };
+        let candidate = match candidate {
+            Ok(Some(candidate)) => candidate,
+            Ok(None) => return Err(NotSent::NotLiquidatable(user).into()),
+            Err(err) => return Err(format!("failed to evaluate user {}: {}", user, err).into()),
+        };
+        let executor = self.executor();
+        if !args.force && !executor.profitable(&candidate) {
+            return Err(NotSent::Unprofitable(user).into());
+        }
+        info!(%user, kind = candidate.liquidation.kind(), reward = candidate.reward.liquidator as f64 / QUOTE_PRECISION, "liquidating");
+        executor.liquidate(&candidate).await;
+        Ok(())
+    }
+
+    /// Scans the accounts and prints how the user in `args` fares at its prices and at what prices it becomes
+    /// liquidatable
+    pub async fn simulate_price(mut self, args: &SimulatePriceArgs) -> CommandResult {
+        let prices = scenario::Prices::parse(&args.prices, &args.spot_prices)?;
+        let simulated = match self.config.protocol {
+            ProtocolKind::V1 => {
+                self.scan().await?;
+                scenario::v1(&self.cache, &self.config, &args.user, &prices).map(|simulation| output::record(args.output, &simulation))
+            }
+            ProtocolKind::V2 => scenario::v2(&mut self.scan_v2().await?, &args.user, &prices).map(|simulation| output::record(args.output, &simulation)),
+        };
+        simulated.map_err(|err| format!("user {}: {}", bs58::encode(args.user.to_bytes()).into_string(), err).into())
+    }
+
+    /// Creates an address lookup table holding the accounts every liquidation references, or extends the
+    /// configured `lookup_table` with any of them it is missing, e.g. after a new market launched
+    pub async fn setup_lookup_table(mut self) -> CommandResult {
+        let addresses = match self.config.protocol {
+            ProtocolKind::V1 => {
+                self.scan().await?;
+                lookup_table::static_addresses(&self.cache.state, &self.cache.markets.1, &self.liquidator_drift_account)
+            }
+            ProtocolKind::V2 => self.scan_v2().await?.static_addresses(&self.payer.pubkey()),
+        };
+        // the configured table, which the scan loaded
+        let existing = self.lookup_table.as_ref().map(|table| table.key);
+        let address = lookup_table::create_or_extend(&self.rpc, &self.payer, existing, &addresses).await?;
+        info!("lookup table {} holds all {} static accounts, set lookup_table = \"{}\" to use it", address, addresses.len(), address);
+        Ok(())
+    }
+
+    /// Streams account updates and liquidates users as they cross the margin threshold. Returns on SIGINT or
+    /// SIGTERM once the liquidations in flight landed or timed out and the cache was snapshotted, or if the
+    /// data source shuts down. Errs on a script that doesn't compile, a setting that doesn't parse or the
+    /// startup scan failing.
+    pub async fn run(mut self) -> CommandResult {
+        let shutdown = shutdown::listen();
+        // a script that doesn't compile fails here rather than in the risk stage
+        let script = ScriptFilter::new(&self.config)?;
+        // v2 sources only stream the account fields the layout says the cache reads
+        let layout = match self.config.protocol {
+            ProtocolKind::V1 => None,
+            ProtocolKind::V2 => Some(self.v2_layout().await?),
+        };
+
+        // fetcher stage, subscribed before the initial scan so writes landing in between are queued rather than missed.
+        // a watchlist is polled instead, once the scan has found the accounts its users are valued against. the
+        // poll only sends changes, quiet accounts would trip the watchdog, so it runs without one
+        let (update_sender, update_receiver) = channel(self.config.update_channel_size);
+        let watching = !self.config.watchlist.is_empty();
+        if !watching {
+            let source = self.source(layout.as_ref().map_or(vec![], |layout| layout.filters()));
+            if self.config.watchdog_timeout_secs > 0 {
+                watchdog::spawn(source, update_sender.clone(), Duration::from_secs(self.config.watchdog_timeout_secs));
+            } else {
+                source.spawn(update_sender.clone());
+            }
+        }
+        if self.config.discovery_interval_secs > 0 && !watching {
+            discovery::spawn_discovery(self.rpc.clone(), self.program_id(), Duration::from_secs(self.config.discovery_interval_secs), update_sender.clone());
+        }
+        RpcPool::spawn_monitor(self.rpc.clone(), Duration::from_millis(self.config.rpc_monitor_interval_ms));
+        if let Some(addr) = &self.config.health_addr {
+            Health::new(&self.config, self.rpc.clone()).spawn(addr.parse().map_err(|_| format!("health_addr {} isn't an ip:port address", addr))?);
+        }
+        if let Some(addr) = &self.config.api_addr {
+            api::spawn(addr.parse().map_err(|_| format!("api_addr {} isn't an ip:port address", addr))?);
+        }
+        if self.config.admin_addr.is_some() || self.config.admin_socket_path.is_some() {
+            let admin = Arc::new(Admin { reloader: self.reloader.clone(), rpc: self.rpc.clone(), program_id: self.program_id(), updates: update_sender.clone() });
+            if let Some(addr) = &self.config.admin_addr {
+                let token = self.config.admin_token.clone().ok_or("admin_addr needs an admin_token")?;
+                admin::serve_http(admin.clone(), addr.parse().map_err(|_| format!("admin_addr {} isn't an ip:port address", addr))?, token);
+            }
+            if let Some(path) = &self.config.admin_socket_path {
+                ctl::serve(admin, path).map_err(|err| format!("failed to bind admin_socket_path {}: {}", path, err))?;
+            }
+        }
+        let mut notifiers = build_notifiers(&self.config);
+        if let Some(token) = &self.config.telegram_bot_token {
+            let telegram = Telegram {
+                token: token.clone(),
+                chat_ids: self.config.telegram_chat_ids.clone(),
+                http: reqwest::Client::new(),
+                rpc: self.rpc.clone(),
+                payer: self.payer.pubkey(),
+            };
+            telegram.clone().spawn_bot();
+            notifiers.push(Box::new(telegram));
+        }
+        if !notifiers.is_empty() {
+            notify::spawn(notifiers, Duration::from_secs(self.config.notify_batch_interval_secs));
+            notify::spawn_watch(self.rpc.clone(), NOTIFY_WATCH_INTERVAL);
+        }
+        if let Some(url) = &self.config.webhook_url {
+            Webhook { url: url.clone(), secret: self.config.webhook_secret.clone(), http: reqwest::Client::new() }.spawn();
+        }
+        if let Some(store) = history::store(&self.config).await {
+            budget::seed(store.as_ref()).await;
+            History { store }.spawn();
+        }
+        if let Some(path) = &self.config.record_path {
+            Recorder { path: path.clone(), protocol: self.config.protocol }.spawn();
+        }
+        budget::spawn_roll();
+        balance::spawn(
+            self.rpc.clone(),
+            self.payer.pubkey(),
+            self.config.low_balance_alert_lamports,
+            self.config.halt_balance_lamports,
+            Duration::from_secs(self.config.balance_check_interval_secs),
+        );
+        if self.config.dry_run {
+            warn!("dry run, liquidations are logged and nothing is sent");
+        }
+        if !self.config.dry_run && (self.config.deposit_token_account.is_some() || self.config.withdraw_token_account.is_some()) {
+            Keeper {
+                rpc: self.rpc.clone(),
+                payer: self.payer.clone(),
+                deposits: self.config.deposit_token_account.as_ref().map(|token_account| Deposits {
+                    token_account: Pubkey::from_str(token_account).map_err(|_| format!("deposit_token_account {} isn't a pubkey", token_account))?,
+                    amount: self.config.auto_deposit_amount,
+                    min_collateral: self.config.auto_deposit_min_collateral,
+                }),
+                withdrawals: self.config.withdraw_token_account.as_ref().map(|token_account| Withdrawals {
+                    token_account: Pubkey::from_str(token_account).map_err(|_| format!("withdraw_token_account {} isn't a pubkey", token_account))?,
+                    max_collateral: self.config.auto_withdraw_max_collateral,
+                }),
+                margin_percent: self.config.auto_deposit_margin_percent,
+            }
+            .spawn();
+        }
+        if let Some(url) = &self.config.redis_url {
+            RiskFeed {
+                url: url.clone(),
+                key: self.config.redis_key.clone(),
+                channel: self.config.redis_channel.clone(),
+                margin_percent: self.config.redis_margin_percent,
+            }
+            .spawn();
+        }
+        if let Some(url) = &self.config.leader_lock_url {
+            LeaderElection {
+                client: redis::Client::open(url.as_str()).map_err(|err| format!("leader_lock_url {} isn't a redis url: {}", url, err))?,
+                key: self.config.leader_lock_key.clone(),
+                instance_id: self.config.instance_id.clone().unwrap_or_else(|| format!("{:016x}", rand::random::<u64>())),
+                ttl: Duration::from_millis(self.config.leader_lock_ttl_ms),
+            }
+            .spawn();
+        }
+        if self.config.unwind_positions && !self.config.dry_run {
+            Unwinder {
+                rpc: self.rpc.clone(),
+                payer: self.payer.clone(),
+                max_order_value: self.config.unwind_max_order_value,
+                max_slippage_bps: self.config.unwind_max_slippage_bps,
+            }
+            .spawn();
+        }
+        if self.config.max_exposure_notional > 0 || !self.config.exposure_caps.is_empty() {
+            exposure::limit(Caps::parse(self.config.max_exposure_notional, &self.config.exposure_caps)?);
+        }
+        if let (Some(api_key), Some(api_secret), false) = (&self.config.binance_api_key, &self.config.binance_api_secret, self.config.dry_run) {
+            let symbols = hedge::symbols(&self.config.hedge_markets)?;
+            if !symbols.is_empty() {
+                let hedger = Binance::new(api_key.clone(), api_secret.clone(), self.config.binance_url.clone(), reqwest::Client::new());
+                hedge::spawn(Box::new(hedger), symbols, self.config.hedge_impact_bps);
+            }
+        }
+        let pagers = build_pagers(&self.config);
+        if !pagers.is_empty() {
+            incident::spawn(pagers, self.rpc.clone(), self.payer.pubkey(), &self.config);
+        }
+        self.spawn_reload();
+        self.blockhash.spawn_refresh(self.rpc.clone(), Duration::from_millis(self.config.blockhash_refresh_interval_ms));
+
+        let (candidate_sender, candidate_receiver) = channel(self.config.candidate_channel_size);
+        let (executor, risk) = match self.config.protocol {
+            ProtocolKind::V1 => {
+                self.scan_with(true).await?;
+                if watching {
+                    let accounts = self.cache.users.iter().flat_map(|(pubkey, user)| [*pubkey, user.positions]).chain([self.cache.markets.0, self.cache.state.0]).collect();
+                    self.watchlist_source(accounts).spawn(update_sender.clone());
+                }
+                self.spawn_deferred_load(update_sender);
+                let state = &self.cache.state.1;
+                // the accounts every liquidation write locks, whatever user it targets
+                self.spawn_fee_poll(vec![
+                    state.markets,
+                    state.collateral_vault,
+                    state.insurance_vault,
+                    state.trade_history,
+                    state.liquidation_history,
+                    state.funding_payment_history,
+                ]);
+
+                // risk stage
+                let executor = self.executor();
+                (executor, tokio::spawn(risk::run(self.cache, self.config.clone(), script, update_receiver, candidate_sender, shutdown.clone(), self.reloads.clone())))
+            }
+            ProtocolKind::V2 => {
+                let layout = layout.ok_or("the v2 account layout wasn't fetched")?;
+                let cache = self.scan_v2_with(layout, true).await?;
+                if self.config.subscribe_oracles {
+                    // quote markets price at one without an oracle
+                    let oracles: Vec<Pubkey> = cache.oracle_prices().into_keys().filter(|oracle| *oracle != Pubkey::default()).collect();
+                    info!("subscribing to {} oracles", oracles.len());
+                    OracleSource { url: self.config.ws_url.clone(), oracles, commitment: self.config.commitment }.spawn(update_sender.clone());
+                }
+                if watching {
+                    let markets = cache.perp_markets.values().map(|market| market.pubkey).chain(cache.spot_markets.values().map(|market| market.pubkey));
+                    let accounts = cache.users.keys().copied().chain(markets).collect();
+                    self.watchlist_source(accounts).spawn(update_sender.clone());
+                }
+                self.spawn_deferred_load(update_sender);
+                // v2 liquidations write lock the markets they touch
+                self.spawn_fee_poll(cache.perp_markets.values().map(|market| market.pubkey).chain(cache.spot_markets.values().map(|market| market.pubkey)).collect());
+
+                // risk stage
+                let executor = self.executor();
+                (executor, tokio::spawn(v2::run(cache, self.config.clone(), script, update_receiver, candidate_sender, shutdown.clone(), self.reloads.clone())))
+            }
+        };
+
+        // executor stage
+        executor.run(candidate_receiver, shutdown, self.reloads.clone()).await;
+        // the risk stage saves its snapshot on the way out
+        risk.await?;
+        info!("{}", METRICS.summary());
+        logging::shutdown();
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+
+    // swaps in the rpc endpoints and the static priority fee or the polled fee's cap of reloaded configs, the
+    // stages pick up the rest
+    fn spawn_reload(&self) {
+        let mut reloads = self.reloads.clone();
+        let rpc = self.rpc.clone();
+        let fees = self.fees.clone();
+        tokio::spawn(async move {
+            while reloads.changed().await.is_ok() {
+                let config = reloads.borrow_and_update().clone();
+                rpc.set_urls(&rpc_urls(&config));
+                if config.priority_fee_percentile.is_none() {
+                    fees.set(config.compute_unit_price);
+                } else {
+                    fees.set_max(config.max_compute_unit_price);
+                }
+            }
+        });
+    }
+
+    fn spawn_fee_poll(&self, accounts: Vec<Pubkey>) {
+        if let Some(percentile) = self.config.priority_fee_percentile {
+            self.fees.spawn_poll(
+                self.rpc.clone(),
+                accounts,
+                percentile,
+                self.config.max_compute_unit_price,
+                Duration::from_millis(self.config.priority_fee_poll_interval_ms),
+            );
+        }
+    }
+
+    fn program_id(&self) -> Pubkey {
+        match self.config.protocol {
+            ProtocolKind::V1 => program::v1(),
+            ProtocolKind::V2 => v2::program_id(),
+        }
+    }
+
+    fn watchlist_source(&self, accounts: Vec<Pubkey>) -> WatchlistSource {
+        info!(accounts = accounts.len(), "polling the watchlist");
+        WatchlistSource { rpc: self.rpc.clone(), accounts, interval: Duration::from_millis(self.config.watchlist_poll_interval_ms) }
+    }
+
+    // only the geyser source supports filters, the websocket one streams every program account
+    fn source(&self, filters: Vec<AccountFilter>) -> Box<dyn DataSource> {
+        match &self.config.geyser_url {
+            Some(url) => Box::new(GeyserSource {
+                url: url.clone(),
+                x_token: self.config.geyser_x_token.clone(),
+                program_id: self.program_id(),
+                commitment: self.config.commitment,
+                filters,
+            }),
+            None => Box::new(WebsocketSource {
+                url: self.config.ws_url.clone(),
+                program_id: self.program_id(),
+                commitment: self.config.commitment,
+            }),
+        }
+    }
+
+    fn executor(&self) -> Executor {
+        Executor {
+            config: self.config.clone(),
+            rpc: self.rpc.clone(),
+            payer: self.payer.clone(),
+            liquidator_drift_account: self.liquidator_drift_account,
+            state: self.cache.state.clone(),
+            blockhash: self.blockhash.clone(),
+            fees: self.fees.clone(),
+            sender: self.sender.clone(),
+            lookup_table: self.lookup_table.clone(),
+            send_permits: self.send_permits.clone(),
+            in_flight: Default::default(),
+            cooldowns: self.cooldowns.clone(),
+        }
+    }
+}
+
+/// The rpc pool the config describes
+pub fn rpc_pool(config: &Config) -> ClientResult<Arc<RpcPool>> {
+    let timeout = Duration::from_secs(45);
+    let commitment_config = CommitmentConfig { commitment: config.commitment };
+    let retry = RetryPolicy {
+        max_attempts: config.rpc_max_attempts,
+        base_delay: Duration::from_millis(config.rpc_retry_base_delay_ms),
+        max_delay: Duration::from_millis(config.rpc_retry_max_delay_ms),
+    };
+    Ok(Arc::new(RpcPool::new(&rpc_urls(config), timeout, commitment_config, config.rpc_requests_per_second, config.max_concurrent_requests, retry)?))
+}
+
+// the primary rpc url followed by the fallbacks
+fn rpc_urls(config: &Config) -> Vec<String> {
+    std::iter::once(config.rpc_url.clone()).chain(config.fallback_rpc_urls.iter().cloned()).collect()
+}
+
+fn build_pagers(config: &Config) -> Vec<Box<dyn Pager>> {
+    let mut pagers: Vec<Box<dyn Pager>> = vec![];
+    if let Some(routing_key) = &config.pagerduty_routing_key {
+        pagers.push(Box::new(PagerDuty { routing_key: routing_key.clone(), http: reqwest::Client::new() }));
+    }
+    if let Some(api_key) = &config.opsgenie_api_key {
+        pagers.push(Box::new(Opsgenie { api_key: api_key.clone(), http: reqwest::Client::new() }));
+    }
+    pagers
+}
+
+fn build_notifiers(config: &Config) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = vec![];
+    if let Some(webhook_url) = &config.discord_webhook_url {
+        notifiers.push(Box::new(DiscordNotifier { webhook_url: webhook_url.clone(), http: reqwest::Client::new() }));
+    }
+    if let Some(webhook_url) = &config.slack_webhook_url {
+        notifiers.push(Box::new(SlackNotifier {
+            webhook_url: webhook_url.clone(),
+            critical_webhook_url: config.slack_critical_webhook_url.clone(),
+            http: reqwest::Client::new(),
+        }));
+    }
+    notifiers
+}
+
+fn build_sender(kind: SenderKind, config: &Config, rpc: &Arc<RpcPool>) -> Arc<dyn TxSender> {
+    match kind {
+        SenderKind::Rpc => Arc::new(RpcSender {
+            rpc: rpc.clone(),
+            skip_preflight: config.skip_preflight,
+            preflight_commitment: config.send_commitment,
+        }),
+        SenderKind::Jito => Arc::new(JitoSender {
+            url: config.jito_url.clone(),
+            tip_lamports: config.jito_tip_lamports,
+            http: reqwest::Client::new(),
+        }),
+        SenderKind::Tpu => Arc::new(TpuSender::new(config.rpc_url.clone(), config.ws_url.clone(), config.tpu_fanout_slots)),
+        SenderKind::Multi => Arc::new(MultiSender {
+            paths: config
+                .multi_sender_paths
+                .iter()
+                .filter(|path| **path != SenderKind::Multi)
+                .map(|path| SendPath {
+                    name: path.name(),
+                    sender: build_sender(*path, config, rpc),
+                    stats: Arc::new(PathStats::default()),
+                })
+                .collect(),
+            rpc: rpc.clone(),
+        }),
+    }
+}
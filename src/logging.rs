@@ -0,0 +1,41 @@
+use std::{fs::OpenOptions, sync::Mutex};
+
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing_subscriber::{fmt::{self, writer::BoxMakeWriter}, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::LogFormat;
+
+/// Installs the process wide subscriber writing events to stdout, or appending them to `log_path`, in the
+/// configured format and at the level `RUST_LOG` asks for, info by default. Json events carry the fields of the
+/// span they happened in, e.g. the users of the liquidation being sent. With an `otlp_endpoint` the spans are
+/// also exported as traces, one per liquidation. Has to be called from within the runtime, which the exporter
+/// runs on.
+pub fn init(format: LogFormat, log_path: Option<&str>, otlp_endpoint: Option<&str>) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let traces = otlp_endpoint.map(|endpoint| {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+            .with_trace_config(trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", "drift-liquidator")])))
+            .install_batch(runtime::Tokio)
+            .expect("failed to set up the otlp exporter");
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    });
+    let writer = match log_path {
+        Some(path) => BoxMakeWriter::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path).expect("failed to open the log file"))),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+    let layer = fmt::layer().with_writer(writer).with_ansi(log_path.is_none());
+    let registry = tracing_subscriber::registry().with(filter).with(traces);
+    match format {
+        LogFormat::Text => registry.with(layer).init(),
+        LogFormat::Json => registry.with(layer.json().with_current_span(true).with_span_list(false)).init(),
+    }
+}
+
+/// Exports the traces still buffered
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+
+use clearing_house::state::{market::Markets, state::State};
+use solana_sdk::{address_lookup_table::{instruction::{create_lookup_table, extend_lookup_table}, state::AddressLookupTable}, address_lookup_table_account::AddressLookupTableAccount, commitment_config::CommitmentConfig, compute_budget, instruction::Instruction, pubkey::Pubkey, signer::Signer, system_program};
+use tracing::info;
+
+use crate::{program, rpc::RpcPool, signer::SharedSigner};
+
+// addresses added per extend transaction, keeps the transaction under the packet size limit
+const EXTEND_CHUNK_SIZE: usize = 20;
+
+/// The accounts every liquidation references whatever user it targets: the program, the state and its vaults
+/// and histories, the markets account, the liquidator's drift account and the oracles of every initialized market
+pub fn static_addresses(state: &(Pubkey, State), markets: &Markets, liquidator_drift_account: &Pubkey) -> Vec<Pubkey> {
+    let mut addresses = vec![
+        program::v1(),
+        compute_budget::id(),
+        spl_token::id(),
+        // jito tips are system transfers
+        system_program::id(),
+        state.0,
+        state.1.collateral_vault,
+        state.1.collateral_vault_authority,
+        state.1.insurance_vault,
+        state.1.insurance_vault_authority,
+        state.1.markets,
+        state.1.trade_history,
+        state.1.liquidation_history,
+        state.1.funding_payment_history,
+    ];
+
+    let oracles = markets.markets.iter().filter(|market| market.initialized).map(|market| market.amm.oracle);
+    for address in std::iter::once(*liquidator_drift_account).chain(oracles) {
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    addresses
+}
+
+/// Fetches and decodes a lookup table account
+pub async fn load(rpc: &RpcPool, address: Pubkey) -> Result<AddressLookupTableAccount, Box<dyn Error + Send + Sync>> {
+    let account = rpc.call(|client| async move { client.get_account(&address).await }).await?;
+    let table = AddressLookupTable::deserialize(&account.data).map_err(|err| format!("{} is not a lookup table: {}", address, err))?;
+
+    Ok(AddressLookupTableAccount { key: address, addresses: table.addresses.to_vec() })
+}
+
+/// Creates a lookup table owned by the payer if `existing` is unset, then extends it with whatever of
+/// `addresses` it doesn't hold yet. Returns the table's address.
+pub async fn create_or_extend(
+    rpc: &RpcPool,
+    payer: &SharedSigner,
+    existing: Option<Pubkey>,
+    addresses: &[Pubkey],
+) -> Result<Pubkey, Box<dyn Error + Send + Sync>> {
+    let authority = payer.pubkey();
+    let (address, mut missing, mut pending) = match existing {
+        Some(address) => {
+            let table = load(rpc, address).await?;
+            let missing: Vec<Pubkey> = addresses.iter().filter(|address| !table.addresses.contains(address)).cloned().collect();
+            (address, missing, vec![])
+        }
+        None => {
+            // the table address is derived from a recent slot, which must be rooted for the program to accept it
+            let recent_slot = rpc.call(|client| async move { client.get_slot_with_commitment(CommitmentConfig::finalized()).await }).await?;
+            let (create, address) = create_lookup_table(authority, authority, recent_slot);
+            info!(%address, "creating lookup table");
+            (address, addresses.to_vec(), vec![create])
+        }
+    };
+
+    while !missing.is_empty() || !pending.is_empty() {
+        let chunk: Vec<Pubkey> = missing.drain(..missing.len().min(EXTEND_CHUNK_SIZE)).collect();
+        if !chunk.is_empty() {
+            pending.push(extend_lookup_table(address, authority, Some(authority), chunk.clone()));
+        }
+        send(rpc, payer, &pending).await?;
+        pending.clear();
+        info!(%address, addresses = chunk.len(), "added addresses to lookup table");
+    }
+
+    Ok(address)
+}
+
+async fn send(rpc: &RpcPool, payer: &SharedSigner, instructions: &[Instruction]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let signer: &dyn Signer = &**payer;
+    let signature = rpc.send_and_confirm(instructions, &[signer]).await?;
+    info!(%signature, "sent");
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::{collections::BTreeMap, sync::{atomic::{AtomicI64, AtomicU64, Ordering}, Mutex}};
+
+use serde::Serialize;
+
+use crate::risk::Liquidation;
+
+/// Process wide counters, shared by every stage and read by whatever reports them
+#[derive(Serialize)]
+pub struct Metrics {
+    /// liquidation candidates the risk stage handed to the executor
+    pub candidates: AtomicU64,
+    /// cached accounts dropped after they were closed or stopped decoding
+    pub evicted_accounts: AtomicU64,
+    /// candidates skipped because their reward wouldn't cover the fees
+    pub unprofitable_candidates: AtomicU64,
+    /// users held back because an oracle they are valued with was stale or too uncertain
+    pub oracle_guarded_candidates: AtomicU64,
+    /// perp liquidations refused because the liquidator's position in the market was over its exposure cap
+    pub exposure_capped_candidates: AtomicU64,
+    /// times an oracle jump paused the liquidations priced off it
+    pub circuit_breaks: AtomicU64,
+    /// times the watchdog restarted a stalled data source
+    pub watchdog_restarts: AtomicU64,
+    /// users whose evaluation failed, e.g. on a malformed account
+    pub evaluation_errors: AtomicU64,
+    /// v1 candidates the liquidation simulator failed on, rewarded at the flat penalty instead
+    pub prediction_errors: AtomicU64,
+    /// landed liquidations by type
+    pub partial_liquidations: AtomicU64,
+    pub full_liquidations: AtomicU64,
+    pub perp_liquidations: AtomicU64,
+    pub spot_liquidations: AtomicU64,
+    /// liquidations that landed but were rejected by the program
+    pub failed_liquidations: AtomicU64,
+    /// liquidations that never landed, even after re-signing
+    pub expired_liquidations: AtomicU64,
+    /// liquidations a dry run logged instead of sending
+    pub dry_run_liquidations: AtomicU64,
+    pub bankruptcies: AtomicU64,
+    /// the liquidator's expected share of the fees of landed liquidations, in quote precision
+    pub rewards: AtomicU64,
+    /// signature fees, priority fees and tips of the liquidation transactions that landed, failed ones included
+    pub fees_lamports: AtomicU64,
+    /// the tips among them
+    pub tips_lamports: AtomicU64,
+    /// the payer's balance as of its last check
+    pub payer_balance_lamports: AtomicU64,
+    /// rewards and the marked pnl of inherited positions less the fees valued in quote, in quote precision
+    pub pnl: AtomicI64,
+    /// the liquidator's own total collateral as of the last round of updates, in quote precision
+    pub liquidator_collateral: AtomicI64,
+    /// and its margin ratio, 0 without open positions
+    pub liquidator_margin_ratio: AtomicU64,
+    /// deposits topping the liquidator's own account back up
+    pub collateral_deposits: AtomicU64,
+    /// and withdrawals taking what piled up over its ceiling out
+    pub collateral_withdrawals: AtomicU64,
+    /// orders sent to close positions the liquidator inherited
+    pub unwind_orders: AtomicU64,
+    /// orders sent to the hedging venue offsetting or closing the positions that are too big to unwind
+    pub hedge_orders: AtomicU64,
+    /// the shard of the users this instance takes as "<index>/<count>", telling the counters of the instances
+    /// splitting them apart for adding up
+    pub shard: Mutex<Option<String>>,
+    /// bad debt left behind by landed liquidations and resolved bankruptcies per market, in quote precision
+    pub bad_debt: Mutex<BTreeMap<String, u128>>,
+}
+
+// usdc, the quote asset of both protocols, has 6 decimals
+pub const QUOTE_PRECISION: f64 = 1_000_000.0;
+
+pub static METRICS: Metrics = Metrics::new();
+
+impl Metrics {
+    const fn new() -> Metrics {
+        Metrics {
+            candidates: AtomicU64::new(0),
+            unprofitable_candidates: AtomicU64::new(0),
+            oracle_guarded_candidates: AtomicU64::new(0),
+            exposure_capped_candidates: AtomicU64::new(0),
+            circuit_breaks: AtomicU64::new(0),
+            watchdog_restarts: AtomicU64::new(0),
+            evaluation_errors: AtomicU64::new(0),
+            prediction_errors: AtomicU64::new(0),
+            evicted_accounts: AtomicU64::new(0),
+            partial_liquidations: AtomicU64::new(0),
+            full_liquidations: AtomicU64::new(0),
+            perp_liquidations: AtomicU64::new(0),
+            spot_liquidations: AtomicU64::new(0),
+            failed_liquidations: AtomicU64::new(0),
+            expired_liquidations: AtomicU64::new(0),
+            dry_run_liquidations: AtomicU64::new(0),
+            bankruptcies: AtomicU64::new(0),
+            rewards: AtomicU64::new(0),
+            fees_lamports: AtomicU64::new(0),
+            tips_lamports: AtomicU64::new(0),
+            payer_balance_lamports: AtomicU64::new(0),
+            pnl: AtomicI64::new(0),
+            liquidator_collateral: AtomicI64::new(0),
+            liquidator_margin_ratio: AtomicU64::new(0),
+            collateral_deposits: AtomicU64::new(0),
+            collateral_withdrawals: AtomicU64::new(0),
+            unwind_orders: AtomicU64::new(0),
+            hedge_orders: AtomicU64::new(0),
+            shard: Mutex::new(None),
+            bad_debt: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Counts a landed liquidation under its type, adding up any bad debt it left behind
+    pub fn landed(&self, liquidation: &Liquidation) {
+        let counter = match liquidation.kind() {
+            "partial" => &self.partial_liquidations,
+            "full" => &self.full_liquidations,
+            "perp" => &self.perp_liquidations,
+            "spot" => &self.spot_liquidations,
+            _ => &self.bankruptcies,
+        };
+        increment(counter);
+
+        if let Some((market, bad_debt)) = liquidation.bad_debt() {
+            *self.bad_debt.lock().unwrap().entry(market).or_default() += bad_debt;
+        }
+    }
+
+    /// Landed liquidations of every type
+    pub fn landed_liquidations(&self) -> u64 {
+        [&self.partial_liquidations, &self.full_liquidations, &self.perp_liquidations, &self.spot_liquidations, &self.bankruptcies]
+            .iter()
+            .map(|counter| counter.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Bad debt per market, in dollars
+    pub fn bad_debt_summary(&self) -> String {
+        self.bad_debt
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(market, bad_debt)| format!("{} ${:.2}", market, *bad_debt as f64 / QUOTE_PRECISION))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// One line summary of every counter
+    pub fn summary(&self) -> String {
+        let shard = self.shard.lock().unwrap().as_ref().map(|shard| format!("shard {} ", shard)).unwrap_or_default();
+        format!(
+            "{}evicted {} candidates {} unprofitable {} oracle guarded {} circuit breaks {} watchdog restarts {} evaluation errors {} liquidations partial {} full {} perp {} spot {} bankruptcies {} failed {} expired {} rewards ${:.2} fees {} lamports pnl ${:.2}",
+            shard,
+            self.evicted_accounts.load(Ordering::Relaxed),
+            self.candidates.load(Ordering::Relaxed),
+            self.unprofitable_candidates.load(Ordering::Relaxed),
+            self.oracle_guarded_candidates.load(Ordering::Relaxed),
+            self.circuit_breaks.load(Ordering::Relaxed),
+            self.watchdog_restarts.load(Ordering::Relaxed),
+            self.evaluation_errors.load(Ordering::Relaxed),
+            self.partial_liquidations.load(Ordering::Relaxed),
+            self.full_liquidations.load(Ordering::Relaxed),
+            self.perp_liquidations.load(Ordering::Relaxed),
+            self.spot_liquidations.load(Ordering::Relaxed),
+            self.bankruptcies.load(Ordering::Relaxed),
+            self.failed_liquidations.load(Ordering::Relaxed),
+            self.expired_liquidations.load(Ordering::Relaxed),
+            self.rewards.load(Ordering::Relaxed) as f64 / QUOTE_PRECISION,
+            self.fees_lamports.load(Ordering::Relaxed),
+            self.pnl.load(Ordering::Relaxed) as f64 / QUOTE_PRECISION,
+        )
+    }
+}
+
+pub fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
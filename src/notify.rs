@@ -0,0 +1,188 @@
+use std::{error::Error, sync::{Arc, OnceLock}, time::Duration};
+
+use async_trait::async_trait;
+use serde_json::json;
+use tokio::{sync::mpsc::{channel, error::TrySendError, Receiver, Sender}, task::JoinHandle, time::{sleep, Instant}};
+use tracing::warn;
+
+use crate::rpc::RpcPool;
+
+// events waiting for the next batch, more are dropped rather than holding up the stage that raised them
+const EVENT_CHANNEL_SIZE: usize = 1024;
+// discord refuses longer messages
+const DISCORD_MAX_MESSAGE_LEN: usize = 2000;
+// slack truncates longer messages
+const SLACK_MAX_MESSAGE_LEN: usize = 4000;
+
+pub type NotifyResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Info,
+    Critical,
+}
+
+/// Something the operator should hear about, e.g. a landed liquidation or the rpc going down
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub severity: Severity,
+    pub text: String,
+}
+
+impl Event {
+    pub fn info(text: String) -> Event {
+        Event { severity: Severity::Info, text }
+    }
+
+    pub fn critical(text: String) -> Event {
+        Event { severity: Severity::Critical, text }
+    }
+}
+
+/// A channel events are pushed to
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Delivers a batch of events, as few messages as the channel allows
+    async fn send(&self, events: &[Event]) -> NotifyResult;
+}
+
+static EVENTS: OnceLock<Sender<Event>> = OnceLock::new();
+
+/// Hands the event to the notifiers, if any are configured
+pub fn notify(event: Event) {
+    if let Some(events) = EVENTS.get() {
+        if let Err(TrySendError::Full(event)) = events.try_send(event) {
+            warn!(text = event.text.as_str(), "dropping notification, too many queued");
+        }
+    }
+}
+
+/// Sends the events `notify` receives to every notifier, batched so that each gets at most one delivery every
+/// `batch_interval`, which also keeps the channels' rate limits at bay
+pub fn spawn(notifiers: Vec<Box<dyn Notifier>>, batch_interval: Duration) -> JoinHandle<()> {
+    let (sender, receiver) = channel(EVENT_CHANNEL_SIZE);
+    if EVENTS.set(sender).is_err() {
+        warn!("notifications were already set up");
+    }
+    tokio::spawn(dispatch(notifiers, receiver, batch_interval))
+}
+
+async fn dispatch(notifiers: Vec<Box<dyn Notifier>>, mut receiver: Receiver<Event>, batch_interval: Duration) {
+    let mut last_batch: Option<Instant> = None;
+    while let Some(event) = receiver.recv().await {
+        if let Some(last_batch) = last_batch {
+            sleep((last_batch + batch_interval).saturating_duration_since(Instant::now())).await;
+        }
+        let mut events = vec![event];
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        last_batch = Some(Instant::now());
+
+        for notifier in &notifiers {
+            if let Err(err) = notifier.send(&events).await {
+                warn!(notifier = notifier.name(), error = %err, "failed to send notifications");
+            }
+        }
+    }
+}
+
+/// Raises a critical event when every rpc endpoint is down, and clears it with an info event once they are
+/// back. The payer's balance is watched by `balance::spawn`
+pub fn spawn_watch(rpc: Arc<RpcPool>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut rpc_down = false;
+        loop {
+            sleep(interval).await;
+
+            let down = !rpc.status().iter().any(|endpoint| endpoint.healthy);
+            if down != rpc_down {
+                rpc_down = down;
+                notify(if down { Event::critical("every rpc endpoint is down".to_string()) } else { Event::info("rpc endpoints are back up".to_string()) });
+            }
+        }
+    })
+}
+
+/// Posts events to a discord channel through its webhook, critical ones marked as such
+pub struct DiscordNotifier {
+    pub webhook_url: String,
+    pub http: reqwest::Client,
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    async fn send(&self, events: &[Event]) -> NotifyResult {
+        let lines = events.iter().map(|event| match event.severity {
+            Severity::Info => event.text.clone(),
+            Severity::Critical => format!(":rotating_light: **{}**", event.text),
+        });
+        for content in chunks(lines, DISCORD_MAX_MESSAGE_LEN) {
+            let response = self.http.post(&self.webhook_url).json(&json!({ "content": content })).send().await?;
+            // rate limited, the rest waits for the next batch's turn
+            if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err("rate limited by discord".into());
+            }
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+/// Posts events to slack through incoming webhooks, critical ones to a channel of their own if there is one
+pub struct SlackNotifier {
+    pub webhook_url: String,
+    /// where critical events go, `webhook_url` if None
+    pub critical_webhook_url: Option<String>,
+    pub http: reqwest::Client,
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    async fn send(&self, events: &[Event]) -> NotifyResult {
+        let critical_webhook_url = self.critical_webhook_url.as_ref().unwrap_or(&self.webhook_url);
+        let routes = [(Severity::Info, &self.webhook_url), (Severity::Critical, critical_webhook_url)];
+        for (severity, webhook_url) in routes {
+            let lines = events.iter().filter(|event| event.severity == severity).map(|event| match severity {
+                Severity::Info => event.text.clone(),
+                Severity::Critical => format!(":rotating_light: *{}*", event.text),
+            });
+            for text in chunks(lines, SLACK_MAX_MESSAGE_LEN) {
+                self.http.post(webhook_url).json(&json!({ "text": text })).send().await?.error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Joins lines into messages of at most `max_len` bytes, cutting lines that are longer on their own
+pub fn chunks(lines: impl Iterator<Item = String>, max_len: usize) -> Vec<String> {
+    let mut messages: Vec<String> = vec![];
+    for mut line in lines {
+        if line.len() > max_len {
+            let mut end = max_len;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+        match messages.last_mut() {
+            Some(message) if message.len() + 1 + line.len() <= max_len => {
+                message.push('\n');
+                message.push_str(&line);
+            }
+            _ => messages.push(line),
+        }
+    }
+    messages
+}
@@ -0,0 +1,179 @@
+use std::{str::FromStr, time::Duration};
+
+use futures::{stream::select_all, StreamExt};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{nonblocking::pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{account::Account, commitment_config::{CommitmentConfig, CommitmentLevel}, pubkey::Pubkey};
+use tokio::{sync::mpsc::Sender, task::JoinHandle, time::sleep};
+use tracing::warn;
+
+use crate::source::{AccountUpdate, DataSource};
+
+// drift v2 prices carry 6 decimals
+const PRICE_DECIMALS: i32 = 6;
+
+pub const PYTH_PROGRAM_ID: &str = "FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH";
+pub const SWITCHBOARD_PROGRAM_ID: &str = "SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f";
+// the same programs on devnet, whose price accounts local test validators usually clone
+pub const PYTH_DEVNET_PROGRAM_ID: &str = "gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s";
+pub const SWITCHBOARD_DEVNET_PROGRAM_ID: &str = "2TfB33aLaneQb5TNVwyDz3jSZXS6jdW2ARw1Dgf84XCG";
+
+// pyth price account layout (v2)
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_PRICE_ACCOUNT: u32 = 3;
+const PYTH_TRADING: u32 = 1;
+const PYTH_EXPONENT: usize = 20;
+const PYTH_AGGREGATE_PRICE: usize = 208;
+const PYTH_AGGREGATE_CONFIDENCE: usize = 216;
+const PYTH_AGGREGATE_STATUS: usize = 224;
+const PYTH_AGGREGATE_PUBLISH_SLOT: usize = 232;
+
+// switchboard v2 aggregator layout: the latest confirmed round sits behind the discriminator and the aggregator's
+// configuration. its result and standard deviation are decimals of an i128 mantissa and a u32 scale
+const SWITCHBOARD_ROUND_OPEN_SLOT: usize = 350;
+const SWITCHBOARD_RESULT: usize = 366;
+const SWITCHBOARD_STD_DEVIATION: usize = 386;
+
+/// The oracle programs drift markets price off
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OracleKind {
+    Pyth,
+    Switchboard,
+}
+
+impl OracleKind {
+    /// Tells the oracle type apart by the program owning the price account, on mainnet-beta or devnet
+    pub fn of(owner: &Pubkey) -> Option<OracleKind> {
+        let owned_by = |programs: [&str; 2]| programs.iter().any(|program| *owner == Pubkey::from_str(program).unwrap());
+        if owned_by([PYTH_PROGRAM_ID, PYTH_DEVNET_PROGRAM_ID]) {
+            Some(OracleKind::Pyth)
+        } else if owned_by([SWITCHBOARD_PROGRAM_ID, SWITCHBOARD_DEVNET_PROGRAM_ID]) {
+            Some(OracleKind::Switchboard)
+        } else {
+            None
+        }
+    }
+}
+
+/// An oracle's latest price, in drift's price precision
+#[derive(Clone, Copy, Debug)]
+pub struct OraclePrice {
+    pub price: i64,
+    /// how far either side of `price` the true price may be
+    pub confidence: i64,
+    /// slot the price was published at
+    pub slot: u64,
+}
+
+/// The price in an oracle account, decoded by the type its owner says it is
+pub fn price(owner: &Pubkey, data: &[u8]) -> Option<OraclePrice> {
+    match OracleKind::of(owner)? {
+        OracleKind::Pyth => pyth_price(data),
+        OracleKind::Switchboard => switchboard_price(data),
+    }
+}
+
+/// The aggregate price of a Pyth price account. None if the data isn't a Pyth price account or the price isn't
+/// currently trading, e.g. because too few publishers reported.
+pub fn pyth_price(data: &[u8]) -> Option<OraclePrice> {
+    if read_u32(data, 0)? != PYTH_MAGIC || read_u32(data, 8)? != PYTH_PRICE_ACCOUNT || read_u32(data, PYTH_AGGREGATE_STATUS)? != PYTH_TRADING {
+        return None;
+    }
+    let exponent = read_u32(data, PYTH_EXPONENT)? as i32;
+    Some(OraclePrice {
+        price: scale(read_u64(data, PYTH_AGGREGATE_PRICE)? as i64, exponent)?,
+        confidence: scale(read_u64(data, PYTH_AGGREGATE_CONFIDENCE)? as i64, exponent)?,
+        slot: read_u64(data, PYTH_AGGREGATE_PUBLISH_SLOT)?,
+    })
+}
+
+/// The result of a Switchboard aggregator's latest confirmed round, its standard deviation as the confidence
+pub fn switchboard_price(data: &[u8]) -> Option<OraclePrice> {
+    Some(OraclePrice {
+        price: switchboard_decimal(data, SWITCHBOARD_RESULT)?,
+        confidence: switchboard_decimal(data, SWITCHBOARD_STD_DEVIATION)?,
+        slot: read_u64(data, SWITCHBOARD_ROUND_OPEN_SLOT)?,
+    })
+}
+
+// a switchboard decimal in drift's price precision
+fn switchboard_decimal(data: &[u8], offset: usize) -> Option<i64> {
+    let mantissa = i128::from_le_bytes(data.get(offset..offset + 16)?.try_into().ok()?);
+    let scale_decimals = read_u32(data, offset + 16)?;
+    let value = mantissa.checked_mul(10i128.pow(PRICE_DECIMALS as u32))? / 10i128.checked_pow(scale_decimals)?;
+    value.try_into().ok()
+}
+
+// rescales a price with `exponent` decimals to drift's price precision
+fn scale(price: i64, exponent: i32) -> Option<i64> {
+    let shift = PRICE_DECIMALS + exponent;
+    if shift >= 0 {
+        price.checked_mul(10i64.checked_pow(shift as u32)?)
+    } else {
+        Some(price / 10i64.checked_pow(shift.unsigned_abs())?)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// Streams writes to the Pyth and Switchboard accounts the markets price off through the rpc node's
+/// `accountSubscribe` websocket, so prices move at the oracle tick rather than whenever someone next touches the
+/// market account
+pub struct OracleSource {
+    pub url: String,
+    pub oracles: Vec<Pubkey>,
+    pub commitment: CommitmentLevel,
+}
+
+impl DataSource for OracleSource {
+    fn spawn(&self, sender: Sender<AccountUpdate>) -> JoinHandle<()> {
+        let url = self.url.clone();
+        let oracles = self.oracles.clone();
+        let commitment = self.commitment;
+        tokio::spawn(async move {
+            loop {
+                match stream_oracles(&url, &oracles, commitment, &sender).await {
+                    // the receiving end is gone, nothing left to stream to
+                    Ok(()) => return,
+                    Err(err) => warn!(error = %err, "oracle subscription failed"),
+                }
+                sleep(Duration::from_secs(1)).await;
+            }
+        })
+    }
+}
+
+/// Runs one subscription per oracle over a single websocket until it drops or `sender` is closed
+async fn stream_oracles(url: &str, oracles: &[Pubkey], commitment: CommitmentLevel, sender: &Sender<AccountUpdate>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = PubsubClient::new(url).await?;
+    let mut streams = vec![];
+    for oracle in oracles {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig { commitment }),
+            ..RpcAccountInfoConfig::default()
+        };
+        let (stream, _unsubscribe) = client.account_subscribe(oracle, Some(config)).await?;
+        streams.push(stream.map(move |response| (*oracle, response)));
+    }
+
+    let mut updates = select_all(streams);
+    while let Some((pubkey, response)) = updates.next().await {
+        let account = match response.value.decode::<Account>() {
+            Some(account) => account,
+            None => continue,
+        };
+        let update = AccountUpdate { pubkey, owner: account.owner, data: account.data, lamports: account.lamports, slot: response.context.slot };
+        if sender.send(update).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    Err("oracle subscription closed".into())
+}
@@ -0,0 +1,139 @@
+use std::io;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::config::OutputFormat;
+
+/// Prints a command's results, one row each: an aligned table under a header, csv with a header row, or a json
+/// array. Rows are serialized with serde, so their fields and field order make the columns.
+pub fn rows<T: Serialize>(format: OutputFormat, rows: &[T]) {
+    let printed = match format {
+        OutputFormat::Json => print_json(rows),
+        OutputFormat::Csv => objects(rows).and_then(|rows| print_csv(&rows)),
+        OutputFormat::Table => objects(rows).map(|rows| print_table(&rows)),
+    };
+    if let Err(err) = printed {
+        warn!(error = %err, "failed to print the output");
+    }
+}
+
+/// Prints a single result, e.g. one user's breakdown: as a json object, as its fields one per line with its
+/// lists of rows as tables under them, or as csv `field,value` rows with the lists' fields keyed
+/// `<list>.<index>.<field>`
+pub fn record<T: Serialize>(format: OutputFormat, record: &T) {
+    let printed = match format {
+        OutputFormat::Json => print_json(record),
+        OutputFormat::Csv => object(record).and_then(|record| print_fields_csv(&record)),
+        OutputFormat::Table => object(record).map(|record| print_fields(&record)),
+    };
+    if let Err(err) = printed {
+        warn!(error = %err, "failed to print the output");
+    }
+}
+
+type OutputResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> OutputResult<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn object<T: Serialize>(record: &T) -> OutputResult<Map<String, Value>> {
+    match serde_json::to_value(record)? {
+        Value::Object(object) => Ok(object),
+        _ => Err("the output isn't a record".into()),
+    }
+}
+
+fn objects<T: Serialize>(rows: &[T]) -> OutputResult<Vec<Map<String, Value>>> {
+    rows.iter().map(object).collect()
+}
+
+// a value as a table or csv cell, fractional numbers to four places
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        Value::Number(number) if number.is_f64() => format!("{:.4}", number.as_f64().unwrap_or_default()),
+        other => other.to_string(),
+    }
+}
+
+fn print_csv(rows: &[Map<String, Value>]) -> OutputResult<()> {
+    let mut writer = csv::Writer::from_writer(io::stdout());
+    if let Some(first) = rows.first() {
+        writer.write_record(first.keys())?;
+    }
+    for row in rows {
+        writer.write_record(row.values().map(cell))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// columns of numbers are right aligned, the rest left
+fn print_table(rows: &[Map<String, Value>]) {
+    let headers: Vec<&String> = match rows.first() {
+        Some(first) => first.keys().collect(),
+        None => return,
+    };
+    let cells: Vec<Vec<String>> = rows.iter().map(|row| row.values().map(cell).collect()).collect();
+    let widths: Vec<usize> = (0..headers.len()).map(|column| cells.iter().map(|row| row.get(column).map_or(0, String::len)).chain([headers[column].len()]).max().unwrap_or(0)).collect();
+    let numeric: Vec<bool> = (0..headers.len()).map(|column| rows.iter().all(|row| matches!(row.values().nth(column), Some(Value::Number(_) | Value::Null)))).collect();
+    let line = |row: Vec<&str>| {
+        row.iter()
+            .enumerate()
+            .map(|(column, value)| if numeric[column] { format!("{:>width$}", value, width = widths[column]) } else { format!("{:<width$}", value, width = widths[column]) })
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", line(headers.iter().map(|header| header.as_str()).collect()));
+    for row in &cells {
+        println!("{}", line(row.iter().map(String::as_str).collect()));
+    }
+}
+
+// the record's plain fields aligned one per line, then each list of rows as a table under its name
+fn print_fields(record: &Map<String, Value>) {
+    let plain: Vec<(&String, &Value)> = record.iter().filter(|(_, value)| !value.is_array()).collect();
+    let width = plain.iter().map(|(field, _)| field.len()).max().unwrap_or(0);
+    for (field, value) in plain {
+        println!("{:<width$}  {}", field, cell(value), width = width);
+    }
+    for (field, value) in record.iter().filter(|(_, value)| value.is_array()) {
+        let rows: Vec<Map<String, Value>> = value.as_array().into_iter().flatten().filter_map(|row| row.as_object().cloned()).collect();
+        println!("\n{}:", field);
+        if rows.is_empty() {
+            println!("  none");
+        } else {
+            print_table(&rows);
+        }
+    }
+}
+
+fn print_fields_csv(record: &Map<String, Value>) -> OutputResult<()> {
+    let mut fields = vec![];
+    for (field, value) in record {
+        flatten(field, value, &mut fields);
+    }
+    let mut writer = csv::Writer::from_writer(io::stdout());
+    writer.write_record(["field", "value"])?;
+    for (field, value) in fields {
+        writer.write_record([field, value])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// the value's fields as `field,value` pairs, nested ones under dotted keys
+fn flatten(prefix: &str, value: &Value, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(object) => object.iter().for_each(|(field, value)| flatten(&format!("{}.{}", prefix, field), value, fields)),
+        Value::Array(array) => array.iter().enumerate().for_each(|(index, value)| flatten(&format!("{}.{}", prefix, index), value, fields)),
+        value => fields.push((prefix.to_string(), cell(value))),
+    }
+}
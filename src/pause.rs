@@ -0,0 +1,20 @@
+use std::{collections::BTreeSet, sync::Mutex};
+
+// why sends are held back, e.g. an operator asked for it. Any reason pauses the executor
+static REASONS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Holds back liquidation sends until every reason they were paused for is resumed. False if sends were
+/// already paused for this reason.
+pub fn pause(reason: &'static str) -> bool {
+    REASONS.lock().unwrap().insert(reason)
+}
+
+/// Lifts one reason for holding back sends. False if sends weren't paused for it.
+pub fn resume(reason: &'static str) -> bool {
+    REASONS.lock().unwrap().remove(reason)
+}
+
+/// Why sends are paused, empty while they aren't
+pub fn reasons() -> Vec<&'static str> {
+    REASONS.lock().unwrap().iter().copied().collect()
+}
@@ -0,0 +1,276 @@
+use std::{collections::BTreeMap, error::Error, sync::{atomic::Ordering, Mutex}};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+use crate::{config::{Config, PnlArgs}, history::{self, Attempt, SentTransaction}, metrics::{METRICS, QUOTE_PRECISION}, output, watchdog};
+
+/// What the liquidator earned and spent on one market over one utc day, in quote precision unless noted
+#[derive(Clone, Default, Serialize)]
+pub struct PnlEntry {
+    /// liquidations that landed
+    pub liquidations: u64,
+    pub rewards: u64,
+    /// signature and priority fees of the transactions sent, landed or failed
+    pub fees_lamports: u64,
+    pub tips_lamports: u64,
+    /// the fees and tips valued at the SOL price when they were paid
+    pub fees: u64,
+    /// change in the unrealized pnl of the perp positions taken over from liquidated users, the reward they came
+    /// with aside
+    pub inherited_pnl: i64,
+}
+
+impl PnlEntry {
+    /// Rewards and inherited pnl less fees
+    pub fn net(&self) -> i64 {
+        self.rewards as i64 + self.inherited_pnl - self.fees as i64
+    }
+
+    fn add(&mut self, other: &PnlEntry) {
+        self.liquidations += other.liquidations;
+        self.rewards += other.rewards;
+        self.fees_lamports += other.fees_lamports;
+        self.tips_lamports += other.tips_lamports;
+        self.fees += other.fees;
+        self.inherited_pnl += other.inherited_pnl;
+    }
+
+    /// One line summary in dollars and SOL
+    pub fn summary(&self) -> String {
+        format!(
+            "{} liquidations rewards ${:.2} fees {:.6} SOL tips {:.6} SOL (${:.2}) inherited ${:.2} net ${:.2}",
+            self.liquidations,
+            self.rewards as f64 / QUOTE_PRECISION,
+            self.fees_lamports as f64 / LAMPORTS_PER_SOL as f64,
+            self.tips_lamports as f64 / LAMPORTS_PER_SOL as f64,
+            self.fees as f64 / QUOTE_PRECISION,
+            self.inherited_pnl as f64 / QUOTE_PRECISION,
+            self.net() as f64 / QUOTE_PRECISION
+        )
+    }
+}
+
+/// One liquidation of a resolved transaction
+pub struct Settled {
+    /// as `market_name` puts it
+    pub market: String,
+    /// perp market whose position the liquidator took over, if any
+    pub perp_market_index: Option<u16>,
+    pub landed: bool,
+    /// the liquidator's reward, 0 unless the transaction landed
+    pub reward: u64,
+    /// quote price of one SOL when the liquidation was evaluated
+    pub sol_price: u64,
+}
+
+/// PnL by day and market as of the liquidator's start, for the status api and `pnl` to report
+#[derive(Serialize)]
+pub struct PnlReport {
+    pub days: Vec<PnlDay>,
+    /// unrealized pnl of the liquidator's perp positions per market index, as last marked
+    pub unrealized_pnl: BTreeMap<u16, i64>,
+    pub total: PnlEntry,
+    pub net: i64,
+}
+
+#[derive(Serialize)]
+pub struct PnlDay {
+    /// e.g. 2024-01-31
+    pub day: String,
+    pub market: String,
+    #[serde(flatten)]
+    pub entry: PnlEntry,
+    pub net: i64,
+}
+
+/// Running profit and loss: rewards of landed liquidations, fees and tips paid, and the marked pnl of inherited
+/// positions, by day and market
+pub struct Pnl {
+    days: Mutex<BTreeMap<(String, String), PnlEntry>>,
+    marks: Mutex<Marks>,
+}
+
+#[derive(Default)]
+struct Marks {
+    // unrealized pnl per perp market as of the last mark
+    unrealized: BTreeMap<u16, i64>,
+    // rewards booked since the last mark that came as a discount on a taken over position, which the next mark
+    // sees as unrealized pnl
+    rewarded: BTreeMap<u16, i64>,
+}
+
+pub static PNL: Pnl = Pnl::new();
+
+/// The market a liquidation is booked under, from its type and v2 market index
+pub fn market_name(kind: &str, market_index: Option<u16>) -> String {
+    match (kind, market_index) {
+        ("perp" | "perp bankruptcy", Some(market_index)) => format!("perp market {}", market_index),
+        ("spot" | "spot bankruptcy", Some(market_index)) => format!("spot market {}", market_index),
+        _ => "clearing house".to_string(),
+    }
+}
+
+/// The utc date of a time in milliseconds since the unix epoch
+pub fn day(time_ms: u64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(time_ms as i64).map_or_else(String::new, |time| time.format("%Y-%m-%d").to_string())
+}
+
+impl Pnl {
+    const fn new() -> Pnl {
+        Pnl { days: Mutex::new(BTreeMap::new()), marks: Mutex::new(Marks { unrealized: BTreeMap::new(), rewarded: BTreeMap::new() }) }
+    }
+
+    /// Books a resolved transaction: its fee and tip split evenly over the liquidations it carried, and their
+    /// rewards
+    pub fn settle(&self, liquidations: &[Settled], fee_lamports: u64, tip_lamports: u64) {
+        if liquidations.is_empty() {
+            return;
+        }
+        let count = liquidations.len() as u64;
+        let today = day(watchdog::now_ms());
+        let mut days = self.days.lock().unwrap();
+        let mut marks = self.marks.lock().unwrap();
+        for liquidation in liquidations {
+            let (fee_lamports, tip_lamports) = (fee_lamports / count, tip_lamports / count);
+            let entry = PnlEntry {
+                liquidations: if liquidation.landed { 1 } else { 0 },
+                rewards: liquidation.reward,
+                fees_lamports,
+                tips_lamports,
+                fees: ((fee_lamports + tip_lamports) as u128 * liquidation.sol_price as u128 / LAMPORTS_PER_SOL as u128) as u64,
+                inherited_pnl: 0,
+            };
+            METRICS.pnl.fetch_add(entry.net(), Ordering::Relaxed);
+            days.entry((today.clone(), liquidation.market.clone())).or_default().add(&entry);
+            if let Some(market_index) = liquidation.perp_market_index {
+                *marks.rewarded.entry(market_index).or_default() += liquidation.reward as i64;
+            }
+        }
+    }
+
+    /// Marks the liquidator's perp positions to market, booking the change in each market's unrealized pnl since
+    /// the last mark. A market's first mark only sets where it starts from, so positions held before the
+    /// liquidator started don't count.
+    pub fn mark(&self, unrealized: BTreeMap<u16, i64>) {
+        let today = day(watchdog::now_ms());
+        let mut marks = self.marks.lock().unwrap();
+        let mut days = self.days.lock().unwrap();
+        let markets: Vec<u16> = marks.unrealized.keys().chain(unrealized.keys()).copied().collect();
+        for market_index in markets {
+            let now = unrealized.get(&market_index).copied().unwrap_or(0);
+            let before = match marks.unrealized.get(&market_index) {
+                Some(before) => *before,
+                None => {
+                    marks.unrealized.insert(market_index, now);
+                    marks.rewarded.remove(&market_index);
+                    continue;
+                }
+            };
+            let change = now - before - marks.rewarded.remove(&market_index).unwrap_or(0);
+            marks.unrealized.insert(market_index, now);
+            if change != 0 {
+                METRICS.pnl.fetch_add(change, Ordering::Relaxed);
+                days.entry((today.clone(), market_name("perp", Some(market_index)))).or_default().inherited_pnl += change;
+            }
+        }
+    }
+
+    pub fn report(&self) -> PnlReport {
+        let days = self.days.lock().unwrap();
+        let mut total = PnlEntry::default();
+        for entry in days.values() {
+            total.add(entry);
+        }
+        PnlReport {
+            days: days.iter().map(|((day, market), entry)| PnlDay { day: day.clone(), market: market.clone(), entry: entry.clone(), net: entry.net() }).collect(),
+            unrealized_pnl: self.marks.lock().unwrap().unrealized.clone(),
+            net: total.net(),
+            total,
+        }
+    }
+}
+
+/// Adds up, by day and market, what the transactions in the history earned and paid. The history holds no marks,
+/// so inherited positions' pnl is only tracked live.
+pub fn from_history(transactions: &[SentTransaction], attempts: &[Attempt]) -> PnlReport {
+    let mut by_signature: BTreeMap<&str, Vec<&Attempt>> = BTreeMap::new();
+    for attempt in attempts {
+        by_signature.entry(attempt.signature.as_str()).or_default().push(attempt);
+    }
+
+    let mut days: BTreeMap<(String, String), PnlEntry> = BTreeMap::new();
+    for transaction in transactions.iter().filter(|transaction| transaction.outcome.is_some()) {
+        let attempts = match by_signature.get(transaction.signature.as_str()) {
+            Some(attempts) => attempts,
+            None => continue,
+        };
+        let landed = transaction.outcome.as_deref() == Some("landed");
+        let fee_lamports = transaction.fee_lamports as u64 / attempts.len() as u64;
+        let tip_lamports = transaction.tip_lamports as u64 / attempts.len() as u64;
+        for attempt in attempts {
+            let entry = days.entry((day(transaction.sent_ms as u64), market_name(&attempt.liquidation, attempt.market_index.map(|market_index| market_index as u16)))).or_default();
+            if landed {
+                entry.liquidations += 1;
+                entry.rewards += attempt.expected_reward as u64;
+            }
+            entry.fees_lamports += fee_lamports;
+            entry.tips_lamports += tip_lamports;
+            entry.fees += ((fee_lamports + tip_lamports) as u128 * transaction.sol_price as u128 / LAMPORTS_PER_SOL as u128) as u64;
+        }
+    }
+
+    let mut total = PnlEntry::default();
+    for entry in days.values() {
+        total.add(entry);
+    }
+    PnlReport {
+        days: days.into_iter().map(|((day, market), entry)| PnlDay { day, market, net: entry.net(), entry }).collect(),
+        unrealized_pnl: BTreeMap::new(),
+        net: total.net(),
+        total,
+    }
+}
+
+// a day and market of the pnl as `pnl` prints it, in dollars and SOL
+#[derive(Serialize)]
+struct PnlRow<'a> {
+    day: &'a str,
+    market: &'a str,
+    liquidations: u64,
+    rewards: f64,
+    fees_sol: f64,
+    tips_sol: f64,
+    fees: f64,
+    inherited_pnl: f64,
+    net: f64,
+}
+
+impl<'a> PnlRow<'a> {
+    fn new(day: &'a str, market: &'a str, entry: &PnlEntry) -> PnlRow<'a> {
+        PnlRow {
+            day,
+            market,
+            liquidations: entry.liquidations,
+            rewards: entry.rewards as f64 / QUOTE_PRECISION,
+            fees_sol: entry.fees_lamports as f64 / LAMPORTS_PER_SOL as f64,
+            tips_sol: entry.tips_lamports as f64 / LAMPORTS_PER_SOL as f64,
+            fees: entry.fees as f64 / QUOTE_PRECISION,
+            inherited_pnl: entry.inherited_pnl as f64 / QUOTE_PRECISION,
+            net: entry.net() as f64 / QUOTE_PRECISION,
+        }
+    }
+}
+
+/// Prints the pnl of the history in the configured store, a row per day and market and a last one with the
+/// total
+pub async fn print(config: &Config, args: &PnlArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let store = history::store(config).await.ok_or("set history_path or history_postgres_url to add up the history")?;
+    let (from_ms, to_ms) = (args.from.unwrap_or(0), args.to.unwrap_or(i64::MAX));
+    let report = from_history(&store.transactions(from_ms, to_ms).await?, &store.attempts(from_ms, to_ms).await?);
+    let mut rows: Vec<PnlRow> = report.days.iter().map(|day| PnlRow::new(&day.day, &day.market, &day.entry)).collect();
+    rows.push(PnlRow::new("total", "", &report.total));
+    output::rows(args.output, &rows);
+    Ok(())
+}
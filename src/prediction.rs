@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+
+use clearing_house::{math::{collateral::calculate_updated_collateral, position::calculate_base_asset_value_and_pnl}, state::{market::{Markets, AMM}, state::State, user::{MarketPosition, User, UserPositions}}};
+
+use crate::{error::{LiquidatorError, Result}, reward::Reward, risk::{calculate_margin_ratio, LiquidationType}};
+
+// converts quote asset reserves into quote precision at the peg: AMM_RESERVE_PRECISION * PEG_PRECISION /
+// QUOTE_PRECISION
+const AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO: u128 = 10_000_000_000;
+
+/// What a v1 `liquidate` leaves behind, worked out the way the clearing house does it: every position closed
+/// against its amm under the maintenance ratio, or the partial close percentage of each one's value under the
+/// partial ratio, the realized pnl settled into the collateral and the penalty on the total collateral taken
+/// out of it and split between the liquidator and the insurance vault. Oracle guards, the vault balances of
+/// the transfers and the markets' open interest tallies are left out.
+pub struct Prediction {
+    pub liquidation_type: LiquidationType,
+    pub reward: Reward,
+    /// the user and its positions after the liquidation, funding settled
+    pub user: User,
+    pub user_positions: UserPositions,
+    /// the amms of the markets positions were closed in, as the closing trades left them
+    pub amms: BTreeMap<u64, AMM>,
+    /// value of the base asset closed, in quote precision
+    pub base_asset_value_closed: u128,
+}
+
+/// Predicts the liquidation of a user whose funding payments are settled, None if it isn't liquidatable. The
+/// user's own accounts are copies, the markets aren't touched.
+pub fn v1(state: &State, markets: &Markets, user: &User, user_positions: &UserPositions) -> Result<Option<Prediction>> {
+    let mut user = user.clone();
+    let mut user_positions = *user_positions;
+    let (total_collateral, _, base_asset_value, margin_ratio) = calculate_margin_ratio(&user, &mut user_positions, markets)?;
+    let liquidation_type = if margin_ratio <= state.margin_ratio_maintenance {
+        LiquidationType::Full
+    } else if margin_ratio <= state.margin_ratio_partial {
+        LiquidationType::Partial
+    } else {
+        return Ok(None);
+    };
+
+    let mut amms = BTreeMap::new();
+    let mut base_asset_value_closed: u128 = 0;
+    for market_position in user_positions.positions.iter_mut().filter(|position| position.base_asset_amount != 0) {
+        let mut amm = markets.markets.get(Markets::index_from_u64(market_position.market_index)).ok_or(LiquidatorError::UnknownMarket(market_position.market_index))?.amm;
+        let (closed, pnl) = match liquidation_type {
+            LiquidationType::Full => close(&mut amm, market_position)?,
+            LiquidationType::Partial => {
+                let (value, _) = calculate_base_asset_value_and_pnl(market_position, &amm)?;
+                let quote_asset_amount = value
+                    .checked_mul(state.partial_liquidation_close_percentage_numerator)
+                    .and_then(|value| value.checked_div(state.partial_liquidation_close_percentage_denominator))
+                    .ok_or(LiquidatorError::MathOverflow("computing the value to close"))?;
+                reduce(&mut amm, market_position, quote_asset_amount)?
+            }
+        };
+        user.collateral = calculate_updated_collateral(user.collateral, pnl)?;
+        base_asset_value_closed = base_asset_value_closed.checked_add(closed).ok_or(LiquidatorError::MathOverflow("adding up the value closed"))?;
+        amms.insert(market_position.market_index, amm);
+    }
+
+    let (fee, liquidator_share_denominator) = match liquidation_type {
+        LiquidationType::Full => (
+            mul_div(total_collateral, state.full_liquidation_penalty_percentage_numerator, state.full_liquidation_penalty_percentage_denominator)?,
+            state.full_liquidation_liquidator_share_denominator as u128,
+        ),
+        LiquidationType::Partial => {
+            // the partial penalty scales with how much of the targeted value was actually closed
+            let max_fee = mul_div(total_collateral, state.partial_liquidation_penalty_percentage_numerator, state.partial_liquidation_penalty_percentage_denominator)?;
+            let max_closed = mul_div(base_asset_value, state.partial_liquidation_close_percentage_numerator, state.partial_liquidation_close_percentage_denominator)?;
+            let fee = if max_closed == 0 { 0 } else { mul_div(max_fee, base_asset_value_closed, max_closed)? };
+            (fee, state.partial_liquidation_liquidator_share_denominator as u128)
+        }
+    };
+    let fee = fee.min(u64::MAX as u128);
+    let liquidator = fee.checked_div(liquidator_share_denominator).unwrap_or(0);
+    user.collateral = user.collateral.saturating_sub(fee);
+
+    Ok(Some(Prediction {
+        liquidation_type,
+        reward: Reward { liquidator: liquidator as u64, insurance_fund: (fee - liquidator) as u64 },
+        user,
+        user_positions,
+        amms,
+        base_asset_value_closed,
+    }))
+}
+
+// closes the whole position against the amm, returning the value closed and the pnl realized
+fn close(amm: &mut AMM, market_position: &mut MarketPosition) -> Result<(u128, i128)> {
+    let (value, pnl) = calculate_base_asset_value_and_pnl(market_position, amm)?;
+    // closing a long sells its base into the amm, closing a short buys it back out
+    let base_asset_amount = market_position.base_asset_amount.unsigned_abs();
+    let base_asset_reserve = if market_position.base_asset_amount > 0 { amm.base_asset_reserve.checked_add(base_asset_amount) } else { amm.base_asset_reserve.checked_sub(base_asset_amount) };
+    swap_base(amm, base_asset_reserve.ok_or(LiquidatorError::MathOverflow("swapping the base asset"))?)?;
+
+    market_position.base_asset_amount = 0;
+    market_position.quote_asset_amount = 0;
+    market_position.last_cumulative_funding_rate = 0;
+    Ok((value, pnl))
+}
+
+// trades `quote_asset_amount` of the position's value against the amm, returning it and the pnl realized on the
+// share of the position it closed
+fn reduce(amm: &mut AMM, market_position: &mut MarketPosition, quote_asset_amount: u128) -> Result<(u128, i128)> {
+    let quote_asset_reserve_amount = mul_div(quote_asset_amount, AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO, amm.peg_multiplier)?;
+    let long = market_position.base_asset_amount > 0;
+    // reducing a long sells base for the amm's quote, reducing a short pays quote in for its base
+    let quote_asset_reserve = if long { amm.quote_asset_reserve.checked_sub(quote_asset_reserve_amount) } else { amm.quote_asset_reserve.checked_add(quote_asset_reserve_amount) };
+    let quote_asset_reserve = quote_asset_reserve.ok_or(LiquidatorError::MathOverflow("swapping the quote asset"))?;
+    let base_asset_reserve = mul_div(amm.sqrt_k, amm.sqrt_k, quote_asset_reserve)?;
+    let base_asset_swapped = base_asset_reserve.abs_diff(amm.base_asset_reserve).min(market_position.base_asset_amount.unsigned_abs());
+    amm.base_asset_reserve = base_asset_reserve;
+    amm.quote_asset_reserve = quote_asset_reserve;
+
+    let quote_asset_amount_closed = mul_div(market_position.quote_asset_amount, base_asset_swapped, market_position.base_asset_amount.unsigned_abs())?;
+    market_position.quote_asset_amount -= quote_asset_amount_closed;
+    market_position.base_asset_amount -= if long { base_asset_swapped as i128 } else { -(base_asset_swapped as i128) };
+    let pnl = if long { quote_asset_amount as i128 - quote_asset_amount_closed as i128 } else { quote_asset_amount_closed as i128 - quote_asset_amount as i128 };
+    Ok((quote_asset_amount, pnl))
+}
+
+// moves the amm to `base_asset_reserve`, keeping its invariant
+fn swap_base(amm: &mut AMM, base_asset_reserve: u128) -> Result<()> {
+    amm.quote_asset_reserve = mul_div(amm.sqrt_k, amm.sqrt_k, base_asset_reserve)?;
+    amm.base_asset_reserve = base_asset_reserve;
+    Ok(())
+}
+
+// a * b / c rounded down, through a 256 bit product so the amm's invariant (sqrt_k squared) can't overflow
+fn mul_div(a: u128, b: u128, c: u128) -> Result<u128> {
+    if c == 0 {
+        return Err(LiquidatorError::MathOverflow("dividing by zero"));
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Ok(product / c);
+    }
+    let (high, low) = widening_mul(a, b);
+    if high >= c {
+        return Err(LiquidatorError::MathOverflow("multiplying past 128 bits"));
+    }
+    // long division of the 256 bit product, one bit at a time
+    let (mut remainder, mut quotient) = (high, 0u128);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Ok(quotient)
+}
+
+// the 256 bit product of a and b as its high and low halves
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_high, a_low, b_high, b_low) = (a >> 64, a & MASK, b >> 64, b & MASK);
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+    let middle = (low_low >> 64) + (high_low & MASK) + (low_high & MASK);
+    let low = (low_low & MASK) | (middle << 64);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+    (high, low)
+}
+
+#[cfg(test)]
+mod tests {
+    use clearing_house::state::market::Market;
+
+    use super::*;
+    use crate::fixtures::{state, FULL, PARTIAL};
+
+    // $1 a base unit: equal reserves at the peg precision, deep enough that a position barely moves the price
+    const RESERVE: u128 = 10_000_000_000_000_000_000;
+    const BASE: i128 = 10_000_000_000_000_000;
+
+    fn markets() -> Markets {
+        let mut markets = Markets::default();
+        markets.markets[0] = Market {
+            initialized: true,
+            amm: AMM { base_asset_reserve: RESERVE, quote_asset_reserve: RESERVE, sqrt_k: RESERVE, peg_multiplier: 1000, ..AMM::default() },
+            ..Market::default()
+        };
+        markets
+    }
+
+    // $100 of collateral behind a long of 1000 base opened for `entry`, worth $999.000999 closed
+    fn user(entry: u128) -> (User, UserPositions) {
+        let mut user_positions = UserPositions::default();
+        user_positions.positions[0] = MarketPosition { market_index: 0, base_asset_amount: BASE, quote_asset_amount: entry, ..MarketPosition::default() };
+        (User { collateral: 100_000_000, ..User::default() }, user_positions)
+    }
+
+    #[test]
+    fn mul_div_past_128_bits() {
+        // expected quotients of the exact 256 bit products
+        let cases = [
+            (u128::MAX, u128::MAX, u128::MAX, u128::MAX),
+            (10u128.pow(38), 10u128.pow(38), 10u128.pow(38), 10u128.pow(38)),
+            (u128::MAX, 3, 7, 145_835_300_108_973_627_198_589_117_470_757_804_909),
+            (1 << 127, 3, 1 << 100, 402_653_184),
+            (10u128.pow(38), RESERVE, RESERVE + BASE as u128, 99_900_099_900_099_900_099_900_099_900_099_900_099),
+        ];
+        for (a, b, c, expected) in cases {
+            assert_eq!(mul_div(a, b, c).unwrap(), expected, "{} * {} / {}", a, b, c);
+        }
+        assert_eq!(mul_div(7, 9, 4).unwrap(), 15);
+        assert!(mul_div(1 << 127, 4, 2).is_err());
+        assert!(mul_div(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn widening_mul_halves() {
+        assert_eq!(widening_mul(u128::MAX, u128::MAX), (u128::MAX - 1, 1));
+        assert_eq!(widening_mul(1 << 127, 2), (1, 0));
+        assert_eq!(widening_mul(10u128.pow(38), 10u128.pow(38)), (29_387_358_770_557_187_699_218_413_430_556_141_945, 158_788_995_957_577_343_786_214_718_011_688_878_080));
+    }
+
+    #[test]
+    fn full_liquidation() {
+        // opened at $1.06: 39.000999 of collateral left on 999.000999 of value, a 3.9% margin ratio
+        let (user, user_positions) = user(1_060_000_000);
+        let prediction = v1(&state(), &markets(), &user, &user_positions).unwrap().unwrap();
+
+        assert_eq!(prediction.liquidation_type, FULL.liquidation_type());
+        assert_eq!(prediction.reward, FULL.reward());
+        assert_eq!(prediction.base_asset_value_closed, FULL.base_asset_value_closed);
+        assert_eq!(prediction.user.collateral, FULL.total_collateral - FULL.reward().total() as u128);
+        assert_eq!(prediction.user_positions.positions[0].base_asset_amount, 0);
+        assert_eq!(prediction.user_positions.positions[0].quote_asset_amount, 0);
+        let amm = &prediction.amms[&0];
+        assert_eq!(amm.base_asset_reserve, RESERVE + BASE as u128);
+        assert_eq!(amm.quote_asset_reserve, 9_990_009_990_009_990_009);
+    }
+
+    #[test]
+    fn partial_liquidation() {
+        // opened at $1.04: a 5.9% margin ratio, between the maintenance and partial ratios
+        let (user, user_positions) = user(1_040_000_000);
+        let prediction = v1(&state(), &markets(), &user, &user_positions).unwrap().unwrap();
+
+        assert_eq!(prediction.liquidation_type, PARTIAL.liquidation_type());
+        // a quarter of the value closed, so the whole partial penalty
+        assert_eq!(prediction.base_asset_value_closed, PARTIAL.base_asset_value_closed);
+        assert_eq!(prediction.reward, PARTIAL.reward());
+        // the quarter sold for 249.750249 against the 259.805145 it cost
+        assert_eq!(prediction.user.collateral, 100_000_000 - 10_054_896 - 1_475_024);
+        assert_eq!(prediction.user_positions.positions[0].base_asset_amount, 7_501_873_602_310_144);
+        assert_eq!(prediction.user_positions.positions[0].quote_asset_amount, 780_194_855);
+        let amm = &prediction.amms[&0];
+        assert_eq!(amm.quote_asset_reserve, 9_997_502_497_510_000_000);
+        assert_eq!(amm.base_asset_reserve, 10_002_498_126_397_689_856);
+    }
+
+    #[test]
+    fn healthy_user() {
+        // opened at $1: over 10% margin ratio
+        let (user, user_positions) = user(1_000_000_000);
+        assert!(v1(&state(), &markets(), &user, &user_positions).unwrap().is_none());
+    }
+}
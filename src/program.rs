@@ -0,0 +1,31 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+
+use crate::{config::Config, v2};
+
+// the config's `program_id`, standing in for the protocol's own program once set
+static OVERRIDE: OnceLock<Pubkey> = OnceLock::new();
+
+/// Points the program ids at the config's `program_id`, once at startup. Panics on one that isn't a pubkey,
+/// which loading the config already rules out.
+pub fn init(config: &Config) {
+    if let Some(program_id) = &config.program_id {
+        let program_id = Pubkey::from_str(program_id).expect("program_id isn't a pubkey");
+        if OVERRIDE.set(program_id).is_err() {
+            warn!("the program id was already set");
+        }
+        info!(program_id = %program_id, "liquidating on a custom program id");
+    }
+}
+
+/// The v1 clearing house, `clearing_house::id()` unless overridden
+pub fn v1() -> Pubkey {
+    OVERRIDE.get().copied().unwrap_or_else(clearing_house::id)
+}
+
+/// The drift v2 program, `v2::PROGRAM_ID` unless overridden
+pub fn v2() -> Pubkey {
+    OVERRIDE.get().copied().unwrap_or_else(|| Pubkey::from_str(v2::PROGRAM_ID).unwrap())
+}
@@ -0,0 +1,51 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+
+use tokio::time::sleep;
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket allowing `requests_per_second` requests per second on average, with bursts of up to one
+/// second's worth of requests. A rate of 0 disables limiting.
+pub struct RateLimiter {
+    requests_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32) -> RateLimiter {
+        RateLimiter {
+            requests_per_second: requests_per_second as f64,
+            bucket: Mutex::new(Bucket {
+                tokens: requests_per_second as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a request may be made and takes its token
+    pub async fn acquire(&self) {
+        if self.requests_per_second == 0.0 {
+            return;
+        }
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.requests_per_second;
+                bucket.tokens = (bucket.tokens + refill).min(self.requests_per_second);
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.requests_per_second)
+            };
+            sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{error::Error, fs::File, io::{BufReader, BufWriter, Write}, sync::OnceLock, thread::{self, JoinHandle}};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+use tracing::{info, warn};
+
+use crate::{config::ProtocolKind, source::AccountUpdate, watchdog};
+
+// updates waiting to be written, more are dropped rather than holding up the risk stage
+const UPDATE_CHANNEL_SIZE: usize = 65_536;
+/// Bumped whenever the recording format changes
+pub const VERSION: u32 = 1;
+
+/// Opens a recording, written once at its start
+#[derive(Deserialize, Serialize)]
+pub struct Header {
+    pub version: u32,
+    pub protocol: ProtocolKind,
+    /// milliseconds since the unix epoch
+    pub started_ms: u64,
+}
+
+/// An account write as it was recorded
+#[derive(Deserialize, Serialize)]
+pub struct Recorded {
+    /// slot the write was observed at, 0 for the accounts the startup scan loaded, which carry none
+    pub slot: u64,
+    /// when the liquidator saw it, in milliseconds since the unix epoch
+    pub time_ms: u64,
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    /// empty for a closed account
+    pub data: Vec<u8>,
+}
+
+/// Archives every account write the risk stage folds into its cache to a gzipped file at `path`: a bincode
+/// `Header` followed by one bincode `Recorded` per write, the startup scan's accounts first. The stream is
+/// flushed whenever the queue runs dry, so all but the last few writes survive a crash.
+pub struct Recorder {
+    pub path: String,
+    pub protocol: ProtocolKind,
+}
+
+static UPDATES: OnceLock<Sender<Recorded>> = OnceLock::new();
+
+fn write(recorded: Recorded) {
+    if let Some(updates) = UPDATES.get() {
+        if let Err(TrySendError::Full(_)) = updates.try_send(recorded) {
+            warn!("dropping recorded update, too many queued");
+        }
+    }
+}
+
+/// Records an update from the stream, if recording
+pub fn update(update: &AccountUpdate) {
+    if UPDATES.get().is_none() {
+        return;
+    }
+    write(Recorded { slot: update.slot, time_ms: watchdog::now_ms(), pubkey: update.pubkey, owner: update.owner, lamports: update.lamports, data: update.data.clone() });
+}
+
+/// Records the accounts a scan loaded, if recording
+pub fn accounts(accounts: &[(Pubkey, Account)]) {
+    if UPDATES.get().is_none() {
+        return;
+    }
+    let time_ms = watchdog::now_ms();
+    for (pubkey, account) in accounts {
+        write(Recorded { slot: 0, time_ms, pubkey: *pubkey, owner: account.owner, lamports: account.lamports, data: account.data.clone() });
+    }
+}
+
+/// Reads a recording back, in the order it was written. A tail cut short, e.g. by a crash, ends it early.
+pub struct Reader {
+    pub header: Header,
+    decoder: GzDecoder<BufReader<File>>,
+}
+
+impl Reader {
+    pub fn open(path: &str) -> Result<Reader, Box<dyn Error + Send + Sync>> {
+        let mut decoder = GzDecoder::new(BufReader::new(File::open(path)?));
+        let header: Header = bincode::deserialize_from(&mut decoder).map_err(|err| format!("{} isn't a recording: {}", path, err))?;
+        if header.version != VERSION {
+            return Err(format!("{} is a version {} recording, this build reads version {}", path, header.version, VERSION).into());
+        }
+        Ok(Reader { header, decoder })
+    }
+}
+
+impl Iterator for Reader {
+    type Item = Recorded;
+
+    fn next(&mut self) -> Option<Recorded> {
+        bincode::deserialize_from(&mut self.decoder).ok()
+    }
+}
+
+impl Recorded {
+    /// The write as a data source would have delivered it
+    pub fn into_update(self) -> AccountUpdate {
+        AccountUpdate { pubkey: self.pubkey, owner: self.owner, data: self.data, lamports: self.lamports, slot: self.slot }
+    }
+}
+
+impl Recorder {
+    /// Writes the updates `update` and `accounts` queue on a thread of its own, which exiting doesn't wait for.
+    /// Panics if the file can't be created.
+    pub fn spawn(self) -> JoinHandle<()> {
+        let file = File::create(&self.path).unwrap_or_else(|err| panic!("failed to create recording {}: {}", self.path, err));
+        let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::fast());
+        let header = Header { version: VERSION, protocol: self.protocol, started_ms: watchdog::now_ms() };
+        bincode::serialize_into(&mut encoder, &header).unwrap();
+
+        let (sender, receiver) = channel(UPDATE_CHANNEL_SIZE);
+        if UPDATES.set(sender).is_err() {
+            warn!("the recorder was already set up");
+        }
+        info!(path = self.path.as_str(), "recording account updates");
+        thread::spawn(move || record(self.path, encoder, receiver))
+    }
+}
+
+fn record(path: String, mut encoder: GzEncoder<BufWriter<File>>, mut updates: Receiver<Recorded>) {
+    while let Some(recorded) = updates.blocking_recv() {
+        if let Err(err) = write_queued(&mut encoder, recorded, &mut updates) {
+            warn!(path = path.as_str(), error = %err, "failed to write recording, stopping it");
+            return;
+        }
+    }
+}
+
+// writes the update and whatever queued up behind it, then flushes
+fn write_queued(encoder: &mut GzEncoder<BufWriter<File>>, recorded: Recorded, updates: &mut Receiver<Recorded>) -> Result<(), Box<dyn Error>> {
+    bincode::serialize_into(&mut *encoder, &recorded)?;
+    while let Ok(recorded) = updates.try_recv() {
+        bincode::serialize_into(&mut *encoder, &recorded)?;
+    }
+    encoder.flush()?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::{collections::BTreeMap, sync::{Arc, Mutex}};
+
+use serde::Serialize;
+use tokio::{signal::unix::{signal, SignalKind}, sync::watch};
+use tracing::{info, warn};
+
+use crate::config::{Args, Config, MarketSettings};
+
+/// Publishes configs for the stages to pick up the settings that can change without a restart: the config
+/// file re-read, with the same command line and environment overrides, on every SIGHUP or admin api reload,
+/// and the runtime overrides the admin api sets applied on top of it
+pub struct Reloader {
+    args: Args,
+    sender: watch::Sender<Config>,
+    // the config as last read, before the runtime overrides
+    loaded: Mutex<Config>,
+    overrides: Mutex<Overrides>,
+}
+
+/// Settings changed at runtime through the admin api, kept across reloads until cleared
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Overrides {
+    pub compute_unit_price: Option<u64>,
+    pub max_compute_unit_price: Option<u64>,
+    /// whether each perp market is disabled, whatever its `markets` entry says
+    pub disabled_markets: BTreeMap<u16, bool>,
+}
+
+impl Overrides {
+    fn apply(&self, mut config: Config) -> Config {
+        if let Some(compute_unit_price) = self.compute_unit_price {
+            config.compute_unit_price = compute_unit_price;
+        }
+        if let Some(max_compute_unit_price) = self.max_compute_unit_price {
+            config.max_compute_unit_price = max_compute_unit_price;
+        }
+        for (market_index, disabled) in &self.disabled_markets {
+            match config.markets.iter_mut().find(|market| !market.spot && market.market_index == *market_index) {
+                Some(market) => market.disabled = *disabled,
+                None => config.markets.push(MarketSettings { market_index: *market_index, spot: false, disabled: *disabled, max_notional: None, margin_ratio_buffer: None }),
+            }
+        }
+        config
+    }
+}
+
+impl Reloader {
+    /// Re-reads the config. One that fails to load is logged and the previous one kept.
+    pub fn reload(&self) -> Result<(), String> {
+        match Config::try_load(&self.args) {
+            Ok(config) => {
+                info!("reloaded config");
+                *self.loaded.lock().unwrap() = config;
+                self.publish();
+                Ok(())
+            }
+            Err(err) => {
+                warn!(error = %err, "failed to reload config, keeping the current one");
+                Err(err.to_string())
+            }
+        }
+    }
+
+    /// Changes the runtime overrides and publishes the config with them, returning them as changed
+    pub fn update(&self, change: impl FnOnce(&mut Overrides)) -> Overrides {
+        let overrides = {
+            let mut overrides = self.overrides.lock().unwrap();
+            change(&mut overrides);
+            overrides.clone()
+        };
+        info!(?overrides, "runtime overrides changed");
+        self.publish();
+        overrides
+    }
+
+    pub fn overrides(&self) -> Overrides {
+        self.overrides.lock().unwrap().clone()
+    }
+
+    fn publish(&self) {
+        let config = self.overrides.lock().unwrap().apply(self.loaded.lock().unwrap().clone());
+        // no receivers left means the stages are gone, nothing to reconfigure
+        let _ = self.sender.send(config);
+    }
+}
+
+/// Starts publishing to the returned receiver, reloading on every SIGHUP the process gets
+pub fn listen(args: Args, config: Config) -> (Arc<Reloader>, watch::Receiver<Config>) {
+    let (sender, receiver) = watch::channel(config.clone());
+    let reloader = Arc::new(Reloader { args, sender, loaded: Mutex::new(config), overrides: Mutex::new(Overrides::default()) });
+    let hangups = reloader.clone();
+    tokio::spawn(async move {
+        let mut signals = signal(SignalKind::hangup()).unwrap();
+        while signals.recv().await.is_some() {
+            let _ = hangups.reload();
+        }
+    });
+    (reloader, receiver)
+}
+
+/// The reloaded config if there is one the receiver hasn't seen yet
+pub fn changed(reloads: &mut watch::Receiver<Config>) -> Option<Config> {
+    if reloads.has_changed().unwrap_or(false) {
+        Some(reloads.borrow_and_update().clone())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,203 @@
+use std::{collections::{BTreeMap, HashMap}, error::Error, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use tokio::{sync::{mpsc::channel, watch}, time::sleep};
+use tracing::info;
+
+use crate::{cache::AccountCache, config::{Config, ProtocolKind, ReplayArgs}, executor::SIGNATURE_FEE_LAMPORTS, filter::UserFilter, idl, metrics::QUOTE_PRECISION, output, recording::Reader, risk::{self, Candidate, Liquidation}, rpc_pool, script::ScriptFilter, v2, watchdog};
+
+// a liquidation the replayed risk stage found
+struct Detection {
+    candidate: Candidate,
+    /// the slot the risk stage had reached when it found it
+    slot: u64,
+    /// when the write of that slot was recorded, in milliseconds since the unix epoch
+    time_ms: u64,
+    /// from that slot's first write being fed in to the candidate coming out of the risk stage
+    latency: Duration,
+}
+
+// the precision of candidates' margin ratios, v1 and v2 alike
+const MARGIN_PRECISION: f64 = 10_000.0;
+
+/// What `replay` prints, dollar amounts in quote and latencies in microseconds
+#[derive(Serialize)]
+pub struct ReplayReport {
+    pub path: String,
+    pub latency_p50_us: Option<u128>,
+    pub latency_p99_us: Option<u128>,
+    pub latency_max_us: Option<u128>,
+    pub liquidations: Vec<ReplayedLiquidation>,
+    pub fees: Vec<FeeOutcome>,
+}
+
+/// A liquidation the simulated executor would have sent
+#[derive(Serialize)]
+pub struct ReplayedLiquidation {
+    pub time: String,
+    pub slot: u64,
+    pub user: String,
+    pub kind: &'static str,
+    pub market_index: Option<u16>,
+    /// as a fraction
+    pub margin_ratio: f64,
+    pub reward: f64,
+    pub latency_us: u128,
+}
+
+/// What the liquidations would have made at one priority fee
+#[derive(Serialize)]
+pub struct FeeOutcome {
+    pub compute_unit_price: u64,
+    pub liquidations: usize,
+    /// candidates turned down for a reward not covering the fees by `min_reward`
+    pub unprofitable: usize,
+    pub reward: f64,
+    pub fees: f64,
+    pub profit: f64,
+    /// keyed by market index, `-` for v1's liquidations of every market at once
+    pub profit_by_market: BTreeMap<String, f64>,
+}
+
+/// Feeds a recording through the risk stage as its data source would have, then has a simulated executor take
+/// the candidates it found the way the real one would: one liquidation per user until it had `landing_slots`
+/// to land, and only those whose reward covers the fees by `min_reward`. Prints every liquidation detected
+/// with its detection latency, then what they would have made under each priority fee, as `args.output`.
+pub async fn replay(config: &Config, args: &ReplayArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let reader = Reader::open(&args.path)?;
+    let mut config = config.clone();
+    config.protocol = reader.header.protocol;
+    // a replay mustn't overwrite the live liquidator's snapshot
+    config.snapshot_path = None;
+    let filter = UserFilter::new(&config, None)?;
+    let script = ScriptFilter::new(&config)?;
+
+    let (update_sender, update_receiver) = channel(config.update_channel_size);
+    let (candidate_sender, mut candidate_receiver) = channel(config.candidate_channel_size);
+    // held until the replay is through, the stage stops once either closes
+    let (_shutdown, shutdown) = watch::channel(false);
+    let reloads = watch::channel(config.clone()).1;
+    let risk = match config.protocol {
+        ProtocolKind::V1 => {
+            let cache = AccountCache { filter, ..AccountCache::default() };
+            tokio::spawn(risk::run(cache, config.clone(), script, update_receiver, candidate_sender, shutdown, reloads))
+        }
+        ProtocolKind::V2 => {
+            // the recording has the accounts but not their layout
+            let idl = idl::fetch(&rpc_pool(&config)?, &v2::program_id()).await?;
+            let mut cache = v2::Cache::new(v2::Layout::from_idl(&idl)?);
+            cache.filter = filter;
+            tokio::spawn(v2::run(cache, config.clone(), script, update_receiver, candidate_sender, shutdown, reloads))
+        }
+    };
+
+    // when each slot's first write was recorded and fed in
+    let slots: Arc<Mutex<HashMap<u64, (u64, Instant)>>> = Arc::default();
+    let feed = tokio::spawn({
+        let (slots, speed) = (slots.clone(), args.speed);
+        async move {
+            let mut playback: Option<(u64, Instant)> = None;
+            let mut writes = 0u64;
+            for recorded in reader {
+                // the startup scan's accounts carry no slot and go in all at once
+                if speed > 0.0 && recorded.slot > 0 {
+                    let (first_ms, started) = *playback.get_or_insert((recorded.time_ms, Instant::now()));
+                    let due = Duration::from_secs_f64(recorded.time_ms.saturating_sub(first_ms) as f64 / 1000.0 / speed);
+                    if let Some(wait) = due.checked_sub(started.elapsed()) {
+                        sleep(wait).await;
+                    }
+                }
+                slots.lock().unwrap().entry(recorded.slot).or_insert((recorded.time_ms, Instant::now()));
+                if update_sender.send(recorded.into_update()).await.is_err() {
+                    break;
+                }
+                writes += 1;
+            }
+            writes
+        }
+    });
+
+    let mut detections = vec![];
+    while let Some(candidate) = candidate_receiver.recv().await {
+        let slot = watchdog::last_slot();
+        let (time_ms, fed) = slots.lock().unwrap().get(&slot).copied().unwrap_or((0, Instant::now()));
+        detections.push(Detection { candidate, slot, time_ms, latency: fed.elapsed() });
+    }
+    let writes = feed.await?;
+    risk.await?;
+    info!(writes, candidates = detections.len(), "replayed {}", args.path);
+
+    let (liquidations, _) = simulate(&detections, args.landing_slots, |_| true);
+    let mut latencies: Vec<Duration> = liquidations.iter().map(|detection| detection.latency).collect();
+    latencies.sort_unstable();
+    let percentile = |percent: usize| latencies.get(latencies.len() * percent / 100).map(Duration::as_micros);
+    let mut report = ReplayReport {
+        path: args.path.clone(),
+        latency_p50_us: percentile(50),
+        latency_p99_us: percentile(99),
+        latency_max_us: latencies.last().map(Duration::as_micros),
+        liquidations: liquidations
+            .iter()
+            .map(|detection| ReplayedLiquidation {
+                time: DateTime::<Utc>::from_timestamp_millis(detection.time_ms as i64).map_or_else(String::new, |time| time.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                slot: detection.slot,
+                user: bs58::encode(detection.candidate.user.to_bytes()).into_string(),
+                kind: detection.candidate.liquidation.kind(),
+                market_index: detection.candidate.liquidation.market_index(),
+                margin_ratio: detection.candidate.margin_ratio as f64 / MARGIN_PRECISION,
+                reward: detection.candidate.reward.liquidator as f64 / QUOTE_PRECISION,
+                latency_us: detection.latency.as_micros(),
+            })
+            .collect(),
+        fees: vec![],
+    };
+
+    let compute_unit_prices = if args.compute_unit_prices.is_empty() { vec![config.compute_unit_price] } else { args.compute_unit_prices.clone() };
+    for compute_unit_price in compute_unit_prices {
+        let fee_lamports = SIGNATURE_FEE_LAMPORTS + compute_unit_price * config.compute_unit_limit as u64 / 1_000_000 + args.tip_lamports;
+        let fees = |candidate: &Candidate| (fee_lamports as u128 * candidate.sol_price as u128 / LAMPORTS_PER_SOL as u128) as u64;
+        let profitable = |candidate: &Candidate| {
+            matches!(candidate.liquidation, Liquidation::PerpBankruptcy { .. } | Liquidation::SpotBankruptcy { .. }) || candidate.reward.liquidator >= fees(candidate).saturating_add(config.min_reward)
+        };
+        let (sent, unprofitable) = simulate(&detections, args.landing_slots, profitable);
+        let reward: u64 = sent.iter().map(|detection| detection.candidate.reward.liquidator).sum();
+        let paid: u64 = sent.iter().map(|detection| fees(&detection.candidate)).sum();
+        let mut by_market: BTreeMap<String, f64> = BTreeMap::new();
+        for detection in &sent {
+            let market = detection.candidate.liquidation.market_index().map_or_else(|| "-".to_string(), |market_index| market_index.to_string());
+            *by_market.entry(market).or_default() += (detection.candidate.reward.liquidator as i64 - fees(&detection.candidate) as i64) as f64 / QUOTE_PRECISION;
+        }
+        report.fees.push(FeeOutcome {
+            compute_unit_price,
+            liquidations: sent.len(),
+            unprofitable,
+            reward: reward as f64 / QUOTE_PRECISION,
+            fees: paid as f64 / QUOTE_PRECISION,
+            profit: (reward as i64 - paid as i64) as f64 / QUOTE_PRECISION,
+            profit_by_market: by_market,
+        });
+    }
+    output::record(args.output, &report);
+    Ok(())
+}
+
+// the detections the executor would have sent a liquidation for, `send` saying which it takes, and how many of
+// the rest it turned down
+fn simulate<'a>(detections: &'a [Detection], landing_slots: u64, send: impl Fn(&Candidate) -> bool) -> (Vec<&'a Detection>, usize) {
+    let mut in_flight: HashMap<Pubkey, u64> = HashMap::new();
+    let (mut sent, mut turned_down) = (vec![], 0);
+    for detection in detections {
+        if in_flight.get(&detection.candidate.user).map_or(false, |sent_slot| detection.slot < sent_slot + landing_slots) {
+            continue;
+        }
+        if send(&detection.candidate) {
+            in_flight.insert(detection.candidate.user, detection.slot);
+            sent.push(detection);
+        } else {
+            turned_down += 1;
+        }
+    }
+    (sent, turned_down)
+}
@@ -0,0 +1,109 @@
+use std::{collections::HashMap, error::Error, fs::File, io::{self, Write}, str::FromStr, sync::Arc};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, native_token::LAMPORTS_PER_SOL, signature::Signature};
+use solana_transaction_status::UiTransactionEncoding;
+use tracing::{info, warn};
+
+use crate::{config::{Config, ReportArgs}, history::{self, Record}, metrics::QUOTE_PRECISION, rpc::RpcPool, rpc_pool};
+
+// what the chain says about a transaction the history has no result for
+struct Fetched {
+    time_ms: Option<u64>,
+    outcome: &'static str,
+    fee_lamports: u64,
+    tip_lamports: u64,
+}
+
+/// Writes a ledger of every liquidation transaction in the history, one csv row each with its time, signature,
+/// outcome, reward in USDC and the fees and tips it paid in SOL, for tax and accounting tools to import.
+/// Transactions the history has no result or fee for, e.g. because the liquidator stopped while they were in
+/// flight, are looked up over rpc and the history is filled in with what was found. Transactions resolved before
+/// the history kept tips apart have theirs in the fee and a tip of 0.
+pub async fn report(config: &Config, args: &ReportArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let store = history::store(config).await.ok_or("set history_path or history_postgres_url to report on the history")?;
+    let (from_ms, to_ms) = (args.from.unwrap_or(0), args.to.unwrap_or(i64::MAX));
+    let transactions = store.transactions(from_ms, to_ms).await?;
+    let mut rewards: HashMap<String, i64> = HashMap::new();
+    for attempt in store.attempts(from_ms, to_ms).await? {
+        *rewards.entry(attempt.signature).or_default() += attempt.expected_reward;
+    }
+
+    let out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["time", "signature", "outcome", "reward_usdc", "fee_sol", "tip_sol", "sol_price_usdc", "fees_usdc"])?;
+
+    let rpc = rpc_pool(config)?;
+    let mut filled: Vec<Record> = vec![];
+    for transaction in &transactions {
+        let (mut time_ms, mut outcome, mut fee_lamports, mut tip_lamports) =
+            (transaction.resolved_ms.unwrap_or(transaction.sent_ms) as u64, transaction.outcome.clone(), transaction.fee_lamports as u64, transaction.tip_lamports as u64);
+        // expired transactions never landed, so paid nothing
+        let incomplete = outcome.is_none() || (outcome.as_deref() != Some("expired") && fee_lamports == 0);
+        if incomplete {
+            if let Some(fetched) = fetch(&rpc, &transaction.signature).await {
+                time_ms = fetched.time_ms.unwrap_or(time_ms);
+                outcome = Some(fetched.outcome.to_string());
+                fee_lamports = fetched.fee_lamports;
+                tip_lamports = fetched.tip_lamports;
+                filled.push(Record::Resolved {
+                    time_ms,
+                    signature: transaction.signature.clone(),
+                    outcome: fetched.outcome,
+                    latency_ms: time_ms.saturating_sub(transaction.sent_ms as u64),
+                    fee_lamports,
+                    tip_lamports,
+                    reward: if fetched.outcome == "landed" { rewards.get(&transaction.signature).copied().unwrap_or(0) as u64 } else { 0 },
+                });
+            }
+        }
+
+        let reward = if outcome.as_deref() == Some("landed") { rewards.get(&transaction.signature).copied().unwrap_or(0) } else { 0 };
+        let sol_price = transaction.sol_price as f64 / QUOTE_PRECISION;
+        let (fee_sol, tip_sol) = (fee_lamports as f64 / LAMPORTS_PER_SOL as f64, tip_lamports as f64 / LAMPORTS_PER_SOL as f64);
+        writer.write_record([
+            DateTime::<Utc>::from_timestamp_millis(time_ms as i64).map_or_else(String::new, |time| time.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            transaction.signature.clone(),
+            // sent but nowhere to be found, most likely never landed
+            outcome.unwrap_or_else(|| "unknown".to_string()),
+            format!("{:.6}", reward as f64 / QUOTE_PRECISION),
+            format!("{:.9}", fee_sol),
+            format!("{:.9}", tip_sol),
+            format!("{:.6}", sol_price),
+            format!("{:.6}", (fee_sol + tip_sol) * sol_price),
+        ])?;
+    }
+    writer.flush()?;
+
+    if !filled.is_empty() {
+        info!(transactions = filled.len(), "filling in the history with the results found over rpc");
+        store.insert(&filled).await?;
+    }
+    Ok(())
+}
+
+// the transaction's result, fee and tip, taking whatever else the payer spent in it as the tip. None if the rpc
+// doesn't know it
+async fn fetch(rpc: &Arc<RpcPool>, signature: &str) -> Option<Fetched> {
+    let signature = Signature::from_str(signature).ok()?;
+    let config = RpcTransactionConfig { encoding: Some(UiTransactionEncoding::Base64), commitment: Some(CommitmentConfig::confirmed()), max_supported_transaction_version: Some(0) };
+    let transaction = match rpc.call(|client| async move { client.get_transaction_with_config(&signature, config).await }).await {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            warn!(%signature, error = %err, "failed to fetch liquidation transaction");
+            return None;
+        }
+    };
+    let meta = transaction.transaction.meta?;
+    let spent = meta.pre_balances.first().zip(meta.post_balances.first()).map_or(meta.fee, |(pre, post)| pre.saturating_sub(*post));
+    Some(Fetched {
+        time_ms: transaction.block_time.map(|block_time| block_time as u64 * 1000),
+        outcome: if meta.err.is_none() { "landed" } else { "failed" },
+        fee_lamports: meta.fee,
+        tip_lamports: spent.saturating_sub(meta.fee),
+    })
+}
@@ -0,0 +1,40 @@
+use std::{future::Future, time::Duration};
+
+use tokio::time::sleep;
+
+/// Exponential backoff with jitter for retrying transient failures
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// total tries including the first one
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt`, counting from 1. Doubles every attempt up to `max_delay`, with the
+    /// upper half randomized so concurrent retries don't hit the endpoint in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+        let capped = exponential.min(self.max_delay);
+        capped / 2 + capped.mul_f64(rand::random::<f64>() / 2.0)
+    }
+}
+
+/// Runs `operation` until it succeeds, fails with an error `is_retryable` rejects, or the attempt budget runs out
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, is_retryable: impl Fn(&E) -> bool, mut operation: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(err) if attempt < policy.max_attempts && is_retryable(&err) => {
+                sleep(policy.backoff(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
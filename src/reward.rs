@@ -0,0 +1,110 @@
+use clearing_house::state::state::State;
+
+use crate::{risk::LiquidationType, v2::{PerpMarket, SpotMarket, BASE_PRECISION, LIQUIDATION_FEE_PRECISION}};
+
+/// How a liquidation's fee splits between the liquidator and the insurance fund, in quote precision
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Reward {
+    pub liquidator: u64,
+    pub insurance_fund: u64,
+}
+
+impl Reward {
+    /// The whole fee charged to the liquidated user
+    pub fn total(&self) -> u64 {
+        self.liquidator.saturating_add(self.insurance_fund)
+    }
+}
+
+/// The clearing house charges a penalty on the user's total collateral, partial liquidations at the partial
+/// penalty. The liquidator gets 1 / share denominator of it and the rest goes to the insurance vault.
+pub fn v1(state: &State, liquidation_type: LiquidationType, total_collateral: u128) -> Reward {
+    let (numerator, denominator, liquidator_share_denominator) = match liquidation_type {
+        LiquidationType::Partial => (
+            state.partial_liquidation_penalty_percentage_numerator,
+            state.partial_liquidation_penalty_percentage_denominator,
+            state.partial_liquidation_liquidator_share_denominator as u128,
+        ),
+        LiquidationType::Full => (
+            state.full_liquidation_penalty_percentage_numerator,
+            state.full_liquidation_penalty_percentage_denominator,
+            state.full_liquidation_liquidator_share_denominator as u128,
+        ),
+    };
+    if denominator == 0 || liquidator_share_denominator == 0 {
+        return Reward::default();
+    }
+
+    let fee = total_collateral.saturating_mul(numerator) / denominator;
+    let liquidator = fee / liquidator_share_denominator;
+    Reward { liquidator: to_u64(liquidator as i128), insurance_fund: to_u64((fee - liquidator) as i128) }
+}
+
+/// Taking over `base_asset_amount` of a perp position at the oracle price pays the market's liquidator fee
+/// on the notional, and the insurance fund its own fee on top
+pub fn perp(market: &PerpMarket, base_asset_amount: u64) -> Reward {
+    let notional = base_asset_amount as i128 * market.oracle_price as i128 / BASE_PRECISION;
+    Reward {
+        liquidator: to_u64(notional * market.liquidator_fee as i128 / LIQUIDATION_FEE_PRECISION),
+        insurance_fund: to_u64(notional * market.if_liquidation_fee as i128 / LIQUIDATION_FEE_PRECISION),
+    }
+}
+
+/// Repaying `liability_transfer` of a borrow pays the repaid value marked up by the liability market's fee, in
+/// deposits discounted by the asset market's fee. The insurance fund takes the liability market's
+/// insurance fee off the user's deposits separately.
+pub fn spot(asset_market: &SpotMarket, liability_market: &SpotMarket, liability_transfer: u128) -> Reward {
+    let value = liability_transfer as i128 * liability_market.oracle_price as i128 / 10i128.pow(liability_market.decimals);
+    let asset_discount = LIQUIDATION_FEE_PRECISION - asset_market.liquidator_fee as i128;
+    if asset_discount <= 0 {
+        return Reward::default();
+    }
+
+    let received = value * (LIQUIDATION_FEE_PRECISION + liability_market.liquidator_fee as i128) / asset_discount;
+    Reward {
+        liquidator: to_u64(received - value),
+        insurance_fund: to_u64(value * liability_market.if_liquidation_fee as i128 / LIQUIDATION_FEE_PRECISION),
+    }
+}
+
+fn to_u64(amount: i128) -> u64 {
+    amount.clamp(0, u64::MAX as i128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{perp_market, spot_market, state, FULL, PARTIAL};
+
+    #[test]
+    fn v1_records() {
+        for record in [FULL, PARTIAL] {
+            assert_eq!(v1(&state(), record.liquidation_type(), record.total_collateral), record.reward(), "{:?}", record);
+        }
+    }
+
+    #[test]
+    fn v1_unset_state() {
+        assert_eq!(v1(&State::default(), LiquidationType::Full, 39_000_999), Reward::default());
+    }
+
+    #[test]
+    fn perp_split() {
+        // 5 base at $150: 1% of $750 to the liquidator, 0.5% to the insurance fund
+        let market = perp_market(150_000_000, 10_000, 5_000);
+        assert_eq!(perp(&market, 5_000_000_000), Reward { liquidator: 7_500_000, insurance_fund: 3_750_000 });
+    }
+
+    #[test]
+    fn spot_split() {
+        // repaying 2 of a 9 decimal borrow at $150 for usdc deposits, which carry no fee
+        let (usdc, sol) = (spot_market(1_000_000, 6, 0, 0), spot_market(150_000_000, 9, 10_000, 5_000));
+        assert_eq!(spot(&usdc, &sol, 2_000_000_000), Reward { liquidator: 3_000_000, insurance_fund: 1_500_000 });
+        // a discounted asset pays out more for the same $300: 300 * 1.01 / 0.99
+        let discounted = spot_market(1_000_000, 6, 10_000, 0);
+        assert_eq!(spot(&discounted, &sol, 2_000_000_000), Reward { liquidator: 6_060_606, insurance_fund: 1_500_000 });
+        // an asset discounted away entirely pays nothing
+        let worthless = spot_market(1_000_000, 6, LIQUIDATION_FEE_PRECISION as u32, 0);
+        assert_eq!(spot(&worthless, &sol, 2_000_000_000), Reward::default());
+    }
+}
@@ -0,0 +1,568 @@
+use std::{cmp::{Ordering, Reverse}, collections::{BTreeMap, BinaryHeap}, time::{Duration, Instant}};
+
+use anchor_lang::AccountDeserialize;
+use clearing_house::{math::{collateral::calculate_updated_collateral, constants::{AMM_RESERVE_PRECISION, AMM_TO_QUOTE_PRECISION_RATIO_I128, MARGIN_PRECISION}, funding::calculate_funding_payment, position::calculate_base_asset_value_and_pnl}, state::{market::{Market, Markets, AMM}, user::{User, UserPositions}}};
+use solana_sdk::{instruction::AccountMeta, pubkey::Pubkey};
+use tokio::sync::{mpsc::{Receiver, Sender}, watch};
+use tracing::{debug, info, info_span, warn, Span};
+
+use crate::{cache::AccountCache, collateral::{self, Transfer}, config::{Config, StrategyKind}, error::{LiquidatorError, Result}, health, hedge::{self, Exposure}, history, instructions, metrics::{self, METRICS}, prediction, recording, reload, reward::{self, Reward}, script::ScriptFilter, shutdown, snapshot::{Snapshot, SnapshotUser, Snapshotter}, source::AccountUpdate, status::Publisher, strategy::LiquidationStrategy, tiers::Tiers, unwind, watchdog};
+
+/// A user that crossed the liquidation margin ratio, with everything the executor needs to build the
+/// liquidation
+pub struct Candidate {
+    pub user: Pubkey,
+    pub margin_ratio: u128,
+    pub liquidation: Liquidation,
+    /// expected split of the liquidation fee
+    pub reward: Reward,
+    /// quote price of one SOL when the candidate was evaluated, to weigh the transaction fees against the reward
+    pub sol_price: u64,
+    /// rank set by the candidate script, which wins over the strategy's priority
+    pub priority: Option<u128>,
+    /// trace of the liquidation from its detection on, closed once the executor is done with the candidate
+    pub span: Span,
+}
+
+impl Candidate {
+    /// Opens the candidate's trace, `margin_calc` being how long its evaluation took
+    pub fn trace(&mut self, margin_calc: Duration) {
+        self.span = info_span!(
+            "liquidation",
+            user = %bs58::encode(self.user.to_bytes()).into_string(),
+            margin_ratio = self.margin_ratio as u64,
+            liquidation = self.liquidation.kind(),
+            market_index = ?self.liquidation.market_index(),
+            margin_calc_us = margin_calc.as_micros() as u64,
+        );
+    }
+}
+
+/// Max-heap of candidates keyed by the strategy's priority, the liquidator's expected reward by default, so that
+/// when several users go under at once the most valuable ones are liquidated first. Ties go to the lower margin
+/// ratio.
+pub struct CandidateQueue {
+    heap: BinaryHeap<Ranked>,
+    strategy: &'static dyn LiquidationStrategy,
+}
+
+impl Default for CandidateQueue {
+    fn default() -> CandidateQueue {
+        CandidateQueue::new(StrategyKind::Reward.strategy())
+    }
+}
+
+impl CandidateQueue {
+    pub fn new(strategy: &'static dyn LiquidationStrategy) -> CandidateQueue {
+        CandidateQueue { heap: BinaryHeap::new(), strategy }
+    }
+
+    pub fn push(&mut self, candidate: Candidate) {
+        let priority = candidate.priority.unwrap_or_else(|| self.strategy.priority(&candidate));
+        self.heap.push(Ranked(priority, candidate));
+    }
+
+    /// The most valuable candidate left
+    pub fn pop(&mut self) -> Option<Candidate> {
+        self.heap.pop().map(|ranked| ranked.1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Empties the queue, most valuable candidate first
+    pub fn drain(&mut self) -> impl Iterator<Item = Candidate> + '_ {
+        std::iter::from_fn(move || self.pop())
+    }
+}
+
+struct Ranked(u128, Candidate);
+
+impl Ranked {
+    fn key(&self) -> (u128, Reverse<u128>) {
+        (self.0, Reverse(self.1.margin_ratio))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// How much of a v1 user's positions the clearing house closes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LiquidationType {
+    /// below `margin_ratio_partial`: a fraction of every position is closed
+    Partial,
+    /// below `margin_ratio_maintenance`: every position is closed
+    Full,
+}
+
+/// The protocol specific part of a candidate
+pub enum Liquidation {
+    /// clearing house v1 `liquidate`, which picks the partial or full flow by the user's margin ratio
+    V1 {
+        liquidation_type: LiquidationType,
+        /// how far the user's losses exceed its collateral, in quote precision. The insurance vault covers it.
+        bad_debt: u128,
+        user_positions: Pubkey,
+        /// oracles of the markets the user has open positions in
+        oracles: Vec<Pubkey>,
+    },
+    /// drift v2 `liquidate_perp`, taking over the user's position in one perp market
+    Perp {
+        user_authority: Pubkey,
+        market_index: u16,
+        /// most base asset the liquidator takes over
+        max_base_asset_amount: u64,
+        /// oracles, spot markets and perp markets the program needs to value both accounts
+        remaining_accounts: Vec<AccountMeta>,
+    },
+    /// drift v2 `liquidate_spot`, repaying a borrow in exchange for a deposit
+    Spot {
+        user_authority: Pubkey,
+        asset_market_index: u16,
+        liability_market_index: u16,
+        /// most of the borrow repaid, in the liability's token amount
+        max_liability_transfer: u128,
+        remaining_accounts: Vec<AccountMeta>,
+    },
+    /// drift v2 `resolve_perp_bankruptcy`, socializing a bankrupt user's negative perp pnl through the
+    /// insurance fund
+    PerpBankruptcy {
+        user_authority: Pubkey,
+        market_index: u16,
+        /// the pnl written off, in quote precision
+        bad_debt: u128,
+        remaining_accounts: Vec<AccountMeta>,
+    },
+    /// drift v2 `resolve_spot_bankruptcy`, writing off a bankrupt user's borrow
+    SpotBankruptcy {
+        user_authority: Pubkey,
+        market_index: u16,
+        bad_debt: u128,
+        remaining_accounts: Vec<AccountMeta>,
+    },
+}
+
+/// Risk stage: folds account updates into the cache, re-evaluates the users that changed, and on market moves
+/// the users their tier says are due, and forwards the liquidatable ones to the executor, most valuable first.
+/// Returns once either channel is closed or shutdown began, saving a last snapshot.
+pub async fn run(mut cache: AccountCache, mut config: Config, mut script: ScriptFilter, mut updates: Receiver<AccountUpdate>, candidates: Sender<Candidate>, mut shutdown: watch::Receiver<bool>, mut reloads: watch::Receiver<Config>) {
+    let mut snapshotter = Snapshotter::new(&config);
+    let mut publisher = Publisher::default();
+    let mut tiers = Tiers::new(&config);
+    // wait until something changes, then apply everything else that queued up meanwhile
+    'updates: while let Some(update) = shutdown::recv(&mut updates, &mut shutdown).await {
+        apply(&mut cache, &mut tiers, update);
+        while let Ok(update) = updates.try_recv() {
+            apply(&mut cache, &mut tiers, update);
+        }
+        if let Some(reloaded) = reload::changed(&mut reloads) {
+            tiers.reconfigure(&reloaded);
+            cache.filter.reconfigure(&reloaded, cache.liquidator);
+            script.reconfigure(&reloaded);
+            config = reloaded;
+        }
+
+        // a user's own changes always count, a market moving only for its holders that are due
+        let mut users = cache.take_dirty();
+        let repriced = cache.take_repriced();
+        if !repriced.is_empty() {
+            users.extend(tiers.due(repriced.iter()));
+            users.sort_unstable();
+            users.dedup();
+        }
+
+        let strategy = config.strategy.strategy();
+        let mut queue = CandidateQueue::new(strategy);
+        for user_pubkey in users {
+            let evaluated = Instant::now();
+            match evaluate_tiered(&cache, &config, &user_pubkey, Some(&mut tiers)) {
+                Ok(Some(mut candidate)) => {
+                    if !strategy.should_liquidate(&candidate) || !script.accepts(&mut candidate) {
+                        debug!(user = %bs58::encode(user_pubkey.to_bytes()).into_string(), "the strategy or script passed on the candidate");
+                        continue;
+                    }
+                    candidate.trace(evaluated.elapsed());
+                    metrics::increment(&METRICS.candidates);
+                    history::evaluated(&candidate);
+                    debug!(user = %bs58::encode(user_pubkey.to_bytes()).into_string(), margin_ratio = candidate.margin_ratio as u64, liquidation = candidate.liquidation.kind(), "liquidation candidate");
+                    queue.push(candidate);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    metrics::increment(&METRICS.evaluation_errors);
+                    warn!(user = %bs58::encode(user_pubkey.to_bytes()).into_string(), error = %err, "failed to evaluate account");
+                }
+            }
+        }
+        for candidate in queue.drain() {
+            if candidates.send(candidate).await.is_err() {
+                break 'updates;
+            }
+        }
+        snapshotter.tick(|| snapshot(&cache));
+        publisher.tick(cache.state.1.margin_ratio_partial, || snapshot(&cache));
+        observe_liquidator(&cache);
+        health::iterated();
+    }
+    snapshotter.flush(|| snapshot(&cache));
+}
+
+/// The cached users with their margin ratios, for a restart to load the ones close to liquidation first
+pub fn snapshot(cache: &AccountCache) -> Snapshot {
+    let users = cache
+        .users
+        .iter()
+        .map(|(pubkey, user)| SnapshotUser {
+            user: bs58::encode(pubkey.to_bytes()).into_string(),
+            positions: Some(bs58::encode(user.positions.to_bytes()).into_string()),
+            margin_ratio: margin_ratio(cache, pubkey),
+        })
+        .collect();
+    Snapshot { users }
+}
+
+// the user's margin ratio with funding settled, None without open positions or before they are loaded
+fn margin_ratio(cache: &AccountCache, user_pubkey: &Pubkey) -> Option<u128> {
+    let markets = &cache.markets.1;
+    let mut user = cache.users.get(user_pubkey)?.clone();
+    let mut user_positions = *cache.positions.get(&user.positions)?;
+    settle_funding_payment(&mut user, &mut user_positions, markets).ok()?;
+    let (_, _, _, margin_ratio) = calculate_margin_ratio(&user, &mut user_positions, markets).ok()?;
+    Some(margin_ratio).filter(|margin_ratio| *margin_ratio != u128::MAX)
+}
+
+// publishes the liquidator's own collateral and margin ratio, which `collateral` keeps within bounds, and hands
+// the positions it inherited to `unwind` and `hedge`
+fn observe_liquidator(cache: &AccountCache) {
+    let liquidator = match cache.liquidator {
+        Some(liquidator) => liquidator,
+        None => return,
+    };
+    let markets = &cache.markets.1;
+    let (mut user, mut user_positions) = match cache.users.get(&liquidator).and_then(|user| Some((user.clone(), *cache.positions.get(&user.positions)?))) {
+        Some(accounts) => accounts,
+        None => return,
+    };
+    if settle_funding_payment(&mut user, &mut user_positions, markets).is_err() {
+        return;
+    }
+    let (total_collateral, _, _, margin_ratio) = match calculate_margin_ratio(&user, &mut user_positions, markets) {
+        Ok(margin) => margin,
+        Err(_) => return,
+    };
+    // without open positions the collateral is all there is
+    let (total_collateral, margin_ratio) = if margin_ratio == u128::MAX { (user.collateral, None) } else { (total_collateral, Some(margin_ratio)) };
+    collateral::observe(total_collateral as i128, margin_ratio, cache.state.1.margin_ratio_partial, |transfer, token_account, amount| {
+        Some(match transfer {
+            Transfer::Deposit => instructions::deposit_collateral(&cache.state, &liquidator, &user.authority, &user.positions, token_account, amount),
+            // unrealized pnl counts towards the collateral but can't be withdrawn
+            Transfer::Withdraw => instructions::withdraw_collateral(&cache.state, &liquidator, &user.authority, &user.positions, token_account, amount.min(user.collateral as u64)),
+        })
+    });
+
+    let mut exposures = BTreeMap::new();
+    for position in user_positions.positions.iter().filter(|position| position.base_asset_amount != 0) {
+        let amm = match market(markets, position.market_index) {
+            Ok(market) => &market.amm,
+            Err(_) => continue,
+        };
+        let exposure = Exposure {
+            base_amount: position.base_asset_amount as f64 / AMM_RESERVE_PRECISION as f64,
+            impact_bps: hedge::price_impact_bps(amm.base_asset_reserve, position.base_asset_amount),
+        };
+        exposures.insert(position.market_index as u16, exposure);
+        let (value, mark_price) = match (calculate_base_asset_value_and_pnl(position, amm), amm.mark_price()) {
+            (Ok((value, _)), Ok(mark_price)) => (value, mark_price),
+            _ => continue,
+        };
+        // v1 has no reduce only orders. the last one closes the position outright, and the ones before it, worth
+        // less than it, only go out while the position is still at least as big on the same side, so a liquidation
+        // landing in between can't have them flip it the other way
+        let value = value.min(u64::MAX as u128) as u64;
+        let (market_index, base_asset_amount) = (position.market_index, position.base_asset_amount);
+        let recheck = unwind::Recheck {
+            account: user.positions,
+            holds: Box::new(move |data| {
+                UserPositions::try_deserialize(&mut &*data).map_or(false, |user_positions| {
+                    user_positions.positions.iter().any(|position| {
+                        position.market_index == market_index && position.base_asset_amount.signum() == base_asset_amount.signum() && position.base_asset_amount.unsigned_abs() >= base_asset_amount.unsigned_abs()
+                    })
+                })
+            }),
+        };
+        unwind::observe(market_index as u16, value, Some(recheck), |order_value, slippage_bps| {
+            if order_value >= value {
+                return Some(instructions::close_position(&cache.state, &liquidator, &user.authority, &user.positions, market_index, &amm.oracle));
+            }
+            let short = base_asset_amount > 0;
+            let bps = if short { 10_000u128.saturating_sub(slippage_bps as u128) } else { 10_000 + slippage_bps as u128 };
+            let limit_price = mark_price * bps / 10_000;
+            Some(instructions::open_position(&cache.state, &liquidator, &user.authority, &user.positions, market_index, short, order_value as u128, limit_price, &amm.oracle))
+        });
+    }
+    hedge::observe(exposures);
+}
+
+// folds an update into the cache, evicting accounts that were closed or no longer decode
+fn apply(cache: &mut AccountCache, tiers: &mut Tiers, update: AccountUpdate) {
+    recording::update(&update);
+    watchdog::beat(update.slot);
+    if !update.is_closed() && !cache.versions.record(update.pubkey, update.slot, &update.data) {
+        return;
+    }
+    if (update.is_closed() || !cache.apply(update.pubkey, &update.data)) && cache.evict(&update.pubkey) {
+        tiers.remove(&update.pubkey);
+        metrics::increment(&METRICS.evicted_accounts);
+        info!(account = %bs58::encode(update.pubkey.to_bytes()).into_string(), "evicted closed account");
+    }
+}
+
+/// Returns a liquidation candidate if the cached user is in liquidation territory, None if it isn't or either
+/// of its accounts isn't loaded yet
+pub fn evaluate(cache: &AccountCache, config: &Config, user_pubkey: &Pubkey) -> Result<Option<Candidate>> {
+    evaluate_tiered(cache, config, user_pubkey, None)
+}
+
+// `evaluate`, filing the user under the tier of the margin ratio it worked out on the way
+fn evaluate_tiered(cache: &AccountCache, config: &Config, user_pubkey: &Pubkey, tiers: Option<&mut Tiers>) -> Result<Option<Candidate>> {
+    let markets = &cache.markets.1;
+    // work on copies, settling funding below must not leak into the cache
+    let mut user = match cache.users.get(user_pubkey) {
+        Some(user) if cache.filter.allows(user_pubkey, &user.authority) => user.clone(),
+        _ => return Ok(None),
+    };
+    let mut user_positions = match cache.positions.get(&user.positions) {
+        Some(user_positions) => *user_positions,
+        None => return Ok(None),
+    };
+
+    // Settle user's funding payments so that collateral is up to date
+    settle_funding_payment(&mut user, &mut user_positions, markets)?;
+
+    // Verify that the user is in liquidation territory
+    let (total_collateral, unrealized_pnl, _base_asset_value, margin_ratio) =
+        calculate_margin_ratio(&user, &mut user_positions, markets)?;
+    let state = &cache.state.1;
+    if let Some(tiers) = tiers {
+        tiers.classify(*user_pubkey, Some(margin_ratio).filter(|margin_ratio| *margin_ratio != u128::MAX), state.margin_ratio_partial);
+    }
+    if disabled_market(config, &user_positions).is_some() {
+        return Ok(None);
+    }
+    // is liquidatable, fully once under the maintenance ratio
+    let liquidation_type = if margin_ratio <= state.margin_ratio_maintenance {
+        LiquidationType::Full
+    } else if margin_ratio <= state.margin_ratio_partial.saturating_sub(margin_ratio_buffer(config, &user_positions)) {
+        LiquidationType::Partial
+    } else {
+        return Ok(None);
+    };
+
+    // losses beyond the collateral, the liquidation can't recover those
+    let bad_debt = (-(user.collateral as i128 + unrealized_pnl)).max(0) as u128;
+
+    let oracles = user_positions
+        .positions
+        .iter()
+        .filter(|position| position.base_asset_amount != 0)
+        .map(|position| market(markets, position.market_index).map(|market| market.amm.oracle))
+        .collect::<Result<_>>()?;
+
+    // the penalty as the clearing house will work it out, closing trades and all. the prediction only refines the
+    // reward, a user it fails on is liquidated all the same for the flat penalty
+    let reward = match prediction::v1(state, markets, &user, &user_positions) {
+        Ok(Some(prediction)) => prediction.reward,
+        Ok(None) => reward::v1(state, liquidation_type, total_collateral),
+        Err(err) => {
+            metrics::increment(&METRICS.prediction_errors);
+            warn!(user = %bs58::encode(user_pubkey.to_bytes()).into_string(), error = %err, "failed to predict the liquidation, using the flat penalty");
+            reward::v1(state, liquidation_type, total_collateral)
+        }
+    };
+
+    Ok(Some(Candidate {
+        user: *user_pubkey,
+        margin_ratio,
+        liquidation: Liquidation::V1 { liquidation_type, bad_debt, user_positions: user.positions, oracles },
+        reward,
+        // sol-perp, mark prices carry 10 decimals against the quote's 6
+        sol_price: markets.markets[0].amm.mark_price().map_or(0, |price| (price / 10_000) as u64),
+        priority: None,
+        span: Span::none(),
+    }))
+}
+
+impl Liquidation {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Liquidation::V1 { liquidation_type: LiquidationType::Partial, .. } => "partial",
+            Liquidation::V1 { liquidation_type: LiquidationType::Full, .. } => "full",
+            Liquidation::Perp { .. } => "perp",
+            Liquidation::Spot { .. } => "spot",
+            Liquidation::PerpBankruptcy { .. } => "perp bankruptcy",
+            Liquidation::SpotBankruptcy { .. } => "spot bankruptcy",
+        }
+    }
+
+    /// The v2 market the liquidation is on, the liability's for spot liquidations
+    pub fn market_index(&self) -> Option<u16> {
+        match self {
+            Liquidation::V1 { .. } => None,
+            Liquidation::Perp { market_index, .. } | Liquidation::PerpBankruptcy { market_index, .. } | Liquidation::SpotBankruptcy { market_index, .. } => Some(*market_index),
+            Liquidation::Spot { liability_market_index, .. } => Some(*liability_market_index),
+        }
+    }
+
+    /// The market the liquidation leaves bad debt in and how much, in quote precision
+    pub fn bad_debt(&self) -> Option<(String, u128)> {
+        match self {
+            Liquidation::V1 { bad_debt, .. } if *bad_debt > 0 => Some(("clearing house".to_string(), *bad_debt)),
+            Liquidation::PerpBankruptcy { market_index, bad_debt, .. } => Some((format!("perp market {}", market_index), *bad_debt)),
+            Liquidation::SpotBankruptcy { market_index, bad_debt, .. } => Some((format!("spot market {}", market_index), *bad_debt)),
+            _ => None,
+        }
+    }
+}
+
+/// A disabled market the user has a position in. A liquidation closes every position, so that rules the user out.
+pub fn disabled_market(config: &Config, user_positions: &UserPositions) -> Option<u16> {
+    user_positions
+        .positions
+        .iter()
+        .filter(|position| position.base_asset_amount != 0)
+        .map(|position| position.market_index as u16)
+        .find(|market_index| config.market(*market_index).map_or(false, |market| market.disabled))
+}
+
+/// How far below the partial liquidation ratio the user's margin ratio has to fall: the largest buffer of the
+/// markets it has positions in, `margin_ratio_buffer` standing in for markets without one of their own
+pub fn margin_ratio_buffer(config: &Config, user_positions: &UserPositions) -> u128 {
+    user_positions
+        .positions
+        .iter()
+        .filter(|position| position.base_asset_amount != 0)
+        .map(|position| config.market(position.market_index as u16).and_then(|market| market.margin_ratio_buffer).unwrap_or(config.margin_ratio_buffer))
+        .max()
+        .unwrap_or(config.margin_ratio_buffer)
+}
+
+// stripped down internal functions
+
+// the market at a position's index, which a malformed positions account could put out of range
+fn market(markets: &Markets, market_index: u64) -> Result<&Market> {
+    markets.markets.get(Markets::index_from_u64(market_index)).ok_or(LiquidatorError::UnknownMarket(market_index))
+}
+
+/// Funding payments are settled lazily. The amm tracks its cumulative funding rate (for longs and shorts)
+/// and the user's market position tracks how much funding the user been cumulatively paid for that market.
+/// If the two values are not equal, the user owes/is owed funding.
+pub fn settle_funding_payment(
+    user: &mut User,
+    user_positions: &mut UserPositions,
+    markets: &Markets,
+) -> Result<()> {
+    let mut funding_payment: i128 = 0;
+    for market_position in user_positions.positions.iter_mut() {
+        if market_position.base_asset_amount == 0 {
+            continue;
+        }
+
+        let amm: &AMM = &market(markets, market_position.market_index)?.amm;
+
+        let amm_cumulative_funding_rate = if market_position.base_asset_amount > 0 {
+            amm.cumulative_funding_rate_long
+        } else {
+            amm.cumulative_funding_rate_short
+        };
+
+        if amm_cumulative_funding_rate != market_position.last_cumulative_funding_rate {
+            let market_funding_rate_payment =
+                calculate_funding_payment(amm_cumulative_funding_rate, market_position)?;
+
+            funding_payment = funding_payment
+                .checked_add(market_funding_rate_payment)
+                .ok_or(LiquidatorError::MathOverflow("adding up funding payments"))?;
+
+            market_position.last_cumulative_funding_rate = amm_cumulative_funding_rate;
+            market_position.last_funding_rate_ts = amm.last_funding_rate_ts;
+        }
+    }
+
+    let funding_payment_collateral = funding_payment
+        .checked_div(AMM_TO_QUOTE_PRECISION_RATIO_I128)
+        .ok_or(LiquidatorError::MathOverflow("converting funding to collateral"))?;
+
+    user.collateral = calculate_updated_collateral(user.collateral, funding_payment_collateral)?;
+
+    Ok(())
+}
+
+pub fn calculate_margin_ratio(
+    user: &User,
+    user_positions: &mut UserPositions,
+    markets: &Markets,
+) -> Result<(u128, i128, u128, u128)> {
+    let mut base_asset_value: u128 = 0;
+    let mut unrealized_pnl: i128 = 0;
+
+    // loop 1 to calculate unrealized_pnl
+    for market_position in user_positions.positions.iter() {
+        if market_position.base_asset_amount == 0 {
+            continue;
+        }
+
+        let amm = &market(markets, market_position.market_index)?.amm;
+        let (position_base_asset_value, position_unrealized_pnl) =
+            calculate_base_asset_value_and_pnl(market_position, amm)?;
+
+        base_asset_value = base_asset_value
+            .checked_add(position_base_asset_value)
+            .ok_or(LiquidatorError::MathOverflow("adding up base asset values"))?;
+        unrealized_pnl = unrealized_pnl
+            .checked_add(position_unrealized_pnl)
+            .ok_or(LiquidatorError::MathOverflow("adding up unrealized pnl"))?;
+    }
+
+    let total_collateral: u128;
+    let margin_ratio: u128;
+    if base_asset_value == 0 {
+        total_collateral = u128::MAX;
+        margin_ratio = u128::MAX;
+    } else {
+        total_collateral = calculate_updated_collateral(user.collateral, unrealized_pnl)?;
+        margin_ratio = total_collateral
+            .checked_mul(MARGIN_PRECISION)
+            .and_then(|collateral| collateral.checked_div(base_asset_value))
+            .ok_or(LiquidatorError::MathOverflow("computing the margin ratio"))?;
+    }
+
+    Ok((
+        total_collateral,
+        unrealized_pnl,
+        base_asset_value,
+        margin_ratio,
+    ))
+}
\ No newline at end of file
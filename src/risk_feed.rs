@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use serde_json::json;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{info, warn};
+
+use crate::status::STATUS;
+
+// how often the at-risk set is recomputed, the rate the risk stage publishes its users at
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+// backoff while redis can't be reached at startup
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishes the rolling set of users within `margin_percent` percent of the margin ratio they are liquidated at
+/// to redis, for hedgers, dashboards and other bots to consume: the sorted set `key` holds every user's pubkey
+/// scored by its margin ratio, and `channel` gets the whole set as json whenever it changes
+pub struct RiskFeed {
+    pub url: String,
+    pub key: String,
+    pub channel: String,
+    pub margin_percent: u128,
+}
+
+impl RiskFeed {
+    pub fn spawn(self) -> JoinHandle<()> {
+        // the set comes from the published users
+        STATUS.enable();
+        tokio::spawn(async move {
+            let client = redis::Client::open(self.url.as_str()).expect("invalid redis_url");
+            let mut connection = loop {
+                match ConnectionManager::new(client.clone()).await {
+                    Ok(connection) => break connection,
+                    Err(err) => {
+                        warn!(error = %err, "failed to connect to redis");
+                        sleep(CONNECT_RETRY_DELAY).await;
+                    }
+                }
+            };
+            info!(key = self.key.as_str(), channel = self.channel.as_str(), "publishing at-risk users to redis");
+
+            let mut published: Vec<(String, u128)> = vec![];
+            loop {
+                sleep(PUBLISH_INTERVAL).await;
+                let at_risk = STATUS.at_risk(self.margin_percent);
+                if at_risk == published {
+                    continue;
+                }
+                let users: Vec<_> = at_risk.iter().map(|(user, margin_ratio)| json!({ "user": user, "margin_ratio": margin_ratio })).collect();
+
+                // replaced in one transaction so readers never see half a set
+                let mut pipe = redis::pipe();
+                pipe.atomic().del(&self.key).ignore();
+                for (user, margin_ratio) in &at_risk {
+                    pipe.zadd(&self.key, user, *margin_ratio as f64).ignore();
+                }
+                pipe.publish(&self.channel, json!({ "users": users }).to_string()).ignore();
+                match pipe.query_async::<_, ()>(&mut connection).await {
+                    Ok(()) => published = at_risk,
+                    Err(err) => warn!(error = %err, "failed to publish at-risk users to redis"),
+                }
+            }
+        })
+    }
+}
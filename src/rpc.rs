@@ -0,0 +1,341 @@
+use std::{future::Future, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, RwLock}, time::{Duration, Instant}};
+
+use futures::{future::join_all, stream::FuturesUnordered, StreamExt};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::{client_error::{ClientError, ClientErrorKind, Result as ClientResult}, nonblocking::rpc_client::RpcClient, rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig}, rpc_filter::{Memcmp, RpcFilterType}};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, instruction::Instruction, pubkey::Pubkey, signature::Signature, signer::Signer, transaction::Transaction};
+use serde::Serialize;
+use tokio::{sync::Semaphore, task::JoinHandle, time::sleep};
+use tracing::warn;
+
+use crate::{rate_limit::RateLimiter, retry::{retry, RetryPolicy}};
+
+// how long a failing endpoint is passed over before it gets another chance
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+// endpoints trailing the most current one by more slots than this are only read from as a last resort
+const MAX_SLOT_LAG: u64 = 10;
+/// most accounts a single getMultipleAccounts request may ask for
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+struct Endpoint {
+    client: Arc<RpcClient>,
+    limiter: Arc<RateLimiter>,
+    unhealthy_until: Mutex<Option<Instant>>,
+    // moving average of the `getSlot` round trip, in microseconds
+    latency: AtomicU64,
+    // last slot the endpoint reported
+    slot: AtomicU64,
+}
+
+impl Endpoint {
+    fn new(url: &str, timeout: Duration, commitment_config: CommitmentConfig, requests_per_second: u32) -> Endpoint {
+        Endpoint {
+            client: Arc::new(RpcClient::new_with_timeout_and_commitment(url.to_string(), timeout, commitment_config)),
+            limiter: Arc::new(RateLimiter::new(requests_per_second)),
+            unhealthy_until: Mutex::new(None),
+            latency: AtomicU64::new(0),
+            slot: AtomicU64::new(0),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.unhealthy_until.lock().unwrap().map_or(true, |until| Instant::now() >= until)
+    }
+
+    fn mark_unhealthy(&self) {
+        *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + UNHEALTHY_COOLDOWN);
+    }
+
+    fn mark_healthy(&self) {
+        *self.unhealthy_until.lock().unwrap() = None;
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        let sample = latency.as_micros() as u64;
+        let previous = self.latency.load(Ordering::Relaxed);
+        let average = if previous == 0 { sample } else { (previous * 7 + sample) / 8 };
+        self.latency.store(average, Ordering::Relaxed);
+    }
+}
+
+/// An endpoint's health, slot and latency as the monitor last measured them
+#[derive(Serialize)]
+pub struct EndpointStatus {
+    pub url: String,
+    pub healthy: bool,
+    pub slot: u64,
+    pub latency_us: u64,
+}
+
+/// A set of rpc endpoints. Reads go to the healthy endpoint that is closest to the chain tip with the lowest
+/// latency and fail over to the next one on timeouts, connection errors, 5xx and 429 responses, marking the
+/// failed endpoint unhealthy for a while. Sends are broadcast to every healthy endpoint at once. Every request waits
+/// for its endpoint's rate limiter first so public endpoints don't start answering 429s, and for a permit
+/// capping the requests in flight across the pool. When every endpoint failed the whole round is retried with
+/// exponential backoff. The endpoints can be swapped out while the pool is in use, see `set_urls`.
+pub struct RpcPool {
+    endpoints: RwLock<Vec<Arc<Endpoint>>>,
+    timeout: Duration,
+    commitment_config: CommitmentConfig,
+    requests_per_second: u32,
+    permits: Arc<Semaphore>,
+    retry: RetryPolicy,
+}
+
+impl RpcPool {
+    /// Errs without any urls
+    pub fn new(
+        urls: &[String],
+        timeout: Duration,
+        commitment_config: CommitmentConfig,
+        requests_per_second: u32,
+        max_concurrent_requests: usize,
+        retry: RetryPolicy,
+    ) -> ClientResult<RpcPool> {
+        if urls.is_empty() {
+            return Err(ClientErrorKind::Custom("at least one rpc url is required".to_string()).into());
+        }
+        let endpoints = urls.iter().map(|url| Arc::new(Endpoint::new(url, timeout, commitment_config, requests_per_second))).collect();
+
+        Ok(RpcPool {
+            endpoints: RwLock::new(endpoints),
+            timeout,
+            commitment_config,
+            requests_per_second,
+            permits: Arc::new(Semaphore::new(max_concurrent_requests)),
+            retry,
+        })
+    }
+
+    /// Replaces the endpoints, e.g. after a config reload. Endpoints that stay keep their health, latency and
+    /// slot, requests already running finish on the endpoint they started on.
+    pub fn set_urls(&self, urls: &[String]) {
+        if urls.is_empty() {
+            warn!("ignoring an empty list of rpc urls");
+            return;
+        }
+        let mut endpoints = self.endpoints.write().unwrap();
+        let updated: Vec<Arc<Endpoint>> = urls
+            .iter()
+            .map(|url| match endpoints.iter().find(|endpoint| endpoint.client.url() == *url) {
+                Some(endpoint) => endpoint.clone(),
+                None => Arc::new(Endpoint::new(url, self.timeout, self.commitment_config, self.requests_per_second)),
+            })
+            .collect();
+        *endpoints = updated;
+    }
+
+    /// Every endpoint's status, best first
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        self.ranked()
+            .iter()
+            .map(|endpoint| EndpointStatus {
+                url: endpoint.client.url(),
+                healthy: endpoint.is_healthy(),
+                slot: endpoint.slot.load(Ordering::Relaxed),
+                latency_us: endpoint.latency.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    // the current endpoints, so a request doesn't hold the lock while it runs
+    fn endpoints(&self) -> Vec<Arc<Endpoint>> {
+        self.endpoints.read().unwrap().clone()
+    }
+
+    /// Periodically measures every endpoint's latency and slot so reads can be routed to the best one
+    pub fn spawn_monitor(pool: Arc<RpcPool>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let endpoints = pool.endpoints();
+                join_all(endpoints.iter().map(|endpoint| async move {
+                    endpoint.limiter.acquire().await;
+                    let now = Instant::now();
+                    match endpoint.client.get_slot().await {
+                        Ok(slot) => {
+                            endpoint.record_latency(now.elapsed());
+                            endpoint.slot.store(slot, Ordering::Relaxed);
+                        }
+                        Err(err) if is_endpoint_failure(&err) => endpoint.mark_unhealthy(),
+                        Err(_) => {}
+                    }
+                }))
+                .await;
+                sleep(interval).await;
+            }
+        })
+    }
+
+    /// Runs `request` against the endpoints, best first, until one of them answers. Errors that aren't the
+    /// endpoint's fault (e.g. a failed simulation) are fatal and returned right away without failing over.
+    pub async fn call<T, F, Fut>(&self, request: F) -> ClientResult<T>
+    where
+        F: Fn(Arc<RpcClient>) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        retry(&self.retry, is_endpoint_failure, || self.call_once(&request)).await
+    }
+
+    /// Runs `request` against every healthy endpoint concurrently and returns the first success. The remaining
+    /// requests keep running in the background, which is what a transaction send wants.
+    pub async fn broadcast<T, F, Fut>(&self, request: F) -> ClientResult<T>
+    where
+        F: Fn(Arc<RpcClient>) -> Fut,
+        Fut: Future<Output = ClientResult<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        retry(&self.retry, is_endpoint_failure, || self.broadcast_once(&request)).await
+    }
+
+    /// Signs the instructions under a fresh blockhash, the first of `signers` paying for the transaction, and sends
+    /// them, waiting until the transaction confirms
+    pub async fn send_and_confirm(&self, instructions: &[Instruction], signers: &[&dyn Signer]) -> ClientResult<Signature> {
+        let recent_blockhash = self.call(|client| async move { client.get_latest_blockhash().await }).await?;
+        let transaction = Transaction::new_signed_with_payer(instructions, Some(&signers[0].pubkey()), signers, recent_blockhash);
+        self.call(|client| {
+            let transaction = transaction.clone();
+            async move { client.send_and_confirm_transaction(&transaction).await }
+        })
+        .await
+    }
+
+    /// The program's accounts of one anchor account type, picked out by their discriminator (and size, when it's
+    /// fixed) on the rpc node instead of downloading every account and probing it
+    pub async fn get_accounts_by_discriminator(&self, program_id: &Pubkey, discriminator: [u8; 8], data_size: Option<usize>) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.program_accounts(program_id, discriminator, data_size, None).await
+    }
+
+    /// Like `get_accounts_by_discriminator` but without the account data, for listing what exists cheaply
+    pub async fn get_pubkeys_by_discriminator(&self, program_id: &Pubkey, discriminator: [u8; 8]) -> ClientResult<Vec<Pubkey>> {
+        let accounts = self.program_accounts(program_id, discriminator, None, Some(UiDataSliceConfig { offset: 0, length: 0 })).await?;
+        Ok(accounts.into_iter().map(|(pubkey, _)| pubkey).collect())
+    }
+
+    /// Fetches the accounts in as few getMultipleAccounts requests as it can, leaving out the ones that don't exist
+    pub async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> ClientResult<Vec<(Pubkey, Account)>> {
+        let requests = pubkeys
+            .chunks(MAX_MULTIPLE_ACCOUNTS)
+            .map(|chunk| self.call(|client| async move { client.get_multiple_accounts_with_commitment(chunk, client.commitment()).await }));
+        let mut accounts = vec![];
+        for (chunk, response) in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS).zip(join_all(requests).await) {
+            accounts.extend(chunk.iter().copied().zip(response?.value).filter_map(|(pubkey, account)| Some((pubkey, account?))));
+        }
+        Ok(accounts)
+    }
+
+    async fn program_accounts(&self, program_id: &Pubkey, discriminator: [u8; 8], data_size: Option<usize>, data_slice: Option<UiDataSliceConfig>) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.call(|client| async move {
+            let mut filters = vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, discriminator.to_vec()))];
+            filters.extend(data_size.map(|size| RpcFilterType::DataSize(size as u64)));
+            let config = RpcProgramAccountsConfig {
+                filters: Some(filters),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    data_slice,
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            };
+            client.get_program_accounts_with_config(program_id, config).await
+        })
+        .await
+    }
+
+    async fn call_once<T, F, Fut>(&self, request: &F) -> ClientResult<T>
+    where
+        F: Fn(Arc<RpcClient>) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let mut last_err = None;
+        for endpoint in self.ranked() {
+            endpoint.limiter.acquire().await;
+            let permit = self.permits.acquire().await.unwrap();
+            let result = request(endpoint.client.clone()).await;
+            drop(permit);
+            match result {
+                Err(err) if is_endpoint_failure(&err) => {
+                    warn!(endpoint = %endpoint.client.url(), error = %err, "rpc endpoint failed");
+                    endpoint.mark_unhealthy();
+                    last_err = Some(err);
+                }
+                result => {
+                    endpoint.mark_healthy();
+                    return result;
+                }
+            }
+        }
+
+        Err(last_err.unwrap())
+    }
+
+    async fn broadcast_once<T, F, Fut>(&self, request: &F) -> ClientResult<T>
+    where
+        F: Fn(Arc<RpcClient>) -> Fut,
+        Fut: Future<Output = ClientResult<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let endpoints = self.endpoints();
+        let mut targets: Vec<usize> = (0..endpoints.len()).filter(|index| endpoints[*index].is_healthy()).collect();
+        if targets.is_empty() {
+            targets = (0..endpoints.len()).collect();
+        }
+
+        let mut pending: FuturesUnordered<_> = targets
+            .into_iter()
+            .map(|index| {
+                let limiter = endpoints[index].limiter.clone();
+                let permits = self.permits.clone();
+                let request = request(endpoints[index].client.clone());
+                let handle = tokio::spawn(async move {
+                    limiter.acquire().await;
+                    let _permit = permits.acquire_owned().await.unwrap();
+                    request.await
+                });
+                async move { (index, handle.await) }
+            })
+            .collect();
+
+        let mut last_err = None;
+        while let Some((index, result)) = pending.next().await {
+            // a request that panicked counts as failed on its endpoint
+            match result.unwrap_or_else(|err| Err(ClientErrorKind::Custom(format!("rpc request task failed: {}", err)).into())) {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    if is_endpoint_failure(&err) {
+                        warn!(endpoint = %endpoints[index].client.url(), error = %err, "rpc endpoint failed");
+                        endpoints[index].mark_unhealthy();
+                    }
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.unwrap())
+    }
+
+    /// Endpoints in the order reads should try them: healthy and current ones first, fastest first, with the
+    /// ones cooling down only as a last resort
+    fn ranked(&self) -> Vec<Arc<Endpoint>> {
+        let mut ranked = self.endpoints();
+        let best_slot = ranked.iter().map(|endpoint| endpoint.slot.load(Ordering::Relaxed)).max().unwrap_or(0);
+        // stable, so endpoints the monitor hasn't measured yet keep their configured order
+        ranked.sort_by_key(|endpoint| {
+            let lagging = best_slot.saturating_sub(endpoint.slot.load(Ordering::Relaxed)) > MAX_SLOT_LAG;
+            (!endpoint.is_healthy(), lagging, endpoint.latency.load(Ordering::Relaxed))
+        });
+        ranked
+    }
+}
+
+/// Whether the error says the endpoint is down or overloaded, rather than the request itself being bad
+fn is_endpoint_failure(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => {
+            err.is_timeout()
+                || err.is_connect()
+                || err.status().map_or(false, |status| status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use clearing_house::{math::constants::{MARGIN_PRECISION, MARK_PRICE_PRECISION}, state::{market::Markets, user::{User, UserPositions}}};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{cache::AccountCache, config::Config, metrics::QUOTE_PRECISION, risk, v2::{self, MarginType}};
+
+// how far from the price a crossing is looked for, as a factor either way
+const SEARCH_RANGE: f64 = 100.0;
+// halvings of the search range, well past a cent on any price
+const SEARCH_STEPS: usize = 64;
+
+/// Hypothetical prices `simulate-price` values a user at, in quote per unit of base, by market index
+pub struct Prices {
+    pub perp: BTreeMap<u16, f64>,
+    pub spot: BTreeMap<u16, f64>,
+}
+
+impl Prices {
+    /// Reads `<market index>=<price>` entries for perp and spot markets
+    pub fn parse(perp: &[String], spot: &[String]) -> Result<Prices, String> {
+        Ok(Prices { perp: parse_entries("perp", perp)?, spot: parse_entries("spot", spot)? })
+    }
+}
+
+fn parse_entries(kind: &str, entries: &[String]) -> Result<BTreeMap<u16, f64>, String> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (market_index, price) = entry.split_once('=').ok_or_else(|| format!("{} price {} isn't <market index>=<price>", kind, entry))?;
+            let market_index = market_index.trim().parse().map_err(|_| format!("{} price {} has no valid market index", kind, entry))?;
+            let price: f64 = price.trim().parse().map_err(|_| format!("{} price {} has no valid price", kind, entry))?;
+            if price <= 0.0 {
+                return Err(format!("{} price {} isn't positive", kind, entry));
+            }
+            Ok((market_index, price))
+        })
+        .collect()
+}
+
+// the price, moving away from `price` in the direction that hurts the user, at which `liquidatable` first holds,
+// `price` itself if it already does. None if it doesn't until the price moved by over `SEARCH_RANGE`.
+fn crossing(price: f64, mut liquidatable: impl FnMut(f64) -> bool) -> Option<f64> {
+    if liquidatable(price) {
+        return Some(price);
+    }
+    let far = [price / SEARCH_RANGE, price * SEARCH_RANGE].into_iter().find(|far| liquidatable(*far))?;
+    let (mut safe, mut unsafe_price) = (price, far);
+    for _ in 0..SEARCH_STEPS {
+        let middle = (safe + unsafe_price) / 2.0;
+        if liquidatable(middle) {
+            unsafe_price = middle;
+        } else {
+            safe = middle;
+        }
+    }
+    Some(unsafe_price)
+}
+
+/// Where a market or oracle has to go for the user to cross a threshold, the others held at the given prices
+#[derive(Serialize)]
+pub struct Crossing {
+    /// on v2 the first of the user's markets priced off the oracle
+    pub market: String,
+    /// the user's position there, v1 only, v2 users being exposed to the oracle through any of its markets
+    pub side: Option<&'static str>,
+    pub price: f64,
+    pub threshold: &'static str,
+    /// the price itself if the user already crossed it, None if it doesn't before moving `SEARCH_RANGE` times
+    /// either way
+    pub crossing_price: Option<f64>,
+    /// from `price`, as a fraction
+    pub price_move: Option<f64>,
+}
+
+impl Crossing {
+    fn new(market: String, side: Option<&'static str>, price: f64, threshold: &'static str, crossing_price: Option<f64>) -> Crossing {
+        Crossing { market, side, price, threshold, crossing_price, price_move: crossing_price.map(|crossing_price| crossing_price / price - 1.0) }
+    }
+}
+
+/// How v1 values a user now and at the given prices, margin ratios as fractions, None without positions
+#[derive(Serialize)]
+pub struct V1Simulation {
+    pub user: String,
+    pub margin_ratio: Option<f64>,
+    pub margin_ratio_at_prices: Option<f64>,
+    pub crossings: Vec<Crossing>,
+}
+
+/// How v2 values a user's maintenance margin now and at the given prices, in quote. None if a market it has a
+/// position in isn't loaded.
+#[derive(Serialize)]
+pub struct V2Simulation {
+    pub user: String,
+    pub total_collateral: Option<f64>,
+    pub margin_requirement: Option<f64>,
+    pub total_collateral_at_prices: Option<f64>,
+    pub margin_requirement_at_prices: Option<f64>,
+    pub crossings: Vec<Crossing>,
+}
+
+// v1 values positions off the amm, so a market's price moves through its peg, which scales it linearly
+fn set_mark_price(markets: &mut Markets, market_index: u16, price: f64) -> Result<(), String> {
+    let market = markets.markets.get_mut(Markets::index_from_u64(market_index as u64)).filter(|market| market.initialized).ok_or(format!("no market {}", market_index))?;
+    let mark_price = market.amm.mark_price().map_err(|err| format!("{:?}", err))? as f64 / MARK_PRICE_PRECISION as f64;
+    market.amm.peg_multiplier = (market.amm.peg_multiplier as f64 * price / mark_price) as u128;
+    Ok(())
+}
+
+fn mark_price(markets: &Markets, market_index: u16) -> Option<f64> {
+    let market = markets.markets.get(Markets::index_from_u64(market_index as u64))?;
+    Some(market.amm.mark_price().ok()? as f64 / MARK_PRICE_PRECISION as f64)
+}
+
+// the user's margin ratio with the markets as given, u128::MAX without positions
+fn margin_ratio(markets: &Markets, user: &User, user_positions: &UserPositions) -> Result<u128, String> {
+    let mut user_positions = *user_positions;
+    let (_, _, _, margin_ratio) = risk::calculate_margin_ratio(user, &mut user_positions, markets).map_err(|err| err.to_string())?;
+    Ok(margin_ratio)
+}
+
+/// Values the v1 user's margin ratio now and at `prices`, then, for each market it holds a position in, finds
+/// the mark price that market has to reach, the others held at `prices`, for the user to become partially and
+/// then fully liquidatable
+pub fn v1(cache: &AccountCache, config: &Config, user_pubkey: &Pubkey, prices: &Prices) -> Result<V1Simulation, String> {
+    if !prices.spot.is_empty() {
+        return Err("v1 has no spot markets".to_string());
+    }
+    let state = &cache.state.1;
+    let mut user = cache.users.get(user_pubkey).ok_or("no such v1 user")?.clone();
+    let mut user_positions = *cache.positions.get(&user.positions).ok_or("the user's positions account isn't loaded")?;
+    let mut markets = cache.markets.1;
+    risk::settle_funding_payment(&mut user, &mut user_positions, &markets).map_err(|err| err.to_string())?;
+    let ratio = |margin_ratio: u128| Some(margin_ratio).filter(|margin_ratio| *margin_ratio != u128::MAX).map(|margin_ratio| margin_ratio as f64 / MARGIN_PRECISION as f64);
+
+    let now = margin_ratio(&markets, &user, &user_positions)?;
+    for (market_index, price) in &prices.perp {
+        set_mark_price(&mut markets, *market_index, *price)?;
+    }
+    let mut simulation = V1Simulation {
+        user: bs58::encode(user_pubkey.to_bytes()).into_string(),
+        margin_ratio: ratio(now),
+        margin_ratio_at_prices: ratio(margin_ratio(&markets, &user, &user_positions)?),
+        crossings: vec![],
+    };
+
+    let thresholds = [("partially liquidatable", state.margin_ratio_partial.saturating_sub(risk::margin_ratio_buffer(config, &user_positions))), ("fully liquidatable", state.margin_ratio_maintenance)];
+    for position in user_positions.positions.iter().filter(|position| position.base_asset_amount != 0) {
+        let market_index = position.market_index as u16;
+        let price = mark_price(&markets, market_index).ok_or(format!("no market {}", market_index))?;
+        let side = if position.base_asset_amount > 0 { "long" } else { "short" };
+        for (name, threshold) in thresholds {
+            let liquidatable = |price: f64| {
+                let mut moved = markets;
+                set_mark_price(&mut moved, market_index, price).is_ok() && margin_ratio(&moved, &user, &user_positions).map_or(false, |margin_ratio| margin_ratio <= threshold)
+            };
+            simulation.crossings.push(Crossing::new(format!("market {}", market_index), Some(side), price, name, crossing(price, liquidatable)));
+        }
+    }
+    Ok(simulation)
+}
+
+// prices every market on the oracle at `price`, in quote precision per unit of base
+fn set_oracle_price(cache: &mut v2::Cache, oracle: &Pubkey, price: i64) {
+    for market in cache.perp_markets.values_mut().filter(|market| market.oracle == *oracle) {
+        market.oracle_price = price;
+    }
+    for market in cache.spot_markets.values_mut().filter(|market| market.oracle == *oracle) {
+        market.oracle_price = price;
+    }
+}
+
+/// Values the v2 user's maintenance margin now and at `prices`, then, for each oracle it is exposed to, finds the
+/// price it has to reach, the others held at `prices`, for the user to become liquidatable and for its
+/// maintenance collateral to run out. v2 liquidates in steps from the first crossing on, so there is no partial
+/// threshold. Every market priced off an oracle moves with it.
+pub fn v2(cache: &mut v2::Cache, user_pubkey: &Pubkey, prices: &Prices) -> Result<V2Simulation, String> {
+    let user = cache.users.remove(user_pubkey).ok_or("no such v2 user")?;
+    let result = v2_user(cache, &user, user_pubkey, prices);
+    cache.users.insert(*user_pubkey, user);
+    result
+}
+
+fn v2_user(cache: &mut v2::Cache, user: &v2::User, user_pubkey: &Pubkey, prices: &Prices) -> Result<V2Simulation, String> {
+    let margin = |cache: &v2::Cache| v2::margin(cache, user, MarginType::Maintenance).map(|(total_collateral, margin_requirement)| (total_collateral as f64 / QUOTE_PRECISION, margin_requirement as f64 / QUOTE_PRECISION));
+    let now = margin(cache);
+    let moved: Vec<(Pubkey, f64)> = prices
+        .perp
+        .iter()
+        .map(|(market_index, price)| Ok((cache.perp_markets.get(market_index).ok_or(format!("no perp market {}", market_index))?.oracle, *price)))
+        .chain(prices.spot.iter().map(|(market_index, price)| Ok((cache.spot_markets.get(market_index).ok_or(format!("no spot market {}", market_index))?.oracle, *price))))
+        .collect::<Result<_, String>>()?;
+    for (oracle, price) in &moved {
+        set_oracle_price(cache, oracle, (price * QUOTE_PRECISION) as i64);
+    }
+    let at_prices = margin(cache);
+    let mut simulation = V2Simulation {
+        user: bs58::encode(user_pubkey.to_bytes()).into_string(),
+        total_collateral: now.map(|(total_collateral, _)| total_collateral),
+        margin_requirement: now.map(|(_, margin_requirement)| margin_requirement),
+        total_collateral_at_prices: at_prices.map(|(total_collateral, _)| total_collateral),
+        margin_requirement_at_prices: at_prices.map(|(_, margin_requirement)| margin_requirement),
+        crossings: vec![],
+    };
+
+    // the user's oracles, named after the first of its markets priced off each, quote markets having none
+    let mut oracles: Vec<(String, Pubkey, i64)> = vec![];
+    let perp = user.perp_positions.iter().filter_map(|position| cache.perp_markets.get(&position.market_index)).map(|market| (format!("perp market {}", market.market_index), market.oracle, market.oracle_price));
+    let spot = user.spot_positions.iter().filter_map(|position| cache.spot_markets.get(&position.market_index)).map(|market| (format!("spot market {}", market.market_index), market.oracle, market.oracle_price));
+    for (name, oracle, price) in perp.chain(spot).collect::<Vec<_>>() {
+        if oracle != Pubkey::default() && !oracles.iter().any(|(_, known, _)| *known == oracle) {
+            oracles.push((name, oracle, price));
+        }
+    }
+
+    for (name, oracle, oracle_price) in oracles {
+        let price = oracle_price as f64 / QUOTE_PRECISION;
+        for (label, exhausted) in [("liquidatable", false), ("out of collateral", true)] {
+            let liquidatable = |price: f64| {
+                set_oracle_price(cache, &oracle, (price * QUOTE_PRECISION) as i64);
+                let margin = v2::margin(cache, user, MarginType::Maintenance);
+                set_oracle_price(cache, &oracle, oracle_price);
+                margin.map_or(false, |(total_collateral, margin_requirement)| total_collateral < if exhausted { 0 } else { margin_requirement })
+            };
+            simulation.crossings.push(Crossing::new(name.clone(), None, price, label, crossing(price, liquidatable)));
+        }
+    }
+    Ok(simulation)
+}
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use tracing::{info, warn};
+
+use crate::{config::Config, metrics::QUOTE_PRECISION, risk::Candidate};
+
+// keeps a runaway script from stalling the risk stage
+const MAX_OPERATIONS: u64 = 100_000;
+// margin ratios are in margin precision on both protocols
+const MARGIN_PRECISION: f64 = 10_000.0;
+
+/// The rhai script at `script_path`, asked about every candidate the strategy accepted before it is queued.
+/// The script defines `select(candidate)`, handed a map of the candidate: `user`, `margin_ratio` (collateral
+/// over notional on v1, collateral over the maintenance requirement on v2), `kind`, `market_index`, `reward`
+/// and `insurance_fund_reward` in quote and `sol_price`. It returns true to accept the candidate, false or
+/// nothing to reject it, or an integer to accept it ranked by that instead of the strategy's priority, negative
+/// ones rejecting it too. A script that errors accepts the candidate as the strategy has it.
+pub struct ScriptFilter {
+    engine: Engine,
+    script: Option<AST>,
+}
+
+impl Default for ScriptFilter {
+    fn default() -> ScriptFilter {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        ScriptFilter { engine, script: None }
+    }
+}
+
+impl ScriptFilter {
+    /// Compiles the config's script, none without a `script_path`
+    pub fn new(config: &Config) -> Result<ScriptFilter, String> {
+        let mut filter = ScriptFilter::default();
+        if let Some(path) = &config.script_path {
+            let script = filter.engine.compile_file(PathBuf::from(path)).map_err(|err| format!("failed to compile {}: {}", path, err))?;
+            if !script.iter_functions().any(|function| function.name == "select" && function.params.len() == 1) {
+                return Err(format!("{} doesn't define select(candidate)", path));
+            }
+            info!(%path, "selecting candidates with a script");
+            filter.script = Some(script);
+        }
+        Ok(filter)
+    }
+
+    /// Picks up a reloaded config's script, which may have changed on disk since, keeping the current one if it
+    /// doesn't compile
+    pub fn reconfigure(&mut self, config: &Config) {
+        match ScriptFilter::new(config) {
+            Ok(filter) => *self = filter,
+            Err(err) => warn!(error = %err, "keeping the current candidate script"),
+        }
+    }
+
+    /// Whether the script accepts the candidate, setting its priority if the script ranked it
+    pub fn accepts(&self, candidate: &mut Candidate) -> bool {
+        let script = match &self.script {
+            Some(script) => script,
+            None => return true,
+        };
+        let user = bs58::encode(candidate.user.to_bytes()).into_string();
+        let selected = match self.engine.call_fn::<Dynamic>(&mut Scope::new(), script, "select", (describe(candidate, &user),)) {
+            Ok(selected) => selected,
+            Err(err) => {
+                warn!(%user, error = %err, "candidate script failed, accepting the candidate");
+                return true;
+            }
+        };
+        if let Ok(accepted) = selected.as_bool() {
+            accepted
+        } else if let Ok(priority) = selected.as_int() {
+            candidate.priority = u128::try_from(priority).ok();
+            priority >= 0
+        } else if selected.is_unit() {
+            false
+        } else {
+            warn!(%user, returned = selected.type_name(), "candidate script returned neither a bool nor an integer, accepting the candidate");
+            true
+        }
+    }
+}
+
+fn describe(candidate: &Candidate, user: &str) -> Map {
+    let mut map = Map::new();
+    map.insert("user".into(), user.to_string().into());
+    map.insert("margin_ratio".into(), (candidate.margin_ratio as f64 / MARGIN_PRECISION).into());
+    map.insert("kind".into(), candidate.liquidation.kind().to_string().into());
+    map.insert("market_index".into(), candidate.liquidation.market_index().map_or(Dynamic::UNIT, |market_index| (market_index as i64).into()));
+    map.insert("reward".into(), (candidate.reward.liquidator as f64 / QUOTE_PRECISION).into());
+    map.insert("insurance_fund_reward".into(), (candidate.reward.insurance_fund as f64 / QUOTE_PRECISION).into());
+    map.insert("sol_price".into(), (candidate.sol_price as f64 / QUOTE_PRECISION).into());
+    map
+}
@@ -0,0 +1,287 @@
+use std::{error::Error, str::FromStr, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::Deserialize;
+use serde_json::json;
+use solana_client::{nonblocking::{rpc_client::RpcClient, tpu_client::TpuClient}, rpc_config::RpcSendTransactionConfig, tpu_client::TpuClientConfig};
+use solana_sdk::{commitment_config::{CommitmentConfig, CommitmentLevel}, instruction::Instruction, pubkey::Pubkey, signature::Signature, system_instruction, transaction::VersionedTransaction};
+use tokio::{sync::OnceCell, time::sleep};
+use tracing::{info, warn};
+
+use crate::rpc::RpcPool;
+
+pub type SendResult = Result<Signature, Box<dyn Error + Send + Sync>>;
+
+/// A path for getting signed liquidation transactions to the leader
+#[async_trait]
+pub trait TxSender: Send + Sync {
+    /// Instructions appended to every transaction sent through this path, e.g. a tip
+    fn extra_instructions(&self, _payer: &Pubkey) -> Vec<Instruction> {
+        vec![]
+    }
+
+    /// Lamports the extra instructions pay on top of the transaction fee
+    fn tip_lamports(&self) -> u64 {
+        0
+    }
+
+    async fn send(&self, transaction: &VersionedTransaction) -> SendResult;
+}
+
+/// Sends through `sendTransaction` on every healthy rpc endpoint
+pub struct RpcSender {
+    pub rpc: Arc<RpcPool>,
+    pub skip_preflight: bool,
+    pub preflight_commitment: CommitmentLevel,
+}
+
+#[async_trait]
+impl TxSender for RpcSender {
+    async fn send(&self, transaction: &VersionedTransaction) -> SendResult {
+        let config = RpcSendTransactionConfig {
+            skip_preflight: self.skip_preflight,
+            preflight_commitment: Some(self.preflight_commitment),
+            ..RpcSendTransactionConfig::default()
+        };
+        let signature = self
+            .rpc
+            .broadcast(|client| {
+                let transaction = transaction.clone();
+                async move { client.send_transaction_with_config(&transaction, config).await }
+            })
+            .await?;
+        Ok(signature)
+    }
+}
+
+/// Sends straight to the tpu of the current and upcoming leaders over quic, skipping the rpc node's forwarding
+/// queue. The client tracking the leader schedule is set up on the first send.
+pub struct TpuSender {
+    pub rpc_url: String,
+    pub ws_url: String,
+    /// number of upcoming leader slots each transaction is sent to
+    pub fanout_slots: u64,
+    pub client: OnceCell<TpuClient>,
+}
+
+impl TpuSender {
+    pub fn new(rpc_url: String, ws_url: String, fanout_slots: u64) -> TpuSender {
+        TpuSender {
+            rpc_url,
+            ws_url,
+            fanout_slots,
+            client: OnceCell::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl TxSender for TpuSender {
+    async fn send(&self, transaction: &VersionedTransaction) -> SendResult {
+        let client = self
+            .client
+            .get_or_try_init(|| async {
+                let rpc_client = Arc::new(RpcClient::new_with_commitment(self.rpc_url.clone(), CommitmentConfig::processed()));
+                TpuClient::new(rpc_client, &self.ws_url, TpuClientConfig { fanout_slots: self.fanout_slots }).await
+            })
+            .await?;
+
+        if !client.send_wire_transaction(bincode::serialize(transaction)?).await {
+            return Err("no leader tpu accepted the transaction".into());
+        }
+
+        Ok(transaction.signatures[0])
+    }
+}
+
+// jito's mainnet tip payment accounts, any of them works
+const JITO_TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
+
+#[derive(Deserialize)]
+struct JsonRpcError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct JsonRpcResponse {
+    result: Option<String>,
+    error: Option<JsonRpcError>,
+}
+
+/// Submits every liquidation as a single transaction Jito bundle through the block engine. The tip transfer
+/// is part of the liquidation transaction itself, so it is only paid if the liquidation lands.
+pub struct JitoSender {
+    /// block engine url, e.g. `https://mainnet.block-engine.jito.wtf`
+    pub url: String,
+    pub tip_lamports: u64,
+    pub http: reqwest::Client,
+}
+
+#[async_trait]
+impl TxSender for JitoSender {
+    fn extra_instructions(&self, payer: &Pubkey) -> Vec<Instruction> {
+        // spread the tips so concurrent bundles don't all write lock the same account
+        let tip_account = JITO_TIP_ACCOUNTS[rand::random::<usize>() % JITO_TIP_ACCOUNTS.len()];
+        vec![system_instruction::transfer(payer, &Pubkey::from_str(tip_account).unwrap(), self.tip_lamports)]
+    }
+
+    fn tip_lamports(&self) -> u64 {
+        self.tip_lamports
+    }
+
+    async fn send(&self, transaction: &VersionedTransaction) -> SendResult {
+        let encoded = bs58::encode(bincode::serialize(transaction)?).into_string();
+        let response: JsonRpcResponse = self
+            .http
+            .post(format!("{}/api/v1/bundles", self.url.trim_end_matches('/')))
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "sendBundle",
+                "params": [[encoded]],
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(error) = response.error {
+            return Err(format!("jito rejected bundle: {}", error.message).into());
+        }
+        info!(bundle = %response.result.unwrap_or_default(), "submitted jito bundle");
+
+        Ok(transaction.signatures[0])
+    }
+}
+
+/// Send counters of one path of a `MultiSender`
+#[derive(Default)]
+pub struct PathStats {
+    pub sent: AtomicU64,
+    pub accepted: AtomicU64,
+    /// times this path accepted the transaction before any other path did
+    pub first: AtomicU64,
+    /// landed transactions this path was first to accept
+    pub landed: AtomicU64,
+}
+
+pub struct SendPath {
+    pub name: &'static str,
+    pub sender: Arc<dyn TxSender>,
+    pub stats: Arc<PathStats>,
+}
+
+// how long a multi path send is watched for landing
+const LANDING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const LANDING_POLLS: u32 = 120;
+
+/// Sends the same signed transaction through several paths at once. Since all copies share a signature at most
+/// one lands, and the landing is credited to the path that accepted the transaction first, which is the best
+/// signal available for which path actually delivered it.
+pub struct MultiSender {
+    pub paths: Vec<SendPath>,
+    /// used to watch signatures for landing
+    pub rpc: Arc<RpcPool>,
+}
+
+impl MultiSender {
+    pub fn stats(&self) -> Vec<(&'static str, Arc<PathStats>)> {
+        self.paths.iter().map(|path| (path.name, path.stats.clone())).collect()
+    }
+}
+
+/// One line summary of every path's counters
+pub fn stats_summary(stats: &[(&'static str, Arc<PathStats>)]) -> String {
+    stats
+        .iter()
+        .map(|(name, stats)| {
+            format!(
+                "{} landed {} first {} accepted {}/{}",
+                name,
+                stats.landed.load(Ordering::Relaxed),
+                stats.first.load(Ordering::Relaxed),
+                stats.accepted.load(Ordering::Relaxed),
+                stats.sent.load(Ordering::Relaxed),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[async_trait]
+impl TxSender for MultiSender {
+    fn extra_instructions(&self, payer: &Pubkey) -> Vec<Instruction> {
+        self.paths.iter().flat_map(|path| path.sender.extra_instructions(payer)).collect()
+    }
+
+    fn tip_lamports(&self) -> u64 {
+        self.paths.iter().map(|path| path.sender.tip_lamports()).sum()
+    }
+
+    async fn send(&self, transaction: &VersionedTransaction) -> SendResult {
+        let started = Instant::now();
+        let results = join_all(self.paths.iter().map(|path| async move {
+            path.stats.sent.fetch_add(1, Ordering::Relaxed);
+            let result = path.sender.send(transaction).await;
+            (path, result, started.elapsed())
+        }))
+        .await;
+
+        let mut first: Option<(&SendPath, Duration)> = None;
+        let mut last_err = None;
+        for (path, result, elapsed) in results {
+            match result {
+                Ok(_) => {
+                    path.stats.accepted.fetch_add(1, Ordering::Relaxed);
+                    if first.map_or(true, |(_, fastest)| elapsed < fastest) {
+                        first = Some((path, elapsed));
+                    }
+                }
+                Err(err) => {
+                    warn!(path = path.name, error = %err, "send failed");
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        let (first, _) = match first {
+            Some(first) => first,
+            None => return Err(last_err.unwrap()),
+        };
+        first.stats.first.fetch_add(1, Ordering::Relaxed);
+
+        let signature = transaction.signatures[0];
+        let name = first.name;
+        let stats = first.stats.clone();
+        let all_stats = self.stats();
+        let rpc = self.rpc.clone();
+        tokio::spawn(async move {
+            for _ in 0..LANDING_POLLS {
+                sleep(LANDING_POLL_INTERVAL).await;
+                let status = rpc.call(|client| async move { client.get_signature_status(&signature).await }).await;
+                match status {
+                    Ok(Some(Ok(()))) => {
+                        stats.landed.fetch_add(1, Ordering::Relaxed);
+                        info!(%signature, path = name, "transaction landed, first accepted via its path ({})", stats_summary(&all_stats));
+                        return;
+                    }
+                    // landed but failed, nobody gets credit
+                    Ok(Some(Err(_))) => return,
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(signature)
+    }
+}
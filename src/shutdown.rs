@@ -0,0 +1,44 @@
+use tokio::{signal::{self, unix::{self, SignalKind}}, sync::{mpsc::Receiver, watch, Notify}};
+use tracing::info;
+
+// stops requested from inside the process, e.g. by quitting the dashboard
+static REQUESTED: Notify = Notify::const_new();
+
+/// Flips to true once the process is asked to stop with SIGINT (ctrl+c), SIGTERM or `request`, for the stages to
+/// wind down on. A second signal exits right away.
+pub fn listen() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    tokio::spawn(async move {
+        terminated().await;
+        info!("shutting down, signal again to exit right away");
+        let _ = sender.send(true);
+        terminated().await;
+        std::process::exit(130);
+    });
+    receiver
+}
+
+/// The next message on the channel, or None once it closed or shutdown began
+pub async fn recv<T>(receiver: &mut Receiver<T>, shutdown: &mut watch::Receiver<bool>) -> Option<T> {
+    if *shutdown.borrow() {
+        return None;
+    }
+    tokio::select! {
+        message = receiver.recv() => message,
+        _ = shutdown.changed() => None,
+    }
+}
+
+/// Asks the process to stop as if it got a signal
+pub fn request() {
+    REQUESTED.notify_one();
+}
+
+async fn terminated() {
+    let mut terminate = unix::signal(SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+        _ = REQUESTED.notified() => {}
+    }
+}
@@ -0,0 +1,187 @@
+use std::{env, fs::File, sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use solana_sdk::{pubkey::Pubkey, signature::Signature, signer::{keypair::{read_keypair, Keypair}, Signer, SignerError}};
+
+use crate::config::{Config, SignerKind};
+
+/// Environment variable holding the signer secret, either base58 encoded or as the json byte array written
+/// by `solana-keygen`. Takes precedence over `keypair_path` so containers don't need a mounted keyfile.
+pub const KEYPAIR_ENV: &str = "LIQUIDATOR_KEYPAIR";
+
+/// Fee payer and transaction signer shared between the pipeline stages
+pub type SharedSigner = Arc<dyn Signer + Send + Sync>;
+
+/// Builds the signer backend selected by `config.signer`
+pub fn load_signer(config: &Config) -> SharedSigner {
+    match config.signer {
+        SignerKind::Keypair => Arc::new(load_keypair(config)),
+        #[cfg(feature = "ledger")]
+        SignerKind::Ledger => Arc::new(ledger::LedgerSigner::connect(&config.ledger_url, config.ledger_derivation_path.as_deref())),
+        #[cfg(not(feature = "ledger"))]
+        SignerKind::Ledger => panic!("ledger signing requires building with `--features ledger`"),
+        SignerKind::Remote => Arc::new(RemoteSigner::connect(
+            config.remote_signer_url.as_deref().expect("remote signer requires remote_signer_url"),
+            config.remote_signer_token.clone(),
+        )),
+    }
+}
+
+/// Loads the fee payer and transaction signer keypair
+pub fn load_keypair(config: &Config) -> Keypair {
+    match env::var(KEYPAIR_ENV) {
+        Ok(secret) => keypair_from_str(&secret),
+        Err(_) => read_keypair(&mut File::open(&config.keypair_path).unwrap()).unwrap(),
+    }
+}
+
+/// Parses a keypair from a base58 secret key or a json array of its 64 bytes
+pub fn keypair_from_str(secret: &str) -> Keypair {
+    let secret = secret.trim();
+    if secret.starts_with('[') {
+        return read_keypair(&mut secret.as_bytes()).unwrap();
+    }
+
+    Keypair::from_bytes(&bs58::decode(secret).into_vec().unwrap()).unwrap()
+}
+
+#[derive(Deserialize)]
+struct PubkeyResponse {
+    pubkey: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+/// Signs through a remote signing service so the private key never lives on the liquidator host.
+///
+/// The service exposes `GET {url}/pubkey` answering `{"pubkey": "<base58>"}` and `POST {url}/sign` taking
+/// `{"pubkey": "<base58>", "message": "<base64>"}` and answering `{"signature": "<base58>"}`. If a token is
+/// configured it is sent as a bearer `Authorization` header.
+pub struct RemoteSigner {
+    url: String,
+    token: Option<String>,
+    pubkey: Pubkey,
+    agent: ureq::Agent,
+}
+
+impl RemoteSigner {
+    pub fn connect(url: &str, token: Option<String>) -> RemoteSigner {
+        let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(5)).build();
+        let url = url.trim_end_matches('/').to_string();
+
+        let mut request = agent.get(&format!("{}/pubkey", url));
+        if let Some(token) = &token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let response: PubkeyResponse = request.call().unwrap().into_json().unwrap();
+
+        RemoteSigner {
+            pubkey: response.pubkey.parse().unwrap(),
+            url,
+            token,
+            agent,
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let mut request = self.agent.post(&format!("{}/sign", self.url));
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let response: SignResponse = request
+            .send_json(ureq::json!({
+                "pubkey": self.pubkey.to_string(),
+                "message": base64::encode(message),
+            }))
+            .map_err(|err| SignerError::Connection(err.to_string()))?
+            .into_json()
+            .map_err(|err| SignerError::Custom(err.to_string()))?;
+
+        let signature = response
+            .signature
+            .parse::<Signature>()
+            .map_err(|err| SignerError::Custom(err.to_string()))?;
+        // never hand out a signature that doesn't verify, the service could be misconfigured for another key
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Custom("remote signer returned an invalid signature".to_string()));
+        }
+
+        Ok(signature)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "ledger")]
+mod ledger {
+    use std::{sync::mpsc::{channel, Sender}, thread};
+
+    use solana_remote_wallet::{locator::Locator, remote_keypair::generate_remote_keypair, remote_wallet::maybe_wallet_manager};
+    use solana_sdk::{derivation_path::DerivationPath, pubkey::Pubkey, signature::Signature, signer::{Signer, SignerError}};
+
+    type SignRequest = (Vec<u8>, Sender<Result<Signature, SignerError>>);
+
+    /// Signs with a Ledger device. The remote wallet handles aren't thread safe, so the device is owned by a
+    /// dedicated thread and messages are passed to it for signing.
+    pub struct LedgerSigner {
+        pubkey: Pubkey,
+        requests: Sender<SignRequest>,
+    }
+
+    impl LedgerSigner {
+        /// Connects to the device at `url` (e.g. `usb://ledger`) and derives the signing key, `m/44'/501'` unless
+        /// a `account/change` derivation path is given
+        pub fn connect(url: &str, derivation_path: Option<&str>) -> LedgerSigner {
+            let locator = Locator::new_from_path(url).unwrap();
+            let derivation_path = derivation_path.map_or_else(DerivationPath::default, |path| DerivationPath::from_key_str(path).unwrap());
+            let (requests, receiver) = channel::<SignRequest>();
+            let (pubkey_sender, pubkey_receiver) = channel();
+
+            thread::spawn(move || {
+                let wallet_manager = maybe_wallet_manager().unwrap().expect("no ledger device found");
+                let keypair = generate_remote_keypair(locator, derivation_path, &wallet_manager, false, "liquidator").unwrap();
+                pubkey_sender.send(keypair.pubkey()).unwrap();
+
+                for (message, reply) in receiver {
+                    let _ = reply.send(keypair.try_sign_message(&message));
+                }
+            });
+
+            LedgerSigner {
+                pubkey: pubkey_receiver.recv().unwrap(),
+                requests,
+            }
+        }
+    }
+
+    impl Signer for LedgerSigner {
+        fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+            Ok(self.pubkey)
+        }
+
+        fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+            let (reply, signature) = channel();
+            self.requests
+                .send((message.to_vec(), reply))
+                .map_err(|_| SignerError::Connection("ledger thread exited".to_string()))?;
+            signature
+                .recv()
+                .map_err(|_| SignerError::Connection("ledger thread exited".to_string()))?
+        }
+
+        fn is_interactive(&self) -> bool {
+            true
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use clearing_house::error::ErrorCode;
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+
+// clearing house errors a liquidation can run into, decoded by their on chain code
+const LIQUIDATION_ERRORS: [ErrorCode; 9] = [
+    ErrorCode::SufficientCollateral,
+    ErrorCode::ExchangePaused,
+    ErrorCode::MathError,
+    ErrorCode::InvalidOracle,
+    ErrorCode::UnableToLoadOracle,
+    ErrorCode::OracleMarkSpreadLimit,
+    ErrorCode::LiquidationsBlockedByOracle,
+    ErrorCode::UserAccountAndUserPositionsAccountMismatch,
+    ErrorCode::CastingFailure,
+];
+
+/// Names the clearing house error behind a custom program error code, if it is one a liquidation can hit
+pub fn decode_error_code(code: u32) -> Option<String> {
+    LIQUIDATION_ERRORS
+        .iter()
+        .find(|error| u32::from(**error) == code)
+        .map(|error| format!("{:?} ({})", error, error))
+}
+
+/// Human readable reason for a failed simulation. Custom program errors are decoded into clearing house error
+/// names, falling back to the code as it appears in the program logs.
+pub fn describe_failure(err: &TransactionError, logs: &[String]) -> String {
+    let code = match err {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(*code),
+        _ => logs.iter().find_map(|log| parse_custom_error(log)),
+    };
+
+    match code {
+        Some(code) => decode_error_code(code).unwrap_or_else(|| format!("custom program error {}", code)),
+        None => err.to_string(),
+    }
+}
+
+// the runtime logs failed instructions as `Program <id> failed: custom program error: 0x<code>`
+fn parse_custom_error(log: &str) -> Option<u32> {
+    let code = log.split("custom program error: 0x").nth(1)?;
+    u32::from_str_radix(code.trim(), 16).ok()
+}
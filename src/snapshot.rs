@@ -0,0 +1,108 @@
+use std::{collections::HashSet, fs, str::FromStr, time::{Duration, Instant}};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+
+use crate::config::Config;
+
+/// The user accounts the liquidator knew about and how close each was to liquidation, written to disk so a
+/// restart can load the users at risk first and the rest in the background
+#[derive(Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub users: Vec<SnapshotUser>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SnapshotUser {
+    pub user: String,
+    /// v1 positions account
+    #[serde(default)]
+    pub positions: Option<String>,
+    /// margin ratio when the snapshot was taken, None if it had nothing to margin
+    #[serde(default)]
+    pub margin_ratio: Option<u128>,
+}
+
+impl Snapshot {
+    /// Reads the snapshot at `path`, None if there is none or it doesn't parse
+    pub fn load(path: &str) -> Option<Snapshot> {
+        let contents = fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&contents) {
+            Ok(snapshot) => Some(snapshot),
+            Err(err) => {
+                warn!(path, error = %err, "ignoring unreadable snapshot");
+                None
+            }
+        }
+    }
+
+    /// Writes the snapshot through a temporary file so a crash mid write can't leave a truncated one behind
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let temporary = format!("{}.tmp", path);
+        fs::write(&temporary, serde_json::to_vec(self)?)?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// Every account the snapshot lists, users and positions
+    pub fn pubkeys(&self) -> HashSet<Pubkey> {
+        self.users
+            .iter()
+            .flat_map(|user| std::iter::once(&user.user).chain(user.positions.as_ref()))
+            .filter_map(|pubkey| Pubkey::from_str(pubkey).ok())
+            .collect()
+    }
+
+    /// The accounts of the users whose margin ratio was under `threshold`
+    pub fn at_risk(&self, threshold: u128) -> HashSet<Pubkey> {
+        self.users
+            .iter()
+            .filter(|user| user.margin_ratio.map_or(false, |margin_ratio| margin_ratio < threshold))
+            .flat_map(|user| std::iter::once(&user.user).chain(user.positions.as_ref()))
+            .filter_map(|pubkey| Pubkey::from_str(pubkey).ok())
+            .collect()
+    }
+}
+
+/// Saves a snapshot of the cache every `snapshot_interval_secs` while the risk stage runs
+pub struct Snapshotter {
+    path: Option<String>,
+    interval: Duration,
+    last: Instant,
+}
+
+impl Snapshotter {
+    pub fn new(config: &Config) -> Snapshotter {
+        Snapshotter {
+            path: config.snapshot_path.clone(),
+            interval: Duration::from_secs(config.snapshot_interval_secs),
+            last: Instant::now(),
+        }
+    }
+
+    /// Builds and saves a snapshot if one is due
+    pub fn tick(&mut self, snapshot: impl FnOnce() -> Snapshot) {
+        let path = match &self.path {
+            Some(path) if self.last.elapsed() >= self.interval => path,
+            _ => return,
+        };
+
+        self.last = Instant::now();
+        save(path, snapshot());
+    }
+
+    /// Saves a snapshot whether or not one is due, for shutdown
+    pub fn flush(&mut self, snapshot: impl FnOnce() -> Snapshot) {
+        if let Some(path) = &self.path {
+            save(path, snapshot());
+            info!(path = path.as_str(), "saved snapshot");
+        }
+    }
+}
+
+fn save(path: &str, snapshot: Snapshot) {
+    if let Err(err) = snapshot.save(path) {
+        warn!(path, error = %err, "failed to save snapshot");
+    }
+}
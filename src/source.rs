@@ -0,0 +1,83 @@
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
+
+// what anchor writes over the discriminator of an account it closes
+const CLOSED_ACCOUNT_DISCRIMINATOR: [u8; 8] = [255; 8];
+
+/// An account write observed by a data source
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    /// program owning the account
+    pub owner: Pubkey,
+    pub data: Vec<u8>,
+    pub lamports: u64,
+    pub slot: u64,
+}
+
+impl AccountUpdate {
+    /// An update for an account that no longer exists
+    pub fn closed(pubkey: Pubkey, slot: u64) -> AccountUpdate {
+        AccountUpdate { pubkey, owner: Pubkey::default(), data: vec![], lamports: 0, slot }
+    }
+
+    /// Whether the write closed the account
+    pub fn is_closed(&self) -> bool {
+        self.lamports == 0 || self.data.is_empty() || self.data.starts_with(&CLOSED_ACCOUNT_DISCRIMINATOR)
+    }
+}
+
+/// One account type a data source streams, picked out by its anchor discriminator
+#[derive(Clone)]
+pub struct AccountFilter {
+    pub discriminator: [u8; 8],
+    /// stream only these byte ranges of the accounts instead of the whole account
+    pub slices: Option<AccountSlices>,
+}
+
+/// The byte ranges of an account that its decoder reads. Everything else is left out of the stream and zero
+/// filled when the account is rebuilt.
+#[derive(Clone)]
+pub struct AccountSlices {
+    /// (offset, length) pairs, sorted and not overlapping
+    pub ranges: Vec<(usize, usize)>,
+    /// full size of the account
+    pub len: usize,
+}
+
+impl AccountSlices {
+    /// Merges the ranges into the sorted, non overlapping form streams expect
+    pub fn new(mut ranges: Vec<(usize, usize)>, len: usize) -> AccountSlices {
+        ranges.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for (offset, length) in ranges {
+            match merged.last_mut() {
+                Some((last_offset, last_length)) if offset <= *last_offset + *last_length => {
+                    *last_length = (*last_length).max(offset + length - *last_offset);
+                }
+                _ => merged.push((offset, length)),
+            }
+        }
+        AccountSlices { ranges: merged, len }
+    }
+
+    /// Rebuilds a full size account from the concatenated ranges. None if the data doesn't add up to them.
+    pub fn expand(&self, sliced: &[u8]) -> Option<Vec<u8>> {
+        let mut data = vec![0; self.len];
+        let mut position = 0;
+        for (offset, length) in &self.ranges {
+            data.get_mut(*offset..offset + length)?.copy_from_slice(sliced.get(position..position + length)?);
+            position += length;
+        }
+        if position != sliced.len() {
+            return None;
+        }
+        Some(data)
+    }
+}
+
+/// A stream of clearing house program account writes feeding the account cache
+pub trait DataSource: Send + Sync {
+    /// Starts streaming account writes into `sender` on a background task. The source is expected to
+    /// reconnect on its own and only return once `sender` is closed. Aborting the task stops the stream.
+    fn spawn(&self, sender: Sender<AccountUpdate>) -> JoinHandle<()>;
+}
@@ -0,0 +1,106 @@
+use std::{collections::VecDeque, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Mutex, RwLock}, time::{Duration, Instant}};
+
+use serde::Serialize;
+
+use crate::{risk::Candidate, snapshot::{Snapshot, SnapshotUser}, watchdog};
+
+// how often the risk stage publishes its users
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+// liquidation attempts kept around
+const MAX_LIQUIDATIONS: usize = 1000;
+
+/// The liquidator's live view of the protocol, published by the stages for the status api and dashboard to show
+pub struct Status {
+    // whether anything shows the users, publishing them costs a margin calculation per user
+    enabled: AtomicBool,
+    /// every tracked user and its margin ratio as of the last publish
+    pub users: RwLock<Vec<SnapshotUser>>,
+    /// the margin ratio users are liquidated under, in the same units as theirs
+    pub liquidation_ratio: AtomicU64,
+    /// the most recent liquidation attempts, oldest first
+    pub liquidations: Mutex<VecDeque<LiquidationRecord>>,
+}
+
+/// How one user's liquidation went
+#[derive(Clone, Serialize)]
+pub struct LiquidationRecord {
+    pub user: String,
+    pub liquidation: &'static str,
+    pub market_index: Option<u16>,
+    /// "landed", "failed" or "expired"
+    pub outcome: &'static str,
+    pub signature: Option<String>,
+    /// the liquidator's expected share of the liquidation fee, in quote precision
+    pub reward: u64,
+    pub latency_ms: u64,
+    /// when the attempt ended, in milliseconds since the unix epoch
+    pub time_ms: u64,
+}
+
+pub static STATUS: Status = Status::new();
+
+impl Status {
+    const fn new() -> Status {
+        Status { enabled: AtomicBool::new(false), users: RwLock::new(vec![]), liquidation_ratio: AtomicU64::new(0), liquidations: Mutex::new(VecDeque::new()) }
+    }
+
+    /// The published users within `margin_percent` percent of the margin ratio they are liquidated at, e.g. 150
+    /// for the ones under one and a half times it, sorted by user
+    pub fn at_risk(&self, margin_percent: u128) -> Vec<(String, u128)> {
+        let threshold = (self.liquidation_ratio.load(Ordering::Relaxed) as u128).saturating_mul(margin_percent);
+        let mut at_risk: Vec<(String, u128)> = self
+            .users
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|user| Some((user.user.clone(), user.margin_ratio?)))
+            .filter(|(_, margin_ratio)| margin_ratio.saturating_mul(100) < threshold)
+            .collect();
+        at_risk.sort_unstable();
+        at_risk
+    }
+
+    /// Has the risk stage publish its users from now on
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Records how the liquidation of every candidate went, dropping the oldest records past the cap
+    pub fn record(&self, candidates: &[Candidate], outcome: &'static str, signature: Option<String>, latency: Duration) {
+        let mut liquidations = self.liquidations.lock().unwrap();
+        for candidate in candidates {
+            liquidations.push_back(LiquidationRecord {
+                user: bs58::encode(candidate.user.to_bytes()).into_string(),
+                liquidation: candidate.liquidation.kind(),
+                market_index: candidate.liquidation.market_index(),
+                outcome,
+                signature: signature.clone(),
+                reward: candidate.reward.liquidator,
+                latency_ms: latency.as_millis() as u64,
+                time_ms: watchdog::now_ms(),
+            });
+        }
+        while liquidations.len() > MAX_LIQUIDATIONS {
+            liquidations.pop_front();
+        }
+    }
+}
+
+/// Publishes the risk stage's users to `STATUS` every second, once the status api or dashboard enabled it
+#[derive(Default)]
+pub struct Publisher {
+    last: Option<Instant>,
+}
+
+impl Publisher {
+    /// Builds and publishes a snapshot if one is due, `liquidation_ratio` being the margin ratio users are
+    /// liquidated under
+    pub fn tick(&mut self, liquidation_ratio: u128, snapshot: impl FnOnce() -> Snapshot) {
+        if !STATUS.enabled.load(Ordering::Relaxed) || self.last.map_or(false, |last| last.elapsed() < PUBLISH_INTERVAL) {
+            return;
+        }
+        self.last = Some(Instant::now());
+        *STATUS.users.write().unwrap() = snapshot().users;
+        STATUS.liquidation_ratio.store(liquidation_ratio as u64, Ordering::Relaxed);
+    }
+}
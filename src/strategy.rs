@@ -0,0 +1,63 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{config::StrategyKind, risk::Candidate};
+
+/// Hooks deciding which liquidation candidates are sent, in what order and how big, for customizing candidate
+/// selection without touching the risk and executor loops. A strategy is compiled in as a `StrategyKind`
+/// variant and picked with the `strategy` setting, a reload switching strategies from the next candidates on.
+pub trait LiquidationStrategy: Send + Sync {
+    /// Whether to send the liquidatable user's candidate at all, asked by the risk stage before queueing it
+    fn should_liquidate(&self, _candidate: &Candidate) -> bool {
+        true
+    }
+
+    /// Rank among the candidates arriving together, the highest sent first. Ties go to the lower margin ratio.
+    fn priority(&self, candidate: &Candidate) -> u128;
+
+    /// v2: how much of the position or borrow to liquidate, at most `sizing.max`. 0 skips the liquidation. v1
+    /// liquidations are sized by the clearing house.
+    fn size(&self, sizing: &Sizing) -> u128 {
+        sizing.max
+    }
+}
+
+/// A v2 liquidation being sized
+pub struct Sizing<'a> {
+    pub user: &'a Pubkey,
+    pub margin_ratio: u128,
+    /// a perp position, or else a borrow
+    pub perp: bool,
+    /// the perp market, or the borrow's spot market
+    pub market_index: u16,
+    /// the most the liquidator's free collateral and the configured caps allow, perp positions in base precision
+    /// (1e9) and borrows in the borrowed token's precision
+    pub max: u128,
+}
+
+/// The most valuable reward first, every liquidation as big as the liquidator can take on
+pub struct RewardFirst;
+
+impl LiquidationStrategy for RewardFirst {
+    fn priority(&self, candidate: &Candidate) -> u128 {
+        candidate.reward.liquidator as u128
+    }
+}
+
+/// The user closest to bankruptcy first, whatever the reward, for operators backstopping the protocol rather
+/// than chasing fees
+pub struct RiskiestFirst;
+
+impl LiquidationStrategy for RiskiestFirst {
+    fn priority(&self, candidate: &Candidate) -> u128 {
+        u128::MAX - candidate.margin_ratio
+    }
+}
+
+impl StrategyKind {
+    pub fn strategy(&self) -> &'static dyn LiquidationStrategy {
+        match self {
+            StrategyKind::Reward => &RewardFirst,
+            StrategyKind::Riskiest => &RiskiestFirst,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+
+use clearing_house::state::market::Markets;
+use serde::Serialize;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+
+use crate::{cache::AccountCache, config::{Config, StressArgs}, executor::SIGNATURE_FEE_LAMPORTS, metrics::QUOTE_PRECISION, output, risk::{self, Candidate}, v2};
+
+/// What a price move leaves liquidatable, next to what already was before it
+#[derive(Default)]
+pub struct Stress {
+    pub users: usize,
+    pub liquidatable_before: usize,
+    pub liquidatable: usize,
+    /// the liquidator's and the insurance fund's expected shares of the fees, in quote precision
+    pub reward: u64,
+    pub insurance_fund: u64,
+    /// losses beyond the users' collateral the liquidations can't recover, in quote precision
+    pub bad_debt: u128,
+    /// quote price of one SOL, for the fees
+    pub sol_price: u64,
+}
+
+/// What `stress` prints, dollar amounts in quote
+#[derive(Serialize)]
+pub struct StressReport {
+    pub market: String,
+    /// as a fraction
+    pub price_move: f64,
+    pub users: usize,
+    pub liquidatable_before: usize,
+    pub liquidatable: usize,
+    pub reward: f64,
+    pub insurance_fund: f64,
+    pub bad_debt: f64,
+    /// the fee budget to liquidate all of them at the configured priority fee, packed as tightly as they go
+    pub transactions: usize,
+    pub compute_unit_price: u64,
+    pub fees_sol: f64,
+    pub fees: f64,
+}
+
+impl Stress {
+    fn add(&mut self, candidate: &Candidate) {
+        self.liquidatable += 1;
+        self.reward += candidate.reward.liquidator;
+        self.insurance_fund += candidate.reward.insurance_fund;
+        self.bad_debt += candidate.liquidation.bad_debt().map_or(0, |(_, bad_debt)| bad_debt);
+        self.sol_price = candidate.sol_price;
+    }
+
+    pub fn print(&self, config: &Config, args: &StressArgs) {
+        let market = match args.market {
+            Some(market_index) => format!("{} market {}", if args.spot { "spot" } else { "perp" }, market_index),
+            None => "every market".to_string(),
+        };
+        let per_transaction = config.max_liquidations_per_transaction.max(1);
+        let transactions = (self.liquidatable + per_transaction - 1) / per_transaction;
+        let fee_lamports = transactions as u64 * (SIGNATURE_FEE_LAMPORTS + config.compute_unit_price * config.compute_unit_limit as u64 / 1_000_000);
+        let report = StressReport {
+            market,
+            price_move: args.price_move,
+            users: self.users,
+            liquidatable_before: self.liquidatable_before,
+            liquidatable: self.liquidatable,
+            reward: self.reward as f64 / QUOTE_PRECISION,
+            insurance_fund: self.insurance_fund as f64 / QUOTE_PRECISION,
+            bad_debt: self.bad_debt as f64 / QUOTE_PRECISION,
+            transactions,
+            compute_unit_price: config.compute_unit_price,
+            fees_sol: fee_lamports as f64 / LAMPORTS_PER_SOL as f64,
+            fees: fee_lamports as f64 / LAMPORTS_PER_SOL as f64 * self.sol_price as f64 / QUOTE_PRECISION,
+        };
+        output::record(args.output, &report);
+    }
+}
+
+/// Moves the amm prices of the market, or every market, by `args.price_move` through their pegs, which v1 values
+/// positions at, and re-evaluates every user
+pub fn v1(cache: &mut AccountCache, config: &Config, args: &StressArgs) -> Result<Stress, String> {
+    let mut stress = Stress { users: cache.users.len(), ..Stress::default() };
+    let users: Vec<Pubkey> = cache.users.keys().copied().collect();
+    stress.liquidatable_before = users.iter().filter(|user| matches!(risk::evaluate(cache, config, user), Ok(Some(_)))).count();
+
+    let moved = args.market.map(|market_index| Markets::index_from_u64(market_index as u64));
+    if moved.map_or(false, |moved| !cache.markets.1.markets.get(moved).map_or(false, |market| market.initialized)) {
+        return Err(format!("no market {}", args.market.unwrap()));
+    }
+    for (index, market) in cache.markets.1.markets.iter_mut().enumerate() {
+        if market.initialized && moved.map_or(true, |moved| moved == index) {
+            market.amm.peg_multiplier = (market.amm.peg_multiplier as f64 * (1.0 + args.price_move)) as u128;
+        }
+    }
+    for user in &users {
+        if let Ok(Some(candidate)) = risk::evaluate(cache, config, user) {
+            stress.add(&candidate);
+        }
+    }
+    Ok(stress)
+}
+
+/// Moves the oracle of the perp (or with `args.spot` spot) market by `args.price_move`, every market priced off
+/// it included, or every oracle if no market is given, and re-evaluates every user
+pub fn v2(cache: &mut v2::Cache, config: &Config, args: &StressArgs) -> Result<Stress, String> {
+    let mut stress = Stress { users: cache.users.len(), ..Stress::default() };
+    let users: Vec<Pubkey> = cache.users.keys().copied().collect();
+    stress.liquidatable_before = users.iter().filter(|user| matches!(v2::evaluate(cache, config, user), Ok(Some(_)))).count();
+
+    let oracles: HashSet<Pubkey> = match args.market {
+        Some(market_index) if args.spot => HashSet::from([cache.spot_markets.get(&market_index).ok_or(format!("no spot market {}", market_index))?.oracle]),
+        Some(market_index) => HashSet::from([cache.perp_markets.get(&market_index).ok_or(format!("no perp market {}", market_index))?.oracle]),
+        // quote markets price at one without an oracle
+        None => cache.oracle_prices().into_keys().filter(|oracle| *oracle != Pubkey::default()).collect(),
+    };
+    let shock = |price: &mut i64| *price = (*price as f64 * (1.0 + args.price_move)) as i64;
+    for market in cache.perp_markets.values_mut().filter(|market| oracles.contains(&market.oracle)) {
+        shock(&mut market.oracle_price);
+    }
+    for market in cache.spot_markets.values_mut().filter(|market| oracles.contains(&market.oracle)) {
+        shock(&mut market.oracle_price);
+    }
+    for user in &users {
+        if let Ok(Some(candidate)) = v2::evaluate(cache, config, user) {
+            stress.add(&candidate);
+        }
+    }
+    Ok(stress)
+}
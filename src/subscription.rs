@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{nonblocking::pubsub_client::PubsubClient, rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig}};
+use solana_sdk::{account::Account, commitment_config::{CommitmentConfig, CommitmentLevel}, pubkey::Pubkey};
+use tokio::{sync::mpsc::Sender, task::JoinHandle, time::sleep};
+use tracing::warn;
+
+use crate::source::{AccountUpdate, DataSource};
+
+/// Streams program account writes through the rpc node's `programSubscribe` websocket
+pub struct WebsocketSource {
+    pub url: String,
+    pub program_id: Pubkey,
+    pub commitment: CommitmentLevel,
+}
+
+impl DataSource for WebsocketSource {
+    fn spawn(&self, sender: Sender<AccountUpdate>) -> JoinHandle<()> {
+        let url = self.url.clone();
+        let program_id = self.program_id;
+        let commitment = self.commitment;
+        tokio::spawn(async move {
+            loop {
+                match stream_accounts(&url, &program_id, commitment, &sender).await {
+                    // the receiving end is gone, nothing left to stream to
+                    Ok(()) => return,
+                    Err(err) => warn!(error = %err, "program subscription failed"),
+                }
+                sleep(Duration::from_secs(1)).await;
+            }
+        })
+    }
+}
+
+/// Runs a single program subscription until the websocket drops or `sender` is closed
+async fn stream_accounts(url: &str, program_id: &Pubkey, commitment: CommitmentLevel, sender: &Sender<AccountUpdate>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = PubsubClient::new(url).await?;
+    let config = RpcProgramAccountsConfig {
+        filters: None,
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig { commitment }),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let (mut stream, _unsubscribe) = client.program_subscribe(program_id, Some(config)).await?;
+
+    while let Some(response) = stream.next().await {
+        let pubkey = response.value.pubkey.parse::<Pubkey>();
+        let account = response.value.account.decode::<Account>();
+        if pubkey.is_err() || account.is_none() {
+            continue;
+        }
+
+        let account = account.unwrap();
+        let update = AccountUpdate {
+            pubkey: pubkey.unwrap(),
+            owner: account.owner,
+            data: account.data,
+            lamports: account.lamports,
+            slot: response.context.slot,
+        };
+        if sender.send(update).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    Err("program subscription closed".into())
+}
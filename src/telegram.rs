@@ -0,0 +1,190 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{info, warn};
+
+use crate::{metrics::METRICS, notify::{self, Event, Notifier, NotifyResult, Severity}, pause, rpc::RpcPool, status::STATUS, watchdog};
+
+// telegram refuses longer messages
+const MAX_MESSAGE_LEN: usize = 4096;
+// how long a getUpdates call waits for a message before returning empty
+const POLL_TIMEOUT_SECS: u64 = 30;
+// backoff after a failed poll
+const POLL_RETRY_DELAY: Duration = Duration::from_secs(5);
+// users `/top` lists when it isn't given a count, and the most it lists
+const DEFAULT_TOP: usize = 10;
+const MAX_TOP: usize = 50;
+// what `/pause` holds sends back for
+const PAUSE_REASON: &str = "paused from telegram";
+
+const HELP: &str = "/status - counters and feed health\n/top [n] - the n riskiest accounts\n/pause - stop sending liquidations\n/resume - send liquidations again\n/balance - the payer's SOL balance";
+
+/// A telegram bot pushing notifications to the allowlisted chats and answering their commands, so the liquidator
+/// can be checked on and paused from a phone. Messages from any other chat are ignored.
+#[derive(Clone)]
+pub struct Telegram {
+    pub token: String,
+    pub chat_ids: Vec<i64>,
+    pub http: reqwest::Client,
+    pub rpc: Arc<RpcPool>,
+    pub payer: Pubkey,
+}
+
+#[derive(Deserialize)]
+struct Updates {
+    #[serde(default)]
+    result: Vec<Update>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+impl Telegram {
+    /// Long polls for commands in the background
+    pub fn spawn_bot(self) -> JoinHandle<()> {
+        // `/top` reads the published users
+        STATUS.enable();
+        tokio::spawn(async move {
+            let mut offset = 0;
+            loop {
+                let updates = match self.updates(offset).await {
+                    Ok(updates) => updates,
+                    Err(err) => {
+                        warn!(error = %err, "failed to poll telegram");
+                        sleep(POLL_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                for update in updates {
+                    offset = offset.max(update.update_id + 1);
+                    let (chat_id, text) = match update.message {
+                        Some(Message { chat, text: Some(text) }) => (chat.id, text),
+                        _ => continue,
+                    };
+                    if !self.chat_ids.contains(&chat_id) {
+                        warn!(chat_id, "ignoring telegram message from a chat that isn't allowlisted");
+                        continue;
+                    }
+                    info!(chat_id, command = text.as_str(), "telegram command");
+                    let answer = self.answer(&text).await;
+                    if let Err(err) = self.send_message(chat_id, &answer).await {
+                        warn!(chat_id, error = %err, "failed to answer telegram command");
+                    }
+                }
+            }
+        })
+    }
+
+    async fn updates(&self, offset: i64) -> Result<Vec<Update>, reqwest::Error> {
+        let updates: Updates = self
+            .http
+            .get(self.url("getUpdates"))
+            .query(&[("offset", offset), ("timeout", POLL_TIMEOUT_SECS as i64)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(updates.result)
+    }
+
+    async fn answer(&self, text: &str) -> String {
+        let mut words = text.split_whitespace();
+        // commands in groups come as /command@botname
+        let command = words.next().unwrap_or_default().split('@').next().unwrap_or_default();
+        match command {
+            "/status" => status(),
+            "/top" => top(words.next().and_then(|count| count.parse().ok()).unwrap_or(DEFAULT_TOP).min(MAX_TOP)),
+            "/pause" => {
+                pause::pause(PAUSE_REASON);
+                notify::notify(Event::critical("liquidation sends paused from telegram".to_string()));
+                "paused, liquidations are no longer sent".to_string()
+            }
+            "/resume" if pause::resume(PAUSE_REASON) => "resumed".to_string(),
+            "/resume" => "wasn't paused from telegram".to_string(),
+            "/balance" => {
+                let payer = self.payer;
+                match self.rpc.call(|client| async move { client.get_balance(&payer).await }).await {
+                    Ok(balance) => format!("{} holds {:.4} SOL", bs58::encode(payer.to_bytes()).into_string(), balance as f64 / LAMPORTS_PER_SOL as f64),
+                    Err(err) => format!("failed to fetch the balance: {}", err),
+                }
+            }
+            _ => HELP.to_string(),
+        }
+    }
+
+    async fn send_message(&self, chat_id: i64, text: &str) -> NotifyResult {
+        self.http
+            .post(self.url("sendMessage"))
+            .json(&json!({ "chat_id": chat_id, "text": text, "disable_web_page_preview": true }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn url(&self, method: &str) -> String {
+        format!("https://api.telegram.org/bot{}/{}", self.token, method)
+    }
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    async fn send(&self, events: &[Event]) -> NotifyResult {
+        let lines = events.iter().map(|event| match event.severity {
+            Severity::Info => event.text.clone(),
+            Severity::Critical => format!("CRITICAL: {}", event.text),
+        });
+        for text in notify::chunks(lines, MAX_MESSAGE_LEN) {
+            for chat_id in &self.chat_ids {
+                self.send_message(*chat_id, &text).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn status() -> String {
+    let paused = pause::reasons();
+    let last_update = watchdog::since_last_update().map_or("none yet".to_string(), |age| format!("{:.1?} ago", age));
+    format!(
+        "slot {}, last update {}, {}\n{}",
+        watchdog::last_slot(),
+        last_update,
+        if paused.is_empty() { "sending".to_string() } else { format!("paused ({})", paused.join(", ")) },
+        METRICS.summary()
+    )
+}
+
+fn top(count: usize) -> String {
+    let users = STATUS.users.read().unwrap();
+    let mut at_risk: Vec<_> = users.iter().filter_map(|user| Some((user.user.as_str(), user.margin_ratio?))).collect();
+    if at_risk.is_empty() {
+        return "no users with open positions yet".to_string();
+    }
+    at_risk.sort_unstable_by_key(|(_, margin_ratio)| *margin_ratio);
+    // margin ratios carry 4 decimals, 10000 being 100%
+    at_risk.iter().take(count).map(|(user, margin_ratio)| format!("{} {:.2}%", user, *margin_ratio as f64 / 100.0)).collect::<Vec<String>>().join("\n")
+}
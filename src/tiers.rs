@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config::Config;
+
+/// How close a user is to liquidation, which decides how often price moves re-evaluate it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tier {
+    Critical,
+    Watch,
+    Safe,
+}
+
+/// Buckets users by their margin ratio relative to the ratio they're liquidated at. A price move re-evaluates
+/// critical users every time, watched ones every `watch_interval` moves and safe ones every `safe_interval`,
+/// rather than every user on every move. Users not classified yet count as critical.
+pub struct Tiers {
+    tiers: HashMap<Pubkey, Tier>,
+    critical_margin_percent: u128,
+    watch_margin_percent: u128,
+    watch_interval: u64,
+    safe_interval: u64,
+    repricings: u64,
+}
+
+impl Tiers {
+    pub fn new(config: &Config) -> Tiers {
+        Tiers {
+            tiers: HashMap::new(),
+            critical_margin_percent: config.critical_margin_percent,
+            watch_margin_percent: config.watch_margin_percent,
+            watch_interval: config.watch_interval.max(1),
+            safe_interval: config.safe_interval.max(1),
+            repricings: 0,
+        }
+    }
+
+    /// Picks up reloaded tier settings, keeping the users' tiers
+    pub fn reconfigure(&mut self, config: &Config) {
+        self.critical_margin_percent = config.critical_margin_percent;
+        self.watch_margin_percent = config.watch_margin_percent;
+        self.watch_interval = config.watch_interval.max(1);
+        self.safe_interval = config.safe_interval.max(1);
+    }
+
+    /// Files the user under the tier its margin ratio falls in, `liquidation_ratio` being the ratio it is
+    /// liquidated at. None means it has nothing to margin.
+    pub fn classify(&mut self, user: Pubkey, margin_ratio: Option<u128>, liquidation_ratio: u128) {
+        let tier = match margin_ratio {
+            Some(margin_ratio) if margin_ratio.saturating_mul(100) < liquidation_ratio.saturating_mul(self.critical_margin_percent) => Tier::Critical,
+            Some(margin_ratio) if margin_ratio.saturating_mul(100) < liquidation_ratio.saturating_mul(self.watch_margin_percent) => Tier::Watch,
+            _ => Tier::Safe,
+        };
+        self.tiers.insert(user, tier);
+    }
+
+    pub fn remove(&mut self, user: &Pubkey) {
+        self.tiers.remove(user);
+    }
+
+    /// Counts a price move and returns which of `users` are due re-evaluating for it
+    pub fn due<'a>(&mut self, users: impl Iterator<Item = &'a Pubkey>) -> Vec<Pubkey> {
+        self.repricings += 1;
+        users
+            .filter(|user| {
+                let interval = match self.tiers.get(user).copied().unwrap_or(Tier::Critical) {
+                    Tier::Critical => return true,
+                    Tier::Watch => self.watch_interval,
+                    Tier::Safe => self.safe_interval,
+                };
+                // offset by the pubkey so the users of a tier don't all come due on the same move
+                (self.repricings + user.to_bytes()[0] as u64) % interval == 0
+            })
+            .copied()
+            .collect()
+    }
+}
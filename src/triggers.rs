@@ -0,0 +1,64 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, ops::Bound};
+
+use solana_sdk::pubkey::Pubkey;
+
+/// Which way an oracle has to move for a user to cross into liquidation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Falls,
+    Rises,
+}
+
+/// Interval index of the oracle prices at which users cross their maintenance margin, so an oracle move can
+/// pick out the users whose liquidation price it went past instead of re-evaluating everyone
+#[derive(Default)]
+pub struct OracleTriggers {
+    // oracle and direction → liquidation price → users
+    index: HashMap<(Pubkey, Direction), BTreeMap<i64, HashSet<Pubkey>>>,
+    // what each user is filed under, for refiling it
+    users: HashMap<Pubkey, Vec<(Pubkey, Direction, i64)>>,
+    // last price seen per oracle
+    prices: HashMap<Pubkey, i64>,
+}
+
+impl OracleTriggers {
+    /// Replaces the user's liquidation prices
+    pub fn set(&mut self, user: Pubkey, triggers: Vec<(Pubkey, Direction, i64)>) {
+        self.remove(&user);
+        for (oracle, direction, price) in &triggers {
+            self.index.entry((*oracle, *direction)).or_default().entry(*price).or_default().insert(user);
+        }
+        if !triggers.is_empty() {
+            self.users.insert(user, triggers);
+        }
+    }
+
+    pub fn remove(&mut self, user: &Pubkey) {
+        for (oracle, direction, price) in self.users.remove(user).into_iter().flatten() {
+            if let Some(prices) = self.index.get_mut(&(oracle, direction)) {
+                if let Some(users) = prices.get_mut(&price) {
+                    users.remove(user);
+                    if users.is_empty() {
+                        prices.remove(&price);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the oracle's latest price and returns the users whose liquidation price it moved past since the
+    /// last one. The first price seen for an oracle only sets the baseline.
+    pub fn crossed(&mut self, oracle: Pubkey, price: i64) -> Vec<Pubkey> {
+        let last = match self.prices.insert(oracle, price) {
+            Some(last) if last != price => last,
+            _ => return vec![],
+        };
+
+        let (direction, range) = if price < last {
+            (Direction::Falls, (Bound::Included(price), Bound::Excluded(last)))
+        } else {
+            (Direction::Rises, (Bound::Excluded(last), Bound::Included(price)))
+        };
+        self.index.get(&(oracle, direction)).map_or(vec![], |prices| prices.range(range).flat_map(|(_, users)| users.iter().copied()).collect())
+    }
+}
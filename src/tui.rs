@@ -0,0 +1,152 @@
+use std::{io::{self, Stdout}, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc}, time::Duration};
+
+use crossterm::{event::{self, Event, KeyCode, KeyModifiers}, execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
+use ratatui::{backend::CrosstermBackend, layout::{Constraint, Direction, Layout}, style::{Modifier, Style}, widgets::{Block, Borders, Paragraph, Row, Table}, Frame, Terminal};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::{metrics::{METRICS, QUOTE_PRECISION}, rpc::RpcPool, shutdown, status::STATUS, watchdog};
+
+// how often the dashboard redraws, key presses are picked up in between
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A dashboard taking over the terminal: the riskiest accounts, the recent liquidations and their rewards, the
+/// rpc endpoints' health and the fees spent, refreshed in place
+pub struct Tui {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Tui {
+    /// Shows the dashboard until `stop`. Pressing q, esc or ctrl+c closes it and shuts the liquidator down.
+    pub fn spawn(rpc: Arc<RpcPool>) -> Tui {
+        STATUS.enable();
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = tokio::task::spawn_blocking({
+            let stop = stop.clone();
+            move || show(&rpc, &stop)
+        });
+        Tui { stop, handle }
+    }
+
+    /// Closes the dashboard, giving the terminal back
+    pub async fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Ok(Err(err)) = self.handle.await {
+            warn!(error = %err, "dashboard failed");
+        }
+    }
+}
+
+fn show(rpc: &RpcPool, stop: &AtomicBool) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let shown = refresh(&mut terminal, rpc, stop);
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    shown
+}
+
+fn refresh(terminal: &mut Terminal<CrosstermBackend<Stdout>>, rpc: &RpcPool, stop: &AtomicBool) -> io::Result<()> {
+    while !stop.load(Ordering::Relaxed) {
+        terminal.draw(|frame| draw(frame, rpc))?;
+        if !event::poll(REFRESH_INTERVAL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            // raw mode swallows ctrl+c, so it has to be asked for here
+            let interrupted = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if interrupted || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                shutdown::request();
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, rpc: &RpcPool) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(8), Constraint::Length(8)])
+        .split(frame.size());
+    let middle = Layout::default().direction(Direction::Horizontal).constraints([Constraint::Percentage(45), Constraint::Percentage(55)]).split(rows[1]);
+
+    frame.render_widget(summary(), rows[0]);
+    frame.render_widget(riskiest(middle[0].height.saturating_sub(3) as usize), middle[0]);
+    frame.render_widget(liquidations(middle[1].height.saturating_sub(3) as usize), middle[1]);
+    frame.render_widget(endpoints(rpc), rows[2]);
+}
+
+fn summary() -> Paragraph<'static> {
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let last_update = watchdog::since_last_update().map_or("none yet".to_string(), |age| format!("{:.1?} ago", age));
+    let text = format!(
+        "slot {}  last update {}  tracked users {}  candidates {}\nlanded {}  failed {}  expired {}  rewards ${:.2}  fees {:.6} SOL  pnl ${:.2}  (q to quit)",
+        watchdog::last_slot(),
+        last_update,
+        STATUS.users.read().unwrap().len(),
+        load(&METRICS.candidates),
+        METRICS.landed_liquidations(),
+        load(&METRICS.failed_liquidations),
+        load(&METRICS.expired_liquidations),
+        load(&METRICS.rewards) as f64 / QUOTE_PRECISION,
+        load(&METRICS.fees_lamports) as f64 / LAMPORTS_PER_SOL as f64,
+        METRICS.pnl.load(Ordering::Relaxed) as f64 / QUOTE_PRECISION,
+    );
+    Paragraph::new(text).block(titled(" drift-liquidator "))
+}
+
+fn riskiest(count: usize) -> Table<'static> {
+    let users = STATUS.users.read().unwrap();
+    let mut at_risk: Vec<_> = users.iter().filter_map(|user| Some((user.user.clone(), user.margin_ratio?))).collect();
+    at_risk.sort_unstable_by_key(|(_, margin_ratio)| *margin_ratio);
+    // margin ratios carry 4 decimals, 10000 being 100%
+    let rows = at_risk.into_iter().take(count).map(|(user, margin_ratio)| Row::new(vec![user, format!("{:.2}%", margin_ratio as f64 / 100.0)]));
+    Table::new(rows, [Constraint::Min(44), Constraint::Length(14)]).header(header(vec!["user", "margin ratio"])).block(titled(" riskiest accounts "))
+}
+
+fn liquidations(count: usize) -> Table<'static> {
+    let now_ms = watchdog::now_ms();
+    let liquidations = STATUS.liquidations.lock().unwrap();
+    let rows: Vec<Row> = liquidations
+        .iter()
+        .rev()
+        .take(count)
+        .map(|record| {
+            Row::new(vec![
+                format!("{}s ago", now_ms.saturating_sub(record.time_ms) / 1000),
+                record.user.clone(),
+                record.liquidation.to_string(),
+                record.outcome.to_string(),
+                format!("${:.2}", record.reward as f64 / QUOTE_PRECISION),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(9), Constraint::Min(44), Constraint::Length(16), Constraint::Length(8), Constraint::Length(10)];
+    Table::new(rows, widths).header(header(vec!["when", "user", "liquidation", "outcome", "reward"])).block(titled(" recent liquidations "))
+}
+
+fn endpoints(rpc: &RpcPool) -> Table<'static> {
+    let rows = rpc.status().into_iter().map(|endpoint| {
+        Row::new(vec![
+            endpoint.url,
+            if endpoint.healthy { "healthy" } else { "unhealthy" }.to_string(),
+            endpoint.slot.to_string(),
+            format!("{:.1}ms", endpoint.latency_us as f64 / 1000.0),
+        ])
+    });
+    let widths = [Constraint::Min(40), Constraint::Length(10), Constraint::Length(12), Constraint::Length(10)];
+    Table::new(rows, widths).header(header(vec!["rpc endpoint", "state", "slot", "latency"])).block(titled(" rpc health "))
+}
+
+fn header(titles: Vec<&'static str>) -> Row<'static> {
+    Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn titled(title: &'static str) -> Block<'static> {
+    Block::default().borders(Borders::ALL).title(title)
+}
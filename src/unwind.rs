@@ -0,0 +1,121 @@
+use std::{collections::BTreeMap, sync::{Arc, Mutex, OnceLock}};
+
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signer::Signer};
+use tokio::{sync::mpsc::{channel, error::TrySendError, Receiver, Sender}, task::JoinHandle};
+use tracing::{info, warn};
+
+use crate::{metrics::{self, METRICS, QUOTE_PRECISION}, pause, rpc::RpcPool, signer::SharedSigner, watchdog};
+
+// orders waiting to be sent, more wait for the next round of updates
+const ORDER_CHANNEL_SIZE: usize = 16;
+// how long a market's last order gets to land and show up in the account before the position is unwound again
+const COOLDOWN_MS: u64 = 30 * 1000;
+// positions worth less than this, in quote precision, aren't worth the fees of closing
+const MIN_POSITION_VALUE: u64 = 1_000_000;
+
+/// Closes the perp positions liquidations hand the liquidator's own account, a few orders at a time: reduce only
+/// where the protocol has it and otherwise checked against the position right before they go out, each worth at
+/// most `max_order_value` and limited to `max_slippage_bps` off the price the risk stage valued the position at.
+/// Leaves the positions alone while sends are paused.
+pub struct Unwinder {
+    pub rpc: Arc<RpcPool>,
+    pub payer: SharedSigner,
+    /// in quote precision
+    pub max_order_value: u64,
+    pub max_slippage_bps: u64,
+}
+
+// what `observe` needs from the configuration, set once the unwinder is spawned
+struct Limits {
+    max_order_value: u64,
+    max_slippage_bps: u64,
+    orders: Sender<Order>,
+}
+
+/// What the liquidator's position has to still look like when an order that isn't reduce only goes out: the
+/// account holding it, refetched right before the send, and a test of its data
+pub struct Recheck {
+    pub account: Pubkey,
+    pub holds: Box<dyn Fn(&[u8]) -> bool + Send>,
+}
+
+struct Order {
+    market_index: u16,
+    order_value: u64,
+    instruction: Instruction,
+    recheck: Option<Recheck>,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+// when each market's last order was handed over, in milliseconds since the unix epoch
+static LAST_ORDERS: Mutex<BTreeMap<u16, u64>> = Mutex::new(BTreeMap::new());
+
+/// Hands over an order closing the liquidator's position in the market, worth `value` in quote precision, unless
+/// one went out recently. `order` builds it from the value to close now and the slippage it may take, in basis
+/// points. The order is dropped rather than sent if `recheck` no longer holds by then.
+pub fn observe(market_index: u16, value: u64, recheck: Option<Recheck>, order: impl FnOnce(u64, u64) -> Option<Instruction>) {
+    let limits = match LIMITS.get() {
+        Some(limits) => limits,
+        None => return,
+    };
+    if value < MIN_POSITION_VALUE || !pause::reasons().is_empty() {
+        return;
+    }
+    let now_ms = watchdog::now_ms();
+    let mut last_orders = LAST_ORDERS.lock().unwrap();
+    if last_orders.get(&market_index).map_or(false, |last_order| now_ms.saturating_sub(*last_order) < COOLDOWN_MS) {
+        return;
+    }
+    let order_value = value.min(limits.max_order_value);
+    let instruction = match order(order_value, limits.max_slippage_bps) {
+        Some(instruction) => instruction,
+        None => return,
+    };
+    match limits.orders.try_send(Order { market_index, order_value, instruction, recheck }) {
+        Ok(()) => {
+            last_orders.insert(market_index, now_ms);
+            info!(market_index, value = value as f64 / QUOTE_PRECISION, order_value = order_value as f64 / QUOTE_PRECISION, "unwinding inherited position");
+        }
+        Err(TrySendError::Full(_)) => warn!(market_index, "too many unwind orders queued, retrying with the next round"),
+        Err(TrySendError::Closed(_)) => {}
+    }
+}
+
+impl Unwinder {
+    /// Sends the orders `observe` builds in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        let (sender, receiver) = channel(ORDER_CHANNEL_SIZE);
+        let limits = Limits { max_order_value: self.max_order_value, max_slippage_bps: self.max_slippage_bps, orders: sender };
+        if LIMITS.set(limits).is_err() {
+            warn!("the unwinder was already set up");
+        }
+        tokio::spawn(send(self.rpc, self.payer, receiver))
+    }
+}
+
+async fn send(rpc: Arc<RpcPool>, payer: SharedSigner, mut orders: Receiver<Order>) {
+    while let Some(Order { market_index, order_value, instruction, recheck }) = orders.recv().await {
+        let signer: &dyn Signer = &*payer;
+        let order_value = order_value as f64 / QUOTE_PRECISION;
+        if let Some(recheck) = recheck {
+            match rpc.get_multiple_accounts(&[recheck.account]).await {
+                Ok(accounts) if accounts.iter().any(|(_, account)| (recheck.holds)(&account.data)) => {}
+                Ok(_) => {
+                    info!(market_index, order_value, "dropped unwind order, the position changed since it was valued");
+                    continue;
+                }
+                Err(err) => {
+                    warn!(error = %err, market_index, order_value, "dropped unwind order, failed to recheck the position");
+                    continue;
+                }
+            }
+        }
+        match rpc.send_and_confirm(&[instruction], &[signer]).await {
+            Ok(signature) => {
+                metrics::increment(&METRICS.unwind_orders);
+                info!(%signature, market_index, order_value, "sent unwind order");
+            }
+            Err(err) => warn!(error = %err, market_index, order_value, "failed to send unwind order"),
+        }
+    }
+}
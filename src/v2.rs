@@ -0,0 +1,1318 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, time::Instant};
+
+use solana_sdk::{hash, instruction::{AccountMeta, Instruction}, pubkey::Pubkey, system_program, sysvar};
+use tokio::sync::{mpsc::{Receiver, Sender}, watch};
+use tracing::{debug, info, warn, Span};
+
+use crate::{breaker::CircuitBreaker, cache::{Holders, Versions}, collateral::{self, Transfer}, config::Config, error::{self, LiquidatorError}, exposure, filter::UserFilter, health, hedge::{self, Exposure}, history, idl::Idl, instructions::sighash, metrics::{self, METRICS, QUOTE_PRECISION}, oracle::{self, OraclePrice}, pnl::PNL, program, recording, reload, reward::{self, Reward}, risk::{Candidate, CandidateQueue, Liquidation}, script::ScriptFilter, shutdown, snapshot::{Snapshot, SnapshotUser, Snapshotter}, source::{AccountFilter, AccountSlices, AccountUpdate}, status::Publisher, strategy::Sizing, tiers::Tiers, triggers::{Direction, OracleTriggers}, unwind, watchdog};
+
+pub const PROGRAM_ID: &str = "dRiftyHA39MWEi3m9aunc5MzRF1JYuBsbn6VPcn33UH";
+
+// fixed point precisions of the v2 program
+pub const BASE_PRECISION: i128 = 1_000_000_000;
+// margin ratios and spot asset/liability weights
+const MARGIN_PRECISION: i128 = 10_000;
+// liquidation fees
+pub const LIQUIDATION_FEE_PRECISION: i128 = 1_000_000;
+// usdc, the market every perp settles in
+const QUOTE_SPOT_MARKET_INDEX: u16 = 0;
+const SOL_SPOT_MARKET_INDEX: u16 = 1;
+// `User::status` flags
+const BEING_LIQUIDATED: u8 = 1;
+const BANKRUPT: u8 = 2;
+
+/// `PROGRAM_ID`, or the configured `program_id` in its place
+pub fn program_id() -> Pubkey {
+    program::v2()
+}
+
+pub fn state_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"drift_state"], &program_id()).0
+}
+
+pub fn user_address(authority: &Pubkey, sub_account_id: u16) -> Pubkey {
+    Pubkey::find_program_address(&[b"user", authority.as_ref(), &sub_account_id.to_le_bytes()], &program_id()).0
+}
+
+pub fn user_stats_address(authority: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"user_stats", authority.as_ref()], &program_id()).0
+}
+
+pub fn spot_market_vault_address(market_index: u16) -> Pubkey {
+    Pubkey::find_program_address(&[b"spot_market_vault", &market_index.to_le_bytes()], &program_id()).0
+}
+
+pub fn insurance_fund_vault_address(market_index: u16) -> Pubkey {
+    Pubkey::find_program_address(&[b"insurance_fund_vault", &market_index.to_le_bytes()], &program_id()).0
+}
+
+/// The pda that owns the program's token vaults
+pub fn drift_signer_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"drift_signer"], &program_id()).0
+}
+
+// anchor's account discriminator: the first 8 bytes of sha256("account:<account name>")
+fn account_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash::hash(format!("account:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+/// Byte offsets of the account fields the liquidator reads. They are worked out from the program's idl at
+/// startup rather than compiled in, so a program upgrade that moves fields around doesn't need a new build.
+pub struct Layout {
+    /// size of a user account, if the idl gives it a fixed size
+    user_size: Option<usize>,
+    user_discriminator: [u8; 8],
+    perp_market_discriminator: [u8; 8],
+    spot_market_discriminator: [u8; 8],
+    user_authority: usize,
+    user_sub_account_id: usize,
+    user_status: usize,
+    user_spot_positions: usize,
+    spot_positions: usize,
+    spot_position_size: usize,
+    spot_position_scaled_balance: usize,
+    spot_position_market_index: usize,
+    spot_position_balance_type: usize,
+    user_perp_positions: usize,
+    perp_positions: usize,
+    perp_position_size: usize,
+    perp_position_base_asset_amount: usize,
+    perp_position_quote_asset_amount: usize,
+    perp_position_market_index: usize,
+    perp_market_index: usize,
+    perp_market_oracle: usize,
+    perp_market_oracle_price: usize,
+    perp_market_base_asset_reserve: usize,
+    perp_market_margin_ratio_initial: usize,
+    perp_market_margin_ratio_maintenance: usize,
+    perp_market_initial_pnl_asset_weight: usize,
+    perp_market_pnl_asset_weight: usize,
+    perp_market_liquidator_fee: usize,
+    perp_market_if_liquidation_fee: usize,
+    spot_market_index: usize,
+    spot_market_oracle: usize,
+    spot_market_oracle_price: usize,
+    spot_market_decimals: usize,
+    spot_market_deposit_interest: usize,
+    spot_market_borrow_interest: usize,
+    spot_market_initial_asset_weight: usize,
+    spot_market_initial_liability_weight: usize,
+    spot_market_asset_weight: usize,
+    spot_market_liability_weight: usize,
+    spot_market_liquidator_fee: usize,
+    spot_market_if_liquidation_fee: usize,
+}
+
+impl Layout {
+    pub fn from_idl(idl: &Idl) -> Result<Layout, String> {
+        let offset = |name: &str, path: &str| idl.offset_of(name, path).ok_or_else(|| format!("{}.{} is missing from the idl", name, path));
+        // account fields sit behind the discriminator
+        let account = |name: &str, path: &str| offset(name, path).map(|offset| offset + 8);
+        let size = |name: &str| idl.size_of(name).ok_or_else(|| format!("{} has no fixed size in the idl", name));
+        let len = |name: &str, field: &str| idl.array_len(name, field).ok_or_else(|| format!("{}.{} is not an array in the idl", name, field));
+
+        Ok(Layout {
+            user_size: idl.size_of("User").map(|size| size + 8),
+            user_discriminator: account_discriminator("User"),
+            perp_market_discriminator: account_discriminator("PerpMarket"),
+            spot_market_discriminator: account_discriminator("SpotMarket"),
+            user_authority: account("User", "authority")?,
+            user_sub_account_id: account("User", "sub_account_id")?,
+            user_status: account("User", "status")?,
+            user_spot_positions: account("User", "spot_positions")?,
+            spot_positions: len("User", "spot_positions")?,
+            spot_position_size: size("SpotPosition")?,
+            spot_position_scaled_balance: offset("SpotPosition", "scaled_balance")?,
+            spot_position_market_index: offset("SpotPosition", "market_index")?,
+            spot_position_balance_type: offset("SpotPosition", "balance_type")?,
+            user_perp_positions: account("User", "perp_positions")?,
+            perp_positions: len("User", "perp_positions")?,
+            perp_position_size: size("PerpPosition")?,
+            perp_position_base_asset_amount: offset("PerpPosition", "base_asset_amount")?,
+            perp_position_quote_asset_amount: offset("PerpPosition", "quote_asset_amount")?,
+            perp_position_market_index: offset("PerpPosition", "market_index")?,
+            perp_market_index: account("PerpMarket", "market_index")?,
+            perp_market_oracle: account("PerpMarket", "amm.oracle")?,
+            perp_market_oracle_price: account("PerpMarket", "amm.historical_oracle_data.last_oracle_price")?,
+            perp_market_base_asset_reserve: account("PerpMarket", "amm.base_asset_reserve")?,
+            perp_market_margin_ratio_initial: account("PerpMarket", "margin_ratio_initial")?,
+            perp_market_margin_ratio_maintenance: account("PerpMarket", "margin_ratio_maintenance")?,
+            perp_market_initial_pnl_asset_weight: account("PerpMarket", "unrealized_pnl_initial_asset_weight")?,
+            perp_market_pnl_asset_weight: account("PerpMarket", "unrealized_pnl_maintenance_asset_weight")?,
+            perp_market_liquidator_fee: account("PerpMarket", "liquidator_fee")?,
+            perp_market_if_liquidation_fee: account("PerpMarket", "if_liquidation_fee")?,
+            spot_market_index: account("SpotMarket", "market_index")?,
+            spot_market_oracle: account("SpotMarket", "oracle")?,
+            spot_market_oracle_price: account("SpotMarket", "historical_oracle_data.last_oracle_price")?,
+            spot_market_decimals: account("SpotMarket", "decimals")?,
+            spot_market_deposit_interest: account("SpotMarket", "cumulative_deposit_interest")?,
+            spot_market_borrow_interest: account("SpotMarket", "cumulative_borrow_interest")?,
+            spot_market_initial_asset_weight: account("SpotMarket", "initial_asset_weight")?,
+            spot_market_initial_liability_weight: account("SpotMarket", "initial_liability_weight")?,
+            spot_market_asset_weight: account("SpotMarket", "maintenance_asset_weight")?,
+            spot_market_liability_weight: account("SpotMarket", "maintenance_liability_weight")?,
+            spot_market_liquidator_fee: account("SpotMarket", "liquidator_fee")?,
+            spot_market_if_liquidation_fee: account("SpotMarket", "if_liquidation_fee")?,
+        })
+    }
+
+    /// Discriminators of the account types the cache keeps: users, perp markets and spot markets
+    pub fn discriminators(&self) -> [[u8; 8]; 3] {
+        [self.user_discriminator, self.perp_market_discriminator, self.spot_market_discriminator]
+    }
+
+    /// What a data source needs to stream: market accounts whole, but of user accounts only the fields `user`
+    /// decodes. The open orders, which take up most of a user account, are left out.
+    pub fn filters(&self) -> Vec<AccountFilter> {
+        let user_slices = self.user_size.map(|len| {
+            let ranges = vec![
+                (0, 8),
+                (self.user_authority, 32),
+                (self.user_sub_account_id, 2),
+                (self.user_status, 1),
+                (self.user_spot_positions, self.spot_positions * self.spot_position_size),
+                (self.user_perp_positions, self.perp_positions * self.perp_position_size),
+            ];
+            AccountSlices::new(ranges, len)
+        });
+
+        vec![
+            AccountFilter { discriminator: self.user_discriminator, slices: user_slices },
+            AccountFilter { discriminator: self.perp_market_discriminator, slices: None },
+            AccountFilter { discriminator: self.spot_market_discriminator, slices: None },
+        ]
+    }
+
+    fn user(&self, data: &[u8]) -> Option<User> {
+        let mut spot_positions = vec![];
+        for i in 0..self.spot_positions {
+            let position = self.user_spot_positions + i * self.spot_position_size;
+            let scaled_balance = read_u64(data, position + self.spot_position_scaled_balance)?;
+            if scaled_balance == 0 {
+                continue;
+            }
+            spot_positions.push(SpotPosition {
+                scaled_balance,
+                market_index: read_u16(data, position + self.spot_position_market_index)?,
+                borrow: *data.get(position + self.spot_position_balance_type)? == 1,
+            });
+        }
+
+        let mut perp_positions = vec![];
+        for i in 0..self.perp_positions {
+            let position = self.user_perp_positions + i * self.perp_position_size;
+            let base_asset_amount = read_u64(data, position + self.perp_position_base_asset_amount)? as i64;
+            let quote_asset_amount = read_u64(data, position + self.perp_position_quote_asset_amount)? as i64;
+            if base_asset_amount == 0 && quote_asset_amount == 0 {
+                continue;
+            }
+            perp_positions.push(PerpPosition {
+                base_asset_amount,
+                quote_asset_amount,
+                market_index: read_u16(data, position + self.perp_position_market_index)?,
+            });
+        }
+
+        Some(User {
+            authority: read_pubkey(data, self.user_authority)?,
+            sub_account_id: read_u16(data, self.user_sub_account_id)?,
+            status: *data.get(self.user_status)?,
+            spot_positions,
+            perp_positions,
+        })
+    }
+
+    fn perp_market(&self, pubkey: Pubkey, data: &[u8]) -> Option<PerpMarket> {
+        Some(PerpMarket {
+            pubkey,
+            market_index: read_u16(data, self.perp_market_index)?,
+            oracle: read_pubkey(data, self.perp_market_oracle)?,
+            oracle_price: read_u64(data, self.perp_market_oracle_price)? as i64,
+            base_asset_reserve: read_u128(data, self.perp_market_base_asset_reserve)?,
+            margin_ratio_initial: read_u32(data, self.perp_market_margin_ratio_initial)?,
+            margin_ratio_maintenance: read_u32(data, self.perp_market_margin_ratio_maintenance)?,
+            unrealized_pnl_initial_asset_weight: read_u32(data, self.perp_market_initial_pnl_asset_weight)?,
+            unrealized_pnl_maintenance_asset_weight: read_u32(data, self.perp_market_pnl_asset_weight)?,
+            liquidator_fee: read_u32(data, self.perp_market_liquidator_fee)?,
+            if_liquidation_fee: read_u32(data, self.perp_market_if_liquidation_fee)?,
+        })
+    }
+
+    fn spot_market(&self, pubkey: Pubkey, data: &[u8]) -> Option<SpotMarket> {
+        Some(SpotMarket {
+            pubkey,
+            market_index: read_u16(data, self.spot_market_index)?,
+            oracle: read_pubkey(data, self.spot_market_oracle)?,
+            oracle_price: read_u64(data, self.spot_market_oracle_price)? as i64,
+            decimals: read_u32(data, self.spot_market_decimals)?,
+            cumulative_deposit_interest: read_u128(data, self.spot_market_deposit_interest)?,
+            cumulative_borrow_interest: read_u128(data, self.spot_market_borrow_interest)?,
+            initial_asset_weight: read_u32(data, self.spot_market_initial_asset_weight)?,
+            initial_liability_weight: read_u32(data, self.spot_market_initial_liability_weight)?,
+            maintenance_asset_weight: read_u32(data, self.spot_market_asset_weight)?,
+            maintenance_liability_weight: read_u32(data, self.spot_market_liability_weight)?,
+            liquidator_fee: read_u32(data, self.spot_market_liquidator_fee)?,
+            if_liquidation_fee: read_u32(data, self.spot_market_if_liquidation_fee)?,
+        })
+    }
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset + N)?.try_into().ok()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+fn read_u128(data: &[u8], offset: usize) -> Option<u128> {
+    read(data, offset).map(u128::from_le_bytes)
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Option<Pubkey> {
+    read(data, offset).map(Pubkey::new_from_array)
+}
+
+pub struct SpotPosition {
+    /// token balance scaled by the market's cumulative interest
+    pub scaled_balance: u64,
+    pub market_index: u16,
+    pub borrow: bool,
+}
+
+pub struct PerpPosition {
+    pub base_asset_amount: i64,
+    pub quote_asset_amount: i64,
+    pub market_index: u16,
+}
+
+/// The parts of a v2 user account the liquidator needs, with empty positions left out
+pub struct User {
+    pub authority: Pubkey,
+    pub sub_account_id: u16,
+    pub status: u8,
+    pub spot_positions: Vec<SpotPosition>,
+    pub perp_positions: Vec<PerpPosition>,
+}
+
+pub struct PerpMarket {
+    pub pubkey: Pubkey,
+    pub market_index: u16,
+    pub oracle: Pubkey,
+    /// last oracle price the program saw, in price precision
+    pub oracle_price: i64,
+    /// in base precision, for telling how far a trade would move the amm
+    pub base_asset_reserve: u128,
+    pub margin_ratio_initial: u32,
+    pub margin_ratio_maintenance: u32,
+    pub unrealized_pnl_initial_asset_weight: u32,
+    pub unrealized_pnl_maintenance_asset_weight: u32,
+    /// shares of the liquidated notional paid to the liquidator and the insurance fund, in liquidation fee
+    /// precision
+    pub liquidator_fee: u32,
+    pub if_liquidation_fee: u32,
+}
+
+pub struct SpotMarket {
+    pub pubkey: Pubkey,
+    pub market_index: u16,
+    pub oracle: Pubkey,
+    pub oracle_price: i64,
+    pub decimals: u32,
+    pub cumulative_deposit_interest: u128,
+    pub cumulative_borrow_interest: u128,
+    pub initial_asset_weight: u32,
+    pub initial_liability_weight: u32,
+    pub maintenance_asset_weight: u32,
+    pub maintenance_liability_weight: u32,
+    pub liquidator_fee: u32,
+    pub if_liquidation_fee: u32,
+}
+
+/// In-memory view of the v2 program accounts, the counterpart of `AccountCache` for the v1 clearing house
+pub struct Cache {
+    pub layout: Layout,
+    pub users: HashMap<Pubkey, User>,
+    pub perp_markets: HashMap<u16, PerpMarket>,
+    pub spot_markets: HashMap<u16, SpotMarket>,
+    /// the liquidator's own user account, whose positions the program values alongside the liquidated user's
+    pub liquidator: Option<Pubkey>,
+    /// users the risk stage may liquidate, never the liquidator's own account
+    pub filter: UserFilter,
+    /// what each account was last updated to, so redelivered and out of order updates don't mark it dirty
+    pub versions: Versions,
+    dirty: HashSet<Pubkey>,
+    // users by the markets they have positions in
+    perp_holders: Holders<u16>,
+    spot_holders: Holders<u16>,
+    // markets that changed since the last `take_repriced`
+    repriced_perp_markets: HashSet<u16>,
+    repriced_spot_markets: HashSet<u16>,
+    // prices streamed straight from the oracle accounts, which win over the market's last recorded price
+    live_prices: HashMap<Pubkey, OraclePrice>,
+    /// most recent slot an update was seen at
+    pub slot: u64,
+    /// oracles whose liquidations are paused after a jump, disabled until `run` configures it
+    pub breaker: CircuitBreaker,
+}
+
+impl Cache {
+    pub fn new(layout: Layout) -> Cache {
+        Cache {
+            layout,
+            users: HashMap::new(),
+            perp_markets: HashMap::new(),
+            spot_markets: HashMap::new(),
+            liquidator: None,
+            filter: UserFilter::default(),
+            versions: Versions::default(),
+            dirty: HashSet::new(),
+            perp_holders: Holders::default(),
+            spot_holders: Holders::default(),
+            repriced_perp_markets: HashSet::new(),
+            repriced_spot_markets: HashSet::new(),
+            live_prices: HashMap::new(),
+            slot: 0,
+            breaker: CircuitBreaker::default(),
+        }
+    }
+
+    /// Classifies the account data by its anchor discriminator and stores it. Returns false if the data
+    /// isn't an account type the liquidator cares about.
+    pub fn apply(&mut self, pubkey: Pubkey, data: &[u8]) -> bool {
+        let discriminator = match data.get(..8) {
+            Some(discriminator) => discriminator,
+            None => return false,
+        };
+
+        if discriminator == self.layout.user_discriminator {
+            if let Some(user) = self.layout.user(data) {
+                self.perp_holders.set(pubkey, user.perp_positions.iter().map(|position| position.market_index).collect());
+                self.spot_holders.set(pubkey, user.spot_positions.iter().map(|position| position.market_index).collect());
+                self.users.insert(pubkey, user);
+                self.dirty.insert(pubkey);
+                return true;
+            }
+        } else if discriminator == self.layout.perp_market_discriminator {
+            if let Some(mut market) = self.layout.perp_market(pubkey, data) {
+                market.oracle_price = self.live_prices.get(&market.oracle).map_or(market.oracle_price, |oracle| oracle.price);
+                // a price move can push any of the market's holders under
+                self.repriced_perp_markets.insert(market.market_index);
+                self.perp_markets.insert(market.market_index, market);
+                return true;
+            }
+        } else if discriminator == self.layout.spot_market_discriminator {
+            if let Some(mut market) = self.layout.spot_market(pubkey, data) {
+                market.oracle_price = self.live_prices.get(&market.oracle).map_or(market.oracle_price, |oracle| oracle.price);
+                self.repriced_spot_markets.insert(market.market_index);
+                self.spot_markets.insert(market.market_index, market);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Prices the markets on the oracle at the price its account holds, Pyth or Switchboard going by the
+    /// account's owner. Returns false if it holds no usable price.
+    pub fn apply_oracle(&mut self, pubkey: Pubkey, owner: &Pubkey, data: &[u8]) -> bool {
+        match oracle::price(owner, data) {
+            Some(price) => {
+                self.set_oracle_price(pubkey, price);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the account is the oracle of a cached market
+    pub fn is_oracle(&self, pubkey: &Pubkey) -> bool {
+        self.perp_markets.values().any(|market| market.oracle == *pubkey) || self.spot_markets.values().any(|market| market.oracle == *pubkey)
+    }
+
+    // prices every market on the oracle at its latest price
+    fn set_oracle_price(&mut self, oracle: Pubkey, oracle_price: OraclePrice) {
+        self.live_prices.insert(oracle, oracle_price);
+        self.breaker.observe(oracle, oracle_price.slot, oracle_price.price);
+        let price = oracle_price.price;
+        for market in self.perp_markets.values_mut().filter(|market| market.oracle == oracle && market.oracle_price != price) {
+            market.oracle_price = price;
+            self.repriced_perp_markets.insert(market.market_index);
+        }
+        for market in self.spot_markets.values_mut().filter(|market| market.oracle == oracle && market.oracle_price != price) {
+            market.oracle_price = price;
+            self.repriced_spot_markets.insert(market.market_index);
+        }
+    }
+
+    /// Drops a closed user account, returning whether it was cached
+    pub fn evict(&mut self, pubkey: &Pubkey) -> bool {
+        self.dirty.remove(pubkey);
+        self.versions.remove(pubkey);
+        self.perp_holders.remove(pubkey);
+        self.spot_holders.remove(pubkey);
+        self.users.remove(pubkey).is_some()
+    }
+
+    /// Drains the set of users changed since the last call
+    pub fn take_dirty(&mut self) -> Vec<Pubkey> {
+        self.dirty.drain().collect()
+    }
+
+    /// Returns the users with positions in the markets that changed since the last call
+    pub fn take_repriced(&mut self) -> Vec<Pubkey> {
+        let perp = self.repriced_perp_markets.drain().flat_map(|market| self.perp_holders.of(&market));
+        let spot = self.repriced_spot_markets.drain().flat_map(|market| self.spot_holders.of(&market));
+        let mut users: Vec<Pubkey> = perp.chain(spot).collect();
+        users.sort_unstable();
+        users.dedup();
+        users
+    }
+
+    /// The last price of every market oracle. An oracle shared by a perp and a spot market is priced by the perp
+    /// market, so the price of an oracle always comes from the same account.
+    pub fn oracle_prices(&self) -> HashMap<Pubkey, i64> {
+        let spot = self.spot_markets.values().map(|market| (market.oracle, market.oracle_price));
+        spot.chain(self.perp_markets.values().map(|market| (market.oracle, market.oracle_price))).collect()
+    }
+
+    /// Every market account and oracle, plus the accounts of the fixed part of a liquidation
+    pub fn static_addresses(&self, liquidator_authority: &Pubkey) -> Vec<Pubkey> {
+        let mut addresses = vec![program_id(), state_address(), user_stats_address(liquidator_authority)];
+        addresses.extend(self.liquidator);
+        for market in self.perp_markets.values() {
+            addresses.push(market.pubkey);
+            addresses.push(market.oracle);
+        }
+        for market in self.spot_markets.values() {
+            addresses.push(market.pubkey);
+            addresses.push(market.oracle);
+        }
+
+        let mut unique = HashSet::new();
+        addresses.retain(|address| *address != Pubkey::default() && unique.insert(*address));
+        addresses
+    }
+
+    // oracles, then spot markets, then perp markets of every position of the users, the order the program
+    // loads them in
+    fn remaining_accounts(&self, users: &[&User], writable_spot_markets: &[u16], writable_perp_markets: &[u16]) -> Vec<AccountMeta> {
+        let mut spot_indexes = vec![QUOTE_SPOT_MARKET_INDEX];
+        let mut perp_indexes = vec![];
+        for user in users {
+            spot_indexes.extend(user.spot_positions.iter().map(|position| position.market_index));
+            perp_indexes.extend(user.perp_positions.iter().map(|position| position.market_index));
+        }
+        spot_indexes.sort_unstable();
+        spot_indexes.dedup();
+        perp_indexes.sort_unstable();
+        perp_indexes.dedup();
+
+        let spot_markets: Vec<&SpotMarket> = spot_indexes.iter().filter_map(|index| self.spot_markets.get(index)).collect();
+        let perp_markets: Vec<&PerpMarket> = perp_indexes.iter().filter_map(|index| self.perp_markets.get(index)).collect();
+
+        let mut oracles: Vec<Pubkey> = vec![];
+        for oracle in perp_markets.iter().map(|market| market.oracle).chain(spot_markets.iter().map(|market| market.oracle)) {
+            // the quote market has no oracle
+            if oracle != Pubkey::default() && !oracles.contains(&oracle) {
+                oracles.push(oracle);
+            }
+        }
+
+        let mut accounts: Vec<AccountMeta> = oracles.into_iter().map(|oracle| AccountMeta::new_readonly(oracle, false)).collect();
+        for market in spot_markets {
+            accounts.push(if writable_spot_markets.contains(&market.market_index) {
+                AccountMeta::new(market.pubkey, false)
+            } else {
+                AccountMeta::new_readonly(market.pubkey, false)
+            });
+        }
+        for market in perp_markets {
+            accounts.push(if writable_perp_markets.contains(&market.market_index) {
+                AccountMeta::new(market.pubkey, false)
+            } else {
+                AccountMeta::new_readonly(market.pubkey, false)
+            });
+        }
+
+        accounts
+    }
+}
+
+/// Risk stage for v2: folds account updates into the cache, re-evaluates the users that changed, and on market
+/// moves the users whose liquidation price the oracle went past or whose tier says are due, and forwards the
+/// liquidatable ones to the executor, most valuable first. Returns once either channel is closed or shutdown
+/// began, saving a last snapshot.
+pub async fn run(mut cache: Cache, mut config: Config, mut script: ScriptFilter, mut updates: Receiver<AccountUpdate>, candidates: Sender<Candidate>, mut shutdown: watch::Receiver<bool>, mut reloads: watch::Receiver<Config>) {
+    let mut snapshotter = Snapshotter::new(&config);
+    let mut publisher = Publisher::default();
+    let mut tiers = Tiers::new(&config);
+    let mut triggers = OracleTriggers::default();
+    cache.breaker = CircuitBreaker::new(&config);
+    for (oracle, price) in cache.oracle_prices() {
+        triggers.crossed(oracle, price);
+    }
+
+    'updates: while let Some(update) = shutdown::recv(&mut updates, &mut shutdown).await {
+        apply(&mut cache, &mut tiers, &mut triggers, update);
+        while let Ok(update) = updates.try_recv() {
+            apply(&mut cache, &mut tiers, &mut triggers, update);
+        }
+        if let Some(reloaded) = reload::changed(&mut reloads) {
+            tiers.reconfigure(&reloaded);
+            cache.breaker.reconfigure(&reloaded);
+            cache.filter.reconfigure(&reloaded, cache.liquidator);
+            script.reconfigure(&reloaded);
+            config = reloaded;
+        }
+
+        // a user's own changes always count, a market moving only for its holders that are due or whose
+        // liquidation price it crossed
+        let mut users = cache.take_dirty();
+        let repriced = cache.take_repriced();
+        if !repriced.is_empty() {
+            users.extend(tiers.due(repriced.iter()));
+            for (oracle, price) in cache.oracle_prices() {
+                users.extend(triggers.crossed(oracle, price));
+            }
+            users.sort_unstable();
+            users.dedup();
+        }
+
+        let strategy = config.strategy.strategy();
+        let mut queue = CandidateQueue::new(strategy);
+        for user_pubkey in users {
+            let evaluated = Instant::now();
+            let mut candidate = match evaluate_tracked(&cache, &config, &user_pubkey, Some((&mut tiers, &mut triggers))) {
+                Ok(Some(candidate)) => candidate,
+                Ok(None) => continue,
+                Err(err) => {
+                    metrics::increment(&METRICS.evaluation_errors);
+                    warn!(user = %bs58::encode(user_pubkey.to_bytes()).into_string(), error = %err, "failed to evaluate account");
+                    continue;
+                }
+            };
+            if exposure::refuses(&candidate.liquidation) {
+                metrics::increment(&METRICS.exposure_capped_candidates);
+                continue;
+            }
+            if !strategy.should_liquidate(&candidate) || !script.accepts(&mut candidate) {
+                debug!(user = %bs58::encode(user_pubkey.to_bytes()).into_string(), "the strategy or script passed on the candidate");
+                continue;
+            }
+            candidate.trace(evaluated.elapsed());
+            metrics::increment(&METRICS.candidates);
+            history::evaluated(&candidate);
+            debug!(
+                user = %bs58::encode(user_pubkey.to_bytes()).into_string(),
+                margin_ratio = candidate.margin_ratio as u64,
+                liquidation = candidate.liquidation.kind(),
+                market_index = ?candidate.liquidation.market_index(),
+                "liquidation candidate"
+            );
+            queue.push(candidate);
+        }
+        for candidate in queue.drain() {
+            if candidates.send(candidate).await.is_err() {
+                break 'updates;
+            }
+        }
+        snapshotter.tick(|| snapshot(&cache));
+        publisher.tick(MARGIN_PRECISION as u128, || snapshot(&cache));
+        PNL.mark(liquidator_unrealized_pnl(&cache));
+        observe_liquidator(&cache, config.auto_deposit_market_index);
+        health::iterated();
+    }
+    snapshotter.flush(|| snapshot(&cache));
+}
+
+/// The cached users with their maintenance margin ratios, for a restart to load the ones close to liquidation
+/// first
+pub fn snapshot(cache: &Cache) -> Snapshot {
+    let users = cache
+        .users
+        .iter()
+        .map(|(pubkey, user)| SnapshotUser {
+            user: bs58::encode(pubkey.to_bytes()).into_string(),
+            positions: None,
+            margin_ratio: margin(cache, user, MarginType::Maintenance)
+                .filter(|(_, margin_requirement)| *margin_requirement > 0)
+                .map(|(total_collateral, margin_requirement)| (total_collateral.max(0) * MARGIN_PRECISION / margin_requirement) as u128),
+        })
+        .collect();
+    Snapshot { users }
+}
+
+// publishes the liquidator's own maintenance margin, which `collateral` keeps within bounds by moving collateral
+// in and out of `market_index`, and hands its perp positions to `unwind`, `hedge` and `exposure`
+fn observe_liquidator(cache: &Cache, market_index: u16) {
+    let (liquidator, user) = match cache.liquidator.and_then(|liquidator| Some((liquidator, cache.users.get(&liquidator)?))) {
+        Some(liquidator) => liquidator,
+        None => return,
+    };
+    let (total_collateral, margin_requirement) = match margin(cache, user, MarginType::Maintenance) {
+        Some(margin) => margin,
+        None => return,
+    };
+    let margin_ratio = Some(margin_requirement).filter(|margin_requirement| *margin_requirement > 0).map(|margin_requirement| (total_collateral.max(0) * MARGIN_PRECISION / margin_requirement) as u128);
+    collateral::observe(total_collateral, margin_ratio, MARGIN_PRECISION as u128, |transfer, token_account, amount| match transfer {
+        Transfer::Deposit => deposit(cache, &user.authority, &liquidator, token_account, market_index, amount),
+        Transfer::Withdraw => withdraw(cache, &user.authority, &liquidator, token_account, market_index, amount),
+    });
+
+    let mut exposures = BTreeMap::new();
+    let mut notionals = BTreeMap::new();
+    for position in user.perp_positions.iter().filter(|position| position.base_asset_amount != 0) {
+        let market = match cache.perp_markets.get(&position.market_index) {
+            Some(market) => market,
+            None => continue,
+        };
+        let exposure = Exposure {
+            base_amount: position.base_asset_amount as f64 / BASE_PRECISION as f64,
+            impact_bps: hedge::price_impact_bps(market.base_asset_reserve, position.base_asset_amount as i128),
+        };
+        exposures.insert(position.market_index, exposure);
+        let value = perp_value(market, position).unsigned_abs().min(u64::MAX as u128) as u64;
+        notionals.insert(position.market_index, value);
+        unwind::observe(position.market_index, value, None, |order_value, slippage_bps| {
+            let short = position.base_asset_amount > 0;
+            let base_asset_amount = (order_value as i128 * BASE_PRECISION / market.oracle_price.max(1) as i128).min(position.base_asset_amount.unsigned_abs() as i128) as u64;
+            let bps = if short { 10_000u64.saturating_sub(slippage_bps) } else { 10_000 + slippage_bps };
+            let price = (market.oracle_price.max(0) as u128 * bps as u128 / 10_000) as u64;
+            place_perp_order(cache, &user.authority, &liquidator, position.market_index, short, base_asset_amount, price)
+        });
+    }
+    hedge::observe(exposures);
+    exposure::observe(&notionals);
+}
+
+// folds an update into the cache, evicting accounts that were closed or no longer decode
+fn apply(cache: &mut Cache, tiers: &mut Tiers, triggers: &mut OracleTriggers, update: AccountUpdate) {
+    recording::update(&update);
+    cache.slot = cache.slot.max(update.slot);
+    if cache.is_oracle(&update.pubkey) {
+        cache.apply_oracle(update.pubkey, &update.owner, &update.data);
+        return;
+    }
+    // oracles stream on a connection of their own, only program accounts say the data source is alive
+    watchdog::beat(update.slot);
+    if !update.is_closed() && !cache.versions.record(update.pubkey, update.slot, &update.data) {
+        return;
+    }
+    if (update.is_closed() || !cache.apply(update.pubkey, &update.data)) && cache.evict(&update.pubkey) {
+        tiers.remove(&update.pubkey);
+        triggers.remove(&update.pubkey);
+        metrics::increment(&METRICS.evicted_accounts);
+        info!(account = %bs58::encode(update.pubkey.to_bytes()).into_string(), "evicted closed account");
+    }
+}
+
+/// Token amount of a spot position in the mint's own precision
+pub fn token_amount(market: &SpotMarket, position: &SpotPosition) -> i128 {
+    let cumulative_interest = if position.borrow { market.cumulative_borrow_interest } else { market.cumulative_deposit_interest };
+    // scaled balances carry 9 decimals and the cumulative interest 10
+    position.scaled_balance as i128 * cumulative_interest as i128 / 10i128.pow(19 - market.decimals)
+}
+
+/// Quote value of a spot position at the market's last oracle price
+pub fn spot_value(market: &SpotMarket, position: &SpotPosition) -> i128 {
+    token_amount(market, position) * market.oracle_price as i128 / 10i128.pow(market.decimals)
+}
+
+/// Quote value of a perp position's base at the market's last oracle price, negative for shorts
+pub fn perp_value(market: &PerpMarket, position: &PerpPosition) -> i128 {
+    position.base_asset_amount as i128 * market.oracle_price as i128 / BASE_PRECISION
+}
+
+/// Estimates, per oracle the user has positions on, the price at which it crosses its maintenance margin with
+/// every other price held still. `free_collateral` is its maintenance collateral less the requirement.
+/// Unrealized profits are weighted fully, which puts the estimate at or before the real crossing.
+pub fn liquidation_prices(cache: &Cache, user: &User, free_collateral: i128) -> Vec<(Pubkey, Direction, i64)> {
+    price_sensitivities(cache, user)
+        .into_iter()
+        .filter(|(_, (slope, _))| *slope != 0)
+        .filter_map(|(oracle, (slope, price))| {
+            let liquidation_price = (price as i128 - free_collateral * BASE_PRECISION * MARGIN_PRECISION / slope).min(i64::MAX as i128) as i64;
+            if slope < 0 {
+                Some((oracle, Direction::Rises, liquidation_price))
+            } else if liquidation_price > 0 {
+                Some((oracle, Direction::Falls, liquidation_price))
+            } else {
+                // a price can't fall to zero
+                None
+            }
+        })
+        .collect()
+}
+
+// change in the user's maintenance free collateral per unit of each oracle's price, scaled by base and margin
+// precision, along with the oracle's price. perp and spot markets on the same asset share the oracle, so
+// positions on both move together
+fn price_sensitivities(cache: &Cache, user: &User) -> HashMap<Pubkey, (i128, i64)> {
+    let mut slopes: HashMap<Pubkey, (i128, i64)> = HashMap::new();
+    for position in &user.perp_positions {
+        if let Some(market) = cache.perp_markets.get(&position.market_index) {
+            let base = position.base_asset_amount as i128;
+            let slope = base * MARGIN_PRECISION - base.abs() * market.margin_ratio_maintenance as i128;
+            slopes.entry(market.oracle).or_insert((0, market.oracle_price)).0 += slope;
+        }
+    }
+    for position in &user.spot_positions {
+        if let Some(market) = cache.spot_markets.get(&position.market_index) {
+            let per_price = token_amount(market, position) * BASE_PRECISION / 10i128.pow(market.decimals);
+            let slope = if position.borrow { -per_price * market.maintenance_liability_weight as i128 } else { per_price * market.maintenance_asset_weight as i128 };
+            slopes.entry(market.oracle).or_insert((0, market.oracle_price)).0 += slope;
+        }
+    }
+    slopes
+}
+
+// `oracles_usable`, counting and logging the users it holds back
+fn oracles_guard(cache: &Cache, config: &Config, user_pubkey: &Pubkey, user: &User, free_collateral: i128) -> Option<()> {
+    match oracles_usable(cache, config, user, free_collateral) {
+        Ok(()) => Some(()),
+        Err(reason) => {
+            metrics::increment(&METRICS.oracle_guarded_candidates);
+            info!(user = %bs58::encode(user_pubkey.to_bytes()).into_string(), %reason, "holding back liquidation");
+            None
+        }
+    }
+}
+
+/// Whether the oracles the user is valued with can be trusted to liquidate it: none is paused by the circuit
+/// breaker or went without an update for over `max_oracle_staleness_slots`, which the program rejects, and, for
+/// a user under its requirement by `-free_collateral`, the oracles' confidence bands don't reach back over the
+/// requirement, in which case the user may not be liquidatable by the true price. Oracles that aren't streamed
+/// pass.
+pub fn oracles_usable(cache: &Cache, config: &Config, user: &User, free_collateral: i128) -> Result<(), String> {
+    let mut band = 0;
+    for (oracle, (slope, _)) in price_sensitivities(cache, user) {
+        if cache.breaker.is_paused(&oracle) {
+            return Err(format!("oracle {} is paused by the circuit breaker", bs58::encode(oracle.to_bytes()).into_string()));
+        }
+        let live = match cache.live_prices.get(&oracle) {
+            Some(live) => live,
+            None => continue,
+        };
+        let age = cache.slot.saturating_sub(live.slot);
+        if config.max_oracle_staleness_slots > 0 && age > config.max_oracle_staleness_slots {
+            return Err(format!("oracle {} is {} slots old", bs58::encode(oracle.to_bytes()).into_string(), age));
+        }
+        band += slope.abs() * live.confidence as i128 / (BASE_PRECISION * MARGIN_PRECISION);
+    }
+
+    if config.oracle_confidence_guard && free_collateral < 0 && free_collateral + band >= 0 {
+        return Err(format!("oracle confidence of ${:.2} covers the ${:.2} shortfall", band as f64 / QUOTE_PRECISION, -free_collateral as f64 / QUOTE_PRECISION));
+    }
+    Ok(())
+}
+
+/// Which margin weights to value an account with
+#[derive(Clone, Copy, PartialEq)]
+pub enum MarginType {
+    /// what opening or taking over positions requires
+    Initial,
+    /// what an account has to stay above to not be liquidated
+    Maintenance,
+}
+
+/// The user's total collateral and margin requirement in quote precision, valued at the last oracle prices
+/// the markets recorded. None if a market the user has a position in isn't cached.
+pub fn margin(cache: &Cache, user: &User, margin_type: MarginType) -> Option<(i128, i128)> {
+    let (mut total_collateral, mut margin_requirement) = spot_margin(cache, user, margin_type)?;
+
+    for position in &user.perp_positions {
+        let market = cache.perp_markets.get(&position.market_index)?;
+        let (margin_ratio, pnl_asset_weight) = match margin_type {
+            MarginType::Initial => (market.margin_ratio_initial, market.unrealized_pnl_initial_asset_weight),
+            MarginType::Maintenance => (market.margin_ratio_maintenance, market.unrealized_pnl_maintenance_asset_weight),
+        };
+        let base_asset_value = perp_value(market, position);
+        let unrealized_pnl = base_asset_value + position.quote_asset_amount as i128;
+        total_collateral += if unrealized_pnl > 0 { unrealized_pnl * pnl_asset_weight as i128 / MARGIN_PRECISION } else { unrealized_pnl };
+        margin_requirement += base_asset_value.abs() * margin_ratio as i128 / MARGIN_PRECISION;
+    }
+
+    Some((total_collateral, margin_requirement))
+}
+
+/// Unrealized pnl of the liquidator's own perp positions per market, valued like `margin` values them
+pub fn liquidator_unrealized_pnl(cache: &Cache) -> BTreeMap<u16, i64> {
+    let user = match cache.liquidator.and_then(|liquidator| cache.users.get(&liquidator)) {
+        Some(user) => user,
+        None => return BTreeMap::new(),
+    };
+    user.perp_positions
+        .iter()
+        .filter_map(|position| {
+            let market = cache.perp_markets.get(&position.market_index)?;
+            Some((position.market_index, (perp_value(market, position) + position.quote_asset_amount as i128) as i64))
+        })
+        .collect()
+}
+
+/// The spot side of the margin alone: weighted deposits against weighted borrows, in quote precision
+pub fn spot_margin(cache: &Cache, user: &User, margin_type: MarginType) -> Option<(i128, i128)> {
+    let mut weighted_deposits = 0;
+    let mut weighted_borrows = 0;
+    for position in &user.spot_positions {
+        let market = cache.spot_markets.get(&position.market_index)?;
+        let (asset_weight, liability_weight) = match margin_type {
+            MarginType::Initial => (market.initial_asset_weight, market.initial_liability_weight),
+            MarginType::Maintenance => (market.maintenance_asset_weight, market.maintenance_liability_weight),
+        };
+        let value = spot_value(market, position);
+        if position.borrow {
+            weighted_borrows += value * liability_weight as i128 / MARGIN_PRECISION;
+        } else {
+            weighted_deposits += value * asset_weight as i128 / MARGIN_PRECISION;
+        }
+    }
+
+    Some((weighted_deposits, weighted_borrows))
+}
+
+/// Returns a liquidation candidate if the cached user is below its maintenance margin requirement, or still
+/// flagged as being liquidated. Whichever of the user's biggest perp position and biggest borrow is worth
+/// more gets liquidated first. Bankrupt users get their bad debt resolved instead. Errs if a market the user
+/// has a position in isn't cached.
+pub fn evaluate(cache: &Cache, config: &Config, user_pubkey: &Pubkey) -> error::Result<Option<Candidate>> {
+    evaluate_tracked(cache, config, user_pubkey, None)
+}
+
+// `evaluate`, filing the user under the tier of the margin it worked out on the way and refiling its
+// liquidation prices
+fn evaluate_tracked(cache: &Cache, config: &Config, user_pubkey: &Pubkey, tracking: Option<(&mut Tiers, &mut OracleTriggers)>) -> error::Result<Option<Candidate>> {
+    let user = match cache.users.get(user_pubkey) {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+    if !cache.filter.allows(user_pubkey, &user.authority) {
+        return Ok(None);
+    }
+    // liquidations left the user with only losses, which need resolving rather than liquidating
+    if user.status & BANKRUPT != 0 {
+        return match oracles_guard(cache, config, user_pubkey, user, 0) {
+            Some(()) => bankruptcy(cache, user_pubkey, user),
+            None => Ok(None),
+        };
+    }
+
+    let (total_collateral, margin_requirement) = margin(cache, user, MarginType::Maintenance).ok_or_else(|| unknown_market(cache, user))?;
+    let margin_ratio = if margin_requirement > 0 { Some((total_collateral.max(0) * MARGIN_PRECISION / margin_requirement) as u128) } else { None };
+    if let Some((tiers, triggers)) = tracking {
+        // users are liquidated once their collateral falls under the requirement, a ratio of one
+        tiers.classify(*user_pubkey, margin_ratio, MARGIN_PRECISION as u128);
+        triggers.set(*user_pubkey, liquidation_prices(cache, user, total_collateral - margin_requirement));
+    }
+    if total_collateral >= margin_requirement && user.status & BEING_LIQUIDATED == 0 {
+        return Ok(None);
+    }
+    Ok(liquidation(cache, config, user_pubkey, user, margin_ratio, total_collateral - margin_requirement))
+}
+
+// the error for a user whose margin can't be worked out, naming the first of its markets that isn't cached
+fn unknown_market(cache: &Cache, user: &User) -> LiquidatorError {
+    let spot = user.spot_positions.iter().map(|position| position.market_index).find(|market_index| !cache.spot_markets.contains_key(market_index));
+    let perp = user.perp_positions.iter().map(|position| position.market_index).find(|market_index| !cache.perp_markets.contains_key(market_index));
+    LiquidatorError::UnknownMarket(spot.or(perp).unwrap_or_default() as u64)
+}
+
+// the liquidation of a user under its requirement by `-free_collateral`. None without a requirement, while its
+// oracles can't be trusted or if there's nothing the liquidator can take over.
+fn liquidation(cache: &Cache, config: &Config, user_pubkey: &Pubkey, user: &User, margin_ratio: Option<u128>, free_collateral: i128) -> Option<Candidate> {
+    let margin_ratio = margin_ratio?;
+    oracles_guard(cache, config, user_pubkey, user, free_collateral)?;
+
+    let mut users = vec![user];
+    users.extend(cache.liquidator.and_then(|liquidator| cache.users.get(&liquidator)));
+
+    let perp = user
+        .perp_positions
+        .iter()
+        .filter(|position| position.base_asset_amount != 0 && config.market(position.market_index).map_or(true, |market| !market.disabled))
+        .map(|position| (position, perp_value(&cache.perp_markets[&position.market_index], position).abs()))
+        .max_by_key(|(_, value)| *value);
+    let spot = if config.liquidate_borrows { borrow_liquidation(cache, config, user) } else { None };
+    let prefer_spot = match (&perp, &spot) {
+        (Some((_, position_value)), Some((_, borrow))) => spot_value(&cache.spot_markets[&borrow.market_index], borrow) > *position_value,
+        (None, Some(_)) => true,
+        _ => false,
+    };
+
+    let (liquidation, reward) = if prefer_spot {
+        let (asset_market_index, borrow) = spot?;
+        let sizing = Sizing { user: user_pubkey, margin_ratio, perp: false, market_index: borrow.market_index, max: spot_size(cache, config, borrow)? };
+        let max_liability_transfer = strategy_size(config, sizing)?;
+        let liquidation = Liquidation::Spot {
+            user_authority: user.authority,
+            asset_market_index,
+            liability_market_index: borrow.market_index,
+            max_liability_transfer,
+            remaining_accounts: cache.remaining_accounts(&users, &[asset_market_index, borrow.market_index], &[]),
+        };
+        (liquidation, reward::spot(&cache.spot_markets[&asset_market_index], &cache.spot_markets[&borrow.market_index], max_liability_transfer))
+    } else {
+        let (position, _) = perp?;
+        let sizing = Sizing { user: user_pubkey, margin_ratio, perp: true, market_index: position.market_index, max: perp_size(cache, config, position)? as u128 };
+        let max_base_asset_amount = strategy_size(config, sizing)? as u64;
+        let liquidation = Liquidation::Perp {
+            user_authority: user.authority,
+            market_index: position.market_index,
+            max_base_asset_amount,
+            remaining_accounts: cache.remaining_accounts(&users, &[QUOTE_SPOT_MARKET_INDEX], &[position.market_index]),
+        };
+        (liquidation, reward::perp(&cache.perp_markets[&position.market_index], max_base_asset_amount))
+    };
+
+    Some(Candidate { user: *user_pubkey, margin_ratio, liquidation, reward, sol_price: sol_price(cache), priority: None, span: Span::none() })
+}
+
+// the strategy's size, never more than the liquidator can take on. None if the strategy passes on the liquidation.
+fn strategy_size(config: &Config, sizing: Sizing) -> Option<u128> {
+    Some(config.strategy.strategy().size(&sizing).min(sizing.max)).filter(|size| *size > 0)
+}
+
+// oracle prices are in quote precision already
+fn sol_price(cache: &Cache) -> u64 {
+    cache.spot_markets.get(&SOL_SPOT_MARKET_INDEX).map_or(0, |market| market.oracle_price.max(0) as u64)
+}
+
+// resolves the bad debt of a bankrupt user one market at a time, perp losses first as they settle into the
+// quote borrow that the spot bankruptcy then clears
+fn bankruptcy(cache: &Cache, user_pubkey: &Pubkey, user: &User) -> error::Result<Option<Candidate>> {
+    let mut users = vec![user];
+    users.extend(cache.liquidator.and_then(|liquidator| cache.users.get(&liquidator)));
+
+    let perp_loss = user.perp_positions.iter().find(|position| position.base_asset_amount == 0 && position.quote_asset_amount < 0);
+    let liquidation = if let Some(position) = perp_loss {
+        Liquidation::PerpBankruptcy {
+            user_authority: user.authority,
+            market_index: position.market_index,
+            bad_debt: position.quote_asset_amount.unsigned_abs() as u128,
+            remaining_accounts: cache.remaining_accounts(&users, &[QUOTE_SPOT_MARKET_INDEX], &[position.market_index]),
+        }
+    } else {
+        let borrow = match user.spot_positions.iter().find(|position| position.borrow) {
+            Some(borrow) => borrow,
+            None => return Ok(None),
+        };
+        let market = cache.spot_markets.get(&borrow.market_index).ok_or(LiquidatorError::UnknownMarket(borrow.market_index as u64))?;
+        Liquidation::SpotBankruptcy {
+            user_authority: user.authority,
+            market_index: borrow.market_index,
+            bad_debt: spot_value(market, borrow).max(0) as u128,
+            remaining_accounts: cache.remaining_accounts(&users, &[borrow.market_index], &[]),
+        }
+    };
+
+    // resolving pays nothing, but leaves the markets' accounting unblocked
+    Ok(Some(Candidate { user: *user_pubkey, margin_ratio: 0, liquidation, reward: Reward::default(), sol_price: sol_price(cache), priority: None, span: Span::none() }))
+}
+
+// the deposit to take in exchange for the user's biggest borrow, and that borrow, both outside disabled spot
+// markets. None if the user has no such borrow or nothing deposited to pay for it.
+fn borrow_liquidation<'a>(cache: &Cache, config: &Config, user: &'a User) -> Option<(u16, &'a SpotPosition)> {
+    let largest = |borrow: bool| {
+        user.spot_positions
+            .iter()
+            .filter(|position| position.borrow == borrow && config.spot_market(position.market_index).map_or(true, |market| !market.disabled))
+            .max_by_key(|position| spot_value(&cache.spot_markets[&position.market_index], position))
+    };
+
+    let borrow = largest(true)?;
+    let deposit = largest(false)?;
+    Some((deposit.market_index, borrow))
+}
+
+// the most the liquidator can margin out of its free collateral, in quote precision. None, meaning no limit,
+// if the liquidator's account isn't cached
+fn usable_collateral(cache: &Cache, config: &Config) -> Option<i128> {
+    let liquidator = cache.users.get(&cache.liquidator?)?;
+    let (total_collateral, margin_requirement) = margin(cache, liquidator, MarginType::Initial)?;
+    Some((total_collateral - margin_requirement).max(0) * config.free_collateral_usage_percent as i128 / 100)
+}
+
+/// How much of a perp position to take over. Reward scales with size, so all of it, unless the liquidator's
+/// free collateral can only margin less, in which case asking for more would fail the whole liquidation.
+/// Clamped to the market's configured caps, None if that ends up under its minimum.
+pub fn perp_size(cache: &Cache, config: &Config, position: &PerpPosition) -> Option<u64> {
+    let market = &cache.perp_markets[&position.market_index];
+    let mut size = position.base_asset_amount.unsigned_abs();
+
+    // the position is taken over at the oracle price and has to be margined initially
+    let margin_per_base = market.oracle_price as i128 * market.margin_ratio_initial as i128;
+    if let (Some(usable), true) = (usable_collateral(cache, config), margin_per_base > 0) {
+        let affordable = usable * BASE_PRECISION * MARGIN_PRECISION / margin_per_base;
+        size = size.min(affordable.min(u64::MAX as i128) as u64);
+    }
+
+    let cap = config.perp_size_caps.iter().find(|cap| cap.market_index == position.market_index);
+    if let Some(max) = cap.and_then(|cap| cap.max_base_asset_amount) {
+        size = size.min(max);
+    }
+    if let (Some(max_notional), true) = (config.market(position.market_index).and_then(|market| market.max_notional), market.oracle_price > 0) {
+        size = size.min((max_notional as i128 * BASE_PRECISION / market.oracle_price as i128).min(u64::MAX as i128) as u64);
+    }
+    if size == 0 || cap.map_or(false, |cap| size < cap.min_base_asset_amount) {
+        return None;
+    }
+
+    Some(size)
+}
+
+/// How much of a borrow to repay, in the liability's token amount. Sized like `perp_size`: the whole borrow
+/// unless the liquidator's free collateral or the market's caps say otherwise.
+pub fn spot_size(cache: &Cache, config: &Config, borrow: &SpotPosition) -> Option<u128> {
+    let market = &cache.spot_markets[&borrow.market_index];
+    let mut size = token_amount(market, borrow).max(0) as u128;
+
+    // conservatively margin the repaid liability as if the liquidator borrowed it itself
+    let margin_per_token = market.oracle_price as i128 * market.initial_liability_weight as i128;
+    if let (Some(usable), true) = (usable_collateral(cache, config), margin_per_token > 0) {
+        let affordable = usable * 10i128.pow(market.decimals) * MARGIN_PRECISION / margin_per_token;
+        size = size.min(affordable as u128);
+    }
+
+    let cap = config.spot_size_caps.iter().find(|cap| cap.market_index == borrow.market_index);
+    if let Some(max) = cap.and_then(|cap| cap.max_liability_transfer) {
+        size = size.min(max as u128);
+    }
+    if let (Some(max_notional), true) = (config.spot_market(borrow.market_index).and_then(|market| market.max_notional), market.oracle_price > 0) {
+        size = size.min((max_notional as i128 * 10i128.pow(market.decimals) / market.oracle_price as i128) as u128);
+    }
+    if size == 0 || cap.map_or(false, |cap| size < cap.min_liability_transfer as u128) {
+        return None;
+    }
+
+    Some(size)
+}
+
+// state, the liquidator and the liquidated user with their stats accounts, shared by the liquidate instructions
+fn liquidate_accounts(liquidator_authority: &Pubkey, liquidator: &Pubkey, user: &Pubkey, user_authority: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new_readonly(state_address(), false),
+        AccountMeta::new_readonly(*liquidator_authority, true),
+        AccountMeta::new(*liquidator, false),
+        AccountMeta::new(user_stats_address(liquidator_authority), false),
+        AccountMeta::new(*user, false),
+        AccountMeta::new(user_stats_address(user_authority), false),
+    ]
+}
+
+/// Takes over up to `max_base_asset_amount` of the user's position in a perp market at the oracle price
+pub fn liquidate_perp(
+    liquidator_authority: &Pubkey,
+    liquidator: &Pubkey,
+    user: &Pubkey,
+    user_authority: &Pubkey,
+    market_index: u16,
+    max_base_asset_amount: u64,
+    remaining_accounts: &[AccountMeta],
+) -> Instruction {
+    let mut data = sighash("liquidate_perp").to_vec();
+    data.extend_from_slice(&market_index.to_le_bytes());
+    data.extend_from_slice(&max_base_asset_amount.to_le_bytes());
+    // no limit price
+    data.push(0);
+
+    let mut accounts = liquidate_accounts(liquidator_authority, liquidator, user, user_authority);
+    accounts.extend_from_slice(remaining_accounts);
+    Instruction { program_id: program_id(), accounts, data }
+}
+
+/// Repays up to `max_liability_transfer` of the user's borrow in the liability market, taking the user's
+/// deposit in the asset market plus the liquidation fee in exchange
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate_spot(
+    liquidator_authority: &Pubkey,
+    liquidator: &Pubkey,
+    user: &Pubkey,
+    user_authority: &Pubkey,
+    asset_market_index: u16,
+    liability_market_index: u16,
+    max_liability_transfer: u128,
+    remaining_accounts: &[AccountMeta],
+) -> Instruction {
+    let mut data = sighash("liquidate_spot").to_vec();
+    data.extend_from_slice(&asset_market_index.to_le_bytes());
+    data.extend_from_slice(&liability_market_index.to_le_bytes());
+    data.extend_from_slice(&max_liability_transfer.to_le_bytes());
+    data.push(0);
+
+    let mut accounts = liquidate_accounts(liquidator_authority, liquidator, user, user_authority);
+    accounts.extend_from_slice(remaining_accounts);
+    Instruction { program_id: program_id(), accounts, data }
+}
+
+// the insurance fund pays the bad debt out of its vault into the market's vault
+fn bankruptcy_accounts(liquidator_authority: &Pubkey, liquidator: &Pubkey, user: &Pubkey, user_authority: &Pubkey, vault_market_index: u16) -> Vec<AccountMeta> {
+    let mut accounts = liquidate_accounts(liquidator_authority, liquidator, user, user_authority);
+    accounts.extend_from_slice(&[
+        AccountMeta::new(spot_market_vault_address(vault_market_index), false),
+        AccountMeta::new(insurance_fund_vault_address(vault_market_index), false),
+        AccountMeta::new_readonly(drift_signer_address(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ]);
+    accounts
+}
+
+/// Clears a bankrupt user's losses in a perp market, covered by the quote insurance fund and, past that, by
+/// socializing them across the market
+pub fn resolve_perp_bankruptcy(
+    liquidator_authority: &Pubkey,
+    liquidator: &Pubkey,
+    user: &Pubkey,
+    user_authority: &Pubkey,
+    market_index: u16,
+    remaining_accounts: &[AccountMeta],
+) -> Instruction {
+    let mut data = sighash("resolve_perp_bankruptcy").to_vec();
+    data.extend_from_slice(&QUOTE_SPOT_MARKET_INDEX.to_le_bytes());
+    data.extend_from_slice(&market_index.to_le_bytes());
+
+    let mut accounts = bankruptcy_accounts(liquidator_authority, liquidator, user, user_authority, QUOTE_SPOT_MARKET_INDEX);
+    accounts.extend_from_slice(remaining_accounts);
+    Instruction { program_id: program_id(), accounts, data }
+}
+
+/// Creates the authority's user stats account, which every one of its user accounts needs first, paid for by the
+/// authority
+pub fn initialize_user_stats(authority: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: program_id(),
+        accounts: vec![
+            AccountMeta::new(user_stats_address(authority), false),
+            AccountMeta::new(state_address(), false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: sighash("initialize_user_stats").to_vec(),
+    }
+}
+
+/// Creates the authority's user account `sub_account_id`, paid for by the authority
+pub fn initialize_user(authority: &Pubkey, sub_account_id: u16, name: &str) -> Instruction {
+    let mut data = sighash("initialize_user").to_vec();
+    data.extend_from_slice(&sub_account_id.to_le_bytes());
+    // a fixed 32 bytes, padded with spaces the way the sdk pads it
+    let mut padded = [b' '; 32];
+    let len = name.len().min(32);
+    padded[..len].copy_from_slice(&name.as_bytes()[..len]);
+    data.extend_from_slice(&padded);
+
+    Instruction {
+        program_id: program_id(),
+        accounts: vec![
+            AccountMeta::new(user_address(authority, sub_account_id), false),
+            AccountMeta::new(user_stats_address(authority), false),
+            AccountMeta::new(state_address(), false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data,
+    }
+}
+
+/// Places a reduce only market order for `base_asset_amount` of the user's perp position, short or long, filled no
+/// worse than `price` (price precision) over the program's auction
+pub fn place_perp_order(cache: &Cache, authority: &Pubkey, user: &Pubkey, market_index: u16, short: bool, base_asset_amount: u64, price: u64) -> Option<Instruction> {
+    let user_account = cache.users.get(user)?;
+    let mut data = sighash("place_perp_order").to_vec();
+    // a market order on a perp market
+    data.extend_from_slice(&[0, 1, short as u8, 0]);
+    data.extend_from_slice(&base_asset_amount.to_le_bytes());
+    data.extend_from_slice(&price.to_le_bytes());
+    data.extend_from_slice(&market_index.to_le_bytes());
+    // reduce only, not post only nor immediate or cancel
+    data.extend_from_slice(&[1, 0, 0]);
+    // no max ts, trigger price, trigger condition above, no oracle offset nor auction overrides
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0]);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(state_address(), false),
+        AccountMeta::new(*user, false),
+        AccountMeta::new_readonly(*authority, true),
+    ];
+    accounts.extend(cache.remaining_accounts(&[user_account], &[], &[]));
+    Some(Instruction { program_id: program_id(), accounts, data })
+}
+
+/// Deposits `amount` of the spot market's token from `user_token_account` into the user, signed by its
+/// authority. The remaining accounts value the user, the deposited market writable among them.
+pub fn deposit(cache: &Cache, authority: &Pubkey, user: &Pubkey, user_token_account: &Pubkey, market_index: u16, amount: u64) -> Option<Instruction> {
+    let user_account = cache.users.get(user)?;
+    cache.spot_markets.get(&market_index)?;
+    let mut data = sighash("deposit").to_vec();
+    data.extend_from_slice(&market_index.to_le_bytes());
+    data.extend_from_slice(&amount.to_le_bytes());
+    // not reduce only
+    data.push(0);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(state_address(), false),
+        AccountMeta::new(*user, false),
+        AccountMeta::new(user_stats_address(authority), false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(spot_market_vault_address(market_index), false),
+        AccountMeta::new(*user_token_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    accounts.extend(cache.remaining_accounts(&[user_account], &[market_index], &[]));
+    Some(Instruction { program_id: program_id(), accounts, data })
+}
+
+/// Withdraws `amount` of the spot market's token from the user into `user_token_account`, signed by its
+/// authority. Reduce only, so that withdrawing more than the user's deposit doesn't borrow the rest.
+pub fn withdraw(cache: &Cache, authority: &Pubkey, user: &Pubkey, user_token_account: &Pubkey, market_index: u16, amount: u64) -> Option<Instruction> {
+    let user_account = cache.users.get(user)?;
+    cache.spot_markets.get(&market_index)?;
+    let mut data = sighash("withdraw").to_vec();
+    data.extend_from_slice(&market_index.to_le_bytes());
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(1);
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(state_address(), false),
+        AccountMeta::new(*user, false),
+        AccountMeta::new(user_stats_address(authority), false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(spot_market_vault_address(market_index), false),
+        AccountMeta::new_readonly(drift_signer_address(), false),
+        AccountMeta::new(*user_token_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    accounts.extend(cache.remaining_accounts(&[user_account], &[market_index], &[]));
+    Some(Instruction { program_id: program_id(), accounts, data })
+}
+
+/// Clears a bankrupt user's borrow in a spot market out of that market's insurance fund
+pub fn resolve_spot_bankruptcy(
+    liquidator_authority: &Pubkey,
+    liquidator: &Pubkey,
+    user: &Pubkey,
+    user_authority: &Pubkey,
+    market_index: u16,
+    remaining_accounts: &[AccountMeta],
+) -> Instruction {
+    let mut data = sighash("resolve_spot_bankruptcy").to_vec();
+    data.extend_from_slice(&market_index.to_le_bytes());
+
+    let mut accounts = bankruptcy_accounts(liquidator_authority, liquidator, user, user_authority, market_index);
+    accounts.extend_from_slice(remaining_accounts);
+    Instruction { program_id: program_id(), accounts, data }
+}
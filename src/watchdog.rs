@@ -0,0 +1,61 @@
+use std::{sync::atomic::{AtomicU64, Ordering}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use tokio::{sync::mpsc::Sender, task::JoinHandle, time::sleep};
+use tracing::warn;
+
+use crate::{metrics::{self, METRICS}, source::{AccountUpdate, DataSource}};
+
+// how often the watchdog checks on the pipeline
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// when the risk stage last applied a program account update, in milliseconds since the unix epoch. 0 until the
+// risk stage starts, so the startup scan doesn't count as a stall
+static LAST_UPDATE_MS: AtomicU64 = AtomicU64::new(0);
+// highest slot of the program account updates applied so far
+static LAST_SLOT: AtomicU64 = AtomicU64::new(0);
+
+/// Marks that the risk stage applied a program account update written at `slot`
+pub fn beat(slot: u64) {
+    LAST_UPDATE_MS.store(now_ms(), Ordering::Relaxed);
+    LAST_SLOT.fetch_max(slot, Ordering::Relaxed);
+}
+
+/// How long ago the risk stage last applied a program account update, None before the first one
+pub fn since_last_update() -> Option<Duration> {
+    match LAST_UPDATE_MS.load(Ordering::Relaxed) {
+        0 => None,
+        last_update => Some(Duration::from_millis(now_ms().saturating_sub(last_update))),
+    }
+}
+
+/// Highest slot the applied program account updates were written at
+pub fn last_slot() -> u64 {
+    LAST_SLOT.load(Ordering::Relaxed)
+}
+
+/// Runs the data source, restarting it when no program account update made it through the risk stage for
+/// `timeout`. That is mostly a connection that hung without erroring, which the source's own reconnects never
+/// notice, but a risk stage stuck on something else gets reported just the same.
+pub fn spawn(source: Box<dyn DataSource>, sender: Sender<AccountUpdate>, timeout: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut stream = source.spawn(sender.clone());
+        while !sender.is_closed() {
+            sleep(CHECK_INTERVAL).await;
+            let stalled = match since_last_update() {
+                Some(stalled) if stalled >= timeout => stalled,
+                _ => continue,
+            };
+
+            metrics::increment(&METRICS.watchdog_restarts);
+            warn!(stalled_ms = stalled.as_millis() as u64, "no program account update in too long, restarting the data source");
+            stream.abort();
+            stream = source.spawn(sender.clone());
+            // give the new connection as long as the old one had
+            LAST_UPDATE_MS.store(now_ms(), Ordering::Relaxed);
+        }
+    })
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
@@ -0,0 +1,60 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::mpsc::Sender, task::JoinHandle, time::sleep};
+use tracing::warn;
+
+use crate::{rpc::{RpcPool, MAX_MULTIPLE_ACCOUNTS}, source::{AccountUpdate, DataSource}};
+
+/// Polls a fixed set of accounts, the watched users with the markets they are valued against, with
+/// getMultipleAccounts every `interval` in place of a subscription to every program account. Only accounts whose
+/// data changed since the last poll are sent on, the caches would mark every delivered user dirty otherwise.
+pub struct WatchlistSource {
+    pub rpc: Arc<RpcPool>,
+    pub accounts: Vec<Pubkey>,
+    pub interval: Duration,
+}
+
+/// Parses the `watchlist` user accounts. Panics on one that isn't a pubkey.
+pub fn parse(watchlist: &[String]) -> Vec<Pubkey> {
+    watchlist.iter().map(|account| Pubkey::from_str(account).unwrap_or_else(|_| panic!("watchlist entry {} isn't a pubkey", account))).collect()
+}
+
+impl DataSource for WatchlistSource {
+    fn spawn(&self, sender: Sender<AccountUpdate>) -> JoinHandle<()> {
+        let (rpc, accounts, interval) = (self.rpc.clone(), self.accounts.clone(), self.interval);
+        tokio::spawn(async move {
+            let mut last: HashMap<Pubkey, Vec<u8>> = HashMap::new();
+            while poll(&rpc, &accounts, &mut last, &sender).await.is_ok() {
+                sleep(interval).await;
+            }
+        })
+    }
+}
+
+// errors only once the receiving end is gone, a failed request is retried on the next poll
+async fn poll(rpc: &RpcPool, pubkeys: &[Pubkey], last: &mut HashMap<Pubkey, Vec<u8>>, sender: &Sender<AccountUpdate>) -> Result<(), ()> {
+    for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let response = match rpc.call(|client| async move { client.get_multiple_accounts_with_commitment(chunk, client.commitment()).await }).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!(accounts = chunk.len(), error = %err, "failed to poll the watchlist");
+                continue;
+            }
+        };
+
+        for (pubkey, account) in chunk.iter().zip(response.value) {
+            let update = match account {
+                Some(account) if last.get(pubkey) != Some(&account.data) => {
+                    last.insert(*pubkey, account.data.clone());
+                    AccountUpdate { pubkey: *pubkey, owner: account.owner, data: account.data, lamports: account.lamports, slot: response.context.slot }
+                }
+                None if last.remove(pubkey).is_some() => AccountUpdate::closed(*pubkey, response.context.slot),
+                _ => continue,
+            };
+            sender.send(update).await.map_err(|_| ())?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::{sync::OnceLock, time::Duration};
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use solana_sdk::signature::Signature;
+use tokio::{sync::mpsc::{channel, error::TrySendError, Receiver, Sender}, task::JoinHandle};
+use tracing::warn;
+
+use crate::{retry::{retry, RetryPolicy}, risk::{Candidate, Liquidation}, watchdog};
+
+// payloads waiting to be posted, more are dropped rather than holding up the executor
+const PAYLOAD_CHANNEL_SIZE: usize = 1024;
+const RETRY: RetryPolicy = RetryPolicy { max_attempts: 5, base_delay: Duration::from_millis(500), max_delay: Duration::from_secs(30) };
+// carries the hex hmac-sha256 of the body under `webhook_secret`
+const SIGNATURE_HEADER: &str = "X-Liquidator-Signature";
+
+/// What gets posted for a liquidation attempt or result, one per user
+#[derive(Serialize)]
+pub struct Payload {
+    /// "attempt" when a transaction was sent, then "landed", "failed" or "expired"
+    pub event: &'static str,
+    pub user: String,
+    pub liquidation: &'static str,
+    /// the positions the liquidation takes over
+    pub positions: Value,
+    /// the user's margin ratio when it was found liquidatable
+    pub margin_ratio: u128,
+    /// the liquidator's expected share of the liquidation fee, in quote precision
+    pub reward: u64,
+    pub signature: Option<String>,
+    /// latest slot the liquidator had seen when the event happened
+    pub slot: u64,
+    pub time_ms: u64,
+}
+
+/// Posts a json payload for every liquidation attempt and result to `webhook_url`, in order and retried with
+/// backoff, signed with `webhook_secret` if one is set
+pub struct Webhook {
+    pub url: String,
+    pub secret: Option<String>,
+    pub http: reqwest::Client,
+}
+
+static PAYLOADS: OnceLock<Sender<Payload>> = OnceLock::new();
+
+/// Queues a payload per candidate, if a webhook is configured
+pub fn publish(candidates: &[Candidate], event: &'static str, signature: Option<&Signature>) {
+    let payloads = match PAYLOADS.get() {
+        Some(payloads) => payloads,
+        None => return,
+    };
+    for candidate in candidates {
+        let payload = Payload {
+            event,
+            user: bs58::encode(candidate.user.to_bytes()).into_string(),
+            liquidation: candidate.liquidation.kind(),
+            positions: positions(&candidate.liquidation),
+            margin_ratio: candidate.margin_ratio,
+            reward: candidate.reward.liquidator,
+            signature: signature.map(|signature| signature.to_string()),
+            slot: watchdog::last_slot(),
+            time_ms: watchdog::now_ms(),
+        };
+        if let Err(TrySendError::Full(payload)) = payloads.try_send(payload) {
+            warn!(user = payload.user.as_str(), event = payload.event, "dropping webhook payload, too many queued");
+        }
+    }
+}
+
+impl Webhook {
+    /// Delivers the payloads `publish` queues in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        let (sender, receiver) = channel(PAYLOAD_CHANNEL_SIZE);
+        if PAYLOADS.set(sender).is_err() {
+            warn!("the webhook was already set up");
+        }
+        tokio::spawn(self.deliver(receiver))
+    }
+
+    async fn deliver(self, mut payloads: Receiver<Payload>) {
+        while let Some(payload) = payloads.recv().await {
+            let body = serde_json::to_vec(&payload).unwrap();
+            let signature = self.secret.as_ref().map(|secret| sign(secret, &body));
+            let posted = retry(&RETRY, is_retryable, || async {
+                let mut request = self.http.post(&self.url).header("Content-Type", "application/json").body(body.clone());
+                if let Some(signature) = &signature {
+                    request = request.header(SIGNATURE_HEADER, signature.as_str());
+                }
+                request.send().await?.error_for_status()
+            })
+            .await;
+            if let Err(err) = posted {
+                warn!(user = payload.user.as_str(), event = payload.event, error = %err, "failed to post webhook payload");
+            }
+        }
+    }
+}
+
+// "sha256=" and the hex hmac of the body
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// connection errors, timeouts, rate limits and server errors, not requests the receiver refused
+fn is_retryable(err: &reqwest::Error) -> bool {
+    err.status().map_or(true, |status| status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS)
+}
+
+fn positions(liquidation: &Liquidation) -> Value {
+    match liquidation {
+        Liquidation::V1 { user_positions, .. } => json!({ "positions_account": bs58::encode(user_positions.to_bytes()).into_string() }),
+        Liquidation::Perp { market_index, max_base_asset_amount, .. } => json!({ "perp_market_index": market_index, "max_base_asset_amount": max_base_asset_amount }),
+        Liquidation::Spot { asset_market_index, liability_market_index, max_liability_transfer, .. } => json!({
+            "asset_market_index": asset_market_index,
+            "liability_market_index": liability_market_index,
+            "max_liability_transfer": max_liability_transfer.to_string(),
+        }),
+        Liquidation::PerpBankruptcy { market_index, bad_debt, .. } => json!({ "perp_market_index": market_index, "bad_debt": bad_debt.to_string() }),
+        Liquidation::SpotBankruptcy { market_index, bad_debt, .. } => json!({ "spot_market_index": market_index, "bad_debt": bad_debt.to_string() }),
+    }
+}